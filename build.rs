@@ -0,0 +1,16 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    compile_protos()?;
+    Ok(())
+}
+
+/// Generates the gRPC service of `grpc` from the proto file
+#[cfg(feature = "grpc")]
+fn compile_protos() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the bundled protoc unless another one is specified
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+    tonic_build::compile_protos("proto/johari_mirror.proto")?;
+    Ok(())
+}
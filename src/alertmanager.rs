@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use k8s_openapi::chrono::{self, DateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    http,
+    message::{excerpt, ContainerRestartInfo},
+    silence,
+};
+
+/// `alertname` label of alerts
+pub const ALERT_NAME: &str = "ContainerRestarted";
+
+/// Period alerts stay firing after the last restart unless another one is sent
+pub const DEFAULT_ALERT_DURATION: chrono::Duration = chrono::Duration::hours(1);
+
+/// Timeout of each request not to delay notifications when Alertmanager is unavailable
+const ALERTMANAGER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration of sending restarts to Prometheus Alertmanager
+#[derive(Debug, Clone)]
+pub struct AlertmanagerConfig {
+    /// e.g. `http://alertmanager:9093`
+    pub url: String,
+    /// Alerts resolve this period after the last restart
+    pub alert_duration: chrono::Duration,
+    /// Added as the `cluster` label when set
+    pub cluster_name: Option<String>,
+}
+
+impl AlertmanagerConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `ALERTMANAGER_URL` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("ALERTMANAGER_URL") else {
+            return Ok(None);
+        };
+        reqwest::Url::parse(&url).context("Invalid ALERTMANAGER_URL")?;
+        let alert_duration = match std::env::var("ALERTMANAGER_ALERT_DURATION") {
+            Ok(duration) => silence::parse_duration(&duration)
+                .map_err(|e| anyhow::anyhow!("Invalid ALERTMANAGER_ALERT_DURATION: {e}"))?,
+            Err(_) => DEFAULT_ALERT_DURATION,
+        };
+        let cluster_name = std::env::var("CLUSTER_NAME").ok();
+        Ok(Some(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            alert_duration,
+            cluster_name,
+        }))
+    }
+}
+
+/// Posts restarts to the v2 alerts API of Alertmanager
+#[derive(Clone)]
+pub struct Alertmanager {
+    config: AlertmanagerConfig,
+    http: reqwest::Client,
+}
+
+impl Alertmanager {
+    pub fn new(config: AlertmanagerConfig) -> Self {
+        Self {
+            config,
+            http: http::client(),
+        }
+    }
+
+    pub async fn send(&self, info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        let alert = alert(info, &self.config, Utc::now());
+        self.http
+            .post(format!("{}/api/v2/alerts", self.config.url))
+            .timeout(ALERTMANAGER_TIMEOUT)
+            .json(&[alert])
+            .send()
+            .await?
+            .error_for_status()?;
+        log::debug!("Sent alert of {info} to Alertmanager");
+        Ok(())
+    }
+}
+
+/// Alert of a restart. Labels identify the container, so that restarts of the same
+/// container update one alert and extend its `endsAt` instead of firing new ones.
+fn alert(
+    info: &ContainerRestartInfo,
+    config: &AlertmanagerConfig,
+    now: DateTime<Utc>,
+) -> serde_json::Value {
+    let state = info.last_state.as_ref();
+    let starts_at = state
+        .and_then(|s| s.finished_at.as_deref())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map_or(now, |t| t.with_timezone(&Utc));
+    let mut labels = json!({
+        "alertname": ALERT_NAME,
+        "namespace": info.namespace.as_deref().unwrap_or(""),
+        "pod": &info.pod_name,
+        "container": &info.container_name,
+    });
+    if let Some(severity) = &info.options.severity {
+        labels["severity"] = json!(severity);
+    }
+    if let Some(cluster_name) = &config.cluster_name {
+        labels["cluster"] = json!(cluster_name);
+    }
+    let mut annotations = json!({
+        "summary": format!("Container {} restarted", info.container_key()),
+        "image": &info.container_image,
+        "restart_count": info.restart_count.to_string(),
+        "channel": &info.channel,
+    });
+    if let Some(state) = state {
+        annotations["exit_code"] = json!(state.exit_code.to_string());
+        if let Some(reason) = &state.reason {
+            annotations["reason"] = json!(reason);
+        }
+    }
+    if let Some(node_name) = &info.node_name {
+        annotations["node"] = json!(node_name);
+    }
+    match &info.logs.0 {
+        Ok(logs) => annotations["logs"] = json!(excerpt(logs)),
+        Err(e) => annotations["logs"] = json!(format!("Failed to get container logs: {e}")),
+    }
+    json!({
+        "labels": labels,
+        "annotations": annotations,
+        "startsAt": starts_at.to_rfc3339(),
+        "endsAt": (now + config.alert_duration).to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_alert() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.options.severity = Some("critical".to_owned());
+        info.last_state.as_mut().unwrap().finished_at = Some("2024-01-02T03:04:05Z".to_owned());
+        let config = AlertmanagerConfig {
+            url: "http://alertmanager:9093".to_owned(),
+            alert_duration: DEFAULT_ALERT_DURATION,
+            cluster_name: Some("production".to_owned()),
+        };
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 5, 0).unwrap();
+        let alert = alert(&info, &config, now);
+        assert_eq!(
+            alert["labels"],
+            json!({
+                "alertname": "ContainerRestarted",
+                "namespace": "default",
+                "pod": "app-0",
+                "container": "app",
+                "severity": "critical",
+                "cluster": "production",
+            })
+        );
+        assert_eq!(alert["annotations"]["restart_count"], "1");
+        assert_eq!(alert["annotations"]["reason"], "Error");
+        assert_eq!(alert["startsAt"], "2024-01-02T03:04:05+00:00");
+        assert_eq!(alert["endsAt"], "2024-01-02T04:05:00+00:00");
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{self, DateTime, Utc};
+
+use crate::{
+    history::{RestartHistory, RestartRecord, HISTORY_RETENTION_DAYS},
+    message::ContainerRestartInfo,
+    middleware::Middleware,
+    silence,
+};
+
+/// Period of the recent restart rate by default
+pub const DEFAULT_ANOMALY_WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+/// Period of the baseline restart rate by default
+pub const DEFAULT_ANOMALY_BASELINE: chrono::Duration = chrono::Duration::days(7);
+
+/// Minimum days of history to regard restarts as chronic
+const MIN_CHRONIC_DAYS: i64 = 3;
+
+/// Configuration of flagging anomalous restart rates of workloads
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    /// Ratio of the recent rate to the baseline regarded as anomalous, e.g. `3`
+    pub threshold: f64,
+    pub window: chrono::Duration,
+    /// At most the retention of `RestartHistory`
+    pub baseline: chrono::Duration,
+    /// Drop notifications of workloads restarting every day at the baseline rate
+    pub suppress_chronic: bool,
+}
+
+impl AnomalyConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `RESTART_ANOMALY_THRESHOLD` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(threshold) = std::env::var("RESTART_ANOMALY_THRESHOLD") else {
+            return Ok(None);
+        };
+        let threshold = threshold
+            .parse::<f64>()
+            .context("Invalid RESTART_ANOMALY_THRESHOLD")?;
+        if threshold.is_nan() || threshold <= 1.0 {
+            bail!("RESTART_ANOMALY_THRESHOLD must be greater than 1");
+        }
+        let window = match std::env::var("RESTART_ANOMALY_WINDOW") {
+            Ok(window) => silence::parse_duration(&window)
+                .map_err(|e| anyhow::anyhow!("Invalid RESTART_ANOMALY_WINDOW: {e}"))?,
+            Err(_) => DEFAULT_ANOMALY_WINDOW,
+        };
+        let baseline = match std::env::var("RESTART_ANOMALY_BASELINE") {
+            Ok(baseline) => silence::parse_duration(&baseline)
+                .map_err(|e| anyhow::anyhow!("Invalid RESTART_ANOMALY_BASELINE: {e}"))?,
+            Err(_) => DEFAULT_ANOMALY_BASELINE,
+        };
+        if baseline <= window || baseline > chrono::Duration::days(HISTORY_RETENTION_DAYS) {
+            bail!(
+                "RESTART_ANOMALY_BASELINE must be longer than RESTART_ANOMALY_WINDOW \
+                 and at most {HISTORY_RETENTION_DAYS}d"
+            );
+        }
+        let suppress_chronic = match std::env::var("RESTART_ANOMALY_SUPPRESS_CHRONIC") {
+            Ok(suppress) => suppress
+                .parse()
+                .context("Invalid RESTART_ANOMALY_SUPPRESS_CHRONIC")?,
+            Err(_) => false,
+        };
+        Ok(Some(Self {
+            threshold,
+            window,
+            baseline,
+            suppress_chronic,
+        }))
+    }
+}
+
+/// Restart rate of a workload compared with its baseline
+#[derive(Debug, PartialEq)]
+enum Assessment {
+    Normal,
+    /// `recent` restarts in the window while `expected` on average in the baseline
+    Anomalous {
+        recent: usize,
+        expected: f64,
+    },
+    /// Restarting every day at the baseline rate, e.g. a known leak
+    Chronic,
+}
+
+/// Flags notifications of workloads restarting more often than usual and optionally
+/// drops those of chronic restarts, based on `RestartHistory`
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    history: RestartHistory,
+    /// Restarts before are unknown unless restored from the crash store
+    started_at: DateTime<Utc>,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: AnomalyConfig, history: RestartHistory) -> Self {
+        Self {
+            config,
+            history,
+            started_at: Utc::now(),
+        }
+    }
+}
+
+impl Middleware for AnomalyDetector {
+    fn process(&mut self, mut info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        let namespace = info.namespace.as_deref().unwrap_or("");
+        let workload = info.workload.as_deref().unwrap_or(&info.pod_name);
+        let records = self.history.workload_records(namespace, workload);
+        // Records restored from the crash store extend the baseline
+        let observed_since = self
+            .history
+            .recorded_since()
+            .map_or(self.started_at, |since| since.min(self.started_at));
+        let records = records.iter().collect::<Vec<_>>();
+        match assess(&records, Utc::now(), observed_since, &self.config) {
+            Assessment::Normal => {}
+            Assessment::Anomalous { recent, expected } => {
+                let window = silence::format_duration(self.config.window);
+                let note = if expected > 0.0 {
+                    format!(
+                        ":chart_with_upwards_trend: {recent} restarts in the last {window}, \
+                         {:.1}x normal for this workload",
+                        recent as f64 / expected
+                    )
+                } else {
+                    format!(
+                        ":chart_with_upwards_trend: {recent} restarts in the last {window}, \
+                         none in the previous {}",
+                        silence::format_duration(self.config.baseline)
+                    )
+                };
+                log::info!("Anomalous restart rate of {info}: {recent} in {window}");
+                info.notes.push(note);
+            }
+            Assessment::Chronic if self.config.suppress_chronic => {
+                log::info!("Dropping notification of chronic restarts: {info}");
+                return None;
+            }
+            Assessment::Chronic => {}
+        }
+        Some(info)
+    }
+}
+
+/// Compares restarts of a workload in the window before `now` with the baseline before
+/// the window. The baseline is shortened to the history observed since `observed_since`.
+fn assess(
+    records: &[&RestartRecord],
+    now: DateTime<Utc>,
+    observed_since: DateTime<Utc>,
+    config: &AnomalyConfig,
+) -> Assessment {
+    let window_start = now - config.window;
+    let baseline_start = (now - config.baseline).max(observed_since);
+    let baseline = window_start - baseline_start;
+    // Too short to tell the usual rate
+    if baseline < config.window {
+        return Assessment::Normal;
+    }
+    let recent = records.iter().filter(|r| r.time >= window_start).count();
+    let in_baseline = records
+        .iter()
+        .filter(|r| baseline_start <= r.time && r.time < window_start)
+        .collect::<Vec<_>>();
+    let expected = in_baseline.len() as f64 * config.window.num_seconds() as f64
+        / baseline.num_seconds() as f64;
+    // A single restart in the window is not anomalous however rare restarts are
+    if recent as f64 >= config.threshold * expected.max(1.0) {
+        return Assessment::Anomalous { recent, expected };
+    }
+    let days = baseline.num_days();
+    if days >= MIN_CHRONIC_DAYS
+        && (0..days).all(|day| {
+            let end = window_start - chrono::Duration::days(day);
+            let start = end - chrono::Duration::days(1);
+            in_baseline.iter().any(|r| start <= r.time && r.time < end)
+        })
+    {
+        return Assessment::Chronic;
+    }
+    Assessment::Normal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AnomalyConfig {
+        AnomalyConfig {
+            threshold: 3.0,
+            window: chrono::Duration::hours(1),
+            baseline: chrono::Duration::days(7),
+            suppress_chronic: true,
+        }
+    }
+
+    fn record(time: DateTime<Utc>) -> RestartRecord {
+        RestartRecord {
+            time,
+            namespace: "default".to_owned(),
+            workload: "app".to_owned(),
+            pod: "app-abc".to_owned(),
+            container: "app".to_owned(),
+            restart_count: 1,
+            exit_code: Some(1),
+            reason: Some("Error".to_owned()),
+        }
+    }
+
+    /// Restarts every `interval` in the baseline and `recent` restarts in the window
+    fn records(
+        now: DateTime<Utc>,
+        interval: chrono::Duration,
+        recent: usize,
+    ) -> Vec<RestartRecord> {
+        let mut records = Vec::new();
+        let mut time = now - chrono::Duration::days(7);
+        while time < now - chrono::Duration::hours(1) {
+            records.push(record(time));
+            time += interval;
+        }
+        for i in 0..recent {
+            records.push(record(now - chrono::Duration::minutes(i as i64 + 1)));
+        }
+        records
+    }
+
+    #[test]
+    fn test_assess() {
+        let now = Utc::now();
+        let since = now - chrono::Duration::days(14);
+        let config = config();
+
+        // Once in 6 hours usually, then 3 times in the last hour
+        let records = records(now, chrono::Duration::hours(6), 3);
+        let records = records.iter().collect::<Vec<_>>();
+        let Assessment::Anomalous { recent, expected } = assess(&records, now, since, &config)
+        else {
+            panic!("Expected anomalous rate");
+        };
+        assert_eq!(recent, 3);
+        assert!((expected - 28.0 / 167.0).abs() < 0.01);
+
+        // Once in 6 hours as usual
+        let records = self::records(now, chrono::Duration::hours(6), 1);
+        let records = records.iter().collect::<Vec<_>>();
+        assert_eq!(assess(&records, now, since, &config), Assessment::Chronic);
+
+        // Twice an hour usually, then 4 times in the last hour
+        let records = self::records(now, chrono::Duration::minutes(30), 4);
+        let records = records.iter().collect::<Vec<_>>();
+        assert_eq!(assess(&records, now, since, &config), Assessment::Chronic);
+
+        // Not enough history observed
+        let records = self::records(now, chrono::Duration::hours(6), 3);
+        let records = records.iter().collect::<Vec<_>>();
+        let since = now - chrono::Duration::minutes(90);
+        assert_eq!(assess(&records, now, since, &config), Assessment::Normal);
+    }
+
+    #[test]
+    fn test_assess_rare_restarts() {
+        let now = Utc::now();
+        let since = now - chrono::Duration::days(14);
+        let records = [record(now - chrono::Duration::days(3)), record(now)];
+        let records = records.iter().collect::<Vec<_>>();
+        assert_eq!(assess(&records, now, since, &config()), Assessment::Normal);
+    }
+}
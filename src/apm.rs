@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    http::{self, parse_headers},
+    message::ContainerRestartInfo,
+};
+
+pub const DEFAULT_APM_EVENT_TYPE: &str = "ContainerRestart";
+
+/// Label of the version of the application, used as the `version` attribute
+/// https://kubernetes.io/docs/concepts/overview/working-with-objects/common-labels/
+const VERSION_LABEL: &str = "app.kubernetes.io/version";
+
+/// Timeout of each request not to delay notifications when the APM is unavailable
+const APM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration of emitting a custom event per restart to New Relic or another APM
+/// accepting the same format, a JSON array of flat objects with `eventType`
+#[derive(Debug, Clone)]
+pub struct ApmConfig {
+    pub url: String,
+    /// e.g. `Api-Key` of New Relic
+    pub headers: Vec<(String, String)>,
+    pub event_type: String,
+    /// Added as the `cluster` attribute when set
+    pub cluster_name: Option<String>,
+}
+
+impl ApmConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when neither `NEW_RELIC_ACCOUNT_ID` nor `APM_EVENT_URL` is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let (url, headers) = match (
+            std::env::var("NEW_RELIC_ACCOUNT_ID"),
+            std::env::var("APM_EVENT_URL"),
+        ) {
+            (Ok(_), Ok(_)) => bail!("NEW_RELIC_ACCOUNT_ID and APM_EVENT_URL are exclusive"),
+            (Ok(account_id), Err(_)) => {
+                let api_key = std::env::var("NEW_RELIC_API_KEY")
+                    .context("NEW_RELIC_API_KEY is required with NEW_RELIC_ACCOUNT_ID")?;
+                let host = match std::env::var("NEW_RELIC_REGION").as_deref() {
+                    Ok("us") | Err(_) => "insights-collector.newrelic.com",
+                    Ok("eu") => "insights-collector.eu01.nr-data.net",
+                    Ok(region) => bail!("Invalid NEW_RELIC_REGION: {region}"),
+                };
+                (
+                    format!("https://{host}/v1/accounts/{account_id}/events"),
+                    vec![("Api-Key".to_owned(), api_key)],
+                )
+            }
+            (Err(_), Ok(url)) => {
+                reqwest::Url::parse(&url).context("Invalid APM_EVENT_URL")?;
+                let headers = match std::env::var("APM_EVENT_HEADERS") {
+                    Ok(headers) => parse_headers(&headers).context("Invalid APM_EVENT_HEADERS")?,
+                    Err(_) => Vec::new(),
+                };
+                (url, headers)
+            }
+            (Err(_), Err(_)) => return Ok(None),
+        };
+        let event_type =
+            std::env::var("APM_EVENT_TYPE").unwrap_or_else(|_| DEFAULT_APM_EVENT_TYPE.to_owned());
+        let cluster_name = std::env::var("CLUSTER_NAME").ok();
+        Ok(Some(Self {
+            url,
+            headers,
+            event_type,
+            cluster_name,
+        }))
+    }
+}
+
+/// Emits custom events of restarts
+#[derive(Clone)]
+pub struct ApmEvents {
+    config: ApmConfig,
+    http: reqwest::Client,
+}
+
+impl ApmEvents {
+    pub fn new(config: ApmConfig) -> Self {
+        Self {
+            config,
+            http: http::client(),
+        }
+    }
+
+    pub async fn emit(&self, info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        let event = event(info, &self.config, Utc::now());
+        let mut request = self
+            .http
+            .post(&self.config.url)
+            .timeout(APM_TIMEOUT)
+            .json(&[event]);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        request.send().await?.error_for_status()?;
+        log::debug!("Emitted APM event of {info}");
+        Ok(())
+    }
+}
+
+/// Event of a restart with deployment metadata: the workload, image tag, version label
+/// and pod labels as `label.<name>`. `timestamp` is when the container finished, or `now`.
+fn event(info: &ContainerRestartInfo, config: &ApmConfig, now: DateTime<Utc>) -> serde_json::Value {
+    let state = info.last_state.as_ref();
+    let timestamp = state
+        .and_then(|s| s.finished_at.as_deref())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map_or(now, |t| t.with_timezone(&Utc));
+    // Tags follow the last `:` unless it is the port of the registry
+    let image_tag = info
+        .container_image
+        .rsplit_once(':')
+        .map(|(_, tag)| tag)
+        .filter(|tag| !tag.contains('/'));
+    let mut event = json!({
+        "eventType": config.event_type,
+        "timestamp": timestamp.timestamp(),
+        "cluster": config.cluster_name,
+        "namespace": info.namespace,
+        "pod": info.pod_name,
+        "container": info.container_name,
+        "workload": info.workload,
+        "image": info.container_image,
+        "imageTag": image_tag,
+        "version": info.labels.get(VERSION_LABEL),
+        "node": info.node_name,
+        "restartCount": info.restart_count,
+        "exitCode": state.map(|s| s.exit_code),
+        "reason": state.and_then(|s| s.reason.as_deref()),
+        "severity": info.options.severity.as_deref(),
+        "channel": info.channel,
+    });
+    let attributes = event.as_object_mut().unwrap();
+    for (name, value) in &info.labels {
+        attributes.insert(format!("label.{name}"), json!(value));
+    }
+    // Null attributes are rejected by New Relic
+    attributes.retain(|_, value| !value.is_null());
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ApmConfig {
+        ApmConfig {
+            url: "https://insights-collector.newrelic.com/v1/accounts/1/events".to_owned(),
+            headers: Vec::new(),
+            event_type: DEFAULT_APM_EVENT_TYPE.to_owned(),
+            cluster_name: None,
+        }
+    }
+
+    #[test]
+    fn test_event() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "web-5d4f8c7b9-abcde",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.container_image = "registry:5000/web:1.2.3".to_owned();
+        info.workload = Some("web".to_owned());
+        info.labels = [(VERSION_LABEL.to_owned(), "1.2.3".to_owned())].into();
+        info.last_state.as_mut().unwrap().finished_at = Some("2024-01-02T03:04:05Z".to_owned());
+        let restart_event = event(&info, &config(), Utc::now());
+        assert_eq!(restart_event["eventType"], "ContainerRestart");
+        assert_eq!(restart_event["timestamp"], 1704164645);
+        assert_eq!(restart_event["workload"], "web");
+        assert_eq!(restart_event["imageTag"], "1.2.3");
+        assert_eq!(restart_event["version"], "1.2.3");
+        assert_eq!(restart_event["label.app.kubernetes.io/version"], "1.2.3");
+        assert_eq!(restart_event["exitCode"], 1);
+        assert!(restart_event.get("cluster").is_none());
+
+        info.container_image = "registry:5000/web".to_owned();
+        assert!(event(&info, &config(), Utc::now())
+            .get("imageTag")
+            .is_none());
+    }
+}
@@ -0,0 +1,9 @@
+use subtle::ConstantTimeEq;
+
+/// Whether the `authorization` header or gRPC metadata is `Bearer <token>`.
+/// Compared in constant time not to leak the token through response times.
+pub(crate) fn is_bearer(authorization: Option<&str>, token: &str) -> bool {
+    authorization
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|t| t.as_bytes().ct_eq(token.as_bytes()).into())
+}
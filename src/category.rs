@@ -0,0 +1,218 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::message::ContainerState;
+
+/// Log lines before the restart searched for the patterns of categories
+const CLASSIFIED_LOG_LINES: usize = 100;
+
+/// Case-insensitive patterns of logs of panics and uncaught exceptions
+const PANIC_PATTERNS: &[&str] = &[
+    "panicked at",
+    "panic:",
+    "fatal error:",
+    "traceback (most recent call last)",
+    "exception in thread",
+    "unhandled exception",
+    "uncaught exception",
+];
+
+/// Case-insensitive patterns of logs of invalid or missing configuration
+const CONFIG_ERROR_PATTERNS: &[&str] = &[
+    "invalid configuration",
+    "invalid config",
+    "configuration error",
+    "config error",
+    "missing required",
+    "environment variable not found",
+    "is not set",
+];
+
+/// Case-insensitive patterns of logs of unreachable or slow dependencies
+const DEPENDENCY_TIMEOUT_PATTERNS: &[&str] = &[
+    "timed out",
+    "timeout",
+    "deadline exceeded",
+    "connection refused",
+    "no route to host",
+    "could not connect",
+    "failed to connect",
+];
+
+/// Likely cause of a crash, classified from the exit code, the signal and logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashCategory {
+    Oom,
+    Panic,
+    Segfault,
+    ConfigError,
+    DependencyTimeout,
+    /// Killed by the kubelet after the liveness probe failed
+    ProbeKill,
+}
+
+impl CrashCategory {
+    pub const ALL: [Self; 6] = [
+        Self::Oom,
+        Self::Panic,
+        Self::Segfault,
+        Self::ConfigError,
+        Self::DependencyTimeout,
+        Self::ProbeKill,
+    ];
+
+    /// Name in notification rules, e.g. `config_error`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Oom => "oom",
+            Self::Panic => "panic",
+            Self::Segfault => "segfault",
+            Self::ConfigError => "config_error",
+            Self::DependencyTimeout => "dependency_timeout",
+            Self::ProbeKill => "probe_kill",
+        }
+    }
+
+    /// Shown in messages, e.g. `OOM killed`
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Oom => "OOM killed",
+            Self::Panic => "Panic",
+            Self::Segfault => "Segfault",
+            Self::ConfigError => "Config error",
+            Self::DependencyTimeout => "Dependency timeout",
+            Self::ProbeKill => "Liveness probe kill",
+        }
+    }
+}
+
+impl std::fmt::Display for CrashCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for CrashCategory {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::ALL
+            .into_iter()
+            .find(|category| category.as_str() == s)
+        {
+            Some(category) => Ok(category),
+            None => bail!("Unknown crash category: {s}"),
+        }
+    }
+}
+
+/// Classifies the crash ending in `state` with the last lines of `logs` before it.
+/// Kills by SIGKILL or SIGTERM are regarded as probe kills when the container has
+/// a liveness probe. `None` when no category applies, e.g. a plain non-zero exit.
+pub fn classify(
+    state: Option<&ContainerState>,
+    logs: Option<&str>,
+    liveness_probe: bool,
+) -> Option<CrashCategory> {
+    let state = state?;
+    if state.reason.as_deref() == Some("OOMKilled") {
+        return Some(CrashCategory::Oom);
+    }
+    if state.exit_code == 139 || state.signal == Some(11) {
+        return Some(CrashCategory::Segfault);
+    }
+    let tail = logs
+        .map(|logs| {
+            let lines = logs.lines().collect::<Vec<_>>();
+            lines[lines.len().saturating_sub(CLASSIFIED_LOG_LINES)..]
+                .join("\n")
+                .to_lowercase()
+        })
+        .unwrap_or_default();
+    let logged = |patterns: &[&str]| patterns.iter().any(|pattern| tail.contains(pattern));
+    // Rust panics exit with 101
+    if state.exit_code == 101 || logged(PANIC_PATTERNS) {
+        return Some(CrashCategory::Panic);
+    }
+    if liveness_probe && matches!(state.exit_code, 137 | 143) {
+        return Some(CrashCategory::ProbeKill);
+    }
+    if logged(CONFIG_ERROR_PATTERNS) {
+        return Some(CrashCategory::ConfigError);
+    }
+    if logged(DEPENDENCY_TIMEOUT_PATTERNS) {
+        return Some(CrashCategory::DependencyTimeout);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(exit_code: i32, reason: &str) -> ContainerState {
+        ContainerState {
+            exit_code,
+            signal: None,
+            reason: Some(reason.to_owned()),
+            message: None,
+            started_at: None,
+            finished_at: None,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let classify_logs =
+            |exit_code, logs, probe| classify(Some(&state(exit_code, "Error")), Some(logs), probe);
+        assert_eq!(
+            classify(Some(&state(137, "OOMKilled")), None, true),
+            Some(CrashCategory::Oom)
+        );
+        assert_eq!(classify_logs(139, "", false), Some(CrashCategory::Segfault));
+        assert_eq!(
+            classify_logs(
+                1,
+                "thread 'main' panicked at src/main.rs:3:5:\nconnection refused",
+                false
+            ),
+            Some(CrashCategory::Panic)
+        );
+        assert_eq!(
+            classify_logs(1, "Error: DATABASE_URL is not set", false),
+            Some(CrashCategory::ConfigError)
+        );
+        assert_eq!(
+            classify_logs(1, "dial tcp 10.0.0.1:5432: i/o timeout", false),
+            Some(CrashCategory::DependencyTimeout)
+        );
+        assert_eq!(
+            classify_logs(137, "request timed out", true),
+            Some(CrashCategory::ProbeKill)
+        );
+        assert_eq!(
+            classify_logs(137, "request timed out", false),
+            Some(CrashCategory::DependencyTimeout)
+        );
+        assert_eq!(classify_logs(1, "bye", false), None);
+        // Only the lines right before the restart are classified
+        let logs = format!(
+            "connection refused\n{}",
+            "ok\n".repeat(CLASSIFIED_LOG_LINES)
+        );
+        assert_eq!(classify_logs(1, &logs, false), None);
+        assert_eq!(classify(None, Some("panic: oops"), false), None);
+    }
+
+    #[test]
+    fn test_crash_category_from_str() {
+        for category in CrashCategory::ALL {
+            assert_eq!(
+                category.as_str().parse::<CrashCategory>().unwrap(),
+                category
+            );
+        }
+        assert!("oomkilled".parse::<CrashCategory>().is_err());
+    }
+}
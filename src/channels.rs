@@ -0,0 +1,124 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::slack::SlackPoster;
+
+/// Interval to list channels again, e.g. to pick up channels created after startup
+const CHANNEL_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Directory of channels set once by `init` with `SLACK_RESOLVE_CHANNELS=true`
+static DIRECTORY: OnceLock<ChannelDirectory> = OnceLock::new();
+
+/// Cache of channel name -> channel ID, listed with `conversations.list`
+#[derive(Debug, Clone, Default)]
+pub struct ChannelDirectory(Arc<RwLock<HashMap<String, String>>>);
+
+impl ChannelDirectory {
+    /// Lists the channels visible to the bot.
+    pub async fn load(poster: &SlackPoster) -> anyhow::Result<Self> {
+        let directory = Self::default();
+        directory.refresh(poster).await?;
+        Ok(directory)
+    }
+
+    async fn refresh(&self, poster: &SlackPoster) -> anyhow::Result<()> {
+        let ids = poster.list_channels().await?;
+        log::debug!("Fetched {} channels", ids.len());
+        *self.0.write().unwrap() = ids;
+        Ok(())
+    }
+
+    /// Fails with the names in `channels` which are neither channel IDs nor known channels,
+    /// e.g. typos in `SLACK_NOTIFICATION_CONFIG`.
+    pub fn check<'a>(&self, channels: impl IntoIterator<Item = &'a str>) -> anyhow::Result<()> {
+        let mut unknown = channels
+            .into_iter()
+            .filter(|channel| self.id(channel).is_none() && !is_channel_id(channel))
+            .map(|channel| format!("#{}", channel.trim_start_matches('#')))
+            .collect::<Vec<_>>();
+        unknown.sort();
+        unknown.dedup();
+        if !unknown.is_empty() {
+            bail!("Unknown Slack channels: {}", unknown.join(", "));
+        }
+        Ok(())
+    }
+
+    /// ID of the channel named `channel`, with or without the leading `#`
+    fn id(&self, channel: &str) -> Option<String> {
+        let name = channel.trim_start_matches('#');
+        self.0.read().unwrap().get(name).cloned()
+    }
+
+    /// Task to list channels periodically. Failures are logged and the previous channels
+    /// are kept.
+    pub async fn refresh_periodically(self, poster: SlackPoster) {
+        loop {
+            tokio::time::sleep(CHANNEL_REFRESH_INTERVAL).await;
+            if let Err(e) = self.refresh(&poster).await {
+                log::warn!("Failed to refresh Slack channels: {e:#}");
+            }
+        }
+    }
+}
+
+/// Resolves channel names to IDs with `directory` afterwards. Later calls are ignored.
+pub fn init(directory: ChannelDirectory) {
+    if DIRECTORY.set(directory).is_err() {
+        log::warn!("Slack channel directory is already set");
+    }
+}
+
+/// ID of `channel` when it is a known channel name, or `channel` as is, e.g. an ID or
+/// a channel created since the last refresh
+pub fn resolve(channel: &str) -> Cow<'_, str> {
+    match DIRECTORY.get().and_then(|directory| directory.id(channel)) {
+        Some(id) => Cow::Owned(id),
+        None => Cow::Borrowed(channel),
+    }
+}
+
+/// Whether `channel` looks like a conversation or user ID, e.g. `C0123456789`
+fn is_channel_id(channel: &str) -> bool {
+    channel.len() >= 9
+        && channel.starts_with(['C', 'G', 'D', 'U', 'W'])
+        && channel
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let directory = ChannelDirectory::default();
+        *directory.0.write().unwrap() = [
+            ("team-payments".to_owned(), "C0PAYMENTS1".to_owned()),
+            ("alerts".to_owned(), "C0ALERTS01".to_owned()),
+        ]
+        .into();
+        assert_eq!(
+            directory.id("#team-payments").as_deref(),
+            Some("C0PAYMENTS1")
+        );
+        assert_eq!(directory.id("alerts").as_deref(), Some("C0ALERTS01"));
+        assert!(directory
+            .check(["#team-payments", "alerts", "C0123456789"])
+            .is_ok());
+        assert_eq!(
+            directory
+                .check(["#team-payment", "alerts", "ops", "team-payment"])
+                .unwrap_err()
+                .to_string(),
+            "Unknown Slack channels: #ops, #team-payment"
+        );
+    }
+}
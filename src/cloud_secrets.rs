@@ -0,0 +1,380 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use base64::Engine;
+use k8s_openapi::chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{http, sigv4::SigV4, silence, slack::SlackToken};
+
+/// Interval to resolve secrets again to pick up rotations by default
+pub const DEFAULT_SECRET_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Interval to retry after resolving failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Timeout of each request to secret managers and credential endpoints
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Token endpoint of the metadata server, e.g. with Workload Identity on GKE
+const GCP_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// Credentials endpoint of ECS tasks, appended with `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI`
+const ECS_CREDENTIALS_HOST: &str = "http://169.254.170.2";
+
+/// Reference to a secret in a cloud secret manager, the value of an environment variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretUri {
+    /// `aws-sm://<secret name or ARN>[#key]` of AWS Secrets Manager
+    Aws {
+        secret_id: String,
+        key: Option<String>,
+    },
+    /// `gcp-sm://projects/<project>/secrets/<secret>[/versions/<version>][#key]` of
+    /// Google Cloud Secret Manager, the latest version by default
+    Gcp { name: String, key: Option<String> },
+}
+
+impl SecretUri {
+    /// Returns `None` when `value` is not a secret URI, e.g. a plain token.
+    pub fn parse(value: &str) -> anyhow::Result<Option<Self>> {
+        let (scheme, rest) = match value.split_once("://") {
+            Some((scheme @ ("aws-sm" | "gcp-sm"), rest)) => (scheme, rest),
+            _ => return Ok(None),
+        };
+        let (id, key) = match rest.split_once('#') {
+            Some((id, key)) => (id, Some(key.to_owned())),
+            None => (rest, None),
+        };
+        if id.is_empty() {
+            bail!("Missing secret in {value}");
+        }
+        if scheme == "aws-sm" {
+            return Ok(Some(Self::Aws {
+                secret_id: id.to_owned(),
+                key,
+            }));
+        }
+        let segments = id.split('/').collect::<Vec<_>>();
+        let name = match segments.as_slice() {
+            ["projects", _, "secrets", _] => format!("{id}/versions/latest"),
+            ["projects", _, "secrets", _, "versions", _] => id.to_owned(),
+            _ => bail!("Expected gcp-sm://projects/<project>/secrets/<secret>: {value}"),
+        };
+        Ok(Some(Self::Gcp { name, key }))
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            Self::Aws { key, .. } | Self::Gcp { key, .. } => key.as_deref(),
+        }
+    }
+}
+
+/// Environment variable referencing a secret and its value resolved last
+struct ResolvedSecret {
+    env: String,
+    uri: SecretUri,
+    value: String,
+}
+
+/// Resolves environment variables set to `aws-sm://` and `gcp-sm://` URIs to the secrets
+pub struct CloudSecrets {
+    http: reqwest::Client,
+    secrets: Vec<ResolvedSecret>,
+    refresh_interval: Duration,
+}
+
+impl CloudSecrets {
+    /// Resolves the secret URIs in environment variables, which the caller replaces with
+    /// `secrets` to be read by `from_env` of configurations.
+    /// Returns `None` when no environment variable is a secret URI.
+    pub async fn resolve_env() -> anyhow::Result<Option<Self>> {
+        let mut references = Vec::new();
+        for (env, value) in std::env::vars() {
+            if let Some(uri) = SecretUri::parse(&value).with_context(|| format!("Invalid {env}"))? {
+                references.push((env, uri));
+            }
+        }
+        if references.is_empty() {
+            return Ok(None);
+        }
+        let refresh_interval = match std::env::var("SECRET_REFRESH_INTERVAL") {
+            Ok(interval) => silence::parse_duration(&interval)
+                .map_err(|e| anyhow::anyhow!("Invalid SECRET_REFRESH_INTERVAL: {e}"))?
+                .to_std()?,
+            Err(_) => DEFAULT_SECRET_REFRESH_INTERVAL,
+        };
+        let http = http::client_builder().timeout(REQUEST_TIMEOUT).build()?;
+        let mut secrets = Vec::with_capacity(references.len());
+        for (env, uri) in references {
+            let value = resolve(&http, &uri)
+                .await
+                .with_context(|| format!("Failed to resolve {env}"))?;
+            secrets.push(ResolvedSecret { env, uri, value });
+        }
+        Ok(Some(Self {
+            http,
+            secrets,
+            refresh_interval,
+        }))
+    }
+
+    /// Environment variables and their secrets resolved last
+    pub fn secrets(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.secrets
+            .iter()
+            .map(|secret| (secret.env.as_str(), secret.value.as_str()))
+    }
+
+    /// Task to resolve the secrets again to pick up rotations. A rotated `SLACK_TOKEN`
+    /// replaces `slack_token`, while other credentials are applied on the next start.
+    /// The secrets are resolved early when Slack rejects the token.
+    pub async fn refresh_periodically(mut self, slack_token: SlackToken) {
+        let mut interval = self.refresh_interval;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = slack_token.rejected() => log::info!("Resolving cloud secrets again"),
+            }
+            interval = self.refresh_interval;
+            for secret in &mut self.secrets {
+                match resolve(&self.http, &secret.uri).await {
+                    Ok(value) if value != secret.value => {
+                        slack_token.apply_rotated(
+                            &secret.env,
+                            value.clone(),
+                            "the cloud secret manager",
+                        );
+                        secret.value = value;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::error!("Failed to resolve {}: {e:#}", secret.env);
+                        interval = RETRY_INTERVAL;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn resolve(http: &reqwest::Client, uri: &SecretUri) -> anyhow::Result<String> {
+    let secret = match uri {
+        SecretUri::Aws { secret_id, .. } => aws_secret(http, secret_id).await?,
+        SecretUri::Gcp { name, .. } => gcp_secret(http, name).await?,
+    };
+    select_key(secret, uri.key())
+}
+
+/// `key` of the secret in JSON, e.g. of key/value secrets of AWS Secrets Manager,
+/// or the whole secret without `key`
+fn select_key(secret: String, key: Option<&str>) -> anyhow::Result<String> {
+    let Some(key) = key else {
+        return Ok(secret);
+    };
+    let json = serde_json::from_str::<serde_json::Value>(&secret)
+        .context("Secret with #key is not JSON")?;
+    match json.get(key) {
+        Some(serde_json::Value::String(value)) => Ok(value.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => bail!("Secret has no key {key}"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    #[serde(alias = "SessionToken")]
+    token: Option<String>,
+}
+
+/// Credentials from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, or the container
+/// credentials endpoint of EKS Pod Identity or ECS
+async fn aws_credentials(http: &reqwest::Client) -> anyhow::Result<AwsCredentials> {
+    if let Ok(access_key_id) = std::env::var("AWS_ACCESS_KEY_ID") {
+        return Ok(AwsCredentials {
+            access_key_id,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY")
+                .context("AWS_SECRET_ACCESS_KEY is required with AWS_ACCESS_KEY_ID")?,
+            token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        });
+    }
+    let url = match (
+        std::env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI"),
+        std::env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI"),
+    ) {
+        (Ok(url), _) => url,
+        (Err(_), Ok(path)) => format!("{ECS_CREDENTIALS_HOST}{path}"),
+        (Err(_), Err(_)) => {
+            bail!("No AWS credentials, set AWS_ACCESS_KEY_ID or use EKS Pod Identity for aws-sm://")
+        }
+    };
+    let mut request = http.get(url);
+    let authorization = match std::env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE") {
+        Ok(path) => Some(
+            std::fs::read_to_string(path)
+                .context("Failed to read AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE")?,
+        ),
+        Err(_) => std::env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN").ok(),
+    };
+    if let Some(authorization) = authorization {
+        request = request.header("authorization", authorization.trim());
+    }
+    Ok(request
+        .send()
+        .await?
+        .error_for_status()
+        .context("Failed to get AWS credentials")?
+        .json()
+        .await?)
+}
+
+/// Region in the ARN of the secret, or `AWS_REGION`
+fn aws_region(secret_id: &str) -> anyhow::Result<String> {
+    if let Some(region) = secret_id
+        .strip_prefix("arn:")
+        .and_then(|arn| arn.split(':').nth(2))
+    {
+        return Ok(region.to_owned());
+    }
+    std::env::var("AWS_REGION")
+        .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+        .context("AWS_REGION is required with aws-sm:// URIs without ARNs")
+}
+
+/// `SecretString` of the current version, by `GetSecretValue` API
+async fn aws_secret(http: &reqwest::Client, secret_id: &str) -> anyhow::Result<String> {
+    let credentials = aws_credentials(http).await?;
+    let region = aws_region(secret_id)?;
+    let host = format!("secretsmanager.{region}.amazonaws.com");
+    let body = serde_json::to_vec(&json!({ "SecretId": secret_id }))?;
+    let payload_hash = hex::encode(Sha256::digest(&body));
+    let time = Utc::now();
+    let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+    let content_type = "application/x-amz-json-1.1";
+    let target = "secretsmanager.GetSecretValue";
+    let mut headers = vec![
+        ("content-type", content_type),
+        ("host", host.as_str()),
+        ("x-amz-date", amz_date.as_str()),
+    ];
+    if let Some(token) = &credentials.token {
+        headers.push(("x-amz-security-token", token.as_str()));
+    }
+    headers.push(("x-amz-target", target));
+    let authorization = SigV4 {
+        access_key_id: &credentials.access_key_id,
+        secret_access_key: &credentials.secret_access_key,
+        region: &region,
+        service: "secretsmanager",
+    }
+    .authorization("POST", "/", &headers, &payload_hash, time);
+    let mut request = http.post(format!("https://{host}/"));
+    for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+        request = request.header(*name, *value);
+    }
+    let resp = request
+        .header("authorization", authorization)
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    resp["SecretString"]
+        .as_str()
+        .map(str::to_owned)
+        .context("Secret has no SecretString, binary secrets are not supported")
+}
+
+/// Payload of the secret version, with an access token of the metadata server
+async fn gcp_secret(http: &reqwest::Client, name: &str) -> anyhow::Result<String> {
+    let token = http
+        .get(GCP_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await
+        .context("Failed to get an access token from the metadata server")?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    let token = token["access_token"]
+        .as_str()
+        .context("No access token from the metadata server")?;
+    let resp = http
+        .get(format!(
+            "https://secretmanager.googleapis.com/v1/{name}:access"
+        ))
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    let data = resp["payload"]["data"]
+        .as_str()
+        .context("Secret version has no payload")?;
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .context("Invalid payload")?;
+    String::from_utf8(data).context("Secret is not UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret_uri() {
+        assert_eq!(SecretUri::parse("xoxb-token").unwrap(), None);
+        assert_eq!(SecretUri::parse("https://example.com").unwrap(), None);
+        assert_eq!(
+            SecretUri::parse("aws-sm://johari-mirror/slack#token").unwrap(),
+            Some(SecretUri::Aws {
+                secret_id: "johari-mirror/slack".to_owned(),
+                key: Some("token".to_owned()),
+            })
+        );
+        assert_eq!(
+            SecretUri::parse("gcp-sm://projects/example/secrets/slack-token").unwrap(),
+            Some(SecretUri::Gcp {
+                name: "projects/example/secrets/slack-token/versions/latest".to_owned(),
+                key: None,
+            })
+        );
+        assert_eq!(
+            SecretUri::parse("gcp-sm://projects/example/secrets/slack-token/versions/3").unwrap(),
+            Some(SecretUri::Gcp {
+                name: "projects/example/secrets/slack-token/versions/3".to_owned(),
+                key: None,
+            })
+        );
+        assert!(SecretUri::parse("gcp-sm://slack-token").is_err());
+        assert!(SecretUri::parse("aws-sm://").is_err());
+    }
+
+    #[test]
+    fn test_select_key() {
+        assert_eq!(select_key("xoxb".to_owned(), None).unwrap(), "xoxb");
+        let secret = r#"{"token":"xoxb","port":8080}"#.to_owned();
+        assert_eq!(select_key(secret.clone(), Some("token")).unwrap(), "xoxb");
+        assert_eq!(select_key(secret.clone(), Some("port")).unwrap(), "8080");
+        assert!(select_key(secret, Some("missing")).is_err());
+        assert!(select_key("xoxb".to_owned(), Some("token")).is_err());
+    }
+
+    #[test]
+    fn test_aws_region() {
+        assert_eq!(
+            aws_region("arn:aws:secretsmanager:ap-northeast-1:123456789012:secret:slack-AbCdEf")
+                .unwrap(),
+            "ap-northeast-1"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{self, DateTime, Utc};
+
+use crate::{
+    message::{ContainerRestartInfo, ContainerState, MessageLink},
+    silence,
+};
+
+/// Period around the termination of the container in which dumps are looked for
+pub const DEFAULT_CORE_DUMP_WINDOW: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Signals whose crashes dump core and are looked up
+const CORE_DUMP_SIGNALS: &[(i32, &str)] = &[(6, "SIGABRT"), (11, "SIGSEGV")];
+
+/// Depth of subdirectories scanned, e.g. `<date>/<pod UID>/<file>`
+const MAX_DEPTH: usize = 3;
+
+/// Maximum number of dumps linked from a message
+const MAX_LINKS: usize = 3;
+
+/// Configuration of linking core dumps written by a core dump handler
+#[derive(Debug, Clone)]
+pub struct CoreDumpConfig {
+    /// Directory the handler writes dumps to, e.g. a mounted PVC
+    pub dir: PathBuf,
+    /// URL the directory is served at, e.g. `https://dumps.example.com/cores`
+    pub url: String,
+    pub window: chrono::Duration,
+}
+
+impl CoreDumpConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `CORE_DUMP_DIR` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(dir) = std::env::var("CORE_DUMP_DIR") else {
+            return Ok(None);
+        };
+        let dir = PathBuf::from(dir);
+        if !dir.is_dir() {
+            bail!("CORE_DUMP_DIR is not a directory: {}", dir.display());
+        }
+        let url = std::env::var("CORE_DUMP_URL").context("CORE_DUMP_URL is required")?;
+        reqwest::Url::parse(&url).context("Invalid CORE_DUMP_URL")?;
+        let window = match std::env::var("CORE_DUMP_WINDOW") {
+            Ok(window) => silence::parse_duration(&window)
+                .map_err(|e| anyhow::anyhow!("Invalid CORE_DUMP_WINDOW: {e}"))?,
+            Err(_) => DEFAULT_CORE_DUMP_WINDOW,
+        };
+        Ok(Some(Self {
+            dir,
+            url: url.trim_end_matches('/').to_owned(),
+            window,
+        }))
+    }
+}
+
+/// Name of the signal which killed the container if it dumps core.
+/// The signal is often only known from the exit code, which is 128 + the signal.
+pub fn core_dump_signal(state: &ContainerState) -> Option<&'static str> {
+    let signal = state
+        .signal
+        .or((state.exit_code > 128).then(|| state.exit_code - 128))?;
+    CORE_DUMP_SIGNALS
+        .iter()
+        .find(|(number, _)| *number == signal)
+        .map(|(_, name)| *name)
+}
+
+/// Finds dumps of crashed containers in `CoreDumpConfig::dir`
+#[derive(Clone)]
+pub struct CoreDumps {
+    config: CoreDumpConfig,
+}
+
+impl CoreDumps {
+    pub fn new(config: CoreDumpConfig) -> Self {
+        Self { config }
+    }
+
+    /// Links to the dumps whose paths contain the pod UID or name, modified within the
+    /// window around the termination. Empty unless the container crashed by a signal
+    /// dumping core.
+    pub async fn find(&self, info: &ContainerRestartInfo) -> anyhow::Result<Vec<MessageLink>> {
+        let Some(state) = &info.last_state else {
+            return Ok(Vec::new());
+        };
+        let Some(signal) = core_dump_signal(state) else {
+            return Ok(Vec::new());
+        };
+        let end = state
+            .finished_at
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map_or_else(Utc::now, |t| t.with_timezone(&Utc));
+        let keys = info
+            .pod_uid
+            .iter()
+            .chain([&info.pod_name])
+            .cloned()
+            .collect::<Vec<_>>();
+        let dir = self.config.dir.clone();
+        let (from, to) = (end - self.config.window, end + self.config.window);
+        let paths = tokio::task::spawn_blocking(move || scan(&dir, &keys, from.into(), to.into()))
+            .await?
+            .context("Failed to scan CORE_DUMP_DIR")?;
+        paths
+            .into_iter()
+            .map(|path| {
+                let mut url = reqwest::Url::parse(&self.config.url)?;
+                let segments = path
+                    .iter()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>();
+                url.path_segments_mut()
+                    .map_err(|()| anyhow::anyhow!("CORE_DUMP_URL cannot be a base"))?
+                    .extend(&segments);
+                let name = segments.last().cloned().unwrap_or_default();
+                Ok(MessageLink {
+                    title: format!("Core dump ({signal}): {name}"),
+                    url: url.into(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Paths relative to `dir` of files containing one of `keys` and modified between `from`
+/// and `to`, the newest first
+fn scan(
+    dir: &Path,
+    keys: &[String],
+    from: SystemTime,
+    to: SystemTime,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut dirs = vec![(dir.to_owned(), 0)];
+    while let Some((current, depth)) = dirs.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let path = entry.path();
+            if metadata.is_dir() {
+                if depth + 1 < MAX_DEPTH {
+                    dirs.push((path, depth + 1));
+                }
+                continue;
+            }
+            let modified = metadata.modified()?;
+            if modified < from || modified > to {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative_str = relative.to_string_lossy();
+            if keys.iter().any(|key| relative_str.contains(key.as_str())) {
+                found.push((modified, relative.to_owned()));
+            }
+        }
+    }
+    found.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(found
+        .into_iter()
+        .take(MAX_LINKS)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn state(exit_code: i32, signal: Option<i32>) -> ContainerState {
+        ContainerState {
+            exit_code,
+            signal,
+            reason: Some("Error".to_owned()),
+            message: None,
+            started_at: None,
+            finished_at: None,
+        }
+    }
+
+    #[test]
+    fn test_core_dump_signal() {
+        assert_eq!(core_dump_signal(&state(139, None)), Some("SIGSEGV"));
+        assert_eq!(core_dump_signal(&state(0, Some(6))), Some("SIGABRT"));
+        assert_eq!(core_dump_signal(&state(137, None)), None);
+        assert_eq!(core_dump_signal(&state(1, None)), None);
+    }
+
+    #[test]
+    fn test_scan() {
+        let dir = std::env::temp_dir().join(format!(
+            "johari-mirror-core-dump-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(dir.join("2024-01-02/0123-uid")).unwrap();
+        std::fs::create_dir_all(dir.join("2024-01-02/other")).unwrap();
+        std::fs::write(dir.join("2024-01-02/0123-uid/core.app.1.zst"), "core").unwrap();
+        std::fs::write(dir.join("2024-01-02/other/core.app.1.zst"), "core").unwrap();
+        std::fs::write(dir.join("app-0-dump-11.zip"), "core").unwrap();
+
+        let now = SystemTime::now();
+        let window = Duration::from_secs(60);
+        let keys = ["0123-uid".to_owned(), "app-0".to_owned()];
+        let mut found = scan(&dir, &keys, now - window, now + window).unwrap();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                PathBuf::from("2024-01-02/0123-uid/core.app.1.zst"),
+                PathBuf::from("app-0-dump-11.zip"),
+            ]
+        );
+        let found = scan(&dir, &keys, now - window * 2, now - window).unwrap();
+        assert!(found.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    http,
+    message::{excerpt, ContainerRestartInfo},
+};
+
+pub const DEFAULT_ELASTICSEARCH_INDEX: &str = "johari-mirror-restarts";
+
+/// Timeout of each request not to delay notifications when Elasticsearch is unavailable
+const INDEX_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Credentials of Elasticsearch or OpenSearch
+#[derive(Debug, Clone)]
+pub enum ElasticsearchAuth {
+    ApiKey(String),
+    Basic { username: String, password: String },
+}
+
+/// Configuration of indexing restart events into Elasticsearch or OpenSearch
+#[derive(Debug, Clone)]
+pub struct ElasticsearchConfig {
+    /// e.g. `https://elasticsearch:9200`
+    pub url: String,
+    pub index: String,
+    pub auth: Option<ElasticsearchAuth>,
+    /// Included in documents to tell clusters apart in dashboards
+    pub cluster_name: Option<String>,
+}
+
+impl ElasticsearchConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `ELASTICSEARCH_URL` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("ELASTICSEARCH_URL") else {
+            return Ok(None);
+        };
+        reqwest::Url::parse(&url).context("Invalid ELASTICSEARCH_URL")?;
+        let index = std::env::var("ELASTICSEARCH_INDEX")
+            .unwrap_or_else(|_| DEFAULT_ELASTICSEARCH_INDEX.to_owned());
+        let auth = match (
+            std::env::var("ELASTICSEARCH_API_KEY"),
+            std::env::var("ELASTICSEARCH_USERNAME"),
+        ) {
+            (Ok(api_key), _) => Some(ElasticsearchAuth::ApiKey(api_key)),
+            (Err(_), Ok(username)) => Some(ElasticsearchAuth::Basic {
+                username,
+                password: std::env::var("ELASTICSEARCH_PASSWORD")
+                    .context("ELASTICSEARCH_PASSWORD is required with ELASTICSEARCH_USERNAME")?,
+            }),
+            (Err(_), Err(_)) => None,
+        };
+        let cluster_name = std::env::var("CLUSTER_NAME").ok();
+        Ok(Some(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            index,
+            auth,
+            cluster_name,
+        }))
+    }
+}
+
+/// Indexes a document per restart
+#[derive(Clone)]
+pub struct Elasticsearch {
+    config: ElasticsearchConfig,
+    http: reqwest::Client,
+}
+
+impl Elasticsearch {
+    pub fn new(config: ElasticsearchConfig) -> Self {
+        Self {
+            config,
+            http: http::client(),
+        }
+    }
+
+    pub async fn index(&self, info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        let document = document(info, self.config.cluster_name.as_deref(), Utc::now());
+        let mut request = self
+            .http
+            .post(format!("{}/{}/_doc", self.config.url, self.config.index))
+            .timeout(INDEX_TIMEOUT)
+            .json(&document);
+        match &self.config.auth {
+            Some(ElasticsearchAuth::ApiKey(api_key)) => {
+                request = request.header("authorization", format!("ApiKey {api_key}"));
+            }
+            Some(ElasticsearchAuth::Basic { username, password }) => {
+                request = request.basic_auth(username, Some(password));
+            }
+            None => {}
+        }
+        request.send().await?.error_for_status()?;
+        log::debug!("Indexed restart of {info} to {}", self.config.index);
+        Ok(())
+    }
+}
+
+/// Document of a restart. `@timestamp` is when the container finished, or `now`.
+fn document(
+    info: &ContainerRestartInfo,
+    cluster_name: Option<&str>,
+    now: DateTime<Utc>,
+) -> serde_json::Value {
+    let state = info.last_state.as_ref();
+    let timestamp = state
+        .and_then(|s| s.finished_at.as_deref())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map_or(now, |t| t.with_timezone(&Utc));
+    let (log_excerpt, log_error) = match &info.logs.0 {
+        Ok(logs) => (Some(excerpt(logs)), None),
+        Err(e) => (None, Some(e)),
+    };
+    json!({
+        "@timestamp": timestamp.to_rfc3339(),
+        "cluster": cluster_name,
+        "namespace": info.namespace,
+        "pod": info.pod_name,
+        "container": info.container_name,
+        "image": info.container_image,
+        "node": info.node_name,
+        "restart_count": info.restart_count,
+        "exit_code": state.map(|s| s.exit_code),
+        "signal": state.and_then(|s| s.signal),
+        "reason": state.and_then(|s| s.reason.as_deref()),
+        "message": state.and_then(|s| s.message.as_deref()),
+        "started_at": state.and_then(|s| s.started_at.as_deref()),
+        "finished_at": state.and_then(|s| s.finished_at.as_deref()),
+        "severity": info.options.severity.as_deref(),
+        "channel": info.channel,
+        "fingerprint": info.fingerprint(),
+        "log_excerpt": log_excerpt,
+        "log_error": log_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ContainerLog;
+
+    fn restart_info(pod: &str, logs: &str) -> ContainerRestartInfo {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            pod,
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.logs = ContainerLog(Ok(logs.to_owned()));
+        info
+    }
+
+    #[test]
+    fn test_document() {
+        let mut info = restart_info("app-0", "starting\npanic at 12:00:01\n");
+        let now = Utc::now();
+        let doc = document(&info, Some("production"), now);
+        assert_eq!(doc["@timestamp"], now.to_rfc3339());
+        assert_eq!(doc["cluster"], "production");
+        assert_eq!(doc["exit_code"], 1);
+        assert_eq!(doc["reason"], "Error");
+        assert_eq!(doc["log_excerpt"], "starting\npanic at 12:00:01");
+        assert!(doc["log_error"].is_null());
+
+        info.logs = ContainerLog(Err("not found".to_owned()));
+        let doc = document(&info, None, now);
+        assert!(doc["log_excerpt"].is_null());
+        assert_eq!(doc["log_error"], "not found");
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// File uploaded to Slack
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadedFile {
+    pub id: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// Files uploaded to Slack, used to delete old files.
+/// Files are kept in memory and optionally persisted to a JSON file.
+#[derive(Debug, Clone, Default)]
+pub struct FileStore(Arc<Mutex<FileStoreInner>>);
+
+#[derive(Debug, Default)]
+struct FileStoreInner {
+    files: Vec<UploadedFile>,
+    path: Option<PathBuf>,
+}
+
+impl FileStore {
+    /// Loads files from `path`, which is created on the first update if it does not exist.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let files = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .with_context(|| format!("Invalid file store: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self(Arc::new(Mutex::new(FileStoreInner {
+            files,
+            path: Some(path),
+        }))))
+    }
+
+    pub fn insert(&self, file: UploadedFile) {
+        let mut inner = self.0.lock().unwrap();
+        inner.files.push(file);
+        inner.save();
+    }
+
+    /// Returns files uploaded more than `retention` ago.
+    pub fn expired(&self, retention: Duration) -> Vec<UploadedFile> {
+        let expiry = Utc::now() - retention;
+        let inner = self.0.lock().unwrap();
+        inner
+            .files
+            .iter()
+            .filter(|f| f.uploaded_at <= expiry)
+            .cloned()
+            .collect()
+    }
+
+    pub fn remove(&self, id: &str) {
+        let mut inner = self.0.lock().unwrap();
+        inner.files.retain(|f| f.id != id);
+        inner.save();
+    }
+}
+
+/// Default window to link recently uploaded log files instead of uploading identical copies
+pub const DEFAULT_FILE_REUSE_WINDOW: Duration = Duration::hours(1);
+
+/// URLs of log files uploaded within `window`, keyed by the crash and the content,
+/// to link them again on the same crashes in crash loops
+#[derive(Debug)]
+pub struct RecentUploads {
+    window: Duration,
+    files: HashMap<String, (DateTime<Utc>, String)>,
+}
+
+impl RecentUploads {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            files: HashMap::new(),
+        }
+    }
+
+    /// URL of the file uploaded with `key` within the window, if any
+    pub fn get(&mut self, key: &str) -> Option<String> {
+        let expiry = Utc::now() - self.window;
+        self.files
+            .retain(|_, (uploaded_at, _)| *uploaded_at > expiry);
+        self.files.get(key).map(|(_, url)| url.clone())
+    }
+
+    pub fn insert(&mut self, key: String, url: String) {
+        self.files.insert(key, (Utc::now(), url));
+    }
+}
+
+impl FileStoreInner {
+    fn save(&self) {
+        if let Err(e) = self.try_save() {
+            log::error!("Failed to save file store: {e}");
+        }
+    }
+
+    fn try_save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Write to a temporary file first not to corrupt the store on failure
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&self.files)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store() {
+        let path = std::env::temp_dir().join(format!(
+            "johari-mirror-file-store-test-{}.json",
+            std::process::id()
+        ));
+        let old = UploadedFile {
+            id: "F1".to_owned(),
+            uploaded_at: Utc::now() - Duration::days(31),
+        };
+        let new = UploadedFile {
+            id: "F2".to_owned(),
+            uploaded_at: Utc::now(),
+        };
+
+        let store = FileStore::load(path.clone()).unwrap();
+        store.insert(old.clone());
+        store.insert(new);
+
+        let store = FileStore::load(path.clone()).unwrap();
+        assert_eq!(store.expired(Duration::days(30)), vec![old]);
+        store.remove("F1");
+        assert!(store.expired(Duration::days(30)).is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recent_uploads() {
+        let mut uploads = RecentUploads::new(Duration::hours(1));
+        uploads.insert("a".to_owned(), "https://example.com/F1".to_owned());
+        assert_eq!(uploads.get("a").as_deref(), Some("https://example.com/F1"));
+        assert_eq!(uploads.get("b"), None);
+
+        uploads.files.get_mut("a").unwrap().0 = Utc::now() - Duration::hours(2);
+        assert_eq!(uploads.get("a"), None);
+        assert!(uploads.files.is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{bail, Context};
+
+use crate::message::{ContainerRestartInfo, MessageLink};
+
+/// Label Argo CD tracks applications by default
+pub const DEFAULT_ARGOCD_APP_LABEL: &str = "app.kubernetes.io/instance";
+
+/// Labels of Flux Kustomizations and HelmReleases with the name and namespace
+const FLUX_LABELS: &[(&str, &str, &str)] = &[
+    (
+        "Kustomization",
+        "kustomize.toolkit.fluxcd.io/name",
+        "kustomize.toolkit.fluxcd.io/namespace",
+    ),
+    (
+        "HelmRelease",
+        "helm.toolkit.fluxcd.io/name",
+        "helm.toolkit.fluxcd.io/namespace",
+    ),
+];
+
+/// Placeholders of `FLUX_URL_TEMPLATE`
+const FLUX_PLACEHOLDERS: &[&str] = &["{kind}", "{name}", "{namespace}"];
+
+/// Configuration of links to the GitOps application managing the pod
+#[derive(Debug, Clone)]
+pub struct GitOpsConfig {
+    /// e.g. `https://argocd.example.com`
+    pub argocd_url: Option<String>,
+    pub argocd_app_label: String,
+    /// e.g. `https://gitops.example.com/kustomize/details?name={name}&namespace={namespace}`
+    pub flux_url_template: Option<String>,
+}
+
+impl GitOpsConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when neither `ARGOCD_URL` nor `FLUX_URL_TEMPLATE` is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let argocd_url = std::env::var("ARGOCD_URL").ok();
+        let flux_url_template = std::env::var("FLUX_URL_TEMPLATE").ok();
+        if argocd_url.is_none() && flux_url_template.is_none() {
+            return Ok(None);
+        }
+        if let Some(url) = &argocd_url {
+            reqwest::Url::parse(url).context("Invalid ARGOCD_URL")?;
+        }
+        if let Some(template) = &flux_url_template {
+            validate_template(template).context("Invalid FLUX_URL_TEMPLATE")?;
+        }
+        let argocd_app_label = std::env::var("ARGOCD_APP_LABEL")
+            .unwrap_or_else(|_| DEFAULT_ARGOCD_APP_LABEL.to_owned());
+        Ok(Some(Self {
+            argocd_url: argocd_url.map(|url| url.trim_end_matches('/').to_owned()),
+            argocd_app_label,
+            flux_url_template,
+        }))
+    }
+}
+
+/// Rejects unknown placeholders
+fn validate_template(template: &str) -> anyhow::Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end + 1)
+            .context("Unclosed placeholder")?;
+        let placeholder = &rest[start..end];
+        if !FLUX_PLACEHOLDERS.contains(&placeholder) {
+            bail!("Unknown placeholder: {placeholder}");
+        }
+        rest = &rest[end..];
+    }
+    Ok(())
+}
+
+/// Link to the Argo CD application or Flux object managing the pod, found by its labels
+pub fn application_link(info: &ContainerRestartInfo, config: &GitOpsConfig) -> Option<MessageLink> {
+    if let Some(url) = &config.argocd_url {
+        if let Some(app) = info.labels.get(&config.argocd_app_label) {
+            // Applications in any namespace are labeled `<namespace>_<name>`
+            let path = match app.split_once('_') {
+                Some((namespace, name)) => format!("{namespace}/{name}"),
+                None => app.clone(),
+            };
+            return Some(MessageLink {
+                title: format!("Argo CD: {app}"),
+                url: format!("{url}/applications/{path}"),
+            });
+        }
+    }
+    if let Some(template) = &config.flux_url_template {
+        for (kind, name_label, namespace_label) in FLUX_LABELS {
+            let Some(name) = info.labels.get(*name_label) else {
+                continue;
+            };
+            let namespace = info
+                .labels
+                .get(*namespace_label)
+                .or(info.namespace.as_ref())
+                .map_or("", String::as_str);
+            return Some(MessageLink {
+                title: format!("Flux: {kind} {namespace}/{name}"),
+                url: template
+                    .replace("{kind}", kind)
+                    .replace("{name}", name)
+                    .replace("{namespace}", namespace),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart_info(labels: &[(&str, &str)]) -> ContainerRestartInfo {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.labels = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        info
+    }
+
+    fn config() -> GitOpsConfig {
+        GitOpsConfig {
+            argocd_url: Some("https://argocd.example.com".to_owned()),
+            argocd_app_label: DEFAULT_ARGOCD_APP_LABEL.to_owned(),
+            flux_url_template: Some(
+                "https://gitops.example.com/{kind}?name={name}&namespace={namespace}".to_owned(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_application_link() {
+        let link = application_link(
+            &restart_info(&[(DEFAULT_ARGOCD_APP_LABEL, "web")]),
+            &config(),
+        );
+        assert_eq!(
+            link.unwrap().url,
+            "https://argocd.example.com/applications/web"
+        );
+        let link = application_link(
+            &restart_info(&[(DEFAULT_ARGOCD_APP_LABEL, "team_web")]),
+            &config(),
+        );
+        assert_eq!(
+            link.unwrap().url,
+            "https://argocd.example.com/applications/team/web"
+        );
+
+        let link = application_link(
+            &restart_info(&[
+                ("kustomize.toolkit.fluxcd.io/name", "apps"),
+                ("kustomize.toolkit.fluxcd.io/namespace", "flux-system"),
+            ]),
+            &config(),
+        )
+        .unwrap();
+        assert_eq!(link.title, "Flux: Kustomization flux-system/apps");
+        assert_eq!(
+            link.url,
+            "https://gitops.example.com/Kustomization?name=apps&namespace=flux-system"
+        );
+
+        assert_eq!(application_link(&restart_info(&[]), &config()), None);
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("https://example.com/{kind}/{namespace}/{name}").is_ok());
+        assert!(validate_template("https://example.com/{app}").is_err());
+        assert!(validate_template("https://example.com/{name").is_err());
+    }
+}
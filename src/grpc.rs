@@ -0,0 +1,301 @@
+use std::net::SocketAddr;
+
+use k8s_openapi::{
+    api::core::v1::Pod,
+    chrono::{DateTime, TimeZone, Utc},
+};
+use kube::{runtime::reflector::Store, ResourceExt};
+use tonic::{service::Interceptor, Request, Response, Status};
+
+use crate::{
+    bearer,
+    history::{RecentNotifications, RestartHistory},
+    kubernetes::PodRestartCounts,
+    silence::Silences,
+};
+
+/// Types generated from `proto/johari_mirror.proto`
+pub mod proto {
+    tonic::include_proto!("johari_mirror.v1");
+}
+
+use proto::{
+    state_service_server::{StateService, StateServiceServer},
+    ContainerRestartCount, ListRecentNotificationsRequest, ListRecentNotificationsResponse,
+    ListRestartsRequest, ListRestartsResponse, ListSilencesRequest, ListSilencesResponse,
+    ListTrackedPodsRequest, ListTrackedPodsResponse, Notification, Restart, Silence, TrackedPod,
+};
+
+/// Internal state exposed by the gRPC API
+#[derive(Clone)]
+pub struct QueryState {
+    pub pod_store: Store<Pod>,
+    pub pod_restart_count: PodRestartCounts,
+    pub history: RestartHistory,
+    pub silences: Silences,
+    pub recent_notifications: RecentNotifications,
+}
+
+/// Task to serve the gRPC API to query internal state, e.g. for dashboards.
+/// Requests require `authorization: Bearer <token>` metadata when `token` is set.
+pub async fn serve(
+    addr: SocketAddr,
+    token: Option<String>,
+    state: QueryState,
+) -> anyhow::Result<()> {
+    let service = StateServiceServer::with_interceptor(state, BearerAuth(token));
+    log::info!("Serving gRPC API on {addr}");
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+/// Requires `authorization: Bearer <token>` metadata when the token is set
+#[derive(Clone)]
+struct BearerAuth(Option<String>);
+
+impl Interceptor for BearerAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(token) = &self.0 else {
+            return Ok(request);
+        };
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok());
+        if !bearer::is_bearer(authorization, token) {
+            return Err(Status::unauthenticated("Invalid token"));
+        }
+        Ok(request)
+    }
+}
+
+fn timestamp(time: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+#[tonic::async_trait]
+impl StateService for QueryState {
+    async fn list_tracked_pods(
+        &self,
+        request: Request<ListTrackedPodsRequest>,
+    ) -> Result<Response<ListTrackedPodsResponse>, Status> {
+        let namespace = request.into_inner().namespace;
+        let counts = self.pod_restart_count.snapshot();
+        let mut pods = self
+            .pod_store
+            .state()
+            .into_iter()
+            .filter(|p| namespace.is_empty() || p.namespace().as_deref() == Some(&namespace))
+            .map(|p| {
+                let uid = p.uid().unwrap_or_default();
+                let mut containers = counts
+                    .get(&uid)
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, &restart_count)| ContainerRestartCount {
+                        name: name.clone(),
+                        restart_count,
+                    })
+                    .collect::<Vec<_>>();
+                containers.sort_by(|a, b| a.name.cmp(&b.name));
+                TrackedPod {
+                    namespace: p.namespace().unwrap_or_default(),
+                    name: p.name_any(),
+                    uid,
+                    node: p
+                        .spec
+                        .as_ref()
+                        .and_then(|spec| spec.node_name.clone())
+                        .unwrap_or_default(),
+                    containers,
+                }
+            })
+            .collect::<Vec<_>>();
+        pods.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(Response::new(ListTrackedPodsResponse { pods }))
+    }
+
+    async fn list_restarts(
+        &self,
+        request: Request<ListRestartsRequest>,
+    ) -> Result<Response<ListRestartsResponse>, Status> {
+        let request = request.into_inner();
+        let since = match request.since {
+            Some(since) => Utc
+                .timestamp_opt(since.seconds, since.nanos.max(0) as u32)
+                .single()
+                .ok_or_else(|| Status::invalid_argument("Invalid since"))?,
+            None => DateTime::<Utc>::MIN_UTC,
+        };
+        let restarts = self
+            .history
+            .records()
+            .into_iter()
+            .filter(|r| request.namespace.is_empty() || r.namespace == request.namespace)
+            .filter(|r| r.time >= since)
+            .map(|r| Restart {
+                time: Some(timestamp(r.time)),
+                namespace: r.namespace,
+                workload: r.workload,
+                pod: r.pod,
+                container: r.container,
+                reason: r.reason.unwrap_or_default(),
+            })
+            .collect();
+        Ok(Response::new(ListRestartsResponse { restarts }))
+    }
+
+    async fn list_silences(
+        &self,
+        _request: Request<ListSilencesRequest>,
+    ) -> Result<Response<ListSilencesResponse>, Status> {
+        let silences = self
+            .silences
+            .active()
+            .into_iter()
+            .map(|s| Silence {
+                id: s.id,
+                pattern: s.pattern.to_string(),
+                expires_at: Some(timestamp(s.expires_at)),
+                created_by: s.created_by,
+            })
+            .collect();
+        Ok(Response::new(ListSilencesResponse { silences }))
+    }
+
+    async fn list_recent_notifications(
+        &self,
+        request: Request<ListRecentNotificationsRequest>,
+    ) -> Result<Response<ListRecentNotificationsResponse>, Status> {
+        let channel = request.into_inner().channel;
+        let notifications = self
+            .recent_notifications
+            .records()
+            .into_iter()
+            .filter(|n| channel.is_empty() || n.channel == channel)
+            .map(|n| Notification {
+                time: Some(timestamp(n.time)),
+                channel: n.channel,
+                namespace: n.namespace,
+                pod: n.pod,
+                container: n.container,
+                restart_count: n.restart_count,
+                reason: n.reason.unwrap_or_default(),
+            })
+            .collect();
+        Ok(Response::new(ListRecentNotificationsResponse {
+            notifications,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{NotificationRecord, RestartRecord};
+
+    fn restart(namespace: &str, time: DateTime<Utc>) -> RestartRecord {
+        RestartRecord {
+            time,
+            namespace: namespace.to_owned(),
+            workload: "app".to_owned(),
+            pod: "app-0".to_owned(),
+            container: "app".to_owned(),
+            restart_count: 1,
+            exit_code: Some(137),
+            reason: Some("OOMKilled".to_owned()),
+        }
+    }
+
+    fn state() -> QueryState {
+        let (pod_store, _writer) = kube::runtime::reflector::store();
+        QueryState {
+            pod_store,
+            pod_restart_count: PodRestartCounts::default(),
+            history: RestartHistory::default(),
+            silences: Silences::default(),
+            recent_notifications: RecentNotifications::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_restarts() {
+        let state = state();
+        let now = Utc::now();
+        state.history.record(restart(
+            "default",
+            now - k8s_openapi::chrono::Duration::hours(2),
+        ));
+        state.history.record(restart("default", now));
+        state.history.record(restart("other", now));
+
+        let response = state
+            .list_restarts(Request::new(ListRestartsRequest {
+                namespace: "default".to_owned(),
+                since: Some(timestamp(now - k8s_openapi::chrono::Duration::hours(1))),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.restarts.len(), 1);
+        assert_eq!(response.restarts[0].reason, "OOMKilled");
+        assert_eq!(response.restarts[0].time, Some(timestamp(now)));
+    }
+
+    #[tokio::test]
+    async fn test_list_recent_notifications() {
+        let state = state();
+        for channel in ["#alerts", "#other"] {
+            state.recent_notifications.record(NotificationRecord {
+                time: Utc::now(),
+                channel: channel.to_owned(),
+                namespace: "default".to_owned(),
+                pod: "app-0".to_owned(),
+                container: "app".to_owned(),
+                restart_count: 1,
+                reason: None,
+                fingerprint: String::new(),
+            });
+        }
+        let response = state
+            .list_recent_notifications(Request::new(ListRecentNotificationsRequest {
+                channel: "#alerts".to_owned(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.notifications.len(), 1);
+        assert_eq!(response.notifications[0].channel, "#alerts");
+    }
+
+    #[test]
+    fn test_bearer_auth() {
+        let request = || {
+            let mut request = Request::new(());
+            request
+                .metadata_mut()
+                .insert("authorization", "Bearer secret".parse().unwrap());
+            request
+        };
+        assert!(BearerAuth(None).call(Request::new(())).is_ok());
+        assert!(BearerAuth(Some("secret".to_owned()))
+            .call(Request::new(()))
+            .is_err());
+        assert!(BearerAuth(Some("secret".to_owned()))
+            .call(request())
+            .is_ok());
+        assert!(BearerAuth(Some("other".to_owned()))
+            .call(request())
+            .is_err());
+        // Wrong token of the same length
+        assert!(BearerAuth(Some("secreT".to_owned()))
+            .call(request())
+            .is_err());
+    }
+}
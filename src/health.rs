@@ -0,0 +1,103 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::chrono::{DateTime, Utc};
+
+/// Default period without watch events after which the watcher is considered stalled
+pub const DEFAULT_WATCH_STALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Health of background tasks shared with the HTTP server for Kubernetes probes.
+#[derive(Debug, Clone)]
+pub struct Health(Arc<Mutex<HealthInner>>);
+
+#[derive(Debug)]
+struct HealthInner {
+    watch_stall_timeout: Duration,
+    /// Time of the last event received from the watcher, or the start time
+    last_watch_event: Instant,
+    /// Whether the watcher has listed all pods
+    watch_initialized: bool,
+    last_watch_error: Option<(DateTime<Utc>, String)>,
+    /// Result of the last Slack token validation
+    slack_error: Option<String>,
+}
+
+impl Health {
+    pub fn new(watch_stall_timeout: Duration) -> Self {
+        Self(Arc::new(Mutex::new(HealthInner {
+            watch_stall_timeout,
+            last_watch_event: Instant::now(),
+            watch_initialized: false,
+            last_watch_error: None,
+            slack_error: None,
+        })))
+    }
+
+    /// Records an event from the watcher. `initialized` is true on the initial pod list.
+    pub fn watch_event(&self, initialized: bool) {
+        let mut inner = self.0.lock().unwrap();
+        inner.last_watch_event = Instant::now();
+        inner.watch_initialized |= initialized;
+    }
+
+    pub fn watch_error(&self, err: &impl std::fmt::Display) {
+        self.0.lock().unwrap().last_watch_error = Some((Utc::now(), err.to_string()));
+    }
+
+    /// Returns the time and the message of the last watcher error.
+    pub fn last_watch_error(&self) -> Option<(DateTime<Utc>, String)> {
+        self.0.lock().unwrap().last_watch_error.clone()
+    }
+
+    pub fn slack_result(&self, result: &anyhow::Result<()>) {
+        self.0.lock().unwrap().slack_error = result.as_ref().err().map(|e| format!("{e:#}"));
+    }
+
+    /// Returns an error when the watcher has stalled.
+    pub fn liveness(&self) -> Result<(), String> {
+        let inner = self.0.lock().unwrap();
+        let elapsed = inner.last_watch_event.elapsed();
+        if elapsed > inner.watch_stall_timeout {
+            return Err(format!("No watch events for {} seconds", elapsed.as_secs()));
+        }
+        Ok(())
+    }
+
+    /// Returns an error when the watcher is not initialized or Slack is unavailable.
+    pub fn readiness(&self) -> Result<(), String> {
+        self.liveness()?;
+        let inner = self.0.lock().unwrap();
+        if !inner.watch_initialized {
+            return Err("Watcher is not initialized".to_owned());
+        }
+        if let Some(e) = &inner.slack_error {
+            return Err(format!("Slack is unavailable: {e}"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let health = Health::new(DEFAULT_WATCH_STALL_TIMEOUT);
+        assert!(health.liveness().is_ok());
+        assert!(health.readiness().is_err());
+
+        health.watch_event(true);
+        assert!(health.readiness().is_ok());
+        health.slack_result(&Err(anyhow::anyhow!("invalid_auth")));
+        assert!(health.readiness().is_err());
+        health.slack_result(&Ok(()));
+        assert!(health.readiness().is_ok());
+
+        let health = Health::new(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(health.liveness().is_err());
+    }
+}
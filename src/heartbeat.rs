@@ -0,0 +1,49 @@
+use k8s_openapi::chrono::Utc;
+use serde_json::json;
+
+use crate::{health::Health, http, slack::SlackPoster};
+
+/// Task to post heartbeat messages to `channel` and call `url` on `schedule`,
+/// so that silence in the notification channel means no restarts.
+/// Heartbeats are skipped while johari-mirror is unhealthy.
+pub async fn heartbeat(
+    schedule: cron::Schedule,
+    poster: SlackPoster,
+    channel: Option<String>,
+    url: Option<String>,
+    health: Health,
+) {
+    let http = http::client();
+    for next in schedule.upcoming(Utc) {
+        let wait = (next - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        if let Err(e) = health.readiness() {
+            log::warn!("Skipping heartbeat: {e}");
+            continue;
+        }
+        log::debug!("Sending heartbeat");
+        if let Some(channel) = &channel {
+            let blocks = vec![json!({
+                "type": "context",
+                "elements": [{
+                    "type": "mrkdwn",
+                    "text": ":heartbeat: johari-mirror is watching container restarts.",
+                }],
+            })];
+            if let Err(e) = poster.post_blocks(channel, blocks).await {
+                log::error!("Failed to post heartbeat to Slack: {e}");
+            }
+        }
+        if let Some(url) = &url {
+            let result = http
+                .get(url)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+            if let Err(e) = result {
+                log::error!("Failed to call heartbeat URL: {e}");
+            }
+        }
+    }
+}
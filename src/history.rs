@@ -0,0 +1,232 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+
+use crate::message::ContainerRestartInfo;
+
+/// Restart records older than this are discarded.
+pub const HISTORY_RETENTION_DAYS: i64 = 14;
+
+/// Expired records of all workloads are removed at most once in this period.
+/// Records of each workload are also removed when it restarts.
+const PRUNE_INTERVAL_HOURS: i64 = 1;
+
+/// Number of sent notifications kept in `RecentNotifications`
+const RECENT_NOTIFICATIONS_CAPACITY: usize = 200;
+
+/// Record of a detected container restart
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestartRecord {
+    pub time: DateTime<Utc>,
+    pub namespace: String,
+    /// Name of the owner workload, or the pod name for standalone pods
+    pub workload: String,
+    pub pod: String,
+    pub container: String,
+    pub restart_count: i32,
+    pub exit_code: Option<i32>,
+    /// Termination reason (e.g. `OOMKilled`) or exit code of the last state
+    pub reason: Option<String>,
+}
+
+impl RestartRecord {
+    /// `namespace/workload` format
+    pub fn workload_key(&self) -> String {
+        format!("{}/{}", self.namespace, self.workload)
+    }
+}
+
+/// In-memory history of container restarts shared between tasks.
+#[derive(Debug, Clone, Default)]
+pub struct RestartHistory(Arc<Mutex<Records>>);
+
+/// Records of `RestartHistory` by namespace and workload
+#[derive(Debug, Default)]
+struct Records {
+    /// Records of each workload sorted by time
+    by_workload: HashMap<(String, String), VecDeque<RestartRecord>>,
+    /// Time of the first record, or the retention after it expired
+    since: Option<DateTime<Utc>>,
+    pruned_at: DateTime<Utc>,
+}
+
+impl Records {
+    fn prune(&mut self, expiry: DateTime<Utc>) {
+        self.by_workload.retain(|_, records| {
+            prune_workload(records, expiry);
+            !records.is_empty()
+        });
+        self.since = self.since.map(|since| since.max(expiry));
+    }
+}
+
+fn prune_workload(records: &mut VecDeque<RestartRecord>, expiry: DateTime<Utc>) {
+    while records.front().is_some_and(|r| r.time <= expiry) {
+        records.pop_front();
+    }
+}
+
+impl RestartHistory {
+    pub fn record(&self, record: RestartRecord) {
+        let now = Utc::now();
+        let expiry = now - Duration::days(HISTORY_RETENTION_DAYS);
+        let mut records = self.0.lock().unwrap();
+        if now - records.pruned_at >= Duration::hours(PRUNE_INTERVAL_HOURS) {
+            records.prune(expiry);
+            records.pruned_at = now;
+        }
+        records.since = Some(match records.since {
+            Some(since) => since.min(record.time),
+            None => record.time,
+        });
+        let workload = records
+            .by_workload
+            .entry((record.namespace.clone(), record.workload.clone()))
+            .or_default();
+        prune_workload(workload, expiry);
+        // Records restored from the crash store may be older than the recorded ones
+        let index = workload.partition_point(|r| r.time <= record.time);
+        workload.insert(index, record);
+    }
+
+    /// Returns records of all workloads sorted by time.
+    pub fn records(&self) -> Vec<RestartRecord> {
+        let mut records = self
+            .0
+            .lock()
+            .unwrap()
+            .by_workload
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by_key(|r| r.time);
+        records
+    }
+
+    /// Returns records of `workload` in `namespace` sorted by time.
+    pub fn workload_records(&self, namespace: &str, workload: &str) -> Vec<RestartRecord> {
+        let records = self.0.lock().unwrap();
+        records
+            .by_workload
+            .get(&(namespace.to_owned(), workload.to_owned()))
+            .map(|records| records.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Time of the first record still in the history, i.e. since when restarts are known,
+    /// or `None` without records
+    pub fn recorded_since(&self) -> Option<DateTime<Utc>> {
+        self.0.lock().unwrap().since
+    }
+}
+
+/// Record of a notification sent to Slack or `Notifier`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotificationRecord {
+    pub time: DateTime<Utc>,
+    pub channel: String,
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+    pub restart_count: i32,
+    /// Termination reason (e.g. `OOMKilled`) or exit code of the last state
+    pub reason: Option<String>,
+    /// See `ContainerRestartInfo::fingerprint`
+    pub fingerprint: String,
+}
+
+impl NotificationRecord {
+    /// Record of `info` notified now
+    pub fn new(info: &ContainerRestartInfo) -> Self {
+        Self {
+            time: Utc::now(),
+            channel: info.channel.clone(),
+            namespace: info.namespace.clone().unwrap_or_default(),
+            pod: info.pod_name.clone(),
+            container: info.container_name.clone(),
+            restart_count: info.restart_count,
+            reason: info.last_state.as_ref().map(|state| {
+                state
+                    .reason
+                    .clone()
+                    .unwrap_or_else(|| format!("exit code {}", state.exit_code))
+            }),
+            fingerprint: info.fingerprint(),
+        }
+    }
+}
+
+/// Last notifications sent to Slack shared between tasks, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct RecentNotifications(Arc<Mutex<VecDeque<NotificationRecord>>>);
+
+impl RecentNotifications {
+    pub fn record(&self, record: NotificationRecord) {
+        let mut records = self.0.lock().unwrap();
+        if records.len() >= RECENT_NOTIFICATIONS_CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    pub fn records(&self) -> Vec<NotificationRecord> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart(workload: &str, time: DateTime<Utc>) -> RestartRecord {
+        RestartRecord {
+            time,
+            namespace: "default".to_owned(),
+            workload: workload.to_owned(),
+            pod: format!("{workload}-0"),
+            container: "app".to_owned(),
+            restart_count: 1,
+            exit_code: Some(1),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_restart_history() {
+        let now = Utc::now();
+        let history = RestartHistory::default();
+        history.record(restart(
+            "app",
+            now - Duration::days(HISTORY_RETENTION_DAYS + 1),
+        ));
+        history.record(restart("app", now - Duration::hours(1)));
+        history.record(restart("app", now - Duration::hours(2)));
+        history.record(restart("other", now));
+
+        let times =
+            |records: Vec<RestartRecord>| records.into_iter().map(|r| r.time).collect::<Vec<_>>();
+        assert_eq!(
+            times(history.workload_records("default", "app")),
+            [now - Duration::hours(2), now - Duration::hours(1)]
+        );
+        assert_eq!(
+            times(history.records()),
+            [now - Duration::hours(2), now - Duration::hours(1), now]
+        );
+        assert!(history
+            .workload_records("other-namespace", "app")
+            .is_empty());
+        assert_eq!(
+            history.recorded_since(),
+            Some(now - Duration::days(HISTORY_RETENTION_DAYS + 1))
+        );
+
+        history.0.lock().unwrap().prune(now);
+        assert!(history.records().is_empty());
+        assert_eq!(history.recorded_since(), Some(now));
+    }
+}
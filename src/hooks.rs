@@ -0,0 +1,34 @@
+use futures::future::BoxFuture;
+
+use crate::{
+    history::{NotificationRecord, RestartRecord},
+    message::ContainerRestartInfo,
+};
+
+/// Callbacks to add processing to `Watcher`, e.g. linking tickets or recording metrics,
+/// without reimplementing the pipeline. All methods do nothing by default.
+/// Hooks are awaited in the pipeline and should return quickly.
+pub trait Hooks: Send + Sync + 'static {
+    /// Called for each detected restart, including ones skipped by the router or silences.
+    fn on_restart_detected<'a>(&'a self, _restart: &'a RestartRecord) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Called with a routed restart before `Notifier`.
+    /// Returns the restart to notify, which may be modified, or `None` to drop it.
+    fn before_send(
+        &self,
+        event: ContainerRestartInfo,
+    ) -> BoxFuture<'_, Option<ContainerRestartInfo>> {
+        Box::pin(async move { Some(event) })
+    }
+
+    /// Called with the result of `Notifier`.
+    fn after_send<'a>(
+        &'a self,
+        _notification: &'a NotificationRecord,
+        _result: &'a anyhow::Result<()>,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use tokio_rustls::rustls;
+
+/// TLS configuration of HTTP clients, set once by `init` on startup
+static TLS_CONFIG: OnceLock<TlsConfig> = OnceLock::new();
+
+/// Additional root CAs and the client certificate of HTTP clients, e.g. for
+/// TLS-intercepting proxies and self-hosted destinations with private CAs
+#[derive(Clone, Default)]
+pub struct TlsConfig {
+    /// Trusted in addition to the public roots
+    pub root_certificates: Vec<reqwest::Certificate>,
+    /// Client certificate and key presented to servers requiring mutual TLS
+    pub identity: Option<reqwest::Identity>,
+    /// DER of `root_certificates` for clients other than reqwest, e.g. syslog over TLS
+    root_certificates_der: Vec<Vec<u8>>,
+    /// DER of the certificate chain and the private key of `identity`
+    identity_der: Option<(Vec<Vec<u8>>, Vec<u8>)>,
+}
+
+impl TlsConfig {
+    /// Reads configuration from environment variables.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let root_certificates_der = match std::env::var("TLS_CA_FILE") {
+            Ok(path) => {
+                let pem = std::fs::read(&path).context("Failed to read TLS_CA_FILE")?;
+                let certs =
+                    rustls_pemfile::certs(&mut pem.as_slice()).context("Invalid TLS_CA_FILE")?;
+                if certs.is_empty() {
+                    anyhow::bail!("No certificates in TLS_CA_FILE");
+                }
+                certs
+            }
+            Err(_) => Vec::new(),
+        };
+        let root_certificates = root_certificates_der
+            .iter()
+            .map(|der| reqwest::Certificate::from_der(der))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid certificate in TLS_CA_FILE")?;
+        let (identity, identity_der) = match std::env::var("TLS_CLIENT_CERT_FILE") {
+            Ok(cert_path) => {
+                let key_path = std::env::var("TLS_CLIENT_KEY_FILE")
+                    .context("TLS_CLIENT_KEY_FILE is required with TLS_CLIENT_CERT_FILE")?;
+                let key_pem =
+                    std::fs::read(&key_path).context("Failed to read TLS_CLIENT_KEY_FILE")?;
+                let cert_pem =
+                    std::fs::read(&cert_path).context("Failed to read TLS_CLIENT_CERT_FILE")?;
+                let mut pem = key_pem.clone();
+                pem.push(b'\n');
+                pem.extend(&cert_pem);
+                let identity = reqwest::Identity::from_pem(&pem)
+                    .context("Invalid TLS_CLIENT_CERT_FILE or TLS_CLIENT_KEY_FILE")?;
+                let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+                    .context("Invalid TLS_CLIENT_CERT_FILE")?;
+                let key = private_key(&key_pem).context("Invalid TLS_CLIENT_KEY_FILE")?;
+                (Some(identity), Some((certs, key)))
+            }
+            Err(_) => (None, None),
+        };
+        let config = Self {
+            root_certificates,
+            identity,
+            root_certificates_der,
+            identity_der,
+        };
+        // Fail on startup rather than on building clients
+        config
+            .apply(reqwest::Client::builder())
+            .build()
+            .context("Invalid TLS configuration")?;
+        Ok(config)
+    }
+
+    fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+        builder
+    }
+}
+
+/// First private key in PEM, in PKCS#8, PKCS#1 or SEC1 format
+fn private_key(pem: &[u8]) -> anyhow::Result<Vec<u8>> {
+    for item in rustls_pemfile::read_all(&mut &pem[..])? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(key),
+            _ => {}
+        }
+    }
+    anyhow::bail!("No private key found")
+}
+
+/// Sets the TLS configuration of clients built afterwards. Later calls are ignored.
+pub fn init(config: TlsConfig) {
+    if TLS_CONFIG.set(config).is_err() {
+        log::warn!("TLS configuration of HTTP clients is already set");
+    }
+}
+
+/// Builder of HTTP clients with the TLS configuration set by `init`
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    match TLS_CONFIG.get() {
+        Some(config) => config.apply(builder),
+        None => builder,
+    }
+}
+
+/// HTTP client with the TLS configuration set by `init`, in place of `reqwest::Client::new`
+pub fn client() -> reqwest::Client {
+    client_builder()
+        .build()
+        .expect("TLS configuration is validated by TlsConfig::from_env")
+}
+
+/// rustls configuration of clients other than reqwest with the TLS configuration set by
+/// `init`, trusting `extra_roots` in DER in addition
+pub fn rustls_client_config(extra_roots: &[Vec<u8>]) -> anyhow::Result<rustls::ClientConfig> {
+    let default = TlsConfig::default();
+    let config = TLS_CONFIG.get().unwrap_or(&default);
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    for der in config.root_certificates_der.iter().chain(extra_roots) {
+        roots
+            .add(&rustls::Certificate(der.clone()))
+            .context("Invalid CA certificate")?;
+    }
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    match &config.identity_der {
+        Some((certs, key)) => builder
+            .with_client_auth_cert(
+                certs.iter().cloned().map(rustls::Certificate).collect(),
+                rustls::PrivateKey(key.clone()),
+            )
+            .context("Invalid TLS_CLIENT_CERT_FILE or TLS_CLIENT_KEY_FILE"),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Parses `Name: value` pairs delimited by commas
+pub fn parse_headers(s: &str) -> anyhow::Result<Vec<(String, String)>> {
+    s.split(',')
+        .filter(|h| !h.trim().is_empty())
+        .map(|h| {
+            let (name, value) = h
+                .split_once(':')
+                .with_context(|| format!("Missing `:` in header: {h}"))?;
+            Ok((name.trim().to_owned(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(
+            parse_headers("Authorization: Bearer token, X-Source:johari-mirror").unwrap(),
+            [
+                ("Authorization".to_owned(), "Bearer token".to_owned()),
+                ("X-Source".to_owned(), "johari-mirror".to_owned()),
+            ]
+        );
+        assert!(parse_headers("invalid").is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    http,
+    message::{excerpt, ContainerRestartInfo, MessageLink},
+};
+
+pub const DEFAULT_JIRA_RESTART_THRESHOLD: i32 = 5;
+
+pub const DEFAULT_JIRA_ISSUE_TYPE: &str = "Bug";
+
+/// Label on all issues created by johari-mirror
+const JIRA_LABEL: &str = "johari-mirror";
+
+/// Timeout of each request not to delay notifications when Jira is unavailable
+const JIRA_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Credentials of Jira REST API
+#[derive(Debug, Clone)]
+pub enum JiraAuth {
+    /// Email and API token of Jira Cloud
+    Basic { user: String, api_token: String },
+    /// Personal access token of Jira Data Center
+    Bearer(String),
+}
+
+/// Configuration of tracking repeat crashers as Jira issues
+#[derive(Debug, Clone)]
+pub struct JiraConfig {
+    /// e.g. `https://example.atlassian.net`
+    pub url: String,
+    pub project: String,
+    pub issue_type: String,
+    pub auth: JiraAuth,
+    /// Restart count of a container from which issues are created
+    pub restart_threshold: i32,
+}
+
+impl JiraConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `JIRA_URL` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("JIRA_URL") else {
+            return Ok(None);
+        };
+        reqwest::Url::parse(&url).context("Invalid JIRA_URL")?;
+        let project =
+            std::env::var("JIRA_PROJECT").context("JIRA_PROJECT is required with JIRA_URL")?;
+        let issue_type =
+            std::env::var("JIRA_ISSUE_TYPE").unwrap_or_else(|_| DEFAULT_JIRA_ISSUE_TYPE.to_owned());
+        let api_token =
+            std::env::var("JIRA_API_TOKEN").context("JIRA_API_TOKEN is required with JIRA_URL")?;
+        let auth = match std::env::var("JIRA_USER") {
+            Ok(user) => JiraAuth::Basic { user, api_token },
+            Err(_) => JiraAuth::Bearer(api_token),
+        };
+        let restart_threshold = match std::env::var("JIRA_RESTART_THRESHOLD") {
+            Ok(threshold) => threshold
+                .parse()
+                .context("Invalid JIRA_RESTART_THRESHOLD")?,
+            Err(_) => DEFAULT_JIRA_RESTART_THRESHOLD,
+        };
+        if restart_threshold < 1 {
+            bail!("JIRA_RESTART_THRESHOLD must be at least 1");
+        }
+        Ok(Some(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            project,
+            issue_type,
+            auth,
+            restart_threshold,
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    issues: Vec<Issue>,
+}
+
+#[derive(Deserialize)]
+struct Issue {
+    key: String,
+}
+
+/// Creates an issue per repeatedly crashing container, or comments on the open one
+#[derive(Clone)]
+pub struct Jira {
+    config: JiraConfig,
+    http: reqwest::Client,
+}
+
+impl Jira {
+    pub fn new(config: JiraConfig) -> Self {
+        Self {
+            config,
+            http: http::client(),
+        }
+    }
+
+    /// Records the restart in the open issue of the container, creating one if missing.
+    /// Returns the link to the issue, or `None` below the restart threshold.
+    ///
+    /// Issues are found by a label derived from the workload and the container, so that they
+    /// are reused across pods and restarts of johari-mirror. Resolved issues are not reused.
+    pub async fn track(&self, info: &ContainerRestartInfo) -> anyhow::Result<Option<MessageLink>> {
+        if info.restart_count < self.config.restart_threshold {
+            return Ok(None);
+        }
+        let label = workload_label(info);
+        let key = match self.find_open_issue(&label).await? {
+            Some(key) => {
+                self.comment(&key, info).await?;
+                log::info!("Commented on {key} for {info}");
+                key
+            }
+            None => {
+                let key = self.create_issue(&label, info).await?;
+                log::info!("Created {key} for {info}");
+                key
+            }
+        };
+        Ok(Some(MessageLink {
+            title: format!("Jira: {key}"),
+            url: format!("{}/browse/{key}", self.config.url),
+        }))
+    }
+
+    async fn find_open_issue(&self, label: &str) -> anyhow::Result<Option<String>> {
+        let jql = format!(
+            r#"project = "{}" AND labels = "{label}" AND statusCategory != Done ORDER BY created DESC"#,
+            self.config.project
+        );
+        let response = self
+            .request(reqwest::Method::GET, "search")
+            .query(&[
+                ("jql", jql.as_str()),
+                ("fields", "key"),
+                ("maxResults", "1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SearchResponse>()
+            .await?;
+        Ok(response.issues.into_iter().next().map(|i| i.key))
+    }
+
+    async fn create_issue(
+        &self,
+        label: &str,
+        info: &ContainerRestartInfo,
+    ) -> anyhow::Result<String> {
+        let body = json!({
+            "fields": {
+                "project": { "key": self.config.project },
+                "issuetype": { "name": self.config.issue_type },
+                "summary": format!("Container {} keeps restarting", info.workload_key()),
+                "description": description(info),
+                "labels": [JIRA_LABEL, label],
+            },
+        });
+        let issue = self
+            .request(reqwest::Method::POST, "issue")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Issue>()
+            .await?;
+        Ok(issue.key)
+    }
+
+    async fn comment(&self, key: &str, info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        self.request(reqwest::Method::POST, &format!("issue/{key}/comment"))
+            .json(&json!({ "body": description(info) }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Request to REST API v2, which takes wiki markup in descriptions and comments
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}/rest/api/2/{path}", self.config.url))
+            .timeout(JIRA_TIMEOUT);
+        match &self.config.auth {
+            JiraAuth::Basic { user, api_token } => request.basic_auth(user, Some(api_token)),
+            JiraAuth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+/// Label to find the issue of the container of the workload,
+/// e.g. `johari-mirror-0123456789abcdef`
+fn workload_label(info: &ContainerRestartInfo) -> String {
+    let hash = Sha256::digest(info.workload_key().as_bytes());
+    format!("{JIRA_LABEL}-{}", hex::encode(&hash[..8]))
+}
+
+/// Crash details in Jira wiki markup
+fn description(info: &ContainerRestartInfo) -> String {
+    let state = info.last_state.as_ref();
+    let mut lines = vec![
+        format!("*Namespace:* {}", info.namespace.as_deref().unwrap_or("")),
+        format!("*Pod:* {}", info.pod_name),
+        format!("*Container:* {}", info.container_name),
+        format!("*Image:* {}", info.container_image),
+        format!("*Node:* {}", info.node_name.as_deref().unwrap_or("")),
+        format!("*Restart count:* {}", info.restart_count),
+    ];
+    if let Some(state) = state {
+        lines.push(format!("*Exit code:* {}", state.exit_code));
+        if let Some(reason) = &state.reason {
+            lines.push(format!("*Reason:* {reason}"));
+        }
+        if let Some(finished_at) = &state.finished_at {
+            lines.push(format!("*Finished at:* {finished_at}"));
+        }
+    }
+    match &info.logs.0 {
+        // `{noformat}` in logs would end the block early
+        Ok(logs) => lines.push(format!(
+            "{{noformat}}\n{}\n{{noformat}}",
+            excerpt(logs).replace("{noformat}", "{ noformat}")
+        )),
+        Err(e) => lines.push(format!("Failed to get container logs: {e}")),
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart_info(pod: &str) -> ContainerRestartInfo {
+        ContainerRestartInfo::synthetic(
+            "default",
+            pod,
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        )
+    }
+
+    fn workload_restart_info(workload: &str, pod: &str) -> ContainerRestartInfo {
+        let mut info = restart_info(pod);
+        info.workload = Some(workload.to_owned());
+        info
+    }
+
+    #[test]
+    fn test_workload_label() {
+        let label = workload_label(&workload_restart_info("web", "web-7d4b9-abcde"));
+        assert!(label.starts_with("johari-mirror-"));
+        assert_eq!(label.len(), "johari-mirror-".len() + 16);
+        // Replacing pods of the workload share the issue
+        assert_eq!(
+            label,
+            workload_label(&workload_restart_info("web", "web-7d4b9-fghij"))
+        );
+        assert_ne!(
+            label,
+            workload_label(&workload_restart_info("api", "web-7d4b9-abcde"))
+        );
+        // Pods without a known workload are labeled by the pod
+        assert_eq!(
+            workload_label(&restart_info("app-0")),
+            workload_label(&restart_info("app-0"))
+        );
+        assert_ne!(
+            workload_label(&restart_info("app-0")),
+            workload_label(&restart_info("app-1"))
+        );
+    }
+
+    #[test]
+    fn test_description() {
+        let description = description(&restart_info("app-0"));
+        assert!(description.contains("*Pod:* app-0\n"));
+        assert!(description.contains("*Exit code:* 1\n*Reason:* Error\n"));
+        assert!(description
+            .ends_with("{noformat}\nThis is a test notification sent by test.\n{noformat}"));
+
+        let mut info = restart_info("app-0");
+        info.logs.0 = Ok("panic: {noformat}\nh1. injected".to_owned());
+        assert!(super::description(&info)
+            .ends_with("{noformat}\npanic: { noformat}\nh1. injected\n{noformat}"));
+    }
+
+    #[tokio::test]
+    async fn test_track_below_threshold() {
+        let jira = Jira::new(JiraConfig {
+            url: "http://localhost:0".to_owned(),
+            project: "OPS".to_owned(),
+            issue_type: DEFAULT_JIRA_ISSUE_TYPE.to_owned(),
+            auth: JiraAuth::Bearer("token".to_owned()),
+            restart_threshold: 2,
+        });
+        assert_eq!(jira.track(&restart_info("app-0")).await.unwrap(), None);
+    }
+}
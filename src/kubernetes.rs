@@ -1,27 +1,249 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    cell::Cell,
+    collections::{hash_map::Entry, HashMap, HashSet},
     fmt::Display,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use anyhow::Context;
-use futures::StreamExt;
-use k8s_openapi::api::core::v1::{ContainerStatus, Pod};
+use anyhow::{bail, Context};
+use futures::{future::BoxFuture, Stream, StreamExt, TryStreamExt};
+use k8s_openapi::{
+    api::core::v1::{ContainerStatus, Event, Node, Pod, PodSpec, PodStatus},
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    chrono,
+};
 use kube::{
-    api::{Api, LogParams, ResourceExt},
-    runtime::watcher,
+    api::{Api, ListParams, LogParams, ResourceExt},
+    runtime::{
+        reflector::{self, ObjectRef, Store},
+        watcher,
+    },
     Client,
 };
-use tokio::sync::mpsc;
+#[cfg(feature = "script")]
+use rhai::Dynamic;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
 use wildmatch::WildMatch;
 
-use crate::message;
+#[cfg(feature = "script")]
+use crate::script::{RoutingScript, ScriptRoute};
+use crate::{
+    category::{self, CrashCategory},
+    health::{self, Health},
+    history::{RestartHistory, RestartRecord},
+    hooks::Hooks,
+    message, metrics, pod_attachments,
+    pod_events::{EventTarget, NotificationOutcome, PodEvents},
+    queue::NotificationSender,
+    replay::EventRecorder,
+    rollout::{self, RolloutTracker},
+    self_alert::{Component, SelfAlert},
+    silence::{self, Silences},
+    source::{EventSink, EventSource},
+    startup::StartupGrace,
+};
 
 /// Key: container name
 /// Value: container restart count
-type RestartCounts = HashMap<String, i32>;
+pub type RestartCounts = HashMap<String, i32>;
+
+/// Interval to save changed restart counts to the file
+const RESTART_COUNT_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval to remove restart counts of pods which no longer exist
+const RESTART_COUNT_GC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Default maximum number of pods to track restart counts
+pub const DEFAULT_MAX_TRACKED_PODS: usize = 50_000;
+
+/// Cooldowns are forgotten after ending for this long, e.g. of deleted pods
+const COOLDOWN_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// OS and versions of nodes are read again after this period, e.g. after node upgrades
+const NODE_INFO_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Map Pod UID -> container name -> container restart count,
+/// shared with the debug endpoint and optionally persisted to a JSON file.
+#[derive(Debug, Clone, Default)]
+pub struct PodRestartCounts(Arc<Mutex<PodRestartCountsInner>>);
+
+#[derive(Debug, Default)]
+struct PodRestartCountsInner {
+    counts: HashMap<String, RestartCounts>,
+    /// Time of the last event of each pod, used to evict pods over `max_pods`
+    last_seen: HashMap<String, Instant>,
+    /// Pods missing in the last garbage collection, removed when missing again
+    missing: HashSet<String>,
+    /// Unlimited if `None`
+    max_pods: Option<usize>,
+    path: Option<PathBuf>,
+    /// Whether `counts` changed since the last save
+    dirty: bool,
+}
+
+impl PodRestartCounts {
+    /// Loads counts from `path`, which is created on the first save if it does not exist.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let counts = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .with_context(|| format!("Invalid restart count store: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self(Arc::new(Mutex::new(PodRestartCountsInner {
+            counts,
+            path: Some(path),
+            ..Default::default()
+        }))))
+    }
+
+    /// Limits the number of tracked pods. Least recently seen pods are evicted over `max_pods`.
+    pub fn with_max_pods(self, max_pods: usize) -> Self {
+        self.0.lock().unwrap().max_pods = Some(max_pods);
+        self
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, RestartCounts> {
+        self.0.lock().unwrap().counts.clone()
+    }
+
+    /// Applies `f` to the counts, which are saved later.
+    fn update<R>(&self, f: impl FnOnce(&mut HashMap<String, RestartCounts>) -> R) -> R {
+        let mut inner = self.0.lock().unwrap();
+        inner.dirty = true;
+        let result = f(&mut inner.counts);
+        metrics::tracked_pods(inner.counts.len());
+        result
+    }
+
+    /// Records an event of pod `uid` and evicts least recently seen pods over the limit.
+    fn seen(&self, uid: &str) {
+        let mut inner = self.0.lock().unwrap();
+        inner.last_seen.insert(uid.to_owned(), Instant::now());
+        inner.missing.remove(uid);
+        let Some(max_pods) = inner.max_pods else {
+            return;
+        };
+        while inner.counts.len() > max_pods {
+            // Pods restored from the file and not seen yet are evicted first
+            let Some(oldest) = inner
+                .counts
+                .keys()
+                .min_by_key(|uid| inner.last_seen.get(*uid))
+                .cloned()
+            else {
+                break;
+            };
+            log::warn!("Evicting restart counts of pod {oldest} over {max_pods} pods");
+            inner.counts.remove(&oldest);
+            inner.last_seen.remove(&oldest);
+            inner.dirty = true;
+            metrics::pod_evicted(metrics::EvictionReason::Capacity);
+        }
+        metrics::tracked_pods(inner.counts.len());
+    }
+
+    fn remove(&self, uid: &str) {
+        let mut inner = self.0.lock().unwrap();
+        inner.last_seen.remove(uid);
+        inner.missing.remove(uid);
+        if inner.counts.remove(uid).is_some() {
+            inner.dirty = true;
+        }
+        metrics::tracked_pods(inner.counts.len());
+    }
+
+    /// Removes pods not in `living` twice in a row, whose Deleted events were missed.
+    /// Pods missing once may have been created after listing `living`.
+    fn retain_living(&self, living: &HashSet<String>) {
+        let mut inner = self.0.lock().unwrap();
+        let inner = &mut *inner;
+        let mut missing = HashSet::new();
+        for uid in inner.counts.keys().filter(|uid| !living.contains(*uid)) {
+            missing.insert(uid.clone());
+        }
+        for uid in missing.intersection(&inner.missing) {
+            log::info!("Removing restart counts of pod {uid} which no longer exists");
+            inner.counts.remove(uid);
+            inner.last_seen.remove(uid);
+            inner.dirty = true;
+            metrics::pod_evicted(metrics::EvictionReason::Stale);
+        }
+        missing.retain(|uid| inner.counts.contains_key(uid));
+        inner.missing = missing;
+        metrics::tracked_pods(inner.counts.len());
+    }
+
+    /// Task to remove restart counts of pods no longer in `pod_store` periodically
+    pub async fn collect_garbage(self, pod_store: Store<Pod>) {
+        let mut interval = tokio::time::interval(RESTART_COUNT_GC_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pods = pod_store.state();
+            // The store is empty until the watcher is initialized
+            if pods.is_empty() {
+                continue;
+            }
+            let living = pods.iter().filter_map(|p| p.uid()).collect();
+            self.retain_living(&living);
+        }
+    }
+
+    /// Task to save the counts to the file when changed
+    pub async fn save_periodically(self) {
+        let mut interval = tokio::time::interval(RESTART_COUNT_SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut inner = self.0.lock().unwrap();
+            if !inner.dirty {
+                continue;
+            }
+            let Some(path) = &inner.path else {
+                return;
+            };
+            if let Err(e) = save_json(path, &inner.counts) {
+                log::error!("Failed to save restart counts: {e}");
+                continue;
+            }
+            inner.dirty = false;
+        }
+    }
+}
+
+/// Writes `value` to `path` through a temporary file not to corrupt it on failure
+fn save_json(path: &std::path::Path, value: &impl serde::Serialize) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec(value)?)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Default number of log lines to fetch
+pub const DEFAULT_LOG_TAIL_LINES: i64 = 500;
 
-/// Number of log lines to fetch
-const LOG_LINES: i64 = 500;
+/// Default maximum size of container logs to keep in memory
+pub const DEFAULT_LOG_MAX_BYTES: usize = 8 * 1024 * 1024;
+
+/// Default timeout to fetch container logs
+pub const DEFAULT_LOG_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait time before retrying to fetch container logs
+const LOG_FETCH_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Wait time before retrying to fetch container logs which kubelet has not finalized yet
+const LOG_NOT_READY_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Default time to wait for following events of a restart before notifying it.
+/// Coalescing is disabled by default and enabled with `COALESCE_WINDOW`, e.g. `5s`.
+pub const DEFAULT_COALESCE_WINDOW: Duration = Duration::ZERO;
+
+/// Default maximum number of container logs fetched concurrently
+pub const DEFAULT_LOG_FETCH_CONCURRENCY: usize = 8;
 
 /// After container restarted more than `NOTIFICATION_SKIP_THRESHOLD` times,
 /// notifications will be sent every `NOTIFICATION_SKIP_INTERVAL` restarts.
@@ -34,41 +256,432 @@ const NOTIFICATION_SKIP_THRESHOLD: i32 = 10;
 /// is approximately 2 hours.
 const NOTIFICATION_SKIP_INTERVAL: i32 = 24;
 
+/// Configuration of `watch`
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    pub notification_config: NotificationConfig,
+    /// Number of log lines to fetch
+    pub log_tail_lines: i64,
+    /// Only the last `log_max_bytes` bytes of logs are kept
+    pub log_max_bytes: usize,
+    pub log_fetch_timeout: Duration,
+    /// Maximum number of log fetches in flight
+    pub log_fetch_concurrency: usize,
+    /// Coalescing is disabled when zero
+    pub coalesce_window: Duration,
+    /// Script to route restarts before the pattern rules of `notification_config`
+    #[cfg(feature = "script")]
+    pub routing_script: Option<Arc<RoutingScript>>,
+    /// Watcher events are appended to the file when set, to reproduce them with `replay`
+    pub record_path: Option<PathBuf>,
+    /// Records restarts suppressed by silences as Events on the pods
+    pub pod_events: bool,
+    /// Shows the OS image, the container runtime and the kubelet version of the node
+    pub node_info: bool,
+    /// Crashes within the period after an image change or a rollout are called out.
+    /// Disabled when `None`.
+    pub rollout_window: Option<chrono::Duration>,
+    /// Namespaces to watch with a watcher each, for namespaced RBAC.
+    /// Pods in all namespaces are watched when empty.
+    pub namespaces: Vec<String>,
+}
+
+impl WatchConfig {
+    /// Configuration with default values
+    pub fn new(notification_config: NotificationConfig) -> Self {
+        Self {
+            notification_config,
+            log_tail_lines: DEFAULT_LOG_TAIL_LINES,
+            log_max_bytes: DEFAULT_LOG_MAX_BYTES,
+            log_fetch_timeout: DEFAULT_LOG_FETCH_TIMEOUT,
+            log_fetch_concurrency: DEFAULT_LOG_FETCH_CONCURRENCY,
+            coalesce_window: DEFAULT_COALESCE_WINDOW,
+            #[cfg(feature = "script")]
+            routing_script: None,
+            record_path: None,
+            pod_events: false,
+            node_info: false,
+            rollout_window: Some(rollout::DEFAULT_ROLLOUT_WINDOW),
+            namespaces: Vec::new(),
+        }
+    }
+
+    /// Whether restarts are routed by `routing_script` before the pattern rules
+    #[cfg(feature = "script")]
+    pub fn has_routing_script(&self) -> bool {
+        self.routing_script.is_some()
+    }
+
+    /// Restarts are routed only by the pattern rules without the `script` feature
+    #[cfg(not(feature = "script"))]
+    pub fn has_routing_script(&self) -> bool {
+        false
+    }
+
+    /// Reads configuration from environment variables.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let notification_config =
+            std::env::var("SLACK_NOTIFICATION_CONFIG")?.parse::<NotificationConfig>()?;
+        let log_tail_lines = match std::env::var("LOG_TAIL_LINES") {
+            Ok(lines) => lines.parse().context("Invalid LOG_TAIL_LINES")?,
+            Err(_) => DEFAULT_LOG_TAIL_LINES,
+        };
+        let log_max_bytes = match std::env::var("LOG_MAX_BYTES") {
+            Ok(bytes) => bytes.parse().context("Invalid LOG_MAX_BYTES")?,
+            Err(_) => DEFAULT_LOG_MAX_BYTES,
+        };
+        let log_fetch_timeout = match std::env::var("LOG_FETCH_TIMEOUT") {
+            Ok(timeout) => silence::parse_duration(&timeout)
+                .map_err(|e| anyhow::anyhow!("Invalid LOG_FETCH_TIMEOUT: {e}"))?
+                .to_std()?,
+            Err(_) => DEFAULT_LOG_FETCH_TIMEOUT,
+        };
+        let coalesce_window = match std::env::var("COALESCE_WINDOW").as_deref() {
+            Ok("0" | "0s") => Duration::ZERO,
+            Ok(window) => silence::parse_duration(window)
+                .map_err(|e| anyhow::anyhow!("Invalid COALESCE_WINDOW: {e}"))?
+                .to_std()?,
+            Err(_) => DEFAULT_COALESCE_WINDOW,
+        };
+        let log_fetch_concurrency = match std::env::var("LOG_FETCH_CONCURRENCY") {
+            Ok(concurrency) => concurrency
+                .parse()
+                .context("Invalid LOG_FETCH_CONCURRENCY")?,
+            Err(_) => DEFAULT_LOG_FETCH_CONCURRENCY,
+        };
+        #[cfg(feature = "script")]
+        let routing_script = match std::env::var("ROUTING_SCRIPT") {
+            Ok(path) => Some(Arc::new(RoutingScript::load(path.as_ref())?)),
+            Err(_) => None,
+        };
+        #[cfg(not(feature = "script"))]
+        if std::env::var("ROUTING_SCRIPT").is_ok() {
+            bail!("ROUTING_SCRIPT requires the `script` feature");
+        }
+        let pod_events = match std::env::var("POD_EVENTS") {
+            Ok(enabled) => enabled.parse().context("Invalid POD_EVENTS")?,
+            Err(_) => false,
+        };
+        let node_info = match std::env::var("NODE_INFO") {
+            Ok(enabled) => enabled.parse().context("Invalid NODE_INFO")?,
+            Err(_) => false,
+        };
+        let rollout_window = match std::env::var("ROLLOUT_WINDOW").as_deref() {
+            Ok("0") => None,
+            Ok(window) => Some(
+                silence::parse_duration(window)
+                    .map_err(|e| anyhow::anyhow!("Invalid ROLLOUT_WINDOW: {e}"))?,
+            ),
+            Err(_) => Some(rollout::DEFAULT_ROLLOUT_WINDOW),
+        };
+        let namespaces = match std::env::var("WATCH_NAMESPACES") {
+            Ok(namespaces) => namespaces
+                .split(',')
+                .map(str::trim)
+                .filter(|namespace| !namespace.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let config = Self {
+            notification_config,
+            log_tail_lines,
+            log_max_bytes,
+            log_fetch_timeout,
+            log_fetch_concurrency,
+            coalesce_window,
+            #[cfg(feature = "script")]
+            routing_script,
+            record_path: std::env::var("WATCH_RECORD_PATH").ok().map(PathBuf::from),
+            pod_events,
+            node_info,
+            rollout_window,
+            namespaces,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.log_max_bytes == 0 {
+            bail!("LOG_MAX_BYTES must be at least 1");
+        }
+        if self.log_fetch_concurrency == 0 {
+            bail!("LOG_FETCH_CONCURRENCY must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// State of `watch` shared with other tasks
+pub struct WatchState {
+    /// Cache of pruned Pods, which other tasks read through its `Store`
+    pub pod_store: reflector::store::Writer<Pod>,
+    pub restart_counts: PodRestartCounts,
+    /// Restarts are summarized during the grace period after startup when set
+    pub startup_grace: Option<StartupGrace>,
+    pub silences: Silences,
+    pub history: RestartHistory,
+    pub hooks: Option<Arc<dyn Hooks>>,
+}
+
+/// `EventSource` of pods in a Kubernetes cluster.
+/// Restarts are routed by the router and silences of the sink.
+pub(crate) struct KubernetesSource {
+    pub client: Client,
+    pub config: WatchConfig,
+    pub health: Health,
+    pub state: WatchState,
+    pub self_alert: SelfAlert,
+}
+
+impl EventSource for KubernetesSource {
+    fn run(self: Box<Self>, sink: EventSink) -> BoxFuture<'static, anyhow::Result<()>> {
+        let Self {
+            client,
+            mut config,
+            health,
+            mut state,
+            self_alert,
+        } = *self;
+        config.notification_config = sink.router().clone();
+        state.silences = sink.silences().clone();
+        state.hooks = sink.hooks().cloned();
+        Box::pin(watch(
+            client,
+            config,
+            sink.queue().clone(),
+            health,
+            state,
+            self_alert,
+        ))
+    }
+}
+
 /// Task to watch events in kubernetes cluster
 pub async fn watch(
     client: Client,
-    tx: mpsc::Sender<message::ContainerRestartInfo>,
+    config: WatchConfig,
+    queue: NotificationSender,
+    health: Health,
+    state: WatchState,
+    self_alert: SelfAlert,
 ) -> anyhow::Result<()> {
-    // Read pods in all namespaces into the typed interface from k8s-openapi
-    let pods: Api<Pod> = Api::all(client.clone());
+    // Labels are kept for the routing script
+    let keep_labels = config.has_routing_script();
+    let mut recorder = config
+        .record_path
+        .as_deref()
+        .map(EventRecorder::create)
+        .transpose()?;
+    let pods = if config.namespaces.is_empty() {
+        // Read pods in all namespaces into the typed interface from k8s-openapi
+        let pods: Api<Pod> = Api::all(client.clone());
+        watcher(pods, watcher::Config::default())
+            .map(move |res| res.map(|e| ScopedEvent::from(prune_event(e, keep_labels))))
+            .boxed()
+    } else {
+        log::info!(
+            "Watching pods in namespaces {}",
+            config.namespaces.join(", ")
+        );
+        let watchers = config.namespaces.iter().map(|namespace| {
+            let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+            let namespace = namespace.clone();
+            watcher(pods, watcher::Config::default())
+                .map(move |res| {
+                    res.map(|e| ScopedEvent {
+                        namespace: Some(namespace.clone()),
+                        event: prune_event(e, keep_labels),
+                    })
+                })
+                .boxed()
+        });
+        futures::stream::select_all(watchers).boxed()
+    };
+    let watch_stream = pods.map(move |res| {
+        let e = res?;
+        if let Some(recorder) = &mut recorder {
+            recorder.record(&e);
+        }
+        Ok(e)
+    });
+    let env = WatchEnvironment {
+        client: Some(client),
+        health,
+        self_alert,
+    };
+    process_events(env, config, queue, state, watch_stream).await
+}
+
+/// Watcher event of pods in `namespace`, or in all namespaces when `None`.
+/// `Restarted` of a namespace replaces only its pods in the pod store and `PodRestartCounts`,
+/// so that namespaces are watched by separate watchers sharing them.
+#[derive(Debug, Clone)]
+pub struct ScopedEvent {
+    pub namespace: Option<String>,
+    pub event: watcher::Event<Pod>,
+}
+
+impl From<watcher::Event<Pod>> for ScopedEvent {
+    fn from(event: watcher::Event<Pod>) -> Self {
+        Self {
+            namespace: None,
+            event,
+        }
+    }
+}
+
+/// Applies `e` to the pod store through `writer`. `Restarted` of a namespace replaces only
+/// the pods in it, whose UIDs before the event are returned.
+fn apply_to_store(
+    writer: &mut reflector::store::Writer<Pod>,
+    store: &Store<Pod>,
+    e: &ScopedEvent,
+) -> HashSet<String> {
+    let (Some(namespace), watcher::Event::Restarted(living_pods)) = (&e.namespace, &e.event) else {
+        writer.apply_watcher_event(&e.event);
+        return HashSet::new();
+    };
+    let living = living_pods
+        .iter()
+        .filter_map(|p| p.uid())
+        .collect::<HashSet<_>>();
+    let mut replaced = HashSet::new();
+    for p in store.state() {
+        if p.namespace().as_ref() != Some(namespace) {
+            continue;
+        }
+        let uid = p.uid().unwrap_or_default();
+        if !living.contains(&uid) {
+            writer.apply_watcher_event(&watcher::Event::Deleted(Pod::clone(&p)));
+        }
+        replaced.insert(uid);
+    }
+    for p in living_pods {
+        writer.apply_watcher_event(&watcher::Event::Applied(p.clone()));
+    }
+    replaced
+}
+
+/// Processes watcher `events` recorded with `WatchConfig::record_path` in the same way as
+/// `watch`, without Kubernetes. Logs and resources of restarted containers are not
+/// available. Ends when the notifications are queued.
+pub async fn replay(
+    config: WatchConfig,
+    events: Vec<ScopedEvent>,
+    queue: NotificationSender,
+    state: WatchState,
+) -> anyhow::Result<()> {
+    let env = WatchEnvironment {
+        client: None,
+        health: Health::new(health::DEFAULT_WATCH_STALL_TIMEOUT),
+        // Self-alerts are disabled without destinations
+        self_alert: SelfAlert::default(),
+    };
+    let events = futures::stream::iter(events.into_iter().map(Ok));
+    process_events(env, config, queue, state, events).await
+}
+
+/// Queues the notification of the last restart of `container` in `pod` from its current
+/// status and previous logs, e.g. of a crash missed while johari-mirror was down or silenced.
+/// Routed by `config`, or to `channel` when set, regardless of restart counts and silences.
+pub async fn notify_restart(
+    client: Client,
+    config: WatchConfig,
+    queue: NotificationSender,
+    namespace: &str,
+    pod: &str,
+    container: &str,
+    channel: Option<String>,
+) -> anyhow::Result<()> {
+    let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let p = pods
+        .get(pod)
+        .await
+        .with_context(|| format!("Failed to get pod {namespace}/{pod}"))?;
+    let status = containers(&p)
+        .find(|c| c.name == container)
+        .with_context(|| format!("Container {container} is not found in {namespace}/{pod}"))?;
+    if get_last_state(status).is_none() {
+        bail!("Container {container} has not terminated since {namespace}/{pod} started");
+    }
+    let route = config
+        .notification_config
+        .find_route(namespace, pod, container);
+    let options = route
+        .map(|(_, options)| options.clone())
+        .unwrap_or_default();
+    let channel = match channel {
+        Some(channel) => channel,
+        None => route.map(|(channel, _)| channel.to_owned()).context(
+            "Notification of the container is disabled by SLACK_NOTIFICATION_CONFIG, \
+             specify --channel",
+        )?,
+    };
+    let (_, pod_store) = reflector::store();
+    let state = WatchState {
+        pod_store,
+        restart_counts: PodRestartCounts::default(),
+        startup_grace: None,
+        silences: Silences::default(),
+        history: RestartHistory::default(),
+        hooks: None,
+    };
+    let (ctx, _, _) = WatchContext::new(Some(client), config, state, queue);
+    let info = describe_container_status(&ctx, &p, status, &channel, &options).await;
+    log::info!("Notifying the last restart: {info}");
+    ctx.queue.send(info).await
+}
 
-    let notification_config =
-        std::env::var("SLACK_NOTIFICATION_CONFIG")?.parse::<NotificationConfig>()?;
+/// Cluster the events come from, and where their failures are reported
+struct WatchEnvironment {
+    /// `None` when events are replayed
+    client: Option<Client>,
+    health: Health,
+    self_alert: SelfAlert,
+}
 
-    // Map Pod UID -> container name -> container restart count
-    let mut pod_restart_count = HashMap::<String, RestartCounts>::new();
+/// Processes watcher events until `watch_stream` ends.
+async fn process_events(
+    env: WatchEnvironment,
+    config: WatchConfig,
+    queue: NotificationSender,
+    state: WatchState,
+    watch_stream: impl Stream<Item = watcher::Result<ScopedEvent>> + Send + 'static,
+) -> anyhow::Result<()> {
+    config.validate()?;
+    let WatchEnvironment {
+        client,
+        health,
+        self_alert,
+    } = env;
+    let (ctx, mut pod_store, pod_restart_count) = WatchContext::new(client, config, state, queue);
+    let ctx = Arc::new(ctx);
 
-    let mut event_stream = watcher(pods, watcher::Config::default()).boxed();
-    while let Some(res) = event_stream.next().await {
+    let mut watch_stream = watch_stream.boxed();
+    while let Some(res) = watch_stream.next().await {
         let e = match res {
             Ok(e) => e,
             Err(err) => {
                 log::error!("Failure in watcher: {err}");
+                health.watch_error(&err);
+                self_alert.failure(Component::Watcher, &err);
                 continue;
             }
         };
-        match e {
+        self_alert.success(Component::Watcher);
+        health.watch_event(matches!(e.event, watcher::Event::Restarted(_)));
+        // The store is updated before each event is processed
+        let replaced = apply_to_store(&mut pod_store, &ctx.pod_store, &e);
+        match e.event {
             // Pod `p` was added or modified.
             // Note that a container restart is treated as a modification of pod status.
             watcher::Event::Applied(p) => {
-                process_applied(
-                    &mut pod_restart_count,
-                    &notification_config,
-                    &client,
-                    &p,
-                    &tx,
-                )
-                .await?;
+                if let Err(e) = process_applied(&pod_restart_count, &ctx, &p).await {
+                    log::error!("Failed to process pod {}: {e:#}", PodDisplay(&p));
+                }
+                pod_restart_count.seen(&p.uid().unwrap());
             }
             // Pod `p` was terminated successfully.
             watcher::Event::Deleted(p) => {
@@ -77,11 +690,45 @@ pub async fn watch(
             }
             // `watcher` was initialized or restarted.
             // Register all living pods in `pod_restart_count`.
+            // Pods known before, e.g. restored from the file, are checked for missed restarts.
             watcher::Event::Restarted(living_pods) => {
-                pod_restart_count.clear();
+                if let Some(startup_grace) = &ctx.startup_grace {
+                    startup_grace.start();
+                }
+                let mut known = match e.namespace {
+                    None => pod_restart_count.update(std::mem::take),
+                    // Pods in the other namespaces are kept. Those deleted while the watcher
+                    // was down and only restored from the file are removed by `collect_garbage`.
+                    Some(_) => pod_restart_count.update(|counts| {
+                        let living = living_pods.iter().filter_map(|p| p.uid());
+                        replaced
+                            .into_iter()
+                            .chain(living)
+                            .filter_map(|uid| counts.remove_entry(&uid))
+                            .collect()
+                    }),
+                };
                 for p in living_pods {
                     log::info!("Pod detected: {}", PodDisplay(&p));
-                    pod_restart_count.insert(p.uid().unwrap(), restarts_in_pod(&p));
+                    let uid = p.uid().unwrap();
+                    match known.remove(&uid) {
+                        Some(counts) => {
+                            pod_restart_count.update(|c| c.insert(uid.clone(), counts));
+                            // Other pods are checked for missed restarts regardless
+                            if let Err(e) = process_applied(&pod_restart_count, &ctx, &p).await {
+                                log::error!("Failed to process pod {}: {e:#}", PodDisplay(&p));
+                            }
+                        }
+                        None => {
+                            pod_restart_count
+                                .update(|c| c.insert(uid.clone(), restarts_in_pod(&p)));
+                        }
+                    }
+                    pod_restart_count.seen(&uid);
+                }
+                // Pods deleted while the watcher was down
+                for uid in known.keys() {
+                    pod_restart_count.remove(uid);
                 }
             }
         }
@@ -90,65 +737,507 @@ pub async fn watch(
     Ok(())
 }
 
+/// Prunes Pods in watcher event `e` with `prune_pod`.
+fn prune_event(e: watcher::Event<Pod>, keep_labels: bool) -> watcher::Event<Pod> {
+    match e {
+        watcher::Event::Applied(p) => watcher::Event::Applied(prune_pod(p, keep_labels)),
+        watcher::Event::Deleted(p) => watcher::Event::Deleted(prune_pod(p, keep_labels)),
+        watcher::Event::Restarted(pods) => watcher::Event::Restarted(
+            pods.into_iter()
+                .map(|p| prune_pod(p, keep_labels))
+                .collect(),
+        ),
+    }
+}
+
+/// Drops fields of Pod `p` not used to detect restarts, to reduce memory on clusters with
+/// many pods. The full Pod is fetched when composing a notification.
+/// All labels are kept when `keep_labels` is true.
+fn prune_pod(p: Pod, keep_labels: bool) -> Pod {
+    let labels = if keep_labels {
+        p.metadata.labels
+    } else {
+        p.metadata.labels.map(|labels| {
+            labels
+                .into_iter()
+                .filter(|(k, _)| rollout::TEMPLATE_HASH_LABELS.contains(&k.as_str()))
+                .collect()
+        })
+    };
+    Pod {
+        metadata: ObjectMeta {
+            name: p.metadata.name,
+            namespace: p.metadata.namespace,
+            uid: p.metadata.uid,
+            creation_timestamp: p.metadata.creation_timestamp,
+            labels,
+            owner_references: p.metadata.owner_references,
+            ..Default::default()
+        },
+        spec: p.spec.map(|spec| PodSpec {
+            node_name: spec.node_name,
+            priority: spec.priority,
+            priority_class_name: spec.priority_class_name,
+            ..Default::default()
+        }),
+        status: p.status.map(|status| PodStatus {
+            container_statuses: status.container_statuses,
+            // Kept to tell preemption from crashes
+            reason: status.reason,
+            conditions: status.conditions.map(|conditions| {
+                conditions
+                    .into_iter()
+                    .filter(|c| c.type_ == "DisruptionTarget")
+                    .collect()
+            }),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Dependencies to process watcher events
+struct WatchContext {
+    /// `None` when events are replayed
+    client: Option<Client>,
+    pod_events: Option<PodEvents>,
+    /// `None` when `NODE_INFO` is disabled
+    node_infos: Option<NodeInfos>,
+    notification_config: NotificationConfig,
+    /// Number of log lines to fetch
+    log_tail_lines: i64,
+    /// Only the last `log_max_bytes` bytes of logs are kept
+    log_max_bytes: usize,
+    log_fetch_timeout: Duration,
+    /// Limits log fetches in flight
+    log_fetches: Semaphore,
+    /// Limits restarts processed in the background, so that watching is paused while
+    /// they wait for the notification queue with `QueuePolicy::Block`
+    restarts_in_flight: Arc<Semaphore>,
+    /// Coalescing is disabled when zero
+    coalesce_window: Duration,
+    #[cfg(feature = "script")]
+    routing_script: Option<Arc<RoutingScript>>,
+    pending_restarts: PendingRestarts,
+    cooldowns: Cooldowns,
+    pod_store: Store<Pod>,
+    startup_grace: Option<StartupGrace>,
+    silences: Silences,
+    history: RestartHistory,
+    hooks: Option<Arc<dyn Hooks>>,
+    queue: NotificationSender,
+    rollouts: Option<RolloutTracker>,
+}
+
+impl WatchContext {
+    /// Context of processing events with `config` and `state`.
+    /// The writer of the pod store and the restart counts are returned to process events.
+    fn new(
+        client: Option<Client>,
+        config: WatchConfig,
+        state: WatchState,
+        queue: NotificationSender,
+    ) -> (Self, reflector::store::Writer<Pod>, PodRestartCounts) {
+        let WatchState {
+            pod_store,
+            restart_counts,
+            startup_grace,
+            silences,
+            history,
+            hooks,
+        } = state;
+        let pod_events = client
+            .clone()
+            .filter(|_| config.pod_events)
+            .map(PodEvents::new);
+        let ctx = Self {
+            client,
+            pod_events,
+            node_infos: config.node_info.then(NodeInfos::default),
+            notification_config: config.notification_config,
+            log_tail_lines: config.log_tail_lines,
+            log_max_bytes: config.log_max_bytes,
+            log_fetch_timeout: config.log_fetch_timeout,
+            log_fetches: Semaphore::new(config.log_fetch_concurrency),
+            restarts_in_flight: Arc::new(Semaphore::new(queue.monitor().capacity().max(1))),
+            coalesce_window: config.coalesce_window,
+            #[cfg(feature = "script")]
+            routing_script: config.routing_script,
+            pending_restarts: PendingRestarts::default(),
+            cooldowns: Cooldowns::default(),
+            pod_store: pod_store.as_reader(),
+            startup_grace,
+            silences,
+            history,
+            hooks,
+            queue,
+            rollouts: config.rollout_window.map(RolloutTracker::new),
+        };
+        (ctx, pod_store, restart_counts)
+    }
+
+    /// Reads logs with `read_logs` within `log_fetch_timeout`. A permit of `log_fetches`
+    /// is held only while the logs are read, not during retry delays or other requests.
+    async fn read_logs_limited(
+        &self,
+        pods_ns: &Api<Pod>,
+        name: &str,
+        params: &LogParams,
+    ) -> Result<kube::Result<String>, tokio::time::error::Elapsed> {
+        let _permit = self.log_fetches.acquire().await;
+        tokio::time::timeout(
+            self.log_fetch_timeout,
+            read_logs(pods_ns, name, params, self.log_max_bytes),
+        )
+        .await
+    }
+}
+
 /// Processes `watcher::Event::Applied` event
 async fn process_applied(
-    pod_restart_count: &mut HashMap<String, RestartCounts>,
-    notification_config: &NotificationConfig,
-    client: &Client,
+    pod_restart_count: &PodRestartCounts,
+    ctx: &Arc<WatchContext>,
     p: &Pod,
-    tx: &mpsc::Sender<message::ContainerRestartInfo>,
 ) -> anyhow::Result<()> {
-    match pod_restart_count.entry(p.uid().unwrap()) {
-        Entry::Occupied(mut entry) => {
-            for container in containers(p) {
-                let current_restart = entry.get_mut().entry(container.name.clone()).or_default();
-                if container.restart_count <= *current_restart {
-                    continue;
-                }
-                *current_restart = container.restart_count;
-                if is_skipped_interval(container.restart_count) {
-                    continue;
+    if let Some(rollouts) = &ctx.rollouts {
+        rollouts.observe(p, &workload_name(p));
+    }
+    // Update restart counts first not to hold the lock while processing restarts
+    let restarted = pod_restart_count.update(|counts| {
+        let mut restarted = Vec::new();
+        match counts.entry(p.uid().unwrap()) {
+            Entry::Occupied(mut entry) => {
+                for container in containers(p) {
+                    let current_restart =
+                        entry.get_mut().entry(container.name.clone()).or_default();
+                    if container.restart_count <= *current_restart {
+                        continue;
+                    }
+                    *current_restart = container.restart_count;
+                    restarted.push(container);
                 }
-                log::info!(
-                    "Container restarted: {} - {}",
+            }
+            // Pod `p` did not exist until this event
+            Entry::Vacant(entry) => {
+                log::info!("New pod created: {}", PodDisplay(p));
+                entry.insert(restarts_in_pod(p));
+            }
+        }
+        restarted
+    });
+
+    for container in restarted {
+        let span = tracing::info_span!(
+            "container_restart",
+            namespace = p.namespace().as_deref().unwrap_or(""),
+            pod = p.name_any(),
+            container = container.name,
+            restart_count = container.restart_count,
+        );
+        process_restart(ctx, p, container).instrument(span).await?;
+    }
+    Ok(())
+}
+
+/// Processes a restart of `container` in Pod `p` and sends a notification if necessary.
+/// Notifications wait for the coalesce window in the background and are composed from
+/// the latest Pod in the store, so that following events of the same restart are included.
+async fn process_restart(
+    ctx: &Arc<WatchContext>,
+    p: &Pod,
+    container: &ContainerStatus,
+) -> anyhow::Result<()> {
+    let last_state = get_last_state(container);
+    let record = RestartRecord {
+        time: k8s_openapi::chrono::Utc::now(),
+        namespace: p.namespace().unwrap_or_default(),
+        workload: workload_name(p),
+        pod: p.name_any(),
+        container: container.name.clone(),
+        restart_count: container.restart_count,
+        exit_code: last_state.as_ref().map(|state| state.exit_code),
+        reason: last_state.map(|state| {
+            state
+                .reason
+                .unwrap_or_else(|| format!("exit code {}", state.exit_code))
+        }),
+    };
+    if let Some(hooks) = &ctx.hooks {
+        hooks.on_restart_detected(&record).await;
+    }
+    ctx.history.record(record);
+    log::info!(
+        "Container restarted: {} - {}",
+        PodDisplay(p),
+        &container.name
+    );
+    let route = ctx
+        .notification_config
+        .find_route(
+            p.namespace().as_deref().unwrap_or(""),
+            &p.name_any(),
+            &container.name,
+        )
+        .map(|(channel, options)| (channel.to_owned(), options.clone()));
+    #[cfg(feature = "script")]
+    let route = match &ctx.routing_script {
+        Some(script) => route_by_script(script, p, container, route),
+        None => route,
+    };
+    let (channel, options) = match route {
+        // Notify to specified channel
+        Some(route) => route,
+        // Skip notification
+        None => {
+            log::debug!(
+                "Skipping notification: {} - {}",
+                PodDisplay(p),
+                &container.name
+            );
+            return Ok(());
+        }
+    };
+    // The cooldown replaces skipping restarts by their count
+    if options.cooldown.is_none() && is_skipped_interval(container.restart_count) {
+        return Ok(());
+    }
+    if let Some(silence) = ctx.silences.find(
+        p.namespace().as_deref().unwrap_or(""),
+        &p.name_any(),
+        &container.name,
+    ) {
+        log::debug!(
+            "Skipping notification by silence #{}: {} - {}",
+            silence.id,
+            PodDisplay(p),
+            &container.name
+        );
+        if let Some(pod_events) = &ctx.pod_events {
+            pod_events.publish(
+                EventTarget::of_container(p, container),
+                NotificationOutcome::Silenced(silence.id),
+            );
+        }
+        return Ok(());
+    }
+    if let Some(startup_grace) = &ctx.startup_grace {
+        let container_key = format!(
+            "{}/{}/{}",
+            p.namespace().unwrap_or_default(),
+            p.name_any(),
+            container.name
+        );
+        if startup_grace.record(&channel, container_key) {
+            log::debug!(
+                "Summarizing restart during the startup grace period: {} - {}",
+                PodDisplay(p),
+                &container.name
+            );
+            return Ok(());
+        }
+    }
+    let key = format!("{}/{}", p.uid().unwrap(), container.name);
+    let suppressed = match options.cooldown {
+        Some(cooldown) => match ctx.cooldowns.admit(&key, cooldown, Instant::now()) {
+            Some(suppressed) => suppressed,
+            None => {
+                log::debug!(
+                    "Skipping notification in the cooldown: {} - {}",
                     PodDisplay(p),
                     &container.name
                 );
-                let channel = match notification_config.find_channel(
+                return Ok(());
+            }
+        },
+        None => 0,
+    };
+    if !ctx.coalesce_window.is_zero() && !ctx.pending_restarts.insert(key.clone()) {
+        log::debug!(
+            "Coalescing restart into the pending notification: {} - {}",
+            PodDisplay(p),
+            &container.name
+        );
+        return Ok(());
+    }
+    // Waits here, pausing the watcher, while as many restarts as the queue capacity are
+    // in flight
+    let Ok(in_flight) = Arc::clone(&ctx.restarts_in_flight).acquire_owned().await else {
+        return Ok(());
+    };
+    let ctx = Arc::clone(ctx);
+    let (mut p, container_name) = (p.clone(), container.name.clone());
+    tokio::spawn(
+        async move {
+            let _in_flight = in_flight;
+            // Wait for the pod status to settle and notify with the latest one
+            if !ctx.coalesce_window.is_zero() {
+                tokio::time::sleep(ctx.coalesce_window).await;
+                ctx.pending_restarts.remove(&key);
+                let latest = ctx
+                    .pod_store
+                    .get(&ObjectRef::new(&p.name_any()).within(p.namespace().as_deref().unwrap()));
+                if let Some(latest) = latest.filter(|latest| latest.uid() == p.uid()) {
+                    p = Pod::clone(&latest);
+                }
+            }
+            let Some(container) = containers(&p).find(|c| c.name == container_name) else {
+                return;
+            };
+            let mut message =
+                describe_container_status(&ctx, &p, container, &channel, &options).await;
+            if suppressed > 0 {
+                message.notes.push(format!(
+                    ":hourglass: {suppressed} earlier restarts were not notified in the cooldown"
+                ));
+            }
+            // Routed by a rule of other categories before the crash was classified
+            if !message.options.matches_category(message.category) {
+                let route = ctx.notification_config.find_category_route(
                     p.namespace().as_deref().unwrap_or(""),
                     &p.name_any(),
-                    &container.name,
-                ) {
-                    // Notify to specified channel
-                    Some(channel) => channel,
-                    // Skip notification
-                    None => {
-                        log::debug!(
-                            "Skipping notification: {} - {}",
-                            PodDisplay(p),
-                            &container.name
-                        );
-                        continue;
-                    }
-                };
-                let message =
-                    describe_container_status(client.clone(), p, container, channel).await;
-                log::debug!(
-                    "Message queue capacity: {} / {}",
-                    tx.capacity(),
-                    tx.max_capacity()
+                    &container_name,
+                    message.category,
                 );
-                tx.send(message).await?;
+                let Some((channel, options)) = route else {
+                    log::debug!("Skipping notification by category: {message}");
+                    return;
+                };
+                message.channel = channel.to_owned();
+                message.options = options.clone();
+            }
+            if let Err(e) = ctx.queue.send(message).await {
+                log::error!("Failed to queue notification: {e}");
+            }
+        }
+        .in_current_span(),
+    );
+    Ok(())
+}
+
+/// Routes the restart of `container` by `script`, falling back to `route` of the pattern rules.
+#[cfg(feature = "script")]
+fn route_by_script(
+    script: &RoutingScript,
+    p: &Pod,
+    container: &ContainerStatus,
+    route: Option<(String, NotificationOptions)>,
+) -> Option<(String, NotificationOptions)> {
+    let optional = |value: Option<Dynamic>| value.unwrap_or(Dynamic::UNIT);
+    let last_state = get_last_state(container);
+    let labels = p
+        .labels()
+        .iter()
+        .map(|(k, v)| (k.as_str().into(), Dynamic::from(v.clone())))
+        .collect::<rhai::Map>();
+    let restart = rhai::Map::from_iter([
+        ("namespace".into(), p.namespace().unwrap_or_default().into()),
+        ("pod".into(), p.name_any().into()),
+        ("workload".into(), workload_name(p).into()),
+        ("container".into(), container.name.clone().into()),
+        ("image".into(), container.image.clone().into()),
+        (
+            "restart_count".into(),
+            i64::from(container.restart_count).into(),
+        ),
+        (
+            "node".into(),
+            optional(
+                p.spec
+                    .as_ref()
+                    .and_then(|spec| spec.node_name.clone())
+                    .map(Dynamic::from),
+            ),
+        ),
+        (
+            "exit_code".into(),
+            optional(
+                last_state
+                    .as_ref()
+                    .map(|state| Dynamic::from(i64::from(state.exit_code))),
+            ),
+        ),
+        (
+            "reason".into(),
+            optional(
+                last_state
+                    .as_ref()
+                    .and_then(|state| state.reason.clone())
+                    .map(Dynamic::from),
+            ),
+        ),
+        ("labels".into(), labels.into()),
+        (
+            "channel".into(),
+            optional(route.as_ref().map(|(channel, _)| channel.clone().into())),
+        ),
+    ]);
+    match script.route(restart) {
+        Ok(ScriptRoute::Default) => route,
+        Ok(ScriptRoute::Disabled) => None,
+        Ok(ScriptRoute::Channel { channel, severity }) => {
+            let mut options = route.map(|(_, options)| options).unwrap_or_default();
+            if severity.is_some() {
+                options.severity = severity;
             }
+            Some((channel, options))
         }
-        // Pod `p` did not exist until this event
-        Entry::Vacant(entry) => {
-            log::info!("New pod created: {}", PodDisplay(p));
-            entry.insert(restarts_in_pod(p));
+        Err(e) => {
+            log::warn!("{e}, routing by the patterns: {}", PodDisplay(p));
+            route
         }
     }
+}
 
-    Ok(())
+/// Containers whose restart notifications wait for the coalescing window.
+/// Key: `<Pod UID>/<container name>`
+#[derive(Debug, Default)]
+struct PendingRestarts(Mutex<HashSet<String>>);
+
+impl PendingRestarts {
+    /// Returns false if a restart of the container is already pending.
+    fn insert(&self, key: String) -> bool {
+        self.0.lock().unwrap().insert(key)
+    }
+
+    fn remove(&self, key: &str) {
+        self.0.lock().unwrap().remove(key);
+    }
+}
+
+/// Last notification of each container with the `cooldown` option.
+/// Key: `<Pod UID>/<container name>`
+#[derive(Debug, Default)]
+struct Cooldowns(Mutex<HashMap<String, Cooldown>>);
+
+#[derive(Debug)]
+struct Cooldown {
+    until: Instant,
+    /// Restarts not notified until `until`
+    suppressed: usize,
+}
+
+impl Cooldowns {
+    /// Starts the cooldown of the container and returns the number of restarts suppressed
+    /// in the previous one, or `None` when the container is in the cooldown.
+    fn admit(&self, key: &str, cooldown: Duration, now: Instant) -> Option<usize> {
+        let mut cooldowns = self.0.lock().unwrap();
+        cooldowns.retain(|_, c| now.saturating_duration_since(c.until) < COOLDOWN_RETENTION);
+        match cooldowns.get_mut(key) {
+            Some(c) if now < c.until => {
+                c.suppressed += 1;
+                None
+            }
+            _ => {
+                let previous = cooldowns.insert(
+                    key.to_owned(),
+                    Cooldown {
+                        until: now + cooldown,
+                        suppressed: 0,
+                    },
+                );
+                Some(previous.map_or(0, |c| c.suppressed))
+            }
+        }
+    }
 }
 
 fn is_skipped_interval(restart_count: i32) -> bool {
@@ -169,6 +1258,27 @@ fn containers(p: &Pod) -> impl Iterator<Item = &ContainerStatus> {
         .flat_map(|st| st.container_statuses.iter().flatten())
 }
 
+/// Returns the name of the workload owning Pod `p`, or the pod name for standalone pods.
+/// ReplicaSets created by Deployments are resolved to the Deployment name.
+fn workload_name(p: &Pod) -> String {
+    let Some(owner) = p
+        .owner_references()
+        .iter()
+        .find(|o| o.controller == Some(true))
+    else {
+        return p.name_any();
+    };
+    let template_hash = p.labels().get("pod-template-hash");
+    match template_hash {
+        Some(hash) if owner.kind == "ReplicaSet" => owner
+            .name
+            .strip_suffix(&format!("-{hash}"))
+            .unwrap_or(&owner.name)
+            .to_owned(),
+        _ => owner.name.clone(),
+    }
+}
+
 /// Helper struct to display Pod by namespace and name
 struct PodDisplay<'a>(&'a Pod);
 
@@ -180,40 +1290,427 @@ impl<'a> Display for PodDisplay<'a> {
 
 /// Describes status and logs of Container `container` in Pod `p`.
 async fn describe_container_status(
-    client: Client,
+    ctx: &WatchContext,
     p: &Pod,
     container: &ContainerStatus,
     channel: &str,
+    options: &NotificationOptions,
 ) -> message::ContainerRestartInfo {
-    let pods_ns: Api<Pod> = Api::namespaced(client, p.namespace().as_ref().unwrap());
-    let logs = tokio::time::timeout(
-        std::time::Duration::from_secs(10),
-        pods_ns.logs(
-            &p.name_any(),
-            &LogParams {
-                container: Some(container.name.clone()),
-                previous: true,
-                tail_lines: Some(LOG_LINES),
-                ..Default::default()
-            },
-        ),
-    )
-    .await;
+    let rollout_note = ctx
+        .rollouts
+        .as_ref()
+        .and_then(|rollouts| rollouts.crashed(p, &workload_name(p), container));
+    let sibling_note = sibling_impact(&ctx.pod_store, p);
+    let Some(client) = &ctx.client else {
+        // Replayed events have no cluster to read logs from
+        let logs = Err("Logs are not available in replays".to_owned());
+        let mut info = restart_info(p, None, container, logs, channel, options);
+        info.notes.extend(rollout_note);
+        info.notes.extend(sibling_note);
+        return info;
+    };
+    let logs = fetch_logs(ctx, client, p, container)
+        .instrument(tracing::info_span!("fetch_logs"))
+        .await;
     log::debug!("Fetched container logs: {logs:?}");
-    let logs = logs
-        .map_err(|_| "timeout elapsed".to_owned())
-        .and_then(|res| res.map_err(|err| err.to_string()));
+    let sidecar_logs = fetch_sidecar_logs(ctx, client, p, container, &options.sidecar_logs)
+        .instrument(tracing::info_span!("fetch_sidecar_logs"))
+        .await;
+    // Watched Pods are pruned, so resources and labels are read from the full Pod
+    let pods_ns: Api<Pod> = Api::namespaced(client.clone(), p.namespace().as_ref().unwrap());
+    let full = match pods_ns.get(&p.name_any()).await {
+        Ok(full) if full.uid() == p.uid() => Some(full),
+        Ok(_) => None,
+        Err(e) => {
+            log::warn!("Failed to get pod {}: {e}", PodDisplay(p));
+            None
+        }
+    };
+    let mut info = restart_info(p, full.as_ref(), container, logs, channel, options);
+    info.notes.extend(rollout_note);
+    info.notes.extend(sibling_note);
+    info.sidecar_logs = sidecar_logs;
+    if let Some((node_infos, node_name)) = ctx.node_infos.as_ref().zip(info.node_name.clone()) {
+        info.node_info = node_infos.get(client, &node_name).await;
+    }
+    if let Some(full) = full.as_ref().filter(|_| options.pod_spec) {
+        info.attachments.push(pod_attachments::pod_spec(full));
+    }
+    if let Some(full) = full.as_ref().filter(|_| options.describe) {
+        let events = fetch_pod_events(client, full).await;
+        let report = pod_attachments::describe(full, events.as_deref(), chrono::Utc::now());
+        info.attachments.push(report);
+    }
+    info
+}
+
+/// Events of the pod `p`, `None` when they failed to be read
+async fn fetch_pod_events(client: &Client, p: &Pod) -> Option<Vec<Event>> {
+    let events: Api<Event> = Api::namespaced(client.clone(), &p.namespace()?);
+    let params = ListParams::default().fields(&format!("involvedObject.uid={}", p.uid()?));
+    match events.list(&params).await {
+        Ok(list) => Some(list.items),
+        Err(e) => {
+            log::warn!("Failed to list events of pod {}: {e}", PodDisplay(p));
+            None
+        }
+    }
+}
+
+/// Cache of `status.nodeInfo` of Nodes by name, not to read the Node on every restart.
+/// Failures are cached as well not to repeat warnings, e.g. without permissions.
+#[derive(Debug, Default)]
+struct NodeInfos(Mutex<HashMap<String, (Instant, Option<message::NodeInfo>)>>);
+
+impl NodeInfos {
+    /// Info of the node `name`, read if not cached within `NODE_INFO_TTL`
+    async fn get(&self, client: &Client, name: &str) -> Option<message::NodeInfo> {
+        let now = Instant::now();
+        if let Some((read_at, info)) = self.0.lock().unwrap().get(name) {
+            if now.duration_since(*read_at) < NODE_INFO_TTL {
+                return info.clone();
+            }
+        }
+        let info = fetch_node_info(client, name).await;
+        let mut cache = self.0.lock().unwrap();
+        cache.retain(|_, (read_at, _)| now.duration_since(*read_at) < NODE_INFO_TTL);
+        cache.insert(name.to_owned(), (now, info.clone()));
+        info
+    }
+}
+
+/// `status.nodeInfo` of the Node `name`, `None` when it failed to be read
+async fn fetch_node_info(client: &Client, name: &str) -> Option<message::NodeInfo> {
+    let nodes: Api<Node> = Api::all(client.clone());
+    let node = match nodes.get(name).await {
+        Ok(node) => node,
+        Err(e) => {
+            log::warn!("Failed to get node {name}: {e}");
+            return None;
+        }
+    };
+    let info = node.status?.node_info?;
+    Some(message::NodeInfo {
+        os_image: info.os_image,
+        container_runtime_version: info.container_runtime_version,
+        kubelet_version: info.kubelet_version,
+    })
+}
+
+/// How many pods of the workload of `p` in `pod_store` are crash-looping or not ready, e.g.
+/// `3 of 5 pods of web are crash-looping or not ready`, to tell a single pod from an outage.
+/// `None` for standalone pods and workloads of a single pod.
+fn sibling_impact(pod_store: &Store<Pod>, p: &Pod) -> Option<String> {
+    p.owner_references()
+        .iter()
+        .find(|o| o.controller == Some(true))?;
+    let workload = workload_name(p);
+    let siblings = Cell::new(0);
+    let affected = Cell::new(0);
+    // Counted while finding no pod, not to clone the pods of the whole cluster
+    let _ = pod_store.find(|sibling| {
+        let is_sibling = sibling.metadata.namespace == p.metadata.namespace
+            && workload_name(sibling) == workload
+            // Completed pods, e.g. of Jobs, are not replicas
+            && containers(sibling)
+                .any(|c| c.state.as_ref().is_some_and(|s| s.terminated.is_none()));
+        if is_sibling {
+            siblings.set(siblings.get() + 1);
+            if sibling.metadata.uid == p.metadata.uid || containers(sibling).any(is_unhealthy) {
+                affected.set(affected.get() + 1);
+            }
+        }
+        false
+    });
+    if siblings.get() <= 1 {
+        return None;
+    }
+    Some(format!(
+        ":busts_in_silhouette: {} of {} pods of `{workload}` are crash-looping or not ready",
+        affected.get(),
+        siblings.get()
+    ))
+}
+
+/// Waiting after a crash, or running but not ready
+fn is_unhealthy(container: &ContainerStatus) -> bool {
+    let Some(state) = &container.state else {
+        return false;
+    };
+    match &state.waiting {
+        Some(waiting) => !matches!(
+            waiting.reason.as_deref(),
+            Some("ContainerCreating" | "PodInitializing")
+        ),
+        None => state.running.is_some() && !container.ready,
+    }
+}
+
+/// Notification of the restart of `container` in Pod `p`.
+/// Resources and labels are read from `full`, the Pod without pruning, if available.
+fn restart_info(
+    p: &Pod,
+    full: Option<&Pod>,
+    container: &ContainerStatus,
+    logs: Result<String, String>,
+    channel: &str,
+    options: &NotificationOptions,
+) -> message::ContainerRestartInfo {
+    let last_state = get_last_state(container);
+    let category = category::classify(
+        last_state.as_ref(),
+        logs.as_deref().ok(),
+        full.is_some_and(|full| has_liveness_probe(full, container)),
+    );
     message::ContainerRestartInfo {
         namespace: p.namespace(),
         pod_name: p.name_any(),
+        pod_uid: p.uid(),
         container_name: container.name.clone(),
         container_image: container.image.clone(),
         node_name: p.spec.as_ref().and_then(|s| s.node_name.clone()),
+        workload: Some(workload_name(p)),
+        labels: full.unwrap_or(p).labels().clone(),
         restart_count: container.restart_count,
-        last_state: get_last_state(container),
-        resources: get_resources(p, container).unwrap_or_default(),
+        last_state,
+        resources: full
+            .and_then(|full| get_resources(full, container))
+            .unwrap_or_default(),
         logs: message::ContainerLog(logs),
         channel: channel.to_owned(),
+        options: options.clone(),
+        links: Vec::new(),
+        notes: Vec::new(),
+        category,
+        sidecar_logs: Vec::new(),
+        priority: pod_priority(full.unwrap_or(p)),
+        node_info: None,
+        attachments: Vec::new(),
+        span: tracing::Span::current(),
+        queue_id: None,
+    }
+}
+
+/// Priority of the pod, `None` when it has neither a priority class nor a priority.
+/// Preemption is told by the `DisruptionTarget` condition set by the scheduler or
+/// the `Preempting` reason set by the kubelet for critical pods.
+fn pod_priority(p: &Pod) -> Option<message::PodPriority> {
+    let spec = p.spec.as_ref();
+    let status = p.status.as_ref();
+    let preempted = status.is_some_and(|status| {
+        status.reason.as_deref() == Some("Preempting")
+            || status.conditions.iter().flatten().any(|c| {
+                c.type_ == "DisruptionTarget"
+                    && c.status == "True"
+                    && c.reason
+                        .as_deref()
+                        .is_some_and(|r| r.starts_with("Preemption"))
+            })
+    });
+    let priority = message::PodPriority {
+        class_name: spec.and_then(|spec| spec.priority_class_name.clone()),
+        value: spec.and_then(|spec| spec.priority),
+        preempted,
+    };
+    Some(priority).filter(|priority| *priority != message::PodPriority::default())
+}
+
+/// Fetches logs of `container` before the restart.
+/// Retries once on timeout, 404, empty logs or missing previous container,
+/// since logs may not be ready right after the crash.
+async fn fetch_logs(
+    ctx: &WatchContext,
+    client: &Client,
+    p: &Pod,
+    container: &ContainerStatus,
+) -> Result<String, String> {
+    let pods_ns: Api<Pod> = Api::namespaced(client.clone(), p.namespace().as_ref().unwrap());
+    let params = LogParams {
+        container: Some(container.name.clone()),
+        previous: true,
+        tail_lines: Some(ctx.log_tail_lines),
+        ..Default::default()
+    };
+    let mut retried = false;
+    loop {
+        let result = ctx
+            .read_logs_limited(&pods_ns, &p.name_any(), &params)
+            .await;
+        let (error, retry_delay) = match result {
+            Ok(Ok(logs)) if logs.is_empty() && !retried => {
+                log::info!("Container logs are empty, retrying");
+                tokio::time::sleep(LOG_NOT_READY_RETRY_DELAY).await;
+                retried = true;
+                continue;
+            }
+            Ok(Ok(logs)) => return Ok(logs),
+            Ok(Err(e)) => (describe_log_error(&e), log_retry_delay(&e)),
+            Err(_) => (
+                format!(
+                    "Timed out after {} seconds",
+                    ctx.log_fetch_timeout.as_secs_f64()
+                ),
+                Some(LOG_FETCH_RETRY_DELAY),
+            ),
+        };
+        let Some(retry_delay) = retry_delay.filter(|_| !retried) else {
+            return Err(if retried {
+                format!("{error} (retried once)")
+            } else {
+                error
+            });
+        };
+        log::warn!("Failed to fetch container logs, retrying: {error}");
+        tokio::time::sleep(retry_delay).await;
+        retried = true;
+    }
+}
+
+/// Fetches current logs of `sidecars` in Pod `p`, which often show the cause of crashes
+/// of `container`, e.g. connection errors in the service mesh proxy.
+/// Sidecars not in the pod and `container` itself are skipped.
+async fn fetch_sidecar_logs(
+    ctx: &WatchContext,
+    client: &Client,
+    p: &Pod,
+    container: &ContainerStatus,
+    sidecars: &[String],
+) -> Vec<message::SidecarLog> {
+    let pods_ns: Api<Pod> = Api::namespaced(client.clone(), p.namespace().as_ref().unwrap());
+    let mut sidecar_logs = Vec::new();
+    for sidecar in sidecars {
+        if *sidecar == container.name || !containers(p).any(|c| c.name == *sidecar) {
+            continue;
+        }
+        let params = LogParams {
+            container: Some(sidecar.clone()),
+            tail_lines: Some(ctx.log_tail_lines),
+            ..Default::default()
+        };
+        let logs = match ctx
+            .read_logs_limited(&pods_ns, &p.name_any(), &params)
+            .await
+        {
+            Ok(Ok(logs)) => Ok(logs),
+            Ok(Err(e)) => Err(describe_log_error(&e)),
+            Err(_) => Err(format!(
+                "Timed out after {} seconds",
+                ctx.log_fetch_timeout.as_secs_f64()
+            )),
+        };
+        if let Err(e) = &logs {
+            log::warn!("Failed to fetch logs of sidecar {sidecar}: {e}");
+        }
+        sidecar_logs.push(message::SidecarLog {
+            container: sidecar.clone(),
+            logs: message::ContainerLog(logs),
+        });
+    }
+    sidecar_logs
+}
+
+/// Streams container logs keeping only the last `max_bytes` bytes,
+/// so that huge logs do not have to be buffered as a whole.
+async fn read_logs(
+    pods_ns: &Api<Pod>,
+    name: &str,
+    params: &LogParams,
+    max_bytes: usize,
+) -> kube::Result<String> {
+    let mut stream = pods_ns.log_stream(name, params).await?;
+    let mut logs = LogBuffer::new(max_bytes);
+    while let Some(chunk) = stream.try_next().await? {
+        logs.push(&chunk);
+    }
+    Ok(logs.into_string())
+}
+
+/// Buffer holding the tail of streamed logs up to `max_bytes` bytes
+#[derive(Debug)]
+struct LogBuffer {
+    buf: Vec<u8>,
+    max_bytes: usize,
+    truncated: bool,
+    /// Whether the buffer starts in the middle of a line
+    partial_line: bool,
+}
+
+impl LogBuffer {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_bytes,
+            truncated: false,
+            partial_line: false,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+        // Discard old bytes in batches to avoid moving the buffer on every chunk
+        if self.buf.len() > self.max_bytes * 2 {
+            self.discard_head();
+        }
+    }
+
+    fn discard_head(&mut self) {
+        if self.buf.len() > self.max_bytes {
+            let start = self.buf.len() - self.max_bytes;
+            self.partial_line = self.buf[start - 1] != b'\n';
+            self.buf.drain(..start);
+            self.truncated = true;
+        }
+    }
+
+    fn into_string(mut self) -> String {
+        self.discard_head();
+        if !self.truncated {
+            return String::from_utf8_lossy(&self.buf).into_owned();
+        }
+        let start = if self.partial_line {
+            self.buf
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(0, |i| i + 1)
+        } else {
+            0
+        };
+        format!(
+            "[Logs before the last {} bytes are truncated]\n{}",
+            self.max_bytes,
+            String::from_utf8_lossy(&self.buf[start..])
+        )
+    }
+}
+
+/// Returns the wait time before retrying to fetch logs, or `None` if `error` is not retryable.
+fn log_retry_delay(error: &kube::Error) -> Option<Duration> {
+    match error {
+        kube::Error::Api(e) if e.code == 404 => Some(LOG_FETCH_RETRY_DELAY),
+        // kubelet may not have finalized the log file of the terminated container yet
+        kube::Error::Api(e)
+            if e.code == 400 && e.message.contains("previous terminated container") =>
+        {
+            Some(LOG_NOT_READY_RETRY_DELAY)
+        }
+        _ => None,
+    }
+}
+
+/// Describes the cause of failure to fetch container logs for the notification.
+fn describe_log_error(error: &kube::Error) -> String {
+    match error {
+        kube::Error::Api(e) => {
+            let cause = match e.code {
+                401 | 403 => "Permission denied",
+                404 => "Logs not found",
+                429 => "Rate limited",
+                500.. => "Kubernetes API server error",
+                _ => "Kubernetes API error",
+            };
+            format!("{cause} ({} {}): {}", e.code, e.reason, e.message)
+        }
+        e => format!("Failed to connect to Kubernetes API: {e}"),
     }
 }
 
@@ -229,6 +1726,14 @@ fn get_last_state(container: &ContainerStatus) -> Option<message::ContainerState
     })
 }
 
+fn has_liveness_probe(p: &Pod, container: &ContainerStatus) -> bool {
+    p.spec.as_ref().is_some_and(|spec| {
+        spec.containers
+            .iter()
+            .any(|c| c.name == container.name && c.liveness_probe.is_some())
+    })
+}
+
 fn get_resources(p: &Pod, container: &ContainerStatus) -> Option<message::ContainerResources> {
     let resources = p
         .spec
@@ -249,7 +1754,7 @@ fn get_resources(p: &Pod, container: &ContainerStatus) -> Option<message::Contai
 }
 
 /// Rule to control notification destination.
-/// `namespace/pod/container=channel` format.
+/// `namespace/pod/container=channel[;option...]` format.
 /// namespace, pod and container name can include `*` wildcard.
 /// Notification is disabled when channel is empty.
 #[derive(Debug, Clone, PartialEq)]
@@ -259,6 +1764,7 @@ struct NotificationRule {
     container: WildMatch,
     /// `None` disables notification
     channel: Option<String>,
+    options: NotificationOptions,
 }
 
 impl std::str::FromStr for NotificationRule {
@@ -272,6 +1778,7 @@ impl std::str::FromStr for NotificationRule {
             Some((namespace, pod, container, channel))
         })()
         .with_context(|| format!("Invalid notification rule: {}", s))?;
+        let (channel, options) = channel.split_once(';').unwrap_or((channel, ""));
         let channel = if channel.is_empty() {
             None
         } else {
@@ -282,16 +1789,247 @@ impl std::str::FromStr for NotificationRule {
             pod: WildMatch::new(pod),
             container: WildMatch::new(container),
             channel,
+            options: options
+                .parse()
+                .with_context(|| format!("Invalid notification rule: {}", s))?,
         })
     }
 }
 
 impl NotificationRule {
-    fn find_channel(&self, namespace: &str, pod: &str, container: &str) -> Option<Option<&str>> {
-        (self.namespace.matches(namespace)
+    fn matches(&self, namespace: &str, pod: &str, container: &str) -> bool {
+        self.namespace.matches(namespace)
             && self.pod.matches(pod)
-            && self.container.matches(container))
-        .then_some(self.channel.as_deref())
+            && self.container.matches(container)
+    }
+
+    /// `namespace/pod/container` format
+    fn pattern(&self) -> String {
+        format!("{}/{}/{}", self.namespace, self.pod, self.container)
+    }
+}
+
+/// Options of `NotificationRule` delimited by semicolons.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationOptions {
+    /// Post container logs in the thread of the main message instead of inline
+    pub thread_logs: bool,
+    /// Update the previous message of the same container instead of posting a new one
+    pub update: bool,
+    /// Upload container logs as gzip-compressed files
+    pub gzip_logs: bool,
+    /// Handle of Slack user group to mention, without `@`
+    pub mention: Option<String>,
+    /// Severity shown in the message, e.g. `critical`
+    pub severity: Option<String>,
+    /// Restarts beyond this count are escalated
+    pub escalate_after: Option<u32>,
+    /// Channel additionally notified of escalated restarts
+    pub escalate_channel: Option<String>,
+    /// Handle of Slack user group to mention on escalated restarts, without `@`
+    pub escalate_mention: Option<String>,
+    /// The rule only matches crashes of these categories when not empty
+    #[serde(default)]
+    pub categories: Vec<CrashCategory>,
+    /// At most one notification per container in the period, instead of skipping
+    /// restarts by their count
+    #[serde(default)]
+    pub cooldown: Option<Duration>,
+    /// Sibling containers whose current logs are included, e.g. `istio-proxy`
+    #[serde(default)]
+    pub sidecar_logs: Vec<String>,
+    /// Number of lines of logs of the new container to post in the thread a while after
+    /// the restart
+    #[serde(default)]
+    pub startup_logs: Option<usize>,
+    /// Post one message per workload updated on restarts of any of its pods, with the
+    /// notifications of each restart in the thread
+    #[serde(default)]
+    pub aggregate: bool,
+    /// `MessageLayout::Compact` for high-volume channels
+    #[serde(default)]
+    pub layout: message::MessageLayout,
+    /// Name of the template in `MESSAGE_TEMPLATES_PATH` to lay out messages with
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Upload the pod spec with values of environment variables redacted
+    #[serde(default)]
+    pub pod_spec: bool,
+    /// Upload a `kubectl describe pod`-style report with the Events of the pod
+    #[serde(default)]
+    pub describe: bool,
+}
+
+impl NotificationOptions {
+    /// Whether the restart count of the container exceeds `escalate_after`
+    pub fn escalated(&self, restart_count: i32) -> bool {
+        self.escalate_after
+            .is_some_and(|after| i64::from(restart_count) > i64::from(after))
+    }
+
+    /// Whether the crash of `category` matches the categories of the rule
+    pub fn matches_category(&self, category: Option<CrashCategory>) -> bool {
+        self.categories.is_empty() || category.is_some_and(|c| self.categories.contains(&c))
+    }
+
+    /// Handles of user groups to mention in the message to the routed channel.
+    /// `escalate_mention` is mentioned there when escalated without `escalate_channel`.
+    pub fn mentions(&self, restart_count: i32) -> Vec<&str> {
+        let escalate_mention = self
+            .escalate_mention
+            .as_deref()
+            .filter(|_| self.escalate_channel.is_none() && self.escalated(restart_count));
+        self.mention
+            .as_deref()
+            .into_iter()
+            .chain(escalate_mention)
+            .collect()
+    }
+}
+
+impl std::str::FromStr for NotificationOptions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = Self::default();
+        for option in s.split(';').filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                None if option == "thread_logs" => options.thread_logs = true,
+                None if option == "update" => options.update = true,
+                None if option == "gzip_logs" => options.gzip_logs = true,
+                None if option == "aggregate" => options.aggregate = true,
+                None if option == "pod_spec" => options.pod_spec = true,
+                None if option == "describe" => options.describe = true,
+                Some(("mention", handle)) if !handle.is_empty() => {
+                    options.mention = Some(handle.trim_start_matches('@').to_owned())
+                }
+                Some(("severity", severity)) if !severity.is_empty() => {
+                    options.severity = Some(severity.to_owned())
+                }
+                Some(("escalate_after", after)) => {
+                    options.escalate_after = Some(
+                        after
+                            .parse()
+                            .with_context(|| format!("Invalid escalate_after: {after}"))?,
+                    )
+                }
+                Some(("escalate_channel", channel)) if !channel.is_empty() => {
+                    options.escalate_channel = Some(channel.to_owned())
+                }
+                Some(("escalate_mention", handle)) if !handle.is_empty() => {
+                    options.escalate_mention = Some(handle.trim_start_matches('@').to_owned())
+                }
+                Some(("cooldown", cooldown)) => {
+                    options.cooldown = Some(
+                        silence::parse_duration(cooldown)
+                            .map_err(|e| anyhow::anyhow!("Invalid cooldown: {e}"))?
+                            .to_std()?,
+                    )
+                }
+                Some(("category", categories)) => {
+                    options.categories = categories
+                        .split('|')
+                        .map(str::parse)
+                        .collect::<anyhow::Result<_>>()?
+                }
+                Some(("sidecar_logs", containers)) if !containers.is_empty() => {
+                    options.sidecar_logs = containers.split('|').map(str::to_owned).collect()
+                }
+                Some(("template", name)) => options.template = Some(name.to_owned()),
+                Some(("layout", layout)) => {
+                    options.layout = layout
+                        .parse()
+                        .with_context(|| format!("Invalid layout: {layout}"))?
+                }
+                Some(("startup_logs", lines)) => {
+                    options.startup_logs = Some(
+                        lines
+                            .parse()
+                            .with_context(|| format!("Invalid startup_logs: {lines}"))?,
+                    )
+                }
+                _ => bail!("Unknown notification option: {}", option),
+            }
+        }
+        let escalates_to = options.escalate_channel.is_some() || options.escalate_mention.is_some();
+        match (options.escalate_after, escalates_to) {
+            (Some(_), false) => {
+                bail!("escalate_after requires escalate_channel or escalate_mention")
+            }
+            (None, true) => bail!("escalate_channel and escalate_mention require escalate_after"),
+            _ => {}
+        }
+        Ok(options)
+    }
+}
+
+/// Same format as parsed, e.g. `thread_logs;mention=sre`
+impl Display for NotificationOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut options = Vec::new();
+        for (enabled, name) in [
+            (self.thread_logs, "thread_logs"),
+            (self.update, "update"),
+            (self.gzip_logs, "gzip_logs"),
+            (self.aggregate, "aggregate"),
+            (self.pod_spec, "pod_spec"),
+            (self.describe, "describe"),
+        ] {
+            if enabled {
+                options.push(name.to_owned());
+            }
+        }
+        if let Some(mention) = &self.mention {
+            options.push(format!("mention={mention}"));
+        }
+        if let Some(severity) = &self.severity {
+            options.push(format!("severity={severity}"));
+        }
+        if let Some(after) = self.escalate_after {
+            options.push(format!("escalate_after={after}"));
+        }
+        if let Some(channel) = &self.escalate_channel {
+            options.push(format!("escalate_channel={channel}"));
+        }
+        if let Some(mention) = &self.escalate_mention {
+            options.push(format!("escalate_mention={mention}"));
+        }
+        if let Some(cooldown) = self.cooldown {
+            options.push(format!("cooldown={}", format_option_duration(cooldown)));
+        }
+        if !self.categories.is_empty() {
+            let categories = self
+                .categories
+                .iter()
+                .map(|c| c.as_str())
+                .collect::<Vec<_>>();
+            options.push(format!("category={}", categories.join("|")));
+        }
+        if !self.sidecar_logs.is_empty() {
+            options.push(format!("sidecar_logs={}", self.sidecar_logs.join("|")));
+        }
+        if let Some(lines) = self.startup_logs {
+            options.push(format!("startup_logs={lines}"));
+        }
+        if self.layout != message::MessageLayout::default() {
+            options.push(format!("layout={}", self.layout));
+        }
+        if let Some(name) = &self.template {
+            options.push(format!("template={name}"));
+        }
+        write!(f, "{}", options.join(";"))
+    }
+}
+
+/// Formats `duration` in the largest unit parsed by `silence::parse_duration`, e.g. `15m`
+fn format_option_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match [(86400, "d"), (3600, "h"), (60, "m")]
+        .into_iter()
+        .find(|(unit, _)| secs / unit * unit == secs)
+    {
+        Some((unit, suffix)) => format!("{}{suffix}", secs / unit),
+        None => format!("{secs}s"),
     }
 }
 
@@ -299,7 +2037,7 @@ impl NotificationRule {
 /// `namespace/pod/container=channel,namespace/pod/container=channel,...` format.
 /// Earlier rules have higher priority.
 #[derive(Debug, Default, Clone, PartialEq)]
-struct NotificationConfig(Vec<NotificationRule>);
+pub struct NotificationConfig(Vec<NotificationRule>);
 
 impl std::str::FromStr for NotificationConfig {
     type Err = anyhow::Error;
@@ -313,18 +2051,334 @@ impl std::str::FromStr for NotificationConfig {
 }
 
 impl NotificationConfig {
-    fn find_channel(&self, namespace: &str, pod: &str, container: &str) -> Option<&str> {
+    pub fn builder() -> RouterBuilder {
+        RouterBuilder::default()
+    }
+
+    /// Returns the channel and options of the first rule matching the container.
+    /// `None` when no rule matches or notification is disabled.
+    /// Rules of crash categories match any crash, since it is classified with its logs.
+    pub fn find_route(
+        &self,
+        namespace: &str,
+        pod: &str,
+        container: &str,
+    ) -> Option<(&str, &NotificationOptions)> {
+        let rule = self.find_rule(namespace, pod, container)?;
+        Some((rule.channel?, rule.options))
+    }
+
+    /// Returns the channel and options of the first rule matching the container and
+    /// the category of its crash, skipping rules of other categories.
+    pub fn find_category_route(
+        &self,
+        namespace: &str,
+        pod: &str,
+        container: &str,
+        category: Option<CrashCategory>,
+    ) -> Option<(&str, &NotificationOptions)> {
+        let rule = self.0.iter().find(|rule| {
+            rule.matches(namespace, pod, container) && rule.options.matches_category(category)
+        })?;
+        Some((rule.channel.as_deref()?, &rule.options))
+    }
+
+    /// Returns the first rule matching the container, including rules disabling notification.
+    pub fn find_rule(&self, namespace: &str, pod: &str, container: &str) -> Option<RuleMatch<'_>> {
+        let (i, rule) = self
+            .0
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(namespace, pod, container))?;
+        Some(RuleMatch {
+            priority: i + 1,
+            pattern: rule.pattern(),
+            channel: rule.channel.as_deref(),
+            options: &rule.options,
+        })
+    }
+
+    /// Rules in priority order, to inspect the effective routing at runtime
+    pub fn to_json(&self) -> serde_json::Value {
+        self.0
+            .iter()
+            .map(|rule| {
+                json!({
+                    "pattern": rule.pattern(),
+                    "channel": rule.channel,
+                    "options": rule.options,
+                })
+            })
+            .collect()
+    }
+
+    /// Rules in priority order as a table of pattern, channel and options for auditing
+    pub fn to_table(&self) -> String {
+        let mut rows = vec![[
+            "PRIORITY".to_owned(),
+            "PATTERN".to_owned(),
+            "CHANNEL".to_owned(),
+            "OPTIONS".to_owned(),
+        ]];
+        for (i, rule) in self.0.iter().enumerate() {
+            rows.push([
+                (i + 1).to_string(),
+                rule.pattern(),
+                rule.channel
+                    .clone()
+                    .unwrap_or_else(|| "(disabled)".to_owned()),
+                rule.options.to_string(),
+            ]);
+        }
+        let widths = (0..4)
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        rows.iter()
+            .map(|row| {
+                let line = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                format!("{}\n", line.trim_end())
+            })
+            .collect()
+    }
+
+    /// Names of the templates selected by the rules
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
         self.0
             .iter()
-            .find_map(|rule| rule.find_channel(namespace, pod, container))
-            .flatten()
+            .filter_map(|rule| rule.options.template.as_deref())
+    }
+
+    /// Number of the rules, including those disabling notification
+    pub fn rule_count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Channels of the rules and their escalations
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().flat_map(|rule| {
+            let escalate_channel = rule.options.escalate_channel.as_deref();
+            rule.channel.as_deref().into_iter().chain(escalate_channel)
+        })
+    }
+
+    /// Whether any rule uploads describe reports, which read Events of pods
+    pub fn has_describe(&self) -> bool {
+        self.0.iter().any(|rule| rule.options.describe)
+    }
+
+    /// Whether any rule mentions Slack user groups
+    pub fn has_mentions(&self) -> bool {
+        self.0.iter().any(|rule| rule.options.mention.is_some())
+    }
+}
+
+/// Rule of `NotificationConfig` matching a container
+#[derive(Debug, PartialEq)]
+pub struct RuleMatch<'a> {
+    /// 1-based position of the rule, where earlier rules have higher priority
+    pub priority: usize,
+    /// `namespace/pod/container` format
+    pub pattern: String,
+    /// `None` when the rule disables notification
+    pub channel: Option<&'a str>,
+    pub options: &'a NotificationOptions,
+}
+
+/// Builder of `NotificationConfig` to embed johari-mirror as a library.
+/// Names can include `*` wildcard, and earlier rules have higher priority.
+#[derive(Debug, Default)]
+pub struct RouterBuilder {
+    rules: Vec<NotificationRule>,
+}
+
+impl RouterBuilder {
+    /// Routes restarts of matching containers to `channel`.
+    pub fn route(self, namespace: &str, pod: &str, container: &str, channel: &str) -> Self {
+        self.route_with_options(
+            namespace,
+            pod,
+            container,
+            channel,
+            NotificationOptions::default(),
+        )
+    }
+
+    pub fn route_with_options(
+        self,
+        namespace: &str,
+        pod: &str,
+        container: &str,
+        channel: &str,
+        options: NotificationOptions,
+    ) -> Self {
+        self.rule(namespace, pod, container, Some(channel.to_owned()), options)
+    }
+
+    /// Disables notifications of matching containers.
+    pub fn ignore(self, namespace: &str, pod: &str, container: &str) -> Self {
+        self.rule(
+            namespace,
+            pod,
+            container,
+            None,
+            NotificationOptions::default(),
+        )
+    }
+
+    fn rule(
+        mut self,
+        namespace: &str,
+        pod: &str,
+        container: &str,
+        channel: Option<String>,
+        options: NotificationOptions,
+    ) -> Self {
+        self.rules.push(NotificationRule {
+            namespace: WildMatch::new(namespace),
+            pod: WildMatch::new(pod),
+            container: WildMatch::new(container),
+            channel,
+            options,
+        });
+        self
+    }
+
+    pub fn build(self) -> NotificationConfig {
+        NotificationConfig(self.rules)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use k8s_openapi::api::core::v1::PodCondition;
+
     use super::*;
 
+    #[test]
+    fn test_log_buffer() {
+        let mut logs = LogBuffer::new(16);
+        logs.push(b"line 1\n");
+        logs.push(b"line 2\n");
+        assert_eq!(logs.into_string(), "line 1\nline 2\n");
+
+        let mut logs = LogBuffer::new(16);
+        for i in 0..100 {
+            logs.push(format!("line {i}\n").as_bytes());
+            assert!(logs.buf.len() <= 32 + 8);
+        }
+        assert_eq!(
+            logs.into_string(),
+            "[Logs before the last 16 bytes are truncated]\nline 98\nline 99\n"
+        );
+
+        // The partial first line is dropped
+        let mut logs = LogBuffer::new(10);
+        logs.push(b"line 1\nline 2\n");
+        assert_eq!(
+            logs.into_string(),
+            "[Logs before the last 10 bytes are truncated]\nline 2\n"
+        );
+    }
+
+    #[test]
+    fn test_pod_restart_counts_load() {
+        let path = std::env::temp_dir().join(format!(
+            "johari-mirror-restart-counts-test-{}.json",
+            std::process::id()
+        ));
+        let counts = HashMap::from([(
+            "uid".to_owned(),
+            RestartCounts::from([("app".to_owned(), 3)]),
+        )]);
+        save_json(&path, &counts).unwrap();
+        assert_eq!(
+            PodRestartCounts::load(path.clone()).unwrap().snapshot(),
+            counts
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_pod_restart_counts_eviction() {
+        let counts = PodRestartCounts::default().with_max_pods(2);
+        for uid in ["a", "b", "c"] {
+            counts.update(|c| c.insert(uid.to_owned(), RestartCounts::new()));
+            counts.seen(uid);
+        }
+        let uids = |counts: &PodRestartCounts| {
+            let mut uids: Vec<_> = counts.snapshot().into_keys().collect();
+            uids.sort();
+            uids
+        };
+        assert_eq!(uids(&counts), ["b", "c"]);
+
+        // Pods are removed when missing twice in a row
+        let living = HashSet::from(["c".to_owned()]);
+        counts.retain_living(&living);
+        assert_eq!(uids(&counts), ["b", "c"]);
+        counts.retain_living(&living);
+        assert_eq!(uids(&counts), ["c"]);
+    }
+
+    #[test]
+    fn test_describe_log_error() {
+        let error = |code| {
+            kube::Error::Api(kube::error::ErrorResponse {
+                status: "Failure".to_owned(),
+                message: "message".to_owned(),
+                reason: "Reason".to_owned(),
+                code,
+            })
+        };
+        assert_eq!(
+            describe_log_error(&error(403)),
+            "Permission denied (403 Reason): message"
+        );
+        assert_eq!(
+            describe_log_error(&error(404)),
+            "Logs not found (404 Reason): message"
+        );
+        assert_eq!(
+            describe_log_error(&error(503)),
+            "Kubernetes API server error (503 Reason): message"
+        );
+    }
+
+    #[test]
+    fn test_log_retry_delay() {
+        let error = |code, message: &str| {
+            kube::Error::Api(kube::error::ErrorResponse {
+                status: "Failure".to_owned(),
+                message: message.to_owned(),
+                reason: "BadRequest".to_owned(),
+                code,
+            })
+        };
+        assert_eq!(
+            log_retry_delay(&error(404, "not found")),
+            Some(LOG_FETCH_RETRY_DELAY)
+        );
+        assert_eq!(
+            log_retry_delay(&error(
+                400,
+                "previous terminated container \"app\" in pod \"app-0\" not found"
+            )),
+            Some(LOG_NOT_READY_RETRY_DELAY)
+        );
+        assert_eq!(log_retry_delay(&error(400, "bad request")), None);
+        assert_eq!(log_retry_delay(&error(403, "forbidden")), None);
+    }
+
     #[test]
     fn test_is_skipped_interval() {
         for count in 1..11 {
@@ -336,6 +2390,206 @@ mod tests {
         assert!(!is_skipped_interval(34));
     }
 
+    #[test]
+    fn test_prune_pod() {
+        let pod = Pod {
+            metadata: ObjectMeta {
+                name: Some("web-5d4f8c7b9-x2x7k".to_owned()),
+                namespace: Some("default".to_owned()),
+                uid: Some("uid".to_owned()),
+                labels: Some(
+                    [
+                        ("app".to_owned(), "web".to_owned()),
+                        ("pod-template-hash".to_owned(), "5d4f8c7b9".to_owned()),
+                    ]
+                    .into(),
+                ),
+                annotations: Some([("note".to_owned(), "x".repeat(1000))].into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: Some("node".to_owned()),
+                containers: vec![Default::default()],
+                ..Default::default()
+            }),
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    name: "app".to_owned(),
+                    restart_count: 2,
+                    ..Default::default()
+                }]),
+                phase: Some("Running".to_owned()),
+                ..Default::default()
+            }),
+        };
+        let pruned = prune_pod(pod.clone(), false);
+        assert_eq!(pruned.uid(), pod.uid());
+        assert_eq!(pruned.labels().len(), 1);
+        assert_eq!(pruned.annotations().len(), 0);
+        let spec = pruned.spec.as_ref().unwrap();
+        assert!(spec.containers.is_empty());
+        assert_eq!(spec.node_name.as_deref(), Some("node"));
+        assert_eq!(restarts_in_pod(&pruned), restarts_in_pod(&pod));
+    }
+
+    #[test]
+    fn test_pod_priority() {
+        let mut pod = Pod::default();
+        assert_eq!(pod_priority(&pod), None);
+        pod.spec = Some(PodSpec {
+            priority: Some(-100),
+            priority_class_name: Some("batch-low".to_owned()),
+            ..Default::default()
+        });
+        pod.status = Some(PodStatus {
+            conditions: Some(vec![PodCondition {
+                type_: "DisruptionTarget".to_owned(),
+                status: "True".to_owned(),
+                reason: Some("PreemptionByScheduler".to_owned()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let pruned = prune_pod(pod, false);
+        assert_eq!(
+            pod_priority(&pruned),
+            Some(message::PodPriority {
+                class_name: Some("batch-low".to_owned()),
+                value: Some(-100),
+                preempted: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_to_store() {
+        let pod = |namespace: &str, name: &str| Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_owned()),
+                namespace: Some(namespace.to_owned()),
+                uid: Some(format!("uid-{name}")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let restarted = |namespace: &str, pods: Vec<Pod>| ScopedEvent {
+            namespace: Some(namespace.to_owned()),
+            event: watcher::Event::Restarted(pods),
+        };
+        let (store, mut writer) = reflector::store();
+        let names = |store: &Store<Pod>| {
+            let mut names = store
+                .state()
+                .iter()
+                .map(|p| p.name_any())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let replaced = apply_to_store(&mut writer, &store, &restarted("a", vec![pod("a", "a-0")]));
+        assert!(replaced.is_empty());
+        apply_to_store(&mut writer, &store, &restarted("b", vec![pod("b", "b-0")]));
+        apply_to_store(
+            &mut writer,
+            &store,
+            &watcher::Event::Applied(pod("a", "a-1")).into(),
+        );
+        assert_eq!(names(&store), ["a-0", "a-1", "b-0"]);
+
+        // Pods in the other namespaces are kept
+        let e = restarted("a", vec![pod("a", "a-1"), pod("a", "a-2")]);
+        let replaced = apply_to_store(&mut writer, &store, &e);
+        assert_eq!(names(&store), ["a-1", "a-2", "b-0"]);
+        assert_eq!(
+            replaced,
+            ["uid-a-0".to_owned(), "uid-a-1".to_owned()].into()
+        );
+
+        // Restarted of all namespaces replaces all pods
+        let e = watcher::Event::Restarted(vec![pod("b", "b-1")]).into();
+        apply_to_store(&mut writer, &store, &e);
+        assert_eq!(names(&store), ["b-1"]);
+    }
+
+    #[test]
+    fn test_workload_name() {
+        use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+
+        let pod = |owner_kind: &str, owner_name: &str| Pod {
+            metadata: ObjectMeta {
+                name: Some("web-5d4f8c7b9-x2x7k".to_owned()),
+                labels: Some([("pod-template-hash".to_owned(), "5d4f8c7b9".to_owned())].into()),
+                owner_references: Some(vec![OwnerReference {
+                    kind: owner_kind.to_owned(),
+                    name: owner_name.to_owned(),
+                    controller: Some(true),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(workload_name(&pod("ReplicaSet", "web-5d4f8c7b9")), "web");
+        assert_eq!(workload_name(&pod("StatefulSet", "db")), "db");
+        assert_eq!(workload_name(&Pod::default()), "");
+    }
+
+    #[test]
+    fn test_sibling_impact() {
+        use k8s_openapi::{
+            api::core::v1::{ContainerState, ContainerStateRunning, ContainerStateWaiting},
+            apimachinery::pkg::apis::meta::v1::OwnerReference,
+        };
+
+        let pod = |name: &str, waiting: Option<&str>, ready: bool| Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_owned()),
+                namespace: Some("default".to_owned()),
+                uid: Some(name.to_owned()),
+                owner_references: Some(vec![OwnerReference {
+                    kind: "StatefulSet".to_owned(),
+                    name: "db".to_owned(),
+                    controller: Some(true),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    name: "db".to_owned(),
+                    ready,
+                    state: Some(ContainerState {
+                        waiting: waiting.map(|reason| ContainerStateWaiting {
+                            reason: Some(reason.to_owned()),
+                            message: None,
+                        }),
+                        running: waiting.is_none().then(ContainerStateRunning::default),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let pods = vec![
+            pod("db-0", Some("CrashLoopBackOff"), false),
+            pod("db-1", None, false),
+            pod("db-2", None, true),
+            pod("db-3", Some("ContainerCreating"), false),
+        ];
+        let (store, mut writer) = reflector::store();
+        writer.apply_watcher_event(&watcher::Event::Restarted(pods.clone()));
+        assert_eq!(
+            sibling_impact(&store, &pods[0]).as_deref(),
+            Some(":busts_in_silhouette: 2 of 4 pods of `db` are crash-looping or not ready")
+        );
+        assert_eq!(sibling_impact(&store, &Pod::default()), None);
+        let (store, mut writer) = reflector::store();
+        writer.apply_watcher_event(&watcher::Event::Restarted(pods[..1].to_vec()));
+        assert_eq!(sibling_impact(&store, &pods[0]), None);
+    }
+
     #[test]
     fn test_notification_rule_parse() {
         assert_eq!(
@@ -345,6 +2599,7 @@ mod tests {
                 pod: WildMatch::new("bar"),
                 container: WildMatch::new("baz"),
                 channel: Some("qux".to_owned()),
+                options: NotificationOptions::default(),
             }
         );
         assert_eq!(
@@ -354,8 +2609,82 @@ mod tests {
                 pod: WildMatch::new("bar"),
                 container: WildMatch::new("baz"),
                 channel: None,
+                options: NotificationOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_notification_rule_parse_options() {
+        assert_eq!(
+            "foo/bar/baz=qux;thread_logs"
+                .parse::<NotificationRule>()
+                .unwrap()
+                .options,
+            NotificationOptions {
+                thread_logs: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            "foo/bar/baz=qux;thread_logs;update"
+                .parse::<NotificationRule>()
+                .unwrap()
+                .options,
+            NotificationOptions {
+                thread_logs: true,
+                update: true,
+                ..Default::default()
             }
         );
+        assert_eq!(
+            "foo/bar/baz=qux;mention=@payments-oncall"
+                .parse::<NotificationRule>()
+                .unwrap()
+                .options
+                .mention
+                .as_deref(),
+            Some("payments-oncall")
+        );
+        assert_eq!(
+            "foo/bar/baz=qux;severity=critical"
+                .parse::<NotificationRule>()
+                .unwrap()
+                .options
+                .severity
+                .as_deref(),
+            Some("critical")
+        );
+        assert!("foo/bar/baz=qux;mention="
+            .parse::<NotificationRule>()
+            .is_err());
+        assert!("foo/bar/baz=qux;unknown"
+            .parse::<NotificationRule>()
+            .is_err());
+        assert!("foo/bar/baz=qux;escalate_after=5"
+            .parse::<NotificationRule>()
+            .is_err());
+        assert!("foo/bar/baz=qux;escalate_channel=#incidents"
+            .parse::<NotificationRule>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_notification_options_escalation() {
+        let options = "mention=team;escalate_after=3;escalate_mention=@oncall"
+            .parse::<NotificationOptions>()
+            .unwrap();
+        assert!(!options.escalated(3));
+        assert!(options.escalated(4));
+        assert_eq!(options.mentions(3), ["team"]);
+        assert_eq!(options.mentions(4), ["team", "oncall"]);
+
+        let options = "escalate_after=3;escalate_channel=#incidents;escalate_mention=oncall"
+            .parse::<NotificationOptions>()
+            .unwrap();
+        assert!(options.escalated(4));
+        // Mentioned in the escalation channel instead
+        assert!(options.mentions(4).is_empty());
     }
 
     #[test]
@@ -367,6 +2696,7 @@ mod tests {
                 pod: WildMatch::new("bar"),
                 container: WildMatch::new("baz"),
                 channel: Some("qux".to_owned()),
+                options: NotificationOptions::default(),
             }])
         );
         assert_eq!(
@@ -379,12 +2709,14 @@ mod tests {
                     pod: WildMatch::new("bar"),
                     container: WildMatch::new("baz"),
                     channel: Some("qux".to_owned()),
+                    options: NotificationOptions::default(),
                 },
                 NotificationRule {
                     namespace: WildMatch::new("*"),
                     pod: WildMatch::new("*"),
                     container: WildMatch::new("*"),
                     channel: Some("default".to_owned()),
+                    options: NotificationOptions::default(),
                 }
             ])
         );
@@ -395,9 +2727,136 @@ mod tests {
         let config = "foo/bar/baz=qux,ignore/*/*=,foo/*/*=default"
             .parse::<NotificationConfig>()
             .unwrap();
-        assert_eq!(config.find_channel("foo", "bar", "baz"), Some("qux"));
-        assert_eq!(config.find_channel("foo", "bar", "qux"), Some("default"));
-        assert_eq!(config.find_channel("ignore", "bar", "baz"), None);
-        assert_eq!(config.find_channel("nomatch", "bar", "baz"), None);
+        let find_channel = |namespace, pod, container| {
+            config
+                .find_route(namespace, pod, container)
+                .map(|(channel, _)| channel)
+        };
+        assert_eq!(find_channel("foo", "bar", "baz"), Some("qux"));
+        assert_eq!(find_channel("foo", "bar", "qux"), Some("default"));
+        assert_eq!(find_channel("ignore", "bar", "baz"), None);
+        assert_eq!(find_channel("nomatch", "bar", "baz"), None);
+    }
+
+    #[test]
+    fn test_router_builder() {
+        assert_eq!(
+            NotificationConfig::builder()
+                .route("foo", "bar", "baz", "qux")
+                .ignore("ignore", "*", "*")
+                .route("foo", "*", "*", "default")
+                .build(),
+            "foo/bar/baz=qux,ignore/*/*=,foo/*/*=default"
+                .parse::<NotificationConfig>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_notification_config_to_json() {
+        let config = "foo/*/*=qux;update,ignore/*/*="
+            .parse::<NotificationConfig>()
+            .unwrap()
+            .to_json();
+        assert_eq!(config[0]["pattern"], "foo/*/*");
+        assert_eq!(config[0]["channel"], "qux");
+        assert_eq!(config[0]["options"]["update"], true);
+        assert_eq!(config[1]["channel"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_notification_config_find_rule() {
+        let config = "kube-system/*/*=,*/*/*=#alerts;update"
+            .parse::<NotificationConfig>()
+            .unwrap();
+        let rule = config
+            .find_rule("kube-system", "coredns-0", "coredns")
+            .unwrap();
+        assert_eq!(rule.priority, 1);
+        assert_eq!(rule.pattern, "kube-system/*/*");
+        assert_eq!(rule.channel, None);
+        let rule = config.find_rule("default", "app-0", "app").unwrap();
+        assert_eq!(rule.priority, 2);
+        assert_eq!(rule.channel, Some("#alerts"));
+        assert!(rule.options.update);
+    }
+
+    #[test]
+    fn test_cooldowns() {
+        let cooldowns = Cooldowns::default();
+        let cooldown = Duration::from_secs(15 * 60);
+        let now = Instant::now();
+        let at = |minutes: u64| now + Duration::from_secs(minutes * 60);
+        assert_eq!(cooldowns.admit("uid/app", cooldown, at(0)), Some(0));
+        assert_eq!(cooldowns.admit("uid/app", cooldown, at(5)), None);
+        assert_eq!(cooldowns.admit("uid/app", cooldown, at(10)), None);
+        assert_eq!(cooldowns.admit("uid/sidecar", cooldown, at(10)), Some(0));
+        assert_eq!(cooldowns.admit("uid/app", cooldown, at(15)), Some(2));
+        assert_eq!(cooldowns.admit("uid/app", cooldown, at(31)), Some(0));
+    }
+
+    #[test]
+    fn test_notification_config_find_category_route() {
+        let config = "*/*/*=#oom;category=oom,kube-system/*/*=;category=dependency_timeout,\
+                      */*/*=#alerts"
+            .parse::<NotificationConfig>()
+            .unwrap();
+        let route = |namespace, category| {
+            config
+                .find_category_route(namespace, "app-0", "app", category)
+                .map(|(channel, _)| channel)
+        };
+        assert_eq!(route("default", Some(CrashCategory::Oom)), Some("#oom"));
+        assert_eq!(
+            route("default", Some(CrashCategory::Panic)),
+            Some("#alerts")
+        );
+        assert_eq!(route("default", None), Some("#alerts"));
+        assert_eq!(
+            route("kube-system", Some(CrashCategory::DependencyTimeout)),
+            None
+        );
+        // Matched tentatively before the crash is classified
+        let (channel, options) = config.find_route("default", "app-0", "app").unwrap();
+        assert_eq!(channel, "#oom");
+        assert!(!options.matches_category(Some(CrashCategory::Panic)));
+        assert!("category=oom|leak".parse::<NotificationOptions>().is_err());
+    }
+
+    #[test]
+    fn test_notification_options_display() {
+        for options in [
+            "",
+            "thread_logs;gzip_logs",
+            "update;mention=sre;severity=critical",
+            "mention=team;escalate_after=5;escalate_channel=#incidents;escalate_mention=oncall",
+            "severity=critical;category=oom|probe_kill",
+            "cooldown=15m",
+            "cooldown=90s",
+            "thread_logs;sidecar_logs=istio-proxy|log-shipper",
+            "startup_logs=30",
+            "thread_logs;aggregate;mention=sre",
+            "layout=compact",
+            "layout=compact;template=oncall",
+            "thread_logs;pod_spec",
+            "pod_spec;describe",
+        ] {
+            let parsed = options.parse::<NotificationOptions>().unwrap();
+            assert_eq!(parsed.to_string(), options);
+        }
+    }
+
+    #[test]
+    fn test_notification_config_to_table() {
+        let table = "kube-system/*/*=,*/*/*=#alerts;thread_logs;mention=sre"
+            .parse::<NotificationConfig>()
+            .unwrap()
+            .to_table();
+        assert_eq!(
+            table,
+            "PRIORITY  PATTERN          CHANNEL     OPTIONS\n\
+             1         kube-system/*/*  (disabled)\n\
+             2         */*/*            #alerts     thread_logs;mention=sre\n"
+        );
     }
 }
@@ -1,3 +1,124 @@
+//! Detects container restarts in a Kubernetes cluster and notifies them with the logs
+//! before the restart.
+//!
+//! The `johari-mirror` binary posts notifications to Slack. The detection pipeline can be
+//! embedded in other tools with a custom [`Notifier`]:
+//!
+//! ```no_run
+//! use johari_mirror::{RestartEvent, Router, Watcher};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let client = kube::Client::try_default().await?;
+//! let router = Router::builder()
+//!     .ignore("kube-system", "*", "*")
+//!     .route("*", "*", "*", "#alerts")
+//!     .build();
+//! Watcher::builder(client)
+//!     .router(router)
+//!     .notifier(|event: RestartEvent| async move {
+//!         println!("{event}");
+//!         Ok(())
+//!     })
+//!     .build()?
+//!     .run()
+//!     .await
+//! # }
+//! ```
+
+#[cfg(feature = "alertmanager")]
+pub mod alertmanager;
+pub mod anomaly;
+#[cfg(feature = "apm")]
+pub mod apm;
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(any(feature = "server", feature = "grpc"))]
+mod bearer;
+pub mod category;
+#[cfg(feature = "slack")]
+pub mod channels;
+#[cfg(feature = "cloud_secrets")]
+pub mod cloud_secrets;
+#[cfg(feature = "core_dump")]
+pub mod core_dump;
+#[cfg(feature = "database")]
+pub mod crash_store;
+#[cfg(feature = "elasticsearch")]
+pub mod elasticsearch;
+#[cfg(feature = "slack")]
+pub mod file_store;
+#[cfg(feature = "gitops")]
+pub mod gitops;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+#[cfg(feature = "slack")]
+pub mod heartbeat;
+pub mod history;
+pub mod hooks;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "jira")]
+pub mod jira;
 pub mod kubernetes;
+#[cfg(feature = "loki")]
+pub mod loki;
+#[cfg(feature = "slack")]
+pub mod manifest;
 pub mod message;
+#[cfg(feature = "slack")]
+pub mod message_store;
+pub mod metrics;
+pub mod middleware;
+pub mod node_correlation;
+#[cfg(feature = "slack")]
+pub mod pdb;
+pub mod pipeline;
+pub mod pod_annotations;
+pub mod pod_attachments;
+pub mod pod_events;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod queue;
+pub mod rate_limit;
+pub mod rbac;
+pub mod replay;
+#[cfg(feature = "slack")]
+pub mod report;
+pub mod rollout;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod self_alert;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod severity;
+#[cfg(any(feature = "archive", feature = "cloud_secrets"))]
+mod sigv4;
+pub mod silence;
+pub mod silence_store;
+pub mod sinks;
+#[cfg(feature = "slack")]
 pub mod slack;
+#[cfg(feature = "slack")]
+pub mod slash_command;
+pub mod source;
+#[cfg(feature = "slack")]
+pub mod stability;
+pub mod startup;
+#[cfg(feature = "slack")]
+pub mod startup_logs;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+pub mod template;
+#[cfg(feature = "vault")]
+pub mod vault;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod yaml;
+
+pub use hooks::Hooks;
+pub use kubernetes::{NotificationConfig as Router, NotificationOptions, RouterBuilder};
+/// Container restart detected by `Watcher`, routed to `channel`
+pub use message::ContainerRestartInfo as RestartEvent;
+pub use pipeline::{Notifier, Watcher, WatcherBuilder};
+pub use source::{EventSink, EventSource};
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    http,
+    message::{split_log, ContainerRestartInfo},
+};
+
+/// Maximum size of logs in a push request, below the default gRPC message limit of Loki
+const PUSH_BATCH_BYTES: usize = 1024 * 1024;
+
+/// Timeout of each push not to delay notifications when Loki is unavailable
+const PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration of pushing crash logs to Loki
+#[derive(Debug, Clone)]
+pub struct LokiConfig {
+    /// Push API URL, e.g. `http://loki:3100/loki/api/v1/push`
+    pub url: String,
+    /// Sent as `X-Scope-OrgID` for multi-tenant Loki
+    pub tenant_id: Option<String>,
+    /// Username and password of basic authentication, e.g. for Grafana Cloud
+    pub basic_auth: Option<(String, String)>,
+}
+
+impl LokiConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `LOKI_URL` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("LOKI_URL") else {
+            return Ok(None);
+        };
+        reqwest::Url::parse(&url).context("Invalid LOKI_URL")?;
+        let tenant_id = std::env::var("LOKI_TENANT_ID").ok();
+        let basic_auth = match std::env::var("LOKI_USERNAME") {
+            Ok(username) => Some((
+                username,
+                std::env::var("LOKI_PASSWORD")
+                    .context("LOKI_PASSWORD is required with LOKI_USERNAME")?,
+            )),
+            Err(_) => None,
+        };
+        Ok(Some(Self {
+            url,
+            tenant_id,
+            basic_auth,
+        }))
+    }
+}
+
+/// Pushes fetched previous logs of restarted containers to Loki
+#[derive(Clone)]
+pub struct Loki {
+    config: LokiConfig,
+    http: reqwest::Client,
+}
+
+impl Loki {
+    pub fn new(config: LokiConfig) -> Self {
+        Self {
+            config,
+            http: http::client(),
+        }
+    }
+
+    /// Pushes the logs of `info` with `namespace`, `pod`, `container` and `restart_count`
+    /// labels. Does nothing when fetching logs failed.
+    pub async fn push(&self, info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        let Ok(logs) = &info.logs.0 else {
+            return Ok(());
+        };
+        let end = info
+            .last_state
+            .as_ref()
+            .and_then(|s| s.finished_at.as_deref())
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map_or_else(Utc::now, |t| t.with_timezone(&Utc));
+        for body in push_requests(info, logs, end) {
+            let mut request = self
+                .http
+                .post(&self.config.url)
+                .timeout(PUSH_TIMEOUT)
+                .json(&body);
+            if let Some(tenant_id) = &self.config.tenant_id {
+                request = request.header("X-Scope-OrgID", tenant_id);
+            }
+            if let Some((username, password)) = &self.config.basic_auth {
+                request = request.basic_auth(username, Some(password));
+            }
+            request.send().await?.error_for_status()?;
+        }
+        log::debug!("Pushed logs of {info} to Loki");
+        Ok(())
+    }
+}
+
+/// Bodies of push requests with logs split by `PUSH_BATCH_BYTES`.
+/// Logs have no timestamps, so lines get consecutive nanoseconds up to `end`
+/// to keep their order in Loki.
+fn push_requests(
+    info: &ContainerRestartInfo,
+    logs: &str,
+    end: DateTime<Utc>,
+) -> Vec<serde_json::Value> {
+    let labels = json!({
+        "namespace": info.namespace.as_deref().unwrap_or(""),
+        "pod": &info.pod_name,
+        "container": &info.container_name,
+        "restart_count": info.restart_count.to_string(),
+    });
+    let lines = logs.lines().count() as i64;
+    let start = end.timestamp_nanos_opt().unwrap_or_default() - lines + 1;
+    let mut index = 0;
+    split_log(logs, PUSH_BATCH_BYTES)
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let values = part
+                .lines()
+                .map(|line| {
+                    let value = json!([(start + index).to_string(), line]);
+                    index += 1;
+                    value
+                })
+                .collect::<Vec<_>>();
+            json!({ "streams": [{ "stream": &labels, "values": values }] })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_push_requests() {
+        let info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let end = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let requests = push_requests(&info, "first\nsecond\n", end);
+        assert_eq!(
+            requests,
+            [json!({
+                "streams": [{
+                    "stream": {
+                        "namespace": "default",
+                        "pod": "app-0",
+                        "container": "app",
+                        "restart_count": "1",
+                    },
+                    "values": [
+                        ["1699999999999999999", "first"],
+                        ["1700000000000000000", "second"],
+                    ],
+                }],
+            })]
+        );
+
+        let logs = "line\n".repeat(PUSH_BATCH_BYTES / 5 + 1);
+        let requests = push_requests(&info, &logs, end);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1]["streams"][0]["values"][0],
+            json!(["1700000000000000000", "line"])
+        );
+        assert!(push_requests(&info, "", end).is_empty());
+    }
+}
@@ -1,24 +1,1418 @@
-use kube::Client;
-use tokio::sync::mpsc;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    env_logger::Builder::from_env(
-        env_logger::Env::default().default_filter_or("johari_mirror=debug"),
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+#[cfg(feature = "cloud_secrets")]
+use johari_mirror::cloud_secrets::CloudSecrets;
+#[cfg(feature = "database")]
+use johari_mirror::crash_store::CrashStore;
+#[cfg(feature = "grpc")]
+use johari_mirror::grpc;
+#[cfg(feature = "http")]
+use johari_mirror::http::{self, TlsConfig};
+#[cfg(not(feature = "slack"))]
+use johari_mirror::sinks;
+#[cfg(feature = "vault")]
+use johari_mirror::vault::{Vault, VaultConfig};
+use johari_mirror::{
+    anomaly::{AnomalyConfig, AnomalyDetector},
+    health::{self, Health},
+    history::{RecentNotifications, RestartHistory},
+    hooks::Hooks,
+    kubernetes::{self, PodRestartCounts, WatchConfig, WatchState},
+    message::ContainerRestartInfo,
+    metrics,
+    pod_annotations::{AnnotationTarget, PodAnnotator},
+    pod_events::PodEvents,
+    queue::{self, DiskQueue, QueuePolicy},
+    rate_limit::ApiLimits,
+    rbac::{self, RbacFeatures},
+    replay,
+    self_alert::SelfAlert,
+    server,
+    silence::{self, Silences},
+    silence_store::SilenceStore,
+    sinks::{SinkStores, SinksConfig},
+    yaml,
+};
+#[cfg(feature = "slack")]
+use johari_mirror::{
+    channels::{self, ChannelDirectory},
+    file_store::FileStore,
+    heartbeat,
+    kubernetes::NotificationConfig,
+    manifest, message,
+    message_store::MessageStore,
+    pdb::{self, PdbAlertConfig},
+    report,
+    sinks::NotifierKind,
+    slack::{self, SlackConfig, SlackPoster, SlackStores},
+    stability::StabilityTracker,
+    startup::StartupGrace,
+    startup_logs::{StartupLogs, DEFAULT_STARTUP_LOG_DELAY},
+};
+use kube::{client::ClientBuilder, runtime::reflector, Client};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+/// Default time to flush queued notifications on termination,
+/// shorter than the default `terminationGracePeriodSeconds` of 30 seconds
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Time to post the shutdown announcement after flushing notifications
+#[cfg(feature = "slack")]
+const ANNOUNCEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Notifies container restarts in Kubernetes to Slack.
+/// Configured by environment variables.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Defaults to `run`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Watch pods and notify container restarts
+    Run,
+    /// Check the configuration and exit without connecting to Kubernetes or Slack
+    Validate,
+    /// Print the notification rules in priority order
+    Rules,
+    /// Print the rule matching a container and the resulting channel and options
+    Route {
+        namespace: String,
+        pod: String,
+        container: String,
+    },
+    /// Process watcher events recorded with `WATCH_RECORD_PATH` through the pipeline
+    Replay {
+        /// Recording of watcher events
+        path: PathBuf,
+    },
+    /// Send a fake restart of a container through the Slack pipeline
+    SendTest {
+        #[arg(long)]
+        namespace: String,
+        #[arg(long)]
+        pod: String,
+        #[arg(long)]
+        container: String,
+        /// Routed by `SLACK_NOTIFICATION_CONFIG` when omitted
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Notify the last restart of a container now, e.g. missed while johari-mirror was down
+    Notify {
+        #[arg(long)]
+        namespace: String,
+        #[arg(long)]
+        pod: String,
+        #[arg(long)]
+        container: String,
+        /// Routed by `SLACK_NOTIFICATION_CONFIG` when omitted
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Print the Slack app manifest for the current configuration
+    #[cfg(feature = "slack")]
+    GenerateManifest {
+        /// Public URL of johari-mirror, required for slash commands
+        url: Option<String>,
+    },
+    /// Print the ServiceAccount and RBAC manifests for the current configuration
+    GenerateRbac {
+        /// Namespace of johari-mirror
+        #[arg(long)]
+        namespace: String,
+    },
+}
+
+impl Command {
+    /// Whether the command reads the configuration with secrets in Vault and cloud secret
+    /// managers
+    fn reads_secrets(&self) -> bool {
+        match self {
+            Self::Rules | Self::Route { .. } | Self::GenerateRbac { .. } => false,
+            #[cfg(feature = "slack")]
+            Self::GenerateManifest { .. } => false,
+            _ => true,
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // Applied to HTTP clients of Slack and other destinations, including secret managers
+    #[cfg(feature = "http")]
+    http::init(TlsConfig::from_env()?);
+    let command = cli.command.unwrap_or(Command::Run);
+    // Set as environment variables before Sentry and the runtime start threads
+    let secrets = if command.reads_secrets() {
+        load_secrets()?
+    } else {
+        SecretSources::default()
+    };
+    // Errors are reported to Sentry only when the DSN is configured
+    #[cfg(feature = "sentry")]
+    let sentry_guard = std::env::var("SENTRY_DSN").ok().map(|dsn| {
+        sentry::init((
+            dsn,
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                ..Default::default()
+            },
+        ))
+    });
+    #[cfg(feature = "sentry")]
+    let sentry = sentry_guard.is_some();
+    #[cfg(not(feature = "sentry"))]
+    if std::env::var("SENTRY_DSN").is_ok() {
+        anyhow::bail!("SENTRY_DSN requires the `sentry` feature");
+    }
+    #[cfg(not(feature = "sentry"))]
+    let sentry = false;
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async move {
+            // In the runtime, where the OpenTelemetry exporter runs
+            init_tracing(sentry)?;
+            secrets.log_loaded();
+            execute(command, secrets).await
+        })
+}
+
+async fn execute(command: Command, secrets: SecretSources) -> anyhow::Result<()> {
+    match command {
+        Command::Run => run(Config::from_env()?, secrets).await,
+        Command::Validate => validate().await,
+        Command::Rules => rules(),
+        Command::Route {
+            namespace,
+            pod,
+            container,
+        } => route(&namespace, &pod, &container),
+        Command::Replay { path } => replay(&path).await,
+        Command::SendTest {
+            namespace,
+            pod,
+            container,
+            channel,
+        } => send_test(&namespace, &pod, &container, channel).await,
+        Command::Notify {
+            namespace,
+            pod,
+            container,
+            channel,
+        } => notify(&namespace, &pod, &container, channel).await,
+        #[cfg(feature = "slack")]
+        Command::GenerateManifest { url } => generate_manifest(url),
+        Command::GenerateRbac { namespace } => generate_rbac(&namespace),
+    }
+}
+
+/// Configuration read from environment variables, validated before starting any task
+struct Config {
+    sinks: SinksConfig,
+    #[cfg(feature = "slack")]
+    slack: SlackSettings,
+    watch: WatchConfig,
+    watch_stall_timeout: Duration,
+    /// Flags anomalous restart rates based on the restart history
+    anomaly: Option<AnomalyConfig>,
+    pending_queue_dir: Option<PathBuf>,
+    queue_capacity: usize,
+    queue_policy: QueuePolicy,
+    restart_count_store_path: Option<PathBuf>,
+    /// URL of the SQLite or PostgreSQL database of the crash history
+    #[cfg(feature = "database")]
+    crash_store_url: Option<String>,
+    /// Object annotated with the last notification
+    pod_annotations: Option<AnnotationTarget>,
+    /// Namespace of `JohariSilence` resources to persist silences in
+    silence_namespace: Option<String>,
+    /// User and groups the Kubernetes client impersonates
+    impersonate: Option<(String, Vec<String>)>,
+    /// Throttling and timeouts of all requests of the Kubernetes client
+    api_limits: ApiLimits,
+    max_tracked_pods: usize,
+    debug_token: Option<String>,
+    api_token: Option<String>,
+    listen_address: SocketAddr,
+    /// Address and token of the gRPC API
+    #[cfg(feature = "grpc")]
+    grpc: Option<(SocketAddr, Option<String>)>,
+    shutdown_timeout: Duration,
+}
+
+/// Slack API and messages posted to Slack other than notifications
+#[cfg(feature = "slack")]
+struct SlackSettings {
+    config: SlackConfig,
+    message_store_path: Option<PathBuf>,
+    /// Retention and store path of uploaded files
+    file_retention: Option<(k8s_openapi::chrono::Duration, Option<PathBuf>)>,
+    /// Schedule and channel of summary reports
+    summary_report: Option<(cron::Schedule, String)>,
+    /// Schedule of top crashers reports, which are posted to the notified channels
+    #[cfg(feature = "database")]
+    top_crashers_report: Option<cron::Schedule>,
+    ops_channel: Option<String>,
+    ops_webhook_url: Option<String>,
+    /// Channel to announce starts and graceful shutdowns
+    announce_channel: Option<String>,
+    /// Shown in announcements
+    cluster_name: Option<String>,
+    /// Schedule, channel and URL of heartbeats
+    heartbeat: Option<(cron::Schedule, Option<String>, Option<String>)>,
+    pdb_alert: Option<PdbAlertConfig>,
+    startup_grace_period: Option<Duration>,
+    /// Period without restarts after which notifications are followed up
+    stable_after: Option<k8s_openapi::chrono::Duration>,
+    /// Wait time after restarts to capture logs of the new containers
+    startup_log_delay: Duration,
+    signing_secret: Option<String>,
+}
+
+/// Environment variables of `SlackSettings`, rejected without the `slack` feature
+#[cfg(not(feature = "slack"))]
+const SLACK_ENV: &[&str] = &[
+    "SLACK_TOKEN",
+    "SLACK_TOKEN_FILE",
+    "SLACK_FALLBACK_CHANNEL",
+    "SLACK_MESSAGE_STORE_PATH",
+    "SLACK_FILE_RETENTION",
+    "SLACK_RESOLVE_CHANNELS",
+    "SLACK_SIGNING_SECRET",
+    "SUMMARY_REPORT_SCHEDULE",
+    "TOP_CRASHERS_REPORT_SCHEDULE",
+    "OPS_CHANNEL",
+    "OPS_WEBHOOK_URL",
+    "ANNOUNCE_CHANNEL",
+    "HEARTBEAT_SCHEDULE",
+    "PDB_ALERT_CHANNEL",
+    "STARTUP_GRACE_PERIOD",
+    "STABLE_AFTER",
+    "NODE_CORRELATION_THRESHOLD",
+];
+
+impl Config {
+    fn from_env() -> anyhow::Result<Self> {
+        let sinks = SinksConfig::from_env()?;
+        let watch = WatchConfig::from_env()?;
+        for name in watch.notification_config.templates() {
+            if !sinks.templates.contains(name) {
+                anyhow::bail!("Template {name} is not defined in MESSAGE_TEMPLATES_PATH");
+            }
+        }
+        let watch_stall_timeout = match std::env::var("WATCH_STALL_TIMEOUT") {
+            Ok(timeout) => silence::parse_duration(&timeout)
+                .map_err(|e| anyhow::anyhow!("Invalid WATCH_STALL_TIMEOUT: {e}"))?
+                .to_std()?,
+            Err(_) => health::DEFAULT_WATCH_STALL_TIMEOUT,
+        };
+        let impersonate = impersonate_from_env()?;
+        let pending_queue_dir = std::env::var("PENDING_QUEUE_DIR").ok().map(PathBuf::from);
+        let queue_capacity = match std::env::var("NOTIFICATION_QUEUE_CAPACITY") {
+            Ok(capacity) => capacity
+                .parse()
+                .context("Invalid NOTIFICATION_QUEUE_CAPACITY")?,
+            Err(_) => queue::DEFAULT_QUEUE_CAPACITY,
+        };
+        if queue_capacity == 0 {
+            anyhow::bail!("NOTIFICATION_QUEUE_CAPACITY must be at least 1");
+        }
+        let queue_policy = match std::env::var("NOTIFICATION_QUEUE_POLICY") {
+            Ok(policy) => policy.parse()?,
+            Err(_) => QueuePolicy::default(),
+        };
+        if queue_policy == QueuePolicy::Spill && pending_queue_dir.is_none() {
+            anyhow::bail!("PENDING_QUEUE_DIR is required with NOTIFICATION_QUEUE_POLICY=spill");
+        }
+        // The crash history is persisted only when the database is configured
+        let crash_store_url = std::env::var("CRASH_STORE_URL").ok();
+        if crash_store_url.is_some() && !cfg!(feature = "database") {
+            anyhow::bail!("CRASH_STORE_URL requires the `database` feature");
+        }
+        // Built on the crash history, which keeps the channels restarts were notified to
+        if std::env::var("TOP_CRASHERS_REPORT_SCHEDULE").is_ok() && crash_store_url.is_none() {
+            anyhow::bail!("CRASH_STORE_URL is required with TOP_CRASHERS_REPORT_SCHEDULE");
+        }
+        #[cfg(feature = "slack")]
+        let slack = SlackSettings::from_env(sinks.notifier)?;
+        #[cfg(not(feature = "slack"))]
+        if let Some(name) = SLACK_ENV.iter().find(|name| std::env::var(name).is_ok()) {
+            anyhow::bail!("{name} requires the `slack` feature");
+        }
+        let anomaly = AnomalyConfig::from_env()?;
+        let pod_annotations = pod_annotations_from_env()?;
+        let max_tracked_pods = match std::env::var("MAX_TRACKED_PODS") {
+            Ok(max) => max.parse().context("Invalid MAX_TRACKED_PODS")?,
+            Err(_) => kubernetes::DEFAULT_MAX_TRACKED_PODS,
+        };
+        let listen_address = std::env::var("LISTEN_ADDRESS")
+            .unwrap_or_else(|_| server::DEFAULT_LISTEN_ADDRESS.to_owned())
+            .parse()
+            .context("Invalid LISTEN_ADDRESS")?;
+        // The gRPC API is enabled only when the address is configured
+        #[cfg(feature = "grpc")]
+        let grpc = match std::env::var("GRPC_LISTEN_ADDRESS") {
+            Ok(addr) => Some((
+                addr.parse().context("Invalid GRPC_LISTEN_ADDRESS")?,
+                std::env::var("GRPC_TOKEN").ok(),
+            )),
+            Err(_) => None,
+        };
+        #[cfg(not(feature = "grpc"))]
+        if std::env::var("GRPC_LISTEN_ADDRESS").is_ok() {
+            anyhow::bail!("GRPC_LISTEN_ADDRESS requires the `grpc` feature");
+        }
+        let shutdown_timeout = match std::env::var("SHUTDOWN_TIMEOUT") {
+            Ok(timeout) => silence::parse_duration(&timeout)
+                .map_err(|e| anyhow::anyhow!("Invalid SHUTDOWN_TIMEOUT: {e}"))?
+                .to_std()?,
+            Err(_) => DEFAULT_SHUTDOWN_TIMEOUT,
+        };
+        Ok(Self {
+            sinks,
+            #[cfg(feature = "slack")]
+            slack,
+            watch,
+            watch_stall_timeout,
+            anomaly,
+            pending_queue_dir,
+            queue_capacity,
+            queue_policy,
+            restart_count_store_path: std::env::var("RESTART_COUNT_STORE_PATH")
+                .ok()
+                .map(PathBuf::from),
+            #[cfg(feature = "database")]
+            crash_store_url,
+            pod_annotations,
+            silence_namespace: std::env::var("SILENCE_NAMESPACE").ok(),
+            impersonate,
+            api_limits: ApiLimits::from_env()?,
+            max_tracked_pods,
+            // The debug endpoint is enabled only when the token is configured
+            debug_token: std::env::var("DEBUG_TOKEN").ok(),
+            // The management API is enabled only when the token is configured
+            api_token: std::env::var("API_TOKEN").ok(),
+            listen_address,
+            #[cfg(feature = "grpc")]
+            grpc,
+            shutdown_timeout,
+        })
+    }
+
+    /// Slack channels in the configuration, checked by `SLACK_RESOLVE_CHANNELS`
+    #[cfg(feature = "slack")]
+    fn channels(&self) -> Vec<&str> {
+        let slack = &self.slack;
+        let node_correlation = slack.config.node_correlation.as_ref();
+        self.watch
+            .notification_config
+            .channels()
+            .chain(slack.config.fallback_channel.as_deref())
+            .chain(slack.ops_channel.as_deref())
+            .chain(slack.announce_channel.as_deref())
+            .chain(
+                slack
+                    .summary_report
+                    .as_ref()
+                    .map(|(_, channel)| channel.as_str()),
+            )
+            .chain(
+                slack
+                    .heartbeat
+                    .as_ref()
+                    .and_then(|(_, channel, _)| channel.as_deref()),
+            )
+            .chain(
+                slack
+                    .pdb_alert
+                    .as_ref()
+                    .map(|pdb_alert| pdb_alert.channel.as_str()),
+            )
+            .chain(node_correlation.and_then(|config| config.channel.as_deref()))
+            .collect()
+    }
+}
+
+#[cfg(feature = "slack")]
+impl SlackSettings {
+    /// Reads the settings from environment variables.
+    fn from_env(notifier: NotifierKind) -> anyhow::Result<Self> {
+        // Uploaded files are tracked and deleted only when the retention is configured
+        let file_retention = match std::env::var("SLACK_FILE_RETENTION") {
+            Ok(retention) => Some((
+                silence::parse_duration(&retention)
+                    .map_err(|e| anyhow::anyhow!("Invalid SLACK_FILE_RETENTION: {e}"))?,
+                std::env::var("SLACK_FILE_STORE_PATH")
+                    .ok()
+                    .map(PathBuf::from),
+            )),
+            Err(_) => None,
+        };
+        // Summary reports are enabled only when the schedule is configured
+        let summary_report = match std::env::var("SUMMARY_REPORT_SCHEDULE") {
+            Ok(schedule) => Some((
+                schedule
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid SUMMARY_REPORT_SCHEDULE: {e}"))?,
+                std::env::var("SUMMARY_REPORT_CHANNEL")
+                    .context("SUMMARY_REPORT_CHANNEL is required with SUMMARY_REPORT_SCHEDULE")?,
+            )),
+            Err(_) => None,
+        };
+        #[cfg(feature = "database")]
+        let top_crashers_report = match std::env::var("TOP_CRASHERS_REPORT_SCHEDULE") {
+            Ok(schedule) => Some(
+                schedule
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid TOP_CRASHERS_REPORT_SCHEDULE: {e}"))?,
+            ),
+            Err(_) => None,
+        };
+        // Heartbeats are enabled only when the schedule is configured
+        let heartbeat = match std::env::var("HEARTBEAT_SCHEDULE") {
+            Ok(schedule) => {
+                let schedule = schedule
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid HEARTBEAT_SCHEDULE: {e}"))?;
+                let channel = std::env::var("HEARTBEAT_CHANNEL").ok();
+                let url = std::env::var("HEARTBEAT_URL").ok();
+                if channel.is_none() && url.is_none() {
+                    anyhow::bail!(
+                        "HEARTBEAT_CHANNEL or HEARTBEAT_URL is required with HEARTBEAT_SCHEDULE"
+                    );
+                }
+                Some((schedule, channel, url))
+            }
+            Err(_) => None,
+        };
+        // Restarts on startup are summarized only when the grace period is configured
+        let startup_grace_period = match std::env::var("STARTUP_GRACE_PERIOD") {
+            Ok(period) => Some(
+                silence::parse_duration(&period)
+                    .map_err(|e| anyhow::anyhow!("Invalid STARTUP_GRACE_PERIOD: {e}"))?
+                    .to_std()?,
+            ),
+            Err(_) => None,
+        };
+        // Notifications are followed up only when the period is configured
+        let stable_after = match std::env::var("STABLE_AFTER") {
+            Ok(period) => Some(
+                silence::parse_duration(&period)
+                    .map_err(|e| anyhow::anyhow!("Invalid STABLE_AFTER: {e}"))?,
+            ),
+            Err(_) => None,
+        };
+        let startup_log_delay = match std::env::var("STARTUP_LOG_DELAY") {
+            Ok(delay) => silence::parse_duration(&delay)
+                .map_err(|e| anyhow::anyhow!("Invalid STARTUP_LOG_DELAY: {e}"))?
+                .to_std()?,
+            Err(_) => DEFAULT_STARTUP_LOG_DELAY,
+        };
+        Ok(Self {
+            config: SlackConfig::from_env(notifier)?,
+            message_store_path: std::env::var("SLACK_MESSAGE_STORE_PATH")
+                .ok()
+                .map(PathBuf::from),
+            file_retention,
+            summary_report,
+            #[cfg(feature = "database")]
+            top_crashers_report,
+            ops_channel: std::env::var("OPS_CHANNEL").ok(),
+            ops_webhook_url: std::env::var("OPS_WEBHOOK_URL").ok(),
+            announce_channel: std::env::var("ANNOUNCE_CHANNEL").ok(),
+            cluster_name: std::env::var("CLUSTER_NAME").ok(),
+            heartbeat,
+            pdb_alert: PdbAlertConfig::from_env()?,
+            startup_grace_period,
+            stable_after,
+            startup_log_delay,
+            // Slash commands are enabled only when the signing secret is configured
+            signing_secret: std::env::var("SLACK_SIGNING_SECRET").ok(),
+        })
+    }
+}
+
+/// Lists Slack channels and fails on unknown channels in `config` when
+/// `SLACK_RESOLVE_CHANNELS` is enabled. Returns the channels to resolve names with.
+#[cfg(feature = "slack")]
+async fn check_channels(
+    config: &Config,
+    poster: &SlackPoster,
+) -> anyhow::Result<Option<ChannelDirectory>> {
+    if !config.slack.config.resolve_channels || !poster.posts_to_slack() {
+        return Ok(None);
+    }
+    let directory = ChannelDirectory::load(poster).await?;
+    directory
+        .check(config.channels())
+        .context("Invalid channels in the configuration")?;
+    Ok(Some(directory))
+}
+
+/// Connects to the crash history store and restores `history` from it.
+#[cfg(feature = "database")]
+async fn crash_store(
+    url: Option<&str>,
+    history: &RestartHistory,
+) -> anyhow::Result<Option<CrashStore>> {
+    let Some(url) = url else {
+        return Ok(None);
+    };
+    use johari_mirror::history::HISTORY_RETENTION_DAYS;
+    use k8s_openapi::chrono::Utc;
+
+    let store = CrashStore::connect(url).await?;
+    let since = Utc::now() - k8s_openapi::chrono::Duration::days(HISTORY_RETENTION_DAYS);
+    let restarts = store.restarts_since(since).await?;
+    log::info!("Restored {} restarts from the crash store", restarts.len());
+    for restart in restarts {
+        history.record(restart);
+    }
+    Ok(Some(store))
+}
+
+/// Sources of credentials refreshed while running
+#[derive(Default)]
+struct SecretSources {
+    #[cfg(feature = "vault")]
+    vault: Option<Vault>,
+    #[cfg(feature = "cloud_secrets")]
+    cloud: Option<CloudSecrets>,
+}
+
+impl SecretSources {
+    /// Logs the environment variables set from the secrets, after logging is initialized.
+    fn log_loaded(&self) {
+        #[cfg(feature = "vault")]
+        if let Some(vault) = &self.vault {
+            log::info!("Read {} from Vault", env_names(vault.secrets()));
+        }
+        #[cfg(feature = "cloud_secrets")]
+        if let Some(cloud) = &self.cloud {
+            log::info!(
+                "Resolved {} from cloud secret managers",
+                env_names(cloud.secrets())
+            );
+        }
+    }
+}
+
+#[cfg(any(feature = "vault", feature = "cloud_secrets"))]
+fn env_names<'a>(secrets: impl Iterator<Item = (&'a str, &'a str)>) -> String {
+    secrets.map(|(env, _)| env).collect::<Vec<_>>().join(", ")
+}
+
+/// Reads secrets from Vault when `VAULT_ADDR` is set and resolves `aws-sm://` and
+/// `gcp-sm://` URIs, and sets them as environment variables for `Config::from_env`.
+/// Called before the runtime starts, because `std::env::set_var` is not thread-safe.
+/// The secrets are read on a runtime of its own, whose threads are joined when it's
+/// dropped before setting them.
+fn load_secrets() -> anyhow::Result<SecretSources> {
+    let secrets = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(read_secrets())?;
+    #[cfg(feature = "vault")]
+    for (env, value) in secrets.vault.iter().flat_map(Vault::secrets) {
+        std::env::set_var(env, value);
+    }
+    #[cfg(feature = "cloud_secrets")]
+    for (env, value) in secrets.cloud.iter().flat_map(CloudSecrets::secrets) {
+        std::env::set_var(env, value);
+    }
+    Ok(secrets)
+}
+
+async fn read_secrets() -> anyhow::Result<SecretSources> {
+    #[cfg(feature = "vault")]
+    let vault = match VaultConfig::from_env()? {
+        Some(config) => Some(Vault::connect(config).await?),
+        None => None,
+    };
+    #[cfg(not(feature = "vault"))]
+    if std::env::var("VAULT_ADDR").is_ok() {
+        anyhow::bail!("VAULT_ADDR requires the `vault` feature");
+    }
+    #[cfg(feature = "cloud_secrets")]
+    let cloud = CloudSecrets::resolve_env().await?;
+    #[cfg(not(feature = "cloud_secrets"))]
+    if let Some((name, _)) = std::env::vars()
+        .find(|(_, value)| value.starts_with("aws-sm://") || value.starts_with("gcp-sm://"))
+    {
+        anyhow::bail!("Secret manager URI in {name} requires the `cloud_secrets` feature");
+    }
+    Ok(SecretSources {
+        #[cfg(feature = "vault")]
+        vault,
+        #[cfg(feature = "cloud_secrets")]
+        cloud,
+    })
+}
+
+/// Watches pods and notifies container restarts until SIGTERM.
+/// Secrets read from `secrets` are refreshed to pick up rotations.
+async fn run(mut config: Config, secrets: SecretSources) -> anyhow::Result<()> {
+    #[cfg(feature = "slack")]
+    let started_at = k8s_openapi::chrono::Utc::now();
+    let client = kube_client(config.impersonate.take(), &config.api_limits).await?;
+    let health = Health::new(config.watch_stall_timeout);
+    #[cfg(feature = "slack")]
+    let poster = start_slack(&config, &health, secrets).await?;
+    #[cfg(not(feature = "slack"))]
+    let SecretSources {} = secrets;
+    #[cfg(feature = "slack")]
+    let message_store = match config.slack.message_store_path.take() {
+        Some(path) => MessageStore::load(path)?,
+        None => MessageStore::default(),
+    };
+
+    // Files are uploaded only with notifications to Slack
+    #[cfg(feature = "slack")]
+    let file_store = match config
+        .slack
+        .file_retention
+        .take()
+        .filter(|_| config.sinks.notifier == NotifierKind::Slack)
+    {
+        Some((retention, path)) => {
+            let file_store = match path {
+                Some(path) => FileStore::load(path)?,
+                None => FileStore::default(),
+            };
+            tokio::spawn(slack::delete_expired_files(
+                poster.clone(),
+                file_store.clone(),
+                retention,
+            ));
+            Some(file_store)
+        }
+        None => None,
+    };
+
+    let watch_config = config.watch;
+    let silences = Silences::default();
+    if let Some(namespace) = &config.silence_namespace {
+        let store = SilenceStore::new(client.clone(), namespace);
+        silences.restore(store.load().await?);
+        tokio::spawn(store.sync(silences.clone()));
+    }
+    let history = RestartHistory::default();
+    if let Some(anomaly) = config.anomaly.take() {
+        // Before other middlewares, e.g. not to rate limit chronic restarts to be dropped
+        config
+            .sinks
+            .middlewares
+            .prepend(AnomalyDetector::new(anomaly, history.clone()));
+    }
+    let recent_notifications = RecentNotifications::default();
+    #[cfg(feature = "database")]
+    let crash_store = crash_store(config.crash_store_url.as_deref(), &history).await?;
+    #[cfg(all(feature = "database", feature = "slack"))]
+    if let (Some(schedule), Some(store)) = (config.slack.top_crashers_report.take(), &crash_store) {
+        tokio::spawn(report::top_crashers_report(
+            schedule,
+            poster.clone(),
+            store.clone(),
+        ));
+    }
+    #[cfg(feature = "database")]
+    let hooks = crash_store.map(|store| Arc::new(store) as Arc<dyn Hooks>);
+    // `CRASH_STORE_URL` is rejected by `Config::from_env` without the `database` feature
+    #[cfg(not(feature = "database"))]
+    let hooks: Option<Arc<dyn Hooks>> = None;
+
+    #[cfg(feature = "slack")]
+    if let Some((schedule, channel)) = config.slack.summary_report.take() {
+        tokio::spawn(report::summary_report(
+            schedule,
+            poster.clone(),
+            channel,
+            history.clone(),
+        ));
+    }
+
+    #[cfg(feature = "slack")]
+    let self_alert = SelfAlert::new(
+        poster.clone(),
+        config.slack.ops_channel.take(),
+        config.slack.ops_webhook_url.take(),
+    );
+    // Self-alerts are posted only to Slack channels and webhooks
+    #[cfg(not(feature = "slack"))]
+    let self_alert = SelfAlert::default();
+
+    // Notifications are persisted only when the directory is configured
+    let (disk_queue, pending) = match config.pending_queue_dir {
+        Some(dir) => {
+            let (disk_queue, pending) = DiskQueue::open(dir)?;
+            (Some(disk_queue), pending)
+        }
+        None => (None, Vec::new()),
+    };
+    // Notifications left undelivered before the restart are sent first
+    let (tx, rx) = queue::channel(
+        config.queue_capacity,
+        config.queue_policy,
+        disk_queue.clone(),
+        pending,
+    );
+    tokio::spawn(metrics::monitor_queue(tx.monitor(), self_alert.clone()));
+    let pod_restart_count = match config.restart_count_store_path {
+        Some(path) => {
+            let pod_restart_count = PodRestartCounts::load(path)?;
+            tokio::spawn(pod_restart_count.clone().save_periodically());
+            pod_restart_count
+        }
+        None => PodRestartCounts::default(),
+    };
+    let pod_restart_count = pod_restart_count.with_max_pods(config.max_tracked_pods);
+    let (pod_store, pod_store_writer) = reflector::store();
+    tokio::spawn(pod_restart_count.clone().collect_garbage(pod_store.clone()));
+
+    #[cfg(feature = "slack")]
+    if let Some(pdb_alert) = config.slack.pdb_alert.take() {
+        tokio::spawn(pdb::watch(
+            pdb_alert,
+            client.clone(),
+            watch_config.namespaces.clone(),
+            poster.clone(),
+        ));
+    }
+
+    #[cfg(feature = "slack")]
+    if let Some((schedule, channel, url)) = config.slack.heartbeat.take() {
+        tokio::spawn(heartbeat::heartbeat(
+            schedule,
+            poster.clone(),
+            channel,
+            url,
+            health.clone(),
+        ));
+    }
+
+    // Followed up in the threads of notifications, which are posted only to Slack
+    #[cfg(feature = "slack")]
+    let stability = config
+        .slack
+        .stable_after
+        .filter(|_| config.sinks.notifier == NotifierKind::Slack)
+        .map(|stable_after| {
+            let stability = StabilityTracker::new(stable_after);
+            tokio::spawn(
+                stability
+                    .clone()
+                    .follow_up(poster.clone(), pod_store.clone()),
+            );
+            stability
+        });
+
+    // Posted in the threads of notifications, enabled per route by `startup_logs`
+    #[cfg(feature = "slack")]
+    let startup_logs = (config.sinks.notifier == NotifierKind::Slack).then(|| {
+        StartupLogs::new(
+            client.clone(),
+            config.slack.startup_log_delay,
+            config.sinks.middlewares.redaction(),
+        )
+    });
+
+    #[cfg(feature = "slack")]
+    let startup_grace = config.slack.startup_grace_period.map(|period| {
+        let startup_grace = StartupGrace::new(period);
+        tokio::spawn(startup_grace.clone().post_summary(poster.clone()));
+        startup_grace
+    });
+    // Restarts on startup are summarized only to Slack
+    #[cfg(not(feature = "slack"))]
+    let startup_grace = None;
+
+    let debug = config.debug_token.map(|token| server::DebugState {
+        token,
+        pod_restart_count: pod_restart_count.clone(),
+        pod_store: pod_store.clone(),
+        queue: tx.monitor(),
+    });
+    let api = config.api_token.map(|token| server::ApiState {
+        token,
+        router: watch_config.notification_config.clone(),
+        routing_script: watch_config.has_routing_script(),
+        queue: tx.downgrade(),
+    });
+    #[cfg(feature = "slack")]
+    let serve = server::serve(
+        config.listen_address,
+        config.slack.signing_secret.take(),
+        silences.clone(),
+        health.clone(),
+        debug,
+        api,
+    );
+    #[cfg(not(feature = "slack"))]
+    let serve = server::serve(
+        config.listen_address,
+        silences.clone(),
+        health.clone(),
+        debug,
+        api,
+    );
+    tokio::spawn(serve);
+    #[cfg(feature = "grpc")]
+    if let Some((addr, token)) = config.grpc {
+        tokio::spawn(grpc::serve(
+            addr,
+            token,
+            grpc::QueryState {
+                pod_store,
+                pod_restart_count: pod_restart_count.clone(),
+                history: history.clone(),
+                silences: silences.clone(),
+                recent_notifications: recent_notifications.clone(),
+            },
+        ));
+    }
+
+    let pod_events = watch_config
+        .pod_events
+        .then(|| PodEvents::new(client.clone()));
+    let pod_annotator = config
+        .pod_annotations
+        .map(|target| PodAnnotator::new(client.clone(), target));
+    #[cfg(feature = "slack")]
+    if let Some(channel) = config.slack.announce_channel.clone() {
+        let blocks = message::startup_announcement(
+            env!("CARGO_PKG_VERSION"),
+            config.slack.cluster_name.as_deref(),
+            &watch_config.namespaces,
+            watch_config.notification_config.rule_count(),
+            watch_config.has_routing_script(),
+        );
+        let poster = poster.clone();
+        tokio::spawn(async move {
+            if let Err(e) = poster.post_blocks(&channel, blocks).await {
+                log::error!("Failed to post startup announcement: {e:#}");
+            }
+        });
+    }
+    let queue = tx.monitor();
+    let mut watch_handle = tokio::spawn(kubernetes::watch(
+        client,
+        watch_config,
+        tx,
+        health,
+        WatchState {
+            pod_store: pod_store_writer,
+            restart_counts: pod_restart_count,
+            startup_grace,
+            silences,
+            history,
+            hooks: hooks.clone(),
+        },
+        self_alert.clone(),
+    ));
+    let stores = SinkStores {
+        recent_notifications,
+        hooks,
+        pod_events,
+        pod_annotator,
+    };
+    #[cfg(feature = "slack")]
+    let send = slack::slack_send(
+        config.slack.config,
+        config.sinks,
+        SlackStores {
+            message_store,
+            file_store,
+            sinks: stores,
+            stability,
+            startup_logs,
+        },
+        self_alert,
+        disk_queue,
+        rx,
+    );
+    #[cfg(not(feature = "slack"))]
+    let send = sinks::send(config.sinks, stores, self_alert, disk_queue, rx);
+    let send_handle = tokio::spawn(send);
+
+    tokio::select! {
+        result = &mut watch_handle => result??,
+        result = shutdown_signal() => {
+            result?;
+            log::info!("Received termination signal, stopping watching pods");
+            watch_handle.abort();
+        }
+    }
+    // Sending ends when restarts being processed are queued and the queue is empty
+    let before = metrics::delivery_counts();
+    log::info!(
+        "Flushing {} queued notifications",
+        queue.len() + queue.spilled()
+    );
+    match tokio::time::timeout(config.shutdown_timeout, send_handle).await {
+        Ok(result) => result??,
+        Err(_) => log::warn!("Timed out flushing notifications"),
+    }
+    let after = metrics::delivery_counts();
+    log::info!(
+        "Shutting down: {} notifications delivered, {} failed, {} dropped, {} left in the queue",
+        after.sent - before.sent,
+        after.failed - before.failed,
+        after.dropped - before.dropped,
+        queue.len() + queue.spilled(),
+    );
+    #[cfg(feature = "slack")]
+    if let Some(channel) = &config.slack.announce_channel {
+        let uptime = k8s_openapi::chrono::Utc::now() - started_at;
+        let blocks = message::shutdown_announcement(
+            env!("CARGO_PKG_VERSION"),
+            config.slack.cluster_name.as_deref(),
+            &silence::format_duration(uptime),
+            after,
+            queue.len() + queue.spilled(),
+        );
+        match tokio::time::timeout(ANNOUNCEMENT_TIMEOUT, poster.post_blocks(channel, blocks)).await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::error!("Failed to post shutdown announcement: {e:#}"),
+            Err(_) => log::warn!("Timed out posting shutdown announcement"),
+        }
+    }
+
+    // Flush remaining spans
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+    Ok(())
+}
+
+/// Checks the Slack token and channels, and starts checking the connection and refreshing
+/// the token from `secrets`. Returns the poster of Slack messages other than notifications.
+#[cfg(feature = "slack")]
+async fn start_slack(
+    config: &Config,
+    health: &Health,
+    secrets: SecretSources,
+) -> anyhow::Result<SlackPoster> {
+    let slack = &config.slack.config;
+    let slack_token = slack.slack_token.clone();
+    let poster = SlackPoster::new(
+        slack.http_client()?,
+        slack_token.clone(),
+        config.sinks.notifier,
+    );
+    if poster.posts_to_slack() {
+        // Fail fast on invalid tokens instead of failing on the first notification
+        poster.validate_token().await?;
+        tokio::spawn(slack::check_periodically(
+            poster.clone(),
+            slack.slack_token_file.clone(),
+            health.clone(),
+            slack.check_interval,
+            slack.unreachable_timeout,
+        ));
+        if let Some(directory) = check_channels(config, &poster).await? {
+            tokio::spawn(directory.clone().refresh_periodically(poster.clone()));
+            channels::init(directory);
+        }
+    } else {
+        log::info!("Messages to Slack channels are written to logs");
+    }
+    if let Some(path) = slack.slack_token_file.clone() {
+        tokio::spawn(slack::watch_token_file(path, slack_token.clone()));
+    }
+    let SecretSources {
+        #[cfg(feature = "vault")]
+        vault,
+        #[cfg(feature = "cloud_secrets")]
+        cloud,
+    } = secrets;
+    #[cfg(feature = "vault")]
+    if let Some(vault) = vault {
+        tokio::spawn(vault.renew_periodically(slack_token.clone()));
+    }
+    #[cfg(feature = "cloud_secrets")]
+    if let Some(cloud) = cloud {
+        tokio::spawn(cloud.refresh_periodically(slack_token.clone()));
+    }
+    Ok(poster)
+}
+
+/// Checks the configuration in environment variables, including the routing script
+/// and middlewares, without side effects. Secrets in Vault and cloud secret managers are
+/// read to check them, and Slack channels are listed with `SLACK_RESOLVE_CHANNELS=true`.
+async fn validate() -> anyhow::Result<()> {
+    #[cfg(feature = "slack")]
+    {
+        let config = Config::from_env()?;
+        let poster = SlackPoster::new(
+            config.slack.config.http_client()?,
+            config.slack.config.slack_token.clone(),
+            config.sinks.notifier,
+        );
+        check_channels(&config, &poster).await?;
+    }
+    #[cfg(not(feature = "slack"))]
+    Config::from_env()?;
+    println!("Configuration is valid");
+    Ok(())
+}
+
+/// Prints the rules of `SLACK_NOTIFICATION_CONFIG` as a table in priority order.
+fn rules() -> anyhow::Result<()> {
+    let config = WatchConfig::from_env()?;
+    if config.has_routing_script() {
+        println!("Restarts are routed by ROUTING_SCRIPT before these rules.\n");
+    }
+    print!("{}", config.notification_config.to_table());
+    Ok(())
+}
+
+/// Prints the rule of `SLACK_NOTIFICATION_CONFIG` matching the container and its destination.
+fn route(namespace: &str, pod: &str, container: &str) -> anyhow::Result<()> {
+    let config = WatchConfig::from_env()?;
+    match config
+        .notification_config
+        .find_rule(namespace, pod, container)
+    {
+        Some(rule) => {
+            println!("Rule:    #{} {}", rule.priority, rule.pattern);
+            println!(
+                "Channel: {}",
+                rule.channel.unwrap_or("(notification disabled)")
+            );
+            let options = rule.options.to_string();
+            if !options.is_empty() {
+                println!("Options: {options}");
+            }
+        }
+        None => println!("No rule matches, notification disabled"),
+    }
+    if config.has_routing_script() {
+        println!(
+            "\nROUTING_SCRIPT is not evaluated, which needs the Pod, and may override this route."
+        );
+    }
+    Ok(())
+}
+
+/// Processes recorded watcher events through routing, middlewares and the Slack senders,
+/// to reproduce bugs of restart detection. Logs of restarted containers are not available.
+async fn replay(path: &Path) -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    let events = replay::load(path)?;
+    validate_token(&config).await?;
+    log::info!(
+        "Replaying {} watcher events from {}",
+        events.len(),
+        path.display()
+    );
+    let (tx, rx) = queue::channel(config.queue_capacity, QueuePolicy::Block, None, Vec::new());
+    let watch_config = config.watch.clone();
+    let send_handle = tokio::spawn(send_once(config, rx));
+    let (_, pod_store_writer) = reflector::store();
+    kubernetes::replay(
+        watch_config,
+        events,
+        tx,
+        WatchState {
+            pod_store: pod_store_writer,
+            restart_counts: PodRestartCounts::default(),
+            startup_grace: None,
+            silences: Silences::default(),
+            history: RestartHistory::default(),
+            hooks: None,
+        },
     )
-    .init();
+    .await?;
+    send_handle.await??;
+    let counts = metrics::delivery_counts();
+    println!(
+        "Replay finished: {} notifications sent, {} failed",
+        counts.sent, counts.failed
+    );
+    Ok(())
+}
 
-    // Infer the runtime environment and try to create a Kubernetes Client
-    let client = Client::try_default().await?;
+/// Fails fast on invalid Slack tokens with `NOTIFIER=slack`, before sending notifications.
+#[cfg(feature = "slack")]
+async fn validate_token(config: &Config) -> anyhow::Result<()> {
+    if config.sinks.notifier == NotifierKind::Slack {
+        let slack = &config.slack.config;
+        slack::validate_token(&slack.http_client()?, &slack.slack_token.get()).await?;
+    }
+    Ok(())
+}
 
-    let slack_token = std::env::var("SLACK_TOKEN")?;
+/// Other notifiers have no credentials to validate without the `slack` feature.
+#[cfg(not(feature = "slack"))]
+async fn validate_token(_config: &Config) -> anyhow::Result<()> {
+    Ok(())
+}
 
-    let (tx, rx) = mpsc::channel(320);
-    let watch_handle = tokio::spawn(johari_mirror::kubernetes::watch(client, tx));
-    let slack_handle = tokio::spawn(johari_mirror::slack::slack_send(slack_token, rx));
+/// Sends notifications from `rx` until the queue is closed, without the stores of `run`.
+/// Self-alerts are disabled without destinations.
+#[cfg(feature = "slack")]
+async fn send_once(config: Config, rx: queue::NotificationReceiver) -> anyhow::Result<()> {
+    slack::slack_send(
+        config.slack.config,
+        config.sinks,
+        SlackStores {
+            message_store: MessageStore::default(),
+            file_store: None,
+            sinks: SinkStores::default(),
+            stability: None,
+            startup_logs: None,
+        },
+        SelfAlert::default(),
+        None,
+        rx,
+    )
+    .await
+}
 
-    watch_handle.await??;
-    slack_handle.await?;
+/// Sends notifications from `rx` until the queue is closed, without the stores of `run`.
+/// Self-alerts are disabled without destinations.
+#[cfg(not(feature = "slack"))]
+async fn send_once(config: Config, rx: queue::NotificationReceiver) -> anyhow::Result<()> {
+    sinks::send(
+        config.sinks,
+        SinkStores::default(),
+        SelfAlert::default(),
+        None,
+        rx,
+    )
+    .await
+}
+
+/// Kubernetes client of the inferred runtime environment, impersonating the user and groups
+/// of `impersonate` if any. All requests, including those of other tasks sharing the client,
+/// are throttled and timed out by `limits`.
+async fn kube_client(
+    impersonate: Option<(String, Vec<String>)>,
+    limits: &ApiLimits,
+) -> anyhow::Result<Client> {
+    let mut kube_config = kube::Config::infer().await?;
+    if let Some((user, groups)) = impersonate {
+        log::info!("Impersonating {user} in groups {groups:?} in Kubernetes API requests");
+        kube_config.auth_info.impersonate = Some(user);
+        kube_config.auth_info.impersonate_groups = (!groups.is_empty()).then_some(groups);
+    }
+    Ok(ClientBuilder::try_from(kube_config)?
+        .with_layer(limits)
+        .build())
+}
+
+/// Notifies the last restart of the container through the Slack pipeline from the current
+/// status and previous logs, for crashes missed while johari-mirror was down or silenced.
+async fn notify(
+    namespace: &str,
+    pod: &str,
+    container: &str,
+    channel: Option<String>,
+) -> anyhow::Result<()> {
+    let mut config = Config::from_env()?;
+    validate_token(&config).await?;
+    let client = kube_client(config.impersonate.take(), &config.api_limits).await?;
+    let (tx, rx) = queue::channel(1, QueuePolicy::Block, None, Vec::new());
+    let watch_config = config.watch.clone();
+    let send_handle = tokio::spawn(send_once(config, rx));
+    kubernetes::notify_restart(client, watch_config, tx, namespace, pod, container, channel)
+        .await?;
+    send_handle.await??;
+    let counts = metrics::delivery_counts();
+    if counts.failed > 0 {
+        anyhow::bail!("Failed to send the notification");
+    }
+    if counts.sent == 0 {
+        anyhow::bail!("The notification was dropped by NOTIFICATION_MIDDLEWARES");
+    }
+    println!("Notification sent");
+    Ok(())
+}
+
+/// Sends a fake restart of the container through the Slack pipeline, including
+/// routing, middlewares and fallback, to verify the configuration end to end.
+async fn send_test(
+    namespace: &str,
+    pod: &str,
+    container: &str,
+    channel: Option<String>,
+) -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    validate_token(&config).await?;
+    let route = config
+        .watch
+        .notification_config
+        .find_route(namespace, pod, container);
+    let options = route
+        .map(|(_, options)| options.clone())
+        .unwrap_or_default();
+    let channel = match channel {
+        Some(channel) => channel,
+        None => route.map(|(channel, _)| channel.to_owned()).context(
+            "Notification of the container is disabled by SLACK_NOTIFICATION_CONFIG, \
+             specify --channel",
+        )?,
+    };
+    let restart_info = ContainerRestartInfo::synthetic(
+        namespace,
+        pod,
+        container,
+        channel,
+        options,
+        "`johari-mirror send-test`",
+    );
+    log::info!("Sending test notification: {restart_info}");
+
+    let (tx, rx) = queue::channel(1, QueuePolicy::Block, None, Vec::new());
+    tx.send(restart_info).await?;
+    drop(tx);
+    send_once(config, rx).await?;
+    let counts = metrics::delivery_counts();
+    if counts.failed > 0 {
+        anyhow::bail!("Failed to send the test notification");
+    }
+    if counts.sent == 0 {
+        anyhow::bail!("The test notification was dropped by NOTIFICATION_MIDDLEWARES");
+    }
+    println!("Test notification sent");
+    Ok(())
+}
+
+/// Waits for SIGTERM sent by Kubernetes or Ctrl-C.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        result = tokio::signal::ctrl_c() => result?,
+    }
+    Ok(())
+}
+
+/// Prints the Slack app manifest for the current configuration.
+/// `base_url` is the public URL of johari-mirror.
+#[cfg(feature = "slack")]
+fn generate_manifest(base_url: Option<String>) -> anyhow::Result<()> {
+    let config = std::env::var("SLACK_NOTIFICATION_CONFIG")?.parse::<NotificationConfig>()?;
+    // Slash commands are enabled only when the signing secret is configured
+    let command_url = if std::env::var("SLACK_SIGNING_SECRET").is_ok() {
+        let base_url =
+            base_url.context("Public URL of johari-mirror is required for slash commands")?;
+        Some(format!("{}/slack/commands", base_url.trim_end_matches('/')))
+    } else {
+        None
+    };
+    let resolve_channels = match std::env::var("SLACK_RESOLVE_CHANNELS") {
+        Ok(enabled) => enabled.parse().context("Invalid SLACK_RESOLVE_CHANNELS")?,
+        Err(_) => false,
+    };
+    let manifest = manifest::generate(&config, command_url.as_deref(), resolve_channels);
+    println!("{}", serde_json::to_string_pretty(&manifest)?);
+    Ok(())
+}
+
+/// Prints the ServiceAccount in `namespace` and RBAC objects with the permissions required
+/// by the features enabled in the environment variables.
+fn generate_rbac(namespace: &str) -> anyhow::Result<()> {
+    let watch = WatchConfig::from_env()?;
+    let features = RbacFeatures {
+        pod_events: watch.pod_events,
+        pod_annotations: pod_annotations_from_env()?,
+        silence_namespace: std::env::var("SILENCE_NAMESPACE").ok(),
+        impersonate: impersonate_from_env()?,
+        watch_namespaces: watch.namespaces,
+        describe: watch.notification_config.has_describe(),
+        pdb_alerts: std::env::var("PDB_ALERT_CHANNEL").is_ok(),
+        node_info: watch.node_info,
+    };
+    print!("{}", yaml::to_yaml(&rbac::generate(&features, namespace)));
+    Ok(())
+}
+
+/// Object annotated with the last notification by `POD_ANNOTATIONS`, if any
+fn pod_annotations_from_env() -> anyhow::Result<Option<AnnotationTarget>> {
+    match std::env::var("POD_ANNOTATIONS") {
+        Ok(target) => Ok(Some(target.parse().context("Invalid POD_ANNOTATIONS")?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// User and groups impersonated by the Kubernetes client, read from
+/// `KUBE_IMPERSONATE_USER` and `KUBE_IMPERSONATE_GROUPS`
+fn impersonate_from_env() -> anyhow::Result<Option<(String, Vec<String>)>> {
+    let groups = std::env::var("KUBE_IMPERSONATE_GROUPS").ok().map(|groups| {
+        groups
+            .split(',')
+            .filter(|group| !group.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    });
+    // The API server requires a user to impersonate groups
+    match (std::env::var("KUBE_IMPERSONATE_USER"), groups) {
+        (Ok(user), groups) => Ok(Some((user, groups.unwrap_or_default()))),
+        (Err(_), Some(_)) => {
+            anyhow::bail!("KUBE_IMPERSONATE_GROUPS requires KUBE_IMPERSONATE_USER")
+        }
+        (Err(_), None) => Ok(None),
+    }
+}
 
+/// Initializes the logger with `RUST_LOG` filter.
+/// Logs are formatted in JSON with fields of the current span when `LOG_FORMAT=json`.
+/// Spans are exported with OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+/// Error logs are captured with fields of the current span when `sentry` is enabled.
+fn init_tracing(sentry: bool) -> anyhow::Result<()> {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("johari_mirror=debug"));
+    let fmt = tracing_subscriber::fmt::layer();
+    let fmt = if std::env::var("LOG_FORMAT").as_deref() == Ok("json") {
+        fmt.json().flatten_event(true).boxed()
+    } else {
+        fmt.boxed()
+    };
+    #[cfg(feature = "otel")]
+    let otel = if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok() {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+            .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+                opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new(
+                    "service.name",
+                    "johari-mirror",
+                )]),
+            ))
+            .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+        Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    } else {
+        None
+    };
+    #[cfg(not(feature = "otel"))]
+    let otel = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(_) => anyhow::bail!("OTEL_EXPORTER_OTLP_ENDPOINT requires the `otel` feature"),
+        Err(_) => None::<tracing_subscriber::layer::Identity>,
+    };
+    #[cfg(feature = "sentry")]
+    let sentry = sentry.then(sentry::integrations::tracing::layer);
+    #[cfg(not(feature = "sentry"))]
+    let sentry = sentry.then(tracing_subscriber::layer::Identity::new);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt)
+        .with(otel)
+        .with(sentry)
+        .init();
     Ok(())
 }
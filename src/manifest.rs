@@ -0,0 +1,99 @@
+use serde_json::json;
+
+use crate::kubernetes::NotificationConfig;
+
+/// Generates the Slack app manifest for the features enabled in `config`.
+/// Slash commands are enabled when `command_url` is specified.
+/// Channels are listed with `resolve_channels` of `SLACK_RESOLVE_CHANNELS`.
+/// https://api.slack.com/reference/manifests
+pub fn generate(
+    config: &NotificationConfig,
+    command_url: Option<&str>,
+    resolve_channels: bool,
+) -> serde_json::Value {
+    let mut scopes = vec![
+        "chat:write",
+        "chat:write.public",
+        "files:write",
+        "channels:join",
+    ];
+    if config.has_mentions() {
+        scopes.push("usergroups:read");
+    }
+    if resolve_channels {
+        scopes.push("channels:read");
+        scopes.push("groups:read");
+    }
+    if command_url.is_some() {
+        scopes.push("commands");
+    }
+
+    let mut features = json!({
+        "bot_user": {
+            "display_name": "johari-mirror",
+            "always_online": true,
+        },
+    });
+    if let Some(url) = command_url {
+        features["slash_commands"] = json!([{
+            "command": "/johari",
+            "url": url,
+            "description": "Manage notifications of johari-mirror",
+            "usage_hint": "silence <namespace/pod/container> <duration> | silences",
+            "should_escape": false,
+        }]);
+    }
+
+    json!({
+        "display_information": {
+            "name": "johari-mirror",
+            "description": "Notifies container restarts in Kubernetes",
+        },
+        "features": features,
+        "oauth_config": {
+            "scopes": {
+                "bot": scopes,
+            },
+        },
+        "settings": {
+            "org_deploy_enabled": false,
+            "socket_mode_enabled": false,
+            "token_rotation_enabled": false,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let config = "*/*/*=monitoring".parse().unwrap();
+        let manifest = generate(&config, None, false);
+        assert_eq!(
+            manifest["oauth_config"]["scopes"]["bot"],
+            json!([
+                "chat:write",
+                "chat:write.public",
+                "files:write",
+                "channels:join"
+            ])
+        );
+        assert_eq!(
+            manifest["features"]["slash_commands"],
+            serde_json::Value::Null
+        );
+
+        let config = "*/*/*=monitoring;mention=@oncall".parse().unwrap();
+        let url = "https://johari.example.com/slack/commands";
+        let manifest = generate(&config, Some(url), true);
+        let scopes = manifest["oauth_config"]["scopes"]["bot"]
+            .as_array()
+            .unwrap();
+        assert!(scopes.contains(&json!("usergroups:read")));
+        assert!(scopes.contains(&json!("commands")));
+        assert!(scopes.contains(&json!("groups:read")));
+        assert_eq!(manifest["features"]["slash_commands"][0]["url"], url);
+    }
+}
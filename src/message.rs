@@ -1,5 +1,14 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::{
+    category::CrashCategory, kubernetes::NotificationOptions, metrics::DeliveryCounts,
+    severity::Severity,
+};
+
 /// Number of log lines to include in the main message
 const LOG_SUMMARY_LINES: usize = 20;
 
@@ -7,26 +16,353 @@ const LOG_SUMMARY_LINES: usize = 20;
 /// https://api.slack.com/reference/block-kit/blocks#section_fields
 const SECTION_TEXT_LIMIT: usize = 3000;
 
+/// Maximum number of messages to post container logs in the thread
+/// when uploading the log file failed
+const LOG_CHUNKS_LIMIT: usize = 10;
+
+/// Maximum length of each text in `fields` of `section` blocks
+const SECTION_FIELD_TEXT_LIMIT: usize = 2000;
+
+/// Maximum number of `fields` in a `section` block
+const SECTION_FIELDS_LIMIT: usize = 10;
+
+/// Maximum number of blocks in a Slack message
+/// https://api.slack.com/reference/block-kit/blocks
+const MESSAGE_BLOCKS_LIMIT: usize = 50;
+
 /// Number of characters to include in the log summary.
 /// Set 200 characters margin for header and footer.
 const LOG_SUMMARY_CHARS: usize = SECTION_TEXT_LIMIT - 200;
 
-#[derive(Debug)]
+/// Number of last log lines in `excerpt`
+const EXCERPT_LINES: usize = 20;
+
+/// Maximum size of `excerpt` in bytes
+const EXCERPT_BYTES: usize = 4096;
+
+/// Maximum size of a log file uploaded to Slack.
+/// Larger logs are split into multiple files.
+const LOG_FILE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ContainerRestartInfo {
     pub namespace: Option<String>,
     pub pod_name: String,
+    /// UID of the pod, `None` for synthetic restarts
+    #[serde(default)]
+    pub pod_uid: Option<String>,
     pub container_name: String,
     pub container_image: String,
     pub node_name: Option<String>,
+    /// Name of the owning workload, e.g. Deployment, or the pod name for standalone pods
+    #[serde(default)]
+    pub workload: Option<String>,
+    /// Labels of the pod, e.g. `app.kubernetes.io/version`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
     pub restart_count: i32,
     pub last_state: Option<ContainerState>,
     pub resources: ContainerResources,
     pub logs: ContainerLog,
     pub channel: String,
+    pub options: NotificationOptions,
+    /// Links to related resources shown in the message, e.g. issues
+    #[serde(default)]
+    pub links: Vec<MessageLink>,
+    /// Remarks shown in the message, e.g. an anomalous restart rate
+    #[serde(default)]
+    pub notes: Vec<String>,
+    /// Likely cause of the crash, `None` when unknown
+    #[serde(default)]
+    pub category: Option<CrashCategory>,
+    /// Current logs of sibling containers selected by the `sidecar_logs` option
+    #[serde(default)]
+    pub sidecar_logs: Vec<SidecarLog>,
+    /// Priority of the pod, `None` when the pod has no priority
+    #[serde(default)]
+    pub priority: Option<PodPriority>,
+    /// OS and versions of the node, `None` unless `NODE_INFO` is enabled
+    #[serde(default)]
+    pub node_info: Option<NodeInfo>,
+    /// Files uploaded with the logs, e.g. the pod spec
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Span of the restart detection, which the notification span belongs to
+    #[serde(skip, default = "tracing::Span::none")]
+    pub span: tracing::Span,
+    /// ID in `DiskQueue` when the notification is persisted
+    #[serde(skip)]
+    pub queue_id: Option<u64>,
 }
 
 impl ContainerRestartInfo {
-    pub fn to_message(&self, file_url: &Option<String>) -> serde_json::Value {
+    /// Fake restart with canned logs to verify routing and delivery, sent by `sender`
+    /// e.g. `johari-mirror send-test`
+    pub fn synthetic(
+        namespace: &str,
+        pod: &str,
+        container: &str,
+        channel: String,
+        options: NotificationOptions,
+        sender: &str,
+    ) -> Self {
+        Self {
+            namespace: Some(namespace.to_owned()),
+            pod_name: pod.to_owned(),
+            pod_uid: None,
+            container_name: container.to_owned(),
+            container_image: "johari-mirror/synthetic".to_owned(),
+            node_name: None,
+            workload: None,
+            labels: Default::default(),
+            restart_count: 1,
+            last_state: Some(ContainerState {
+                exit_code: 1,
+                signal: None,
+                reason: Some("Error".to_owned()),
+                message: Some(format!("Test notification sent by {sender}")),
+                started_at: None,
+                finished_at: None,
+            }),
+            resources: ContainerResources::default(),
+            logs: ContainerLog(Ok(format!(
+                "This is a test notification sent by {sender}.\n"
+            ))),
+            channel,
+            options,
+            links: Vec::new(),
+            notes: Vec::new(),
+            category: None,
+            sidecar_logs: Vec::new(),
+            priority: None,
+            node_info: None,
+            attachments: Vec::new(),
+            span: tracing::Span::none(),
+            queue_id: None,
+        }
+    }
+
+    pub fn to_message(&self, file_urls: &[String]) -> Vec<serde_json::Value> {
+        if self.options.layout == MessageLayout::Compact {
+            let logs = match file_urls.first() {
+                Some(url) => format!(" | <{url}|Container logs>"),
+                None => String::new(),
+            };
+            return vec![self.compact_block(&logs)];
+        }
+        let mut blocks = self.summary_blocks();
+        blocks.push(self.log_block(file_urls));
+        blocks.extend(self.sidecar_log_blocks());
+        blocks
+    }
+
+    /// Titles and contents of files of container logs, empty when logs are empty or failed
+    /// to fetch. Logs larger than `LOG_FILE_BYTES` are split into multiple files.
+    pub fn log_files(&self) -> Vec<(String, &str)> {
+        let log = match self.logs.0.as_ref().map(|log| log.trim_end()) {
+            Ok(log) if !log.is_empty() => log,
+            _empty_or_error => return Vec::new(),
+        };
+        let title = format!(
+            "{}_{}_{}",
+            self.namespace.as_deref().unwrap_or(""),
+            &self.pod_name,
+            &self.container_name
+        );
+        let parts = split_log(log, LOG_FILE_BYTES);
+        let count = parts.len();
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let title = if count == 1 {
+                    title.clone()
+                } else {
+                    format!("{}_part{}of{}", title, i + 1, count)
+                };
+                (title, part)
+            })
+            .collect()
+    }
+
+    /// Message without container logs, which are posted in the thread by `to_log_message`.
+    pub fn to_summary_message(&self) -> Vec<serde_json::Value> {
+        if self.options.layout == MessageLayout::Compact {
+            return vec![self.compact_block(" | Container logs in the thread")];
+        }
+        let mut blocks = self.summary_blocks();
+        blocks.push(json!({
+            "type": "context",
+            "elements": [markdown_text("Container logs are posted in the thread.")],
+        }));
+        blocks
+    }
+
+    /// Message to the escalation channel without container logs, linking the notification
+    /// in the routed channel when its permalink is known.
+    pub fn to_escalation_message(
+        &self,
+        escalate_after: u32,
+        permalink: Option<&str>,
+    ) -> Vec<serde_json::Value> {
+        let mut blocks = self.summary_blocks();
+        let notification = match permalink {
+            Some(permalink) => format!("<{permalink}|notification in {}>", self.channel),
+            None => format!("notification in {}", self.channel),
+        };
+        blocks.push(json!({
+            "type": "context",
+            "elements": [markdown_text(&format!(
+                "Escalated after more than {escalate_after} restarts. \
+                 See the {notification} for container logs."
+            ))],
+        }));
+        blocks
+    }
+
+    /// Message with container logs only, to be posted in the thread.
+    pub fn to_log_message(&self, file_urls: &[String]) -> Vec<serde_json::Value> {
+        let mut blocks = vec![self.log_block(file_urls)];
+        blocks.extend(self.sidecar_log_blocks());
+        blocks
+    }
+
+    /// Messages with the whole container logs as code blocks, to be posted in the thread
+    /// when uploading the log file failed.
+    pub fn to_log_chunk_messages(&self) -> Vec<Vec<serde_json::Value>> {
+        self.logs
+            .chunks()
+            .into_iter()
+            .map(|chunk| {
+                vec![json!({
+                    "type": "section",
+                    "text": markdown_text(&format!("```\n{chunk}\n```")),
+                })]
+            })
+            .collect()
+    }
+
+    /// Slack message metadata for workflows and bots consuming notifications
+    /// https://api.slack.com/metadata
+    pub fn to_metadata(&self) -> serde_json::Value {
+        json!({
+            "event_type": "container_restarted",
+            "event_payload": {
+                "namespace": self.namespace.as_deref().unwrap_or(""),
+                "pod": &self.pod_name,
+                "container": &self.container_name,
+                "image": &self.container_image,
+                "restart_count": self.restart_count,
+                "exit_code": self.last_state.as_ref().map(|s| s.exit_code),
+                "reason": self.last_state.as_ref().and_then(|s| s.reason.as_deref()),
+                "severity": self.options.severity.as_deref(),
+            },
+        })
+    }
+
+    /// Identifies similar crashes across pods of a workload and restarts.
+    /// Hash of the namespace, container name, exit code, reason and the last log line
+    /// with numbers masked, e.g. timestamps and IDs.
+    pub fn fingerprint(&self) -> String {
+        let state = self.last_state.as_ref();
+        let last_line = match &self.logs.0 {
+            Ok(logs) => logs.lines().rev().find(|l| !l.trim().is_empty()),
+            Err(_) => None,
+        };
+        let mut masked = String::new();
+        for c in last_line.unwrap_or("").chars() {
+            if !c.is_ascii_digit() {
+                masked.push(c);
+            } else if !masked.ends_with('0') {
+                masked.push('0');
+            }
+        }
+        let key = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.namespace.as_deref().unwrap_or(""),
+            self.container_name,
+            state.map_or(String::new(), |s| s.exit_code.to_string()),
+            state.and_then(|s| s.reason.as_deref()).unwrap_or(""),
+            masked,
+        );
+        // FNV-1a, stable across builds unlike `DefaultHasher`
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        format!("{hash:016x}")
+    }
+
+    /// Key of the message of the restart in `MessageStore`, which is of the container of
+    /// the workload with the `aggregate` option
+    pub fn message_key(&self) -> String {
+        if self.options.aggregate {
+            self.workload_key()
+        } else {
+            self.container_key()
+        }
+    }
+
+    /// Key to identify the container across restarts
+    pub fn container_key(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.namespace.as_deref().unwrap_or(""),
+            self.pod_name,
+            self.container_name
+        )
+    }
+
+    /// Key to identify the container across pods of the workload, or of the pod without a
+    /// known workload
+    pub fn workload_key(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.namespace.as_deref().unwrap_or(""),
+            self.workload.as_deref().unwrap_or(&self.pod_name),
+            self.container_name
+        )
+    }
+
+    /// Header with the category and the severity, e.g. `Container restarted: OOM killed`
+    fn header(&self) -> String {
+        let mut header = match self.category {
+            Some(category) => format!("Container restarted: {}", category.label()),
+            None => "Container restarted".to_owned(),
+        };
+        if let Some(severity) = &self.options.severity {
+            header.push_str(&format!(" [{severity}]"));
+            if let Ok(severity) = severity.parse::<Severity>() {
+                header = format!("{} {header}", severity.emoji());
+            }
+        }
+        header
+    }
+
+    /// One section of the header, the container, the exit code and `logs` for
+    /// `MessageLayout::Compact`
+    fn compact_block(&self, logs: &str) -> serde_json::Value {
+        let exit = match &self.last_state {
+            Some(state) => format!(
+                "exited with `{}` ({})",
+                state.exit_code,
+                format_name(&state.reason)
+            ),
+            None => "restarted".to_owned(),
+        };
+        json!({
+            "type": "section",
+            "text": markdown_text(&format!(
+                "*{}*\n`{}/{}` `{}` {exit}, {} restarts{logs}",
+                self.header(),
+                escape_mrkdwn(self.namespace.as_deref().unwrap_or("")),
+                escape_mrkdwn(&self.pod_name),
+                escape_mrkdwn(&self.container_name),
+                self.restart_count,
+            )),
+        })
+    }
+
+    fn summary_blocks(&self) -> Vec<serde_json::Value> {
         let container_identity = format!(
             r"Namespace: {}
 Pod: `{}`
@@ -34,40 +370,92 @@ Container Name: `{}`
 Container Image: `{}`
 Node Name: {}",
             format_name(&self.namespace),
-            &self.pod_name,
-            &self.container_name,
-            &self.container_image,
+            escape_mrkdwn(&self.pod_name),
+            escape_mrkdwn(&self.container_name),
+            escape_mrkdwn(&self.container_image),
             format_name(&self.node_name),
         );
+        let container_identity = match &self.node_info {
+            Some(node_info) => format!("{container_identity}\nNode: {}", node_info.to_message()),
+            None => container_identity,
+        };
+        let container_identity = match &self.priority {
+            Some(priority) => format!("{container_identity}\nPriority: {}", priority.to_message()),
+            None => container_identity,
+        };
         let stats = build_container_stats(self.restart_count, &self.last_state);
         let resources = self.resources.to_message();
-        let logs = self.logs.to_message(file_url);
 
-        json!([
-            {
+        let mut blocks = vec![
+            json!({
                 "type": "header",
                 "text": {
                     "type": "plain_text",
-                    "text": "Container restarted",
+                    "text": self.header(),
                 },
-            },
-            {
+            }),
+            json!({
                 "type": "section",
                 "text": markdown_text(&container_identity),
-            },
-            {
+            }),
+        ];
+        if self
+            .priority
+            .as_ref()
+            .is_some_and(|priority| priority.preempted)
+        {
+            blocks.push(json!({
+                "type": "context",
+                "elements": [markdown_text(
+                    ":arrow_down_small: The pod was preempted for pods of higher priority, \
+                     which is not a crash of the container.",
+                )],
+            }));
+        }
+        blocks.extend([
+            json!({
                 "type": "section",
                 "fields": stats,
-            },
-            {
+            }),
+            json!({
                 "type": "section",
                 "fields": resources,
-            },
-            {
+            }),
+        ]);
+        if !self.notes.is_empty() {
+            blocks.push(json!({
+                "type": "context",
+                "elements": [markdown_text(&self.notes.join("\n"))],
+            }));
+        }
+        if !self.links.is_empty() {
+            let links = self
+                .links
+                .iter()
+                .map(|l| format!("<{}|{}>", l.url, escape_mrkdwn(&l.title)))
+                .collect::<Vec<_>>();
+            blocks.push(json!({
+                "type": "context",
+                "elements": [markdown_text(&links.join(" | "))],
+            }));
+        }
+        blocks
+    }
+
+    fn log_block(&self, file_urls: &[String]) -> serde_json::Value {
+        json!({
+            "type": "section",
+            "text": markdown_text(&self.logs.to_message(file_urls)),
+        })
+    }
+
+    fn sidecar_log_blocks(&self) -> impl Iterator<Item = serde_json::Value> + '_ {
+        self.sidecar_logs.iter().map(|sidecar| {
+            json!({
                 "type": "section",
-                "text": markdown_text(&logs),
-            },
-        ])
+                "text": markdown_text(&sidecar.to_message()),
+            })
+        })
     }
 }
 
@@ -83,6 +471,334 @@ impl std::fmt::Display for ContainerRestartInfo {
     }
 }
 
+/// Section block to mention users, e.g. `<!subteam^ID>`
+pub fn mention_block(mention: &str) -> serde_json::Value {
+    json!({
+        "type": "section",
+        "text": markdown_text(mention),
+    })
+}
+
+/// Reply to a notification when the container has been running without restarts
+pub fn stable_message(container_name: &str, since: DateTime<Utc>) -> Vec<serde_json::Value> {
+    let running_for = crate::silence::format_duration(Utc::now() - since);
+    vec![json!({
+        "type": "section",
+        "text": markdown_text(&format!(
+            ":white_check_mark: `{container_name}` has stabilized, running without restarts \
+             for {running_for} since <!date^{}^{{date_short_pretty}} {{time}}|{}>.",
+            since.timestamp(),
+            since.to_rfc3339(),
+        )),
+    })]
+}
+
+/// Reply to a notification with the first lines of logs of the new container `delay`
+/// after the restart, headed by its `health`
+pub fn startup_log_message(
+    container_name: &str,
+    delay: std::time::Duration,
+    health: &str,
+    logs: &Result<String, String>,
+) -> Vec<serde_json::Value> {
+    let title = format!(
+        "*Startup logs of `{}`* {} after the restart",
+        escape_mrkdwn(container_name),
+        crate::silence::format_duration(
+            k8s_openapi::chrono::Duration::from_std(delay).unwrap_or_default()
+        ),
+    );
+    let logs = match logs {
+        Ok(logs) if logs.is_empty() => "(empty)".to_owned(),
+        Ok(logs) => format!("```\n{}\n```", ContainerLog::tail_lines(logs)),
+        Err(err) => format!("Failed to get container logs: {}", escape_mrkdwn(err)),
+    };
+    vec![json!({
+        "type": "section",
+        "text": markdown_text(&format!("{health}\n{title}\n{logs}")),
+    })]
+}
+
+/// Context block appended to a message which is updated on repeated restarts
+pub fn updated_context(notified_restarts: usize) -> serde_json::Value {
+    let now = k8s_openapi::chrono::Utc::now();
+    json!({
+        "type": "context",
+        "elements": [markdown_text(&format!(
+            "Updated on {} restarts, last at <!date^{}^{{date_short_pretty}} {{time}}|{}>",
+            notified_restarts,
+            now.timestamp(),
+            now.to_rfc3339(),
+        ))],
+    })
+}
+
+/// Number of pods listed in the context of messages of workloads
+const WORKLOAD_PODS_LIMIT: usize = 10;
+
+/// Context block of the message of `workload` with the `aggregate` option, listing the
+/// restart counts of `pods`
+pub fn workload_context(workload: &str, pods: &BTreeMap<String, i32>) -> serde_json::Value {
+    let mut listed = pods
+        .iter()
+        .take(WORKLOAD_PODS_LIMIT)
+        .map(|(pod, count)| format!("`{}` ({count})", escape_mrkdwn(pod)))
+        .collect::<Vec<_>>();
+    if pods.len() > WORKLOAD_PODS_LIMIT {
+        listed.push(format!("and {} more", pods.len() - WORKLOAD_PODS_LIMIT));
+    }
+    json!({
+        "type": "context",
+        "elements": [markdown_text(&format!(
+            ":package: {} pods of `{}` restarted: {}. Each restart is in the thread.",
+            pods.len(),
+            escape_mrkdwn(workload),
+            listed.join(", "),
+        ))],
+    })
+}
+
+/// Context block prepended to a message rerouted to the fallback channel
+pub fn fallback_context(channel: &str, error: &str) -> serde_json::Value {
+    json!({
+        "type": "context",
+        "elements": [markdown_text(&format!(
+            "Failed to post to `{channel}` ({error}), posted here instead."
+        ))],
+    })
+}
+
+/// Context block posted instead of notifications dropped by the full queue
+pub fn dropped_notifications_block(count: usize) -> serde_json::Value {
+    json!({
+        "type": "context",
+        "elements": [markdown_text(&format!(
+            ":warning: {count} restart notifications were dropped because the notification queue was full."
+        ))],
+    })
+}
+
+/// Summary of restarts not notified due to the rate limit.
+/// `restarts` maps container keys to the number of restarts.
+pub fn rate_limited_summary(
+    restarts: &std::collections::BTreeMap<String, usize>,
+) -> Vec<serde_json::Value> {
+    let total: usize = restarts.values().sum();
+    restarts_summary(
+        &format!(
+            ":warning: {total} container restarts were not notified individually due to the rate limit."
+        ),
+        restarts,
+    )
+}
+
+/// Alert of restarts of many containers on `node` within `window`.
+/// `restarts` maps container keys to the number of restarts.
+pub fn node_alert(
+    node: &str,
+    window: &str,
+    restarts: &std::collections::BTreeMap<String, usize>,
+) -> Vec<serde_json::Value> {
+    restarts_summary(
+        &format!(
+            ":rotating_light: {} containers on node `{}` restarted in {window}. The node may be unhealthy.",
+            restarts.len(),
+            escape_mrkdwn(node),
+        ),
+        restarts,
+    )
+}
+
+/// Alert of the PodDisruptionBudget `pdb` allowing no disruptions for `period` while
+/// `crash_looping` of its `pods` are crash-looping
+pub fn pdb_alert(
+    pdb: &str,
+    period: &str,
+    crash_looping: &[String],
+    pods: usize,
+) -> Vec<serde_json::Value> {
+    let mut listed = crash_looping
+        .iter()
+        .take(WORKLOAD_PODS_LIMIT)
+        .map(|pod| format!("`{}`", escape_mrkdwn(pod)))
+        .collect::<Vec<_>>();
+    if crash_looping.len() > WORKLOAD_PODS_LIMIT {
+        listed.push(format!(
+            "and {} more",
+            crash_looping.len() - WORKLOAD_PODS_LIMIT
+        ));
+    }
+    vec![json!({
+        "type": "section",
+        "text": markdown_text(&format!(
+            ":construction: PodDisruptionBudget `{}` has allowed no disruptions for {period} \
+             while {} of {pods} pods are crash-looping: {}. Draining their nodes is blocked \
+             until the pods recover.",
+            escape_mrkdwn(pdb),
+            crash_looping.len(),
+            listed.join(", "),
+        )),
+    })]
+}
+
+/// Summary of restarts detected during the startup grace period.
+/// `restarts` maps container keys to the number of restarts.
+pub fn startup_summary(
+    restarts: &std::collections::BTreeMap<String, usize>,
+) -> Vec<serde_json::Value> {
+    let total: usize = restarts.values().sum();
+    restarts_summary(
+        &format!(
+            ":arrows_counterclockwise: {total} container restarts were detected while johari-mirror was starting."
+        ),
+        restarts,
+    )
+}
+
+/// Announcement of johari-mirror `version` starting with `rules` rules of
+/// `SLACK_NOTIFICATION_CONFIG`, watching `namespaces` or all namespaces when empty
+pub fn startup_announcement(
+    version: &str,
+    cluster: Option<&str>,
+    namespaces: &[String],
+    rules: usize,
+    routing_script: bool,
+) -> Vec<serde_json::Value> {
+    let namespaces = if namespaces.is_empty() {
+        "all namespaces".to_owned()
+    } else {
+        namespaces
+            .iter()
+            .map(|namespace| format!("`{}`", escape_mrkdwn(namespace)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let script = if routing_script {
+        " and ROUTING_SCRIPT"
+    } else {
+        ""
+    };
+    vec![json!({
+        "type": "section",
+        "text": markdown_text(&format!(
+            ":large_green_circle: johari-mirror `{}` started{}, watching {namespaces} with \
+             {rules} rules{script}.",
+            escape_mrkdwn(version),
+            in_cluster(cluster),
+        )),
+    })]
+}
+
+/// Announcement of johari-mirror `version` stopping gracefully after running for `uptime`,
+/// with `counts` of notifications since the start and `left` notifications not delivered
+pub fn shutdown_announcement(
+    version: &str,
+    cluster: Option<&str>,
+    uptime: &str,
+    counts: DeliveryCounts,
+    left: usize,
+) -> Vec<serde_json::Value> {
+    vec![json!({
+        "type": "section",
+        "text": markdown_text(&format!(
+            ":red_circle: johari-mirror `{}` stopped{} after running for {uptime}: {} \
+             notifications delivered, {} failed, {} dropped, {left} left in the queue. \
+             Restarts are not notified until it starts again.",
+            escape_mrkdwn(version),
+            in_cluster(cluster),
+            counts.sent,
+            counts.failed,
+            counts.dropped,
+        )),
+    })]
+}
+
+/// e.g. `` in cluster `production` ``, or empty without the cluster name
+fn in_cluster(cluster: Option<&str>) -> String {
+    match cluster {
+        Some(cluster) => format!(" in cluster `{}`", escape_mrkdwn(cluster)),
+        None => String::new(),
+    }
+}
+
+fn restarts_summary(
+    header: &str,
+    restarts: &std::collections::BTreeMap<String, usize>,
+) -> Vec<serde_json::Value> {
+    let containers = restarts
+        .iter()
+        .map(|(container, count)| format!("• `{}` ({count})", escape_mrkdwn(container)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    vec![
+        json!({
+            "type": "section",
+            "text": markdown_text(header),
+        }),
+        json!({
+            "type": "section",
+            "text": markdown_text(&containers),
+        }),
+    ]
+}
+
+/// Splits and trims `blocks` to fit in Slack limits.
+/// Returns blocks of each message, the first of which is the main message.
+/// - Texts of sections and fields are trimmed.
+/// - Sections with too many fields are split into multiple sections.
+/// - Blocks are split into multiple messages when there are too many.
+pub fn fit_blocks(blocks: Vec<serde_json::Value>) -> Vec<Vec<serde_json::Value>> {
+    let mut fitted = Vec::new();
+    for mut block in blocks {
+        if block["type"] != "section" {
+            fitted.push(block);
+            continue;
+        }
+        if let Some(text) = block["text"]["text"].as_str() {
+            block["text"]["text"] = trim_text(text, SECTION_TEXT_LIMIT).into();
+        }
+        let Some(fields) = block["fields"].as_array().cloned() else {
+            fitted.push(block);
+            continue;
+        };
+        let fields = fields
+            .into_iter()
+            .map(|mut field| {
+                if let Some(text) = field["text"].as_str() {
+                    field["text"] = trim_text(text, SECTION_FIELD_TEXT_LIMIT).into();
+                }
+                field
+            })
+            .collect::<Vec<_>>();
+        for chunk in fields.chunks(SECTION_FIELDS_LIMIT) {
+            let mut section = block.clone();
+            section["fields"] = chunk.into();
+            fitted.push(section);
+            // Text is shown only once, in the first section
+            if let Some(block) = block.as_object_mut() {
+                block.remove("text");
+            }
+        }
+    }
+    if fitted.is_empty() {
+        return vec![fitted];
+    }
+    fitted
+        .chunks(MESSAGE_BLOCKS_LIMIT)
+        .map(<[_]>::to_vec)
+        .collect()
+}
+
+/// Trims `text` to `limit` characters, marking the omission with an ellipsis
+fn trim_text(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_owned();
+    }
+    let mut trimmed = text.chars().take(limit - 1).collect::<String>();
+    trimmed.push('…');
+    trimmed
+}
+
 fn build_container_stats(
     restart_count: i32,
     state: &Option<ContainerState>,
@@ -114,11 +830,97 @@ fn build_container_stats(
             "Finished at: {}",
             format_name(&state.finished_at)
         )));
+        if let Some(backoff) = Backoff::estimate(restart_count, state) {
+            container_stats.extend(backoff.to_fields());
+        }
     }
     container_stats
 }
 
-#[derive(Debug)]
+/// Initial delay of CrashLoopBackOff of the kubelet, doubled on each crash
+const BACKOFF_INITIAL_SECS: i64 = 10;
+const BACKOFF_MAX_SECS: i64 = 300;
+/// The kubelet resets the delay of containers which ran longer than this
+const BACKOFF_RESET_SECS: i64 = 600;
+
+/// CrashLoopBackOff delay before the restart of the terminated container, estimated in
+/// the same way as the kubelet
+#[derive(Debug, PartialEq, Eq)]
+struct Backoff {
+    delay_secs: i64,
+    next_restart: DateTime<Utc>,
+}
+
+impl Backoff {
+    /// `None` when the termination time is unknown
+    fn estimate(restart_count: i32, state: &ContainerState) -> Option<Self> {
+        let parse = |t: &Option<String>| {
+            DateTime::parse_from_rfc3339(t.as_deref()?)
+                .ok()
+                .map(|t| t.with_timezone(&Utc))
+        };
+        let finished_at = parse(&state.finished_at)?;
+        let ran_secs = parse(&state.started_at).map(|t| (finished_at - t).num_seconds());
+        let delay_secs = if ran_secs.is_some_and(|secs| secs >= BACKOFF_RESET_SECS) {
+            BACKOFF_INITIAL_SECS
+        } else {
+            // 10s, 20s, 40s, ... up to 5m on consecutive crashes
+            let doublings = (restart_count - 1).clamp(0, 5) as u32;
+            (BACKOFF_INITIAL_SECS << doublings).min(BACKOFF_MAX_SECS)
+        };
+        Some(Self {
+            delay_secs,
+            next_restart: finished_at + k8s_openapi::chrono::Duration::seconds(delay_secs),
+        })
+    }
+
+    /// Delay, whether the restarts are still accelerating, and the next restart time
+    fn to_fields(&self) -> [serde_json::Value; 2] {
+        let trend = if self.delay_secs < BACKOFF_MAX_SECS {
+            "doubling on the next crash"
+        } else {
+            "max"
+        };
+        [
+            markdown_text(&format!("Backoff: `{}s` ({trend})", self.delay_secs)),
+            markdown_text(&format!(
+                "Next restart: <!date^{}^{{date_short_pretty}} {{time_secs}}|{}>",
+                self.next_restart.timestamp(),
+                self.next_restart.to_rfc3339(),
+            )),
+        ]
+    }
+}
+
+/// File uploaded with the notification besides the logs, e.g. `pod.yaml`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Shown in the link to the file, e.g. `Pod spec`
+    pub title: String,
+    pub filename: String,
+    pub content: String,
+}
+
+/// Links to the uploaded attachments of `files` of titles and URLs
+pub fn attachments_context(files: &[(String, String)]) -> serde_json::Value {
+    let links = files
+        .iter()
+        .map(|(title, url)| format!(":paperclip: <{url}|{}>", escape_mrkdwn(title)))
+        .collect::<Vec<_>>();
+    json!({
+        "type": "context",
+        "elements": [markdown_text(&links.join(" | "))],
+    })
+}
+
+/// Link shown in the notification, e.g. `Jira: PROJ-123`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageLink {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ContainerState {
     pub exit_code: i32,
     pub signal: Option<i32>,
@@ -128,57 +930,255 @@ pub struct ContainerState {
     pub finished_at: Option<String>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ContainerResources {
     pub limits: Vec<(String, String)>,
     pub requests: Vec<(String, String)>,
 }
 
 impl ContainerResources {
+    /// One field per resource with its request and limit side by side,
+    /// CPU and memory first and extended resources, e.g. GPUs, last
     fn to_message(&self) -> Vec<serde_json::Value> {
         if self.limits.is_empty() && self.requests.is_empty() {
             return vec![markdown_text("No resource limits or requests")];
         }
-        let mut message = Vec::new();
-        for (resource, quantity) in &self.limits {
-            message.push(markdown_text(&format!("{resource} limit: `{quantity}`")));
+        let mut names = self
+            .requests
+            .iter()
+            .chain(&self.limits)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort_by_key(|name| (resource_order(name), *name));
+        names.dedup();
+        let quantity = |quantities: &[(String, String)], name: &str| {
+            quantities.iter().find(|(n, _)| n == name).map_or_else(
+                || "none".to_owned(),
+                |(_, q)| format!("`{}`", escape_mrkdwn(q)),
+            )
+        };
+        names
+            .into_iter()
+            .map(|name| {
+                markdown_text(&format!(
+                    "{}: request {} / limit {}",
+                    resource_label(name),
+                    quantity(&self.requests, name),
+                    quantity(&self.limits, name)
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Order of resources in messages: CPU, memory, storage, hugepages and extended resources
+fn resource_order(name: &str) -> u8 {
+    match name {
+        "cpu" => 0,
+        "memory" => 1,
+        "ephemeral-storage" => 2,
+        _ if name.starts_with("hugepages-") => 3,
+        _ => 4,
+    }
+}
+
+/// Label of resource `name`, with the name of extended resources to tell vendors apart
+fn resource_label(name: &str) -> String {
+    match name {
+        "cpu" => "*CPU*".to_owned(),
+        "memory" => "*Memory*".to_owned(),
+        "ephemeral-storage" => "*Ephemeral storage*".to_owned(),
+        _ => match name.strip_prefix("hugepages-") {
+            Some(size) => format!("*Hugepages {}*", escape_mrkdwn(size)),
+            None if name.ends_with("/gpu") => format!("*GPU* ({})", escape_mrkdwn(name)),
+            None => format!("*{}*", escape_mrkdwn(name)),
+        },
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerLog(pub Result<String, String>);
+
+/// Layout of notification messages, chosen per rule with the `layout` option
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageLayout {
+    /// All the details of the restart with logs
+    #[default]
+    Detailed,
+    /// One section of the container, the exit code and the link to the log file
+    Compact,
+}
+
+impl std::str::FromStr for MessageLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "detailed" => Ok(Self::Detailed),
+            "compact" => Ok(Self::Compact),
+            _ => anyhow::bail!("Unknown layout: {s}"),
+        }
+    }
+}
+
+impl std::fmt::Display for MessageLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Detailed => f.write_str("detailed"),
+            Self::Compact => f.write_str("compact"),
         }
-        for (resource, quantity) in &self.requests {
-            message.push(markdown_text(&format!("{resource} request: `{quantity}`")));
+    }
+}
+
+/// Priority of the pod and whether it was preempted, which looks like a crash otherwise
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PodPriority {
+    /// `priorityClassName`, `None` when the default priority is used
+    pub class_name: Option<String>,
+    pub value: Option<i32>,
+    /// Terminated by the scheduler or the kubelet to run pods of higher priority
+    pub preempted: bool,
+}
+
+impl PodPriority {
+    /// e.g. `` `batch-low` (-100) ``
+    fn to_message(&self) -> String {
+        match (&self.class_name, self.value) {
+            (Some(class_name), Some(value)) => format!("`{}` ({value})", escape_mrkdwn(class_name)),
+            (Some(class_name), None) => format!("`{}`", escape_mrkdwn(class_name)),
+            (None, Some(value)) => value.to_string(),
+            (None, None) => "unknown".to_owned(),
         }
-        message
     }
 }
 
-#[derive(Debug)]
-pub struct ContainerLog(pub Result<String, String>);
+/// OS image, container runtime and kubelet of the node, from `status.nodeInfo` of the Node
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub os_image: String,
+    /// e.g. `containerd://1.7.2`
+    pub container_runtime_version: String,
+    pub kubelet_version: String,
+}
+
+impl NodeInfo {
+    /// e.g. `` `Ubuntu 22.04.3 LTS`, `containerd://1.7.2`, kubelet `v1.29.1` ``
+    fn to_message(&self) -> String {
+        format!(
+            "`{}`, `{}`, kubelet `{}`",
+            escape_mrkdwn(&self.os_image),
+            escape_mrkdwn(&self.container_runtime_version),
+            escape_mrkdwn(&self.kubelet_version),
+        )
+    }
+}
+
+/// Logs of a sibling container at the time of the restart, e.g. `istio-proxy`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SidecarLog {
+    pub container: String,
+    pub logs: ContainerLog,
+}
+
+impl SidecarLog {
+    fn to_message(&self) -> String {
+        let container = escape_mrkdwn(&self.container);
+        match &self.logs.0 {
+            Ok(log) if log.is_empty() => format!("*Logs of sidecar `{container}`*\n(empty)"),
+            Ok(log) => format!(
+                "*Logs of sidecar `{container}`*\n```\n{}\n```",
+                ContainerLog::tail_lines(log)
+            ),
+            Err(err) => format!(
+                "Failed to get logs of sidecar `{container}`: {}",
+                escape_mrkdwn(err)
+            ),
+        }
+    }
+}
 
 impl ContainerLog {
-    fn to_message(&self, file_url: &Option<String>) -> String {
+    fn to_message(&self, file_urls: &[String]) -> String {
         match &self.0 {
             Ok(log) => {
                 if log.is_empty() {
                     "*Container logs before restart*\n(empty)".to_owned()
                 } else {
-                    // file_url is non-empty when log is not empty
-                    let file_url = file_url.as_deref().unwrap_or_default();
+                    // file_urls is empty when the upload failed
+                    let title = match file_urls.first() {
+                        Some(url) => format!("<{url}|*Container logs before restart*>"),
+                        None => "*Container logs before restart*".to_owned(),
+                    };
+                    // Links to the rest of the files when the log is split
+                    let parts = if file_urls.len() > 1 {
+                        let links = file_urls
+                            .iter()
+                            .enumerate()
+                            .map(|(i, url)| format!("<{}|part {}>", url, i + 1))
+                            .collect::<Vec<_>>();
+                        format!(" ({})", links.join(", "))
+                    } else {
+                        String::new()
+                    };
                     format!(
-                        r"<{}|*Container logs before restart*>
+                        r"{}{}
 ```
 {}
 ```",
-                        file_url,
+                        title,
+                        parts,
                         Self::tail_lines(log)
                     )
                 }
             }
-            Err(err) => format!("Failed to get container logs: {}", err),
+            Err(err) => format!("Failed to get container logs: {}", escape_mrkdwn(err)),
+        }
+    }
+
+    /// Splits the whole log into escaped chunks fitting in a section block,
+    /// keeping the last `LOG_CHUNKS_LIMIT` chunks.
+    fn chunks(&self) -> Vec<String> {
+        let Ok(log) = &self.0 else {
+            return Vec::new();
+        };
+        let mut chunks = Vec::new();
+        let mut chunk = String::new();
+        let mut count = 0;
+        for line in log.trim_end().split_inclusive('\n') {
+            let escaped = escape_mrkdwn(line);
+            let line_count = escaped.chars().count();
+            if count + line_count > LOG_SUMMARY_CHARS && count > 0 {
+                chunks.push(std::mem::take(&mut chunk));
+                count = 0;
+            }
+            if line_count <= LOG_SUMMARY_CHARS {
+                chunk += &escaped;
+                count += line_count;
+                continue;
+            }
+            // Split a long line between escaped characters
+            for c in line.chars() {
+                let escaped = escape_mrkdwn(c.encode_utf8(&mut [0; 4]));
+                let char_count = escaped.chars().count();
+                if count + char_count > LOG_SUMMARY_CHARS {
+                    chunks.push(std::mem::take(&mut chunk));
+                    count = 0;
+                }
+                chunk += &escaped;
+                count += char_count;
+            }
         }
+        if count > 0 {
+            chunks.push(chunk);
+        }
+        let skip = chunks.len().saturating_sub(LOG_CHUNKS_LIMIT);
+        chunks.split_off(skip)
     }
 
-    /// Returns suffix of `log`, shorter one of:
+    /// Returns escaped suffix of `log`, shorter one of:
     /// - last `LOG_SUMMARY_LINES` lines
-    /// - last `LOG_SUMMARY_CHARS` characters
+    /// - last `LOG_SUMMARY_CHARS` characters after escaping
     fn tail_lines(log: &str) -> String {
         let mut lines = log
             .lines()
@@ -186,18 +1186,46 @@ impl ContainerLog {
             .take(LOG_SUMMARY_LINES)
             .collect::<Vec<_>>();
         lines.reverse();
-        suffix(&lines.join("\n"), LOG_SUMMARY_CHARS).to_owned()
+        let lines = lines.join("\n");
+        // Escaping expands the text, so shrink the suffix until it fits
+        let mut limit = LOG_SUMMARY_CHARS;
+        loop {
+            let escaped = escape_mrkdwn(suffix(&lines, limit));
+            let count = escaped.chars().count();
+            if count <= LOG_SUMMARY_CHARS {
+                return escaped;
+            }
+            limit = (limit * LOG_SUMMARY_CHARS / count).min(limit - 1);
+        }
     }
 }
 
 fn format_name(name: &Option<impl AsRef<str>>) -> String {
     if let Some(name) = name.as_ref() {
-        format!("`{}`", name.as_ref())
+        format!("`{}`", escape_mrkdwn(name.as_ref()))
     } else {
         "unknown".to_owned()
     }
 }
 
+/// Escapes control characters of Slack mrkdwn in `text`.
+/// https://api.slack.com/reference/surfaces/formatting#escaping
+/// Backticks are replaced with a look-alike character
+/// because they cannot be escaped in code blocks.
+pub fn escape_mrkdwn(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '`' => escaped.push('\u{02CB}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 fn markdown_text(text: &str) -> serde_json::Value {
     json!({
         "type": "mrkdwn",
@@ -221,10 +1249,123 @@ fn suffix(text: &str, limit: usize) -> &str {
     }
 }
 
+/// Last `EXCERPT_LINES` lines of `logs` within `EXCERPT_BYTES`, for destinations limiting
+/// the size, e.g. Elasticsearch documents and Jira issues
+pub fn excerpt(logs: &str) -> &str {
+    let logs = logs.trim_end();
+    let mut start = logs
+        .rmatch_indices('\n')
+        .nth(EXCERPT_LINES - 1)
+        .map_or(0, |(i, _)| i + 1);
+    start = start.max(logs.len().saturating_sub(EXCERPT_BYTES));
+    while !logs.is_char_boundary(start) {
+        start += 1;
+    }
+    &logs[start..]
+}
+
+/// Splits `log` into parts of at most `limit` bytes at line boundaries.
+/// Lines longer than `limit` are split at character boundaries.
+pub fn split_log(log: &str, limit: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = log;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        // Prefer splitting after the last newline within the limit
+        if let Some(newline) = rest[..end].rfind('\n') {
+            end = newline + 1;
+        }
+        let (part, remaining) = rest.split_at(end);
+        parts.push(part);
+        rest = remaining;
+    }
+    parts.push(rest);
+    parts
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_split_log() {
+        assert_eq!(split_log("abc", 5), vec!["abc"]);
+        assert_eq!(split_log("ab\ncd\nef", 6), vec!["ab\ncd\n", "ef"]);
+        assert_eq!(split_log("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(split_log("こんにちは", 7), vec!["こん", "にち", "は"]);
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let logs = (0..30).map(|i| format!("{i}\n")).collect::<String>();
+        assert_eq!(
+            excerpt(&logs),
+            (10..30)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        assert_eq!(excerpt("a\nb"), "a\nb");
+        assert_eq!(excerpt(&"é".repeat(EXCERPT_BYTES)).len(), EXCERPT_BYTES);
+    }
+
+    #[test]
+    fn test_log_chunks() {
+        let line = "a".repeat(LOG_SUMMARY_CHARS / 2 - 1);
+        let log = ContainerLog(Ok(format!("{line}\n{line}\n{line}\n")));
+        let chunks = log.chunks();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], format!("{line}\n{line}\n"));
+        assert_eq!(chunks[1], line);
+
+        let log = ContainerLog(Ok("<".repeat(LOG_SUMMARY_CHARS)));
+        let chunks = log.chunks();
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks
+            .iter()
+            .all(|c| c.chars().count() <= LOG_SUMMARY_CHARS));
+        assert_eq!(chunks.concat(), "&lt;".repeat(LOG_SUMMARY_CHARS));
+    }
+
+    #[test]
+    fn test_fit_blocks() {
+        let fields = (0..12)
+            .map(|i| markdown_text(&i.to_string()))
+            .collect::<Vec<_>>();
+        let blocks = vec![
+            json!({
+                "type": "section",
+                "text": markdown_text(&"a".repeat(SECTION_TEXT_LIMIT + 1)),
+                "fields": fields,
+            }),
+            json!({
+                "type": "section",
+                "fields": [markdown_text(&"b".repeat(SECTION_FIELD_TEXT_LIMIT + 1))],
+            }),
+        ];
+        let messages = fit_blocks(blocks);
+        assert_eq!(messages.len(), 1);
+        let blocks = &messages[0];
+        assert_eq!(blocks.len(), 3);
+        let text = blocks[0]["text"]["text"].as_str().unwrap();
+        assert_eq!(text.chars().count(), SECTION_TEXT_LIMIT);
+        assert!(text.ends_with('…'));
+        assert_eq!(blocks[0]["fields"].as_array().unwrap().len(), 10);
+        assert_eq!(blocks[1]["text"], serde_json::Value::Null);
+        assert_eq!(blocks[1]["fields"].as_array().unwrap().len(), 2);
+        let field = blocks[2]["fields"][0]["text"].as_str().unwrap();
+        assert_eq!(field.chars().count(), SECTION_FIELD_TEXT_LIMIT);
+
+        let blocks = vec![json!({"type": "divider"}); MESSAGE_BLOCKS_LIMIT + 1];
+        let messages = fit_blocks(blocks);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].len(), MESSAGE_BLOCKS_LIMIT);
+        assert_eq!(messages[1].len(), 1);
+    }
+
     #[test]
     fn test_suffix() {
         assert_eq!(suffix("hello", 6), "hello");
@@ -236,6 +1377,285 @@ mod tests {
         assert_eq!(suffix("hello", 0), "");
     }
 
+    #[test]
+    fn test_escape_mrkdwn() {
+        assert_eq!(escape_mrkdwn("plain text"), "plain text");
+        assert_eq!(
+            escape_mrkdwn("<!channel> & <https://example.com|link>"),
+            "&lt;!channel&gt; &amp; &lt;https://example.com|link&gt;"
+        );
+        assert_eq!(escape_mrkdwn("```code```"), "ˋˋˋcodeˋˋˋ");
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let restart_info = |pod: &str, logs: &str| {
+            let mut info = ContainerRestartInfo::synthetic(
+                "default",
+                pod,
+                "app",
+                "#alerts".to_owned(),
+                Default::default(),
+                "test",
+            );
+            info.logs = ContainerLog(Ok(logs.to_owned()));
+            info
+        };
+        let a = restart_info("app-0", "panic at 12:00:01 id=123\n\n");
+        let b = restart_info("app-1", "ok\npanic at 13:59:02 id=4\n");
+        let c = restart_info("app-0", "connection refused\n");
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_ne!(a.fingerprint(), c.fingerprint());
+        assert_eq!(a.fingerprint().len(), 16);
+    }
+
+    #[test]
+    fn test_links() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let blocks = info.summary_blocks();
+        info.links.push(MessageLink {
+            title: "Jira: OPS-1".to_owned(),
+            url: "https://example.atlassian.net/browse/OPS-1".to_owned(),
+        });
+        let linked = info.summary_blocks();
+        assert_eq!(linked.len(), blocks.len() + 1);
+        assert_eq!(
+            linked.last().unwrap()["elements"][0]["text"],
+            "<https://example.atlassian.net/browse/OPS-1|Jira: OPS-1>"
+        );
+    }
+
+    #[test]
+    fn test_sidecar_logs() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let blocks = info.to_message(&[]);
+        info.sidecar_logs = vec![
+            SidecarLog {
+                container: "istio-proxy".to_owned(),
+                logs: ContainerLog(Ok("upstream connect error\n".to_owned())),
+            },
+            SidecarLog {
+                container: "log-shipper".to_owned(),
+                logs: ContainerLog(Err("Timed out after 10 seconds".to_owned())),
+            },
+        ];
+        let with_sidecars = info.to_message(&[]);
+        assert_eq!(with_sidecars.len(), blocks.len() + 2);
+        assert_eq!(
+            with_sidecars[blocks.len()]["text"]["text"],
+            "*Logs of sidecar `istio-proxy`*\n```\nupstream connect error\n```"
+        );
+        assert_eq!(
+            with_sidecars[blocks.len() + 1]["text"]["text"],
+            "Failed to get logs of sidecar `log-shipper`: Timed out after 10 seconds"
+        );
+        assert_eq!(info.to_log_message(&[]).len(), 3);
+    }
+
+    #[test]
+    fn test_priority_message() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "batch-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let blocks = info.to_message(&[]);
+        info.priority = Some(PodPriority {
+            class_name: Some("batch-low".to_owned()),
+            value: Some(-100),
+            preempted: true,
+        });
+        let preempted = info.to_message(&[]);
+        assert_eq!(preempted.len(), blocks.len() + 1);
+        assert!(preempted[1]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("\nPriority: `batch-low` (-100)"));
+        assert_eq!(preempted[2]["type"], "context");
+    }
+
+    #[test]
+    fn test_node_info_message() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.node_info = Some(NodeInfo {
+            os_image: "Ubuntu 22.04.3 LTS".to_owned(),
+            container_runtime_version: "containerd://1.7.2".to_owned(),
+            kubelet_version: "v1.29.1".to_owned(),
+        });
+        assert!(info.to_message(&[])[1]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("\nNode: `Ubuntu 22.04.3 LTS`, `containerd://1.7.2`, kubelet `v1.29.1`"));
+    }
+
+    #[test]
+    fn test_announcements() {
+        let started = startup_announcement(
+            "1.2.0",
+            Some("production"),
+            &["web".to_owned(), "batch".to_owned()],
+            12,
+            true,
+        );
+        assert_eq!(
+            started[0]["text"]["text"],
+            ":large_green_circle: johari-mirror `1.2.0` started in cluster `production`, \
+             watching `web`, `batch` with 12 rules and ROUTING_SCRIPT."
+        );
+        let counts = DeliveryCounts {
+            sent: 5,
+            failed: 1,
+            dropped: 0,
+        };
+        let stopped = shutdown_announcement("1.2.0", None, "3d", counts, 2);
+        assert_eq!(
+            stopped[0]["text"]["text"],
+            ":red_circle: johari-mirror `1.2.0` stopped after running for 3d: 5 notifications \
+             delivered, 1 failed, 0 dropped, 2 left in the queue. Restarts are not notified \
+             until it starts again."
+        );
+    }
+
+    #[test]
+    fn test_compact_layout() {
+        let info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            "layout=compact;severity=critical".parse().unwrap(),
+            "test",
+        );
+        let blocks = info.to_message(&["https://files.example.com/F1".to_owned()]);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            blocks[0]["text"]["text"],
+            "*:rotating_light: Container restarted [critical]*\n`default/app-0` `app` exited \
+             with `1` (`Error`), 1 restarts | <https://files.example.com/F1|Container logs>"
+        );
+        assert_eq!(info.to_summary_message().len(), 1);
+    }
+
+    #[test]
+    fn test_workload_context() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "web-7d4b9-abcde",
+            "app",
+            "#alerts".to_owned(),
+            "aggregate".parse().unwrap(),
+            "test",
+        );
+        assert_eq!(info.message_key(), "default/web-7d4b9-abcde/app");
+        info.workload = Some("web".to_owned());
+        assert_eq!(info.message_key(), "default/web/app");
+        // Replicas of the workload have their own messages with `update`
+        info.options = "update".parse().unwrap();
+        assert_eq!(info.message_key(), "default/web-7d4b9-abcde/app");
+
+        let pods = (0..12)
+            .map(|i| (format!("web-{i:02}"), i))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            workload_context("web", &pods)["elements"][0]["text"],
+            ":package: 12 pods of `web` restarted: `web-00` (0), `web-01` (1), `web-02` (2), \
+             `web-03` (3), `web-04` (4), `web-05` (5), `web-06` (6), `web-07` (7), \
+             `web-08` (8), `web-09` (9), and 2 more. Each restart is in the thread."
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let state = |started_at: &str| ContainerState {
+            exit_code: 1,
+            signal: None,
+            reason: Some("Error".to_owned()),
+            message: None,
+            started_at: Some(started_at.to_owned()),
+            finished_at: Some("2024-01-02T03:04:05Z".to_owned()),
+        };
+        let crashed = state("2024-01-02T03:04:00Z");
+        assert_eq!(Backoff::estimate(1, &crashed).unwrap().delay_secs, 10);
+        assert_eq!(Backoff::estimate(3, &crashed).unwrap().delay_secs, 40);
+        let backoff = Backoff::estimate(8, &crashed).unwrap();
+        assert_eq!(backoff.delay_secs, 300);
+        assert_eq!(
+            backoff.next_restart.to_rfc3339(),
+            "2024-01-02T03:09:05+00:00"
+        );
+        assert_eq!(backoff.to_fields()[0]["text"], "Backoff: `300s` (max)");
+        // Reset after running for 10 minutes
+        let ran = state("2024-01-02T02:00:00Z");
+        assert_eq!(Backoff::estimate(8, &ran).unwrap().delay_secs, 10);
+        let unknown = ContainerState {
+            finished_at: None,
+            ..state("2024-01-02T03:04:00Z")
+        };
+        assert_eq!(Backoff::estimate(1, &unknown), None);
+    }
+
+    #[test]
+    fn test_resources_message() {
+        let resources = ContainerResources {
+            limits: vec![
+                ("memory".to_owned(), "16Gi".to_owned()),
+                ("nvidia.com/gpu".to_owned(), "1".to_owned()),
+            ],
+            requests: vec![
+                ("cpu".to_owned(), "4".to_owned()),
+                ("hugepages-2Mi".to_owned(), "1Gi".to_owned()),
+                ("memory".to_owned(), "8Gi".to_owned()),
+                ("nvidia.com/gpu".to_owned(), "1".to_owned()),
+            ],
+        };
+        let fields = resources
+            .to_message()
+            .into_iter()
+            .map(|field| field["text"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                "*CPU*: request `4` / limit none",
+                "*Memory*: request `8Gi` / limit `16Gi`",
+                "*Hugepages 2Mi*: request `1Gi` / limit none",
+                "*GPU* (nvidia.com/gpu): request `1` / limit `1`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail_lines_escaped_limit() {
+        let log = "<".repeat(LOG_SUMMARY_CHARS);
+        let tail = ContainerLog::tail_lines(&log);
+        assert!(tail.chars().count() <= LOG_SUMMARY_CHARS);
+        assert!(tail.starts_with("&lt;"));
+    }
+
     #[test]
     fn test_suffix_multibyte() {
         assert_eq!(suffix("こんにちは", 6), "こんにちは");
@@ -0,0 +1,102 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Messages not updated for this period are discarded.
+const MESSAGE_RETENTION_DAYS: i64 = 30;
+
+/// Message posted to Slack
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostedMessage {
+    /// Channel ID, which is required by `chat.update` instead of channel name
+    pub channel: String,
+    pub ts: String,
+    /// Number of restarts notified by the message
+    pub notified_restarts: usize,
+    pub updated_at: DateTime<Utc>,
+    /// Restart counts of pods notified by the message of a workload with `aggregate`
+    #[serde(default)]
+    pub pods: BTreeMap<String, i32>,
+}
+
+/// Slack messages posted per container, used to update messages and reply in threads.
+/// Messages are kept in memory and optionally persisted to a JSON file.
+#[derive(Debug, Default)]
+pub struct MessageStore {
+    /// Map `ContainerRestartInfo::message_key` -> message
+    messages: HashMap<String, PostedMessage>,
+    path: Option<PathBuf>,
+}
+
+impl MessageStore {
+    /// Loads messages from `path`, which is created on the first update if it does not exist.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let messages = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .with_context(|| format!("Invalid message store: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self {
+            messages,
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PostedMessage> {
+        self.messages.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, message: PostedMessage) {
+        let expiry = Utc::now() - Duration::days(MESSAGE_RETENTION_DAYS);
+        self.messages.retain(|_, m| m.updated_at > expiry);
+        self.messages.insert(key, message);
+        if let Err(e) = self.save() {
+            log::error!("Failed to save message store: {e}");
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Write to a temporary file first not to corrupt the store on failure
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&self.messages)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_store_persistence() {
+        let path = std::env::temp_dir().join(format!(
+            "johari-mirror-message-store-{}.json",
+            std::process::id()
+        ));
+        let message = PostedMessage {
+            channel: "C123".to_owned(),
+            ts: "1700000000.000100".to_owned(),
+            notified_restarts: 2,
+            updated_at: Utc::now(),
+            pods: [("app-0".to_owned(), 3)].into(),
+        };
+
+        let mut store = MessageStore::load(path.clone()).unwrap();
+        assert_eq!(store.get("ns/pod/container"), None);
+        store.insert("ns/pod/container".to_owned(), message.clone());
+
+        let store = MessageStore::load(path.clone()).unwrap();
+        assert_eq!(store.get("ns/pod/container"), Some(&message));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+#[cfg(feature = "slack")]
+use std::{collections::BTreeMap, sync::Mutex};
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+#[cfg(feature = "slack")]
+use crate::slack;
+use crate::{
+    queue::QueueMonitor,
+    self_alert::{Component, SelfAlert},
+};
+
+/// Interval to sample the notification queue
+const QUEUE_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Queue utilization in percent regarded as saturated
+const QUEUE_SATURATION_PERCENT: usize = 80;
+
+/// Number of Slack API failures per error code
+#[cfg(feature = "slack")]
+static SLACK_ERRORS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Number of notifications in the queue at the last sample
+static QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static QUEUE_CAPACITY: AtomicUsize = AtomicUsize::new(0);
+static QUEUE_SPILLED: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of notifications dropped by the full queue
+static QUEUE_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Number of times the watcher waited for the full queue
+static QUEUE_FULL: AtomicU64 = AtomicU64::new(0);
+
+/// Total time the watcher waited for the full queue
+static QUEUE_BLOCKED_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of notifications processed by `slack_send` per result
+static NOTIFICATIONS_SENT: AtomicU64 = AtomicU64::new(0);
+static NOTIFICATIONS_FAILED: AtomicU64 = AtomicU64::new(0);
+
+/// Number of pods whose restart counts are tracked
+static TRACKED_PODS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of pods evicted from restart counts per reason
+static POD_EVICTIONS_STALE: AtomicU64 = AtomicU64::new(0);
+static POD_EVICTIONS_CAPACITY: AtomicU64 = AtomicU64::new(0);
+
+/// Reason to stop tracking restart counts of a pod
+#[derive(Debug, Clone, Copy)]
+pub enum EvictionReason {
+    /// The pod no longer exists, but its Deleted event was missed
+    Stale,
+    /// Too many pods are tracked
+    Capacity,
+}
+
+/// Counts a Slack API failure with error `code`, e.g. `channel_not_found`.
+#[cfg(feature = "slack")]
+pub fn slack_error(code: &str) {
+    *SLACK_ERRORS
+        .lock()
+        .unwrap()
+        .entry(code.to_owned())
+        .or_default() += 1;
+}
+
+/// Records that the watcher waited `blocked` for the full queue.
+pub fn queue_blocked(blocked: Duration) {
+    QUEUE_FULL.fetch_add(1, Ordering::Relaxed);
+    QUEUE_BLOCKED_MILLIS.fetch_add(blocked.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn notification_sent() {
+    NOTIFICATIONS_SENT.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn notification_failed() {
+    NOTIFICATIONS_FAILED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of notifications by result since startup
+#[derive(Debug, Clone, Copy)]
+pub struct DeliveryCounts {
+    pub sent: u64,
+    pub failed: u64,
+    pub dropped: u64,
+}
+
+pub fn delivery_counts() -> DeliveryCounts {
+    DeliveryCounts {
+        sent: NOTIFICATIONS_SENT.load(Ordering::Relaxed),
+        failed: NOTIFICATIONS_FAILED.load(Ordering::Relaxed),
+        dropped: QUEUE_DROPPED.load(Ordering::Relaxed),
+    }
+}
+
+pub fn tracked_pods(count: usize) {
+    TRACKED_PODS.store(count, Ordering::Relaxed);
+}
+
+pub fn pod_evicted(reason: EvictionReason) {
+    let counter = match reason {
+        EvictionReason::Stale => &POD_EVICTIONS_STALE,
+        EvictionReason::Capacity => &POD_EVICTIONS_CAPACITY,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn queue_dropped() {
+    QUEUE_DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Task to sample the notification queue and alert when it stays saturated.
+pub async fn monitor_queue(queue: QueueMonitor, self_alert: SelfAlert) {
+    let mut interval = tokio::time::interval(QUEUE_SAMPLE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let depth = queue.len();
+        QUEUE_DEPTH.store(depth, Ordering::Relaxed);
+        QUEUE_CAPACITY.store(queue.capacity(), Ordering::Relaxed);
+        QUEUE_SPILLED.store(queue.spilled(), Ordering::Relaxed);
+        if depth * 100 >= queue.capacity() * QUEUE_SATURATION_PERCENT {
+            let error = format!("{depth} / {} notifications are queued", queue.capacity());
+            log::warn!("Notification queue is saturated: {error}");
+            self_alert.failure(Component::Queue, &error);
+        } else {
+            self_alert.success(Component::Queue);
+        }
+    }
+}
+
+/// Renders metrics in Prometheus text format.
+/// https://prometheus.io/docs/instrumenting/exposition_formats/
+pub fn render() -> String {
+    let mut text = String::new();
+    #[cfg(feature = "slack")]
+    {
+        write_header(
+            &mut text,
+            "johari_mirror_slack_errors_total",
+            "counter",
+            "Number of Slack API failures by error code.",
+        );
+        for (code, count) in SLACK_ERRORS.lock().unwrap().iter() {
+            let class = slack::ErrorClass::of(code);
+            let _ = writeln!(
+                text,
+                "johari_mirror_slack_errors_total{{code=\"{code}\",class=\"{class}\"}} {count}"
+            );
+        }
+    }
+    write_header(
+        &mut text,
+        "johari_mirror_notifications_total",
+        "counter",
+        "Number of notifications processed by result.",
+    );
+    for (result, counter) in [
+        ("sent", &NOTIFICATIONS_SENT),
+        ("failed", &NOTIFICATIONS_FAILED),
+    ] {
+        let _ = writeln!(
+            text,
+            "johari_mirror_notifications_total{{result=\"{result}\"}} {}",
+            counter.load(Ordering::Relaxed)
+        );
+    }
+    write_metric(
+        &mut text,
+        "johari_mirror_queue_depth",
+        "gauge",
+        "Number of notifications waiting for Slack delivery.",
+        QUEUE_DEPTH.load(Ordering::Relaxed),
+    );
+    write_metric(
+        &mut text,
+        "johari_mirror_queue_capacity",
+        "gauge",
+        "Maximum number of queued notifications.",
+        QUEUE_CAPACITY.load(Ordering::Relaxed),
+    );
+    write_metric(
+        &mut text,
+        "johari_mirror_queue_spilled",
+        "gauge",
+        "Number of notifications kept only on the disk by the full queue.",
+        QUEUE_SPILLED.load(Ordering::Relaxed),
+    );
+    write_metric(
+        &mut text,
+        "johari_mirror_queue_dropped_total",
+        "counter",
+        "Number of notifications dropped by the full queue.",
+        QUEUE_DROPPED.load(Ordering::Relaxed),
+    );
+    write_metric(
+        &mut text,
+        "johari_mirror_queue_full_total",
+        "counter",
+        "Number of times the watcher waited for the full queue.",
+        QUEUE_FULL.load(Ordering::Relaxed),
+    );
+    write_metric(
+        &mut text,
+        "johari_mirror_queue_blocked_seconds_total",
+        "counter",
+        "Time the watcher waited for the full queue.",
+        QUEUE_BLOCKED_MILLIS.load(Ordering::Relaxed) as f64 / 1000.0,
+    );
+    write_metric(
+        &mut text,
+        "johari_mirror_tracked_pods",
+        "gauge",
+        "Number of pods whose restart counts are tracked.",
+        TRACKED_PODS.load(Ordering::Relaxed),
+    );
+    write_header(
+        &mut text,
+        "johari_mirror_pod_evictions_total",
+        "counter",
+        "Number of pods evicted from restart counts by reason.",
+    );
+    for (reason, counter) in [
+        ("stale", &POD_EVICTIONS_STALE),
+        ("capacity", &POD_EVICTIONS_CAPACITY),
+    ] {
+        let _ = writeln!(
+            text,
+            "johari_mirror_pod_evictions_total{{reason=\"{reason}\"}} {}",
+            counter.load(Ordering::Relaxed)
+        );
+    }
+    text
+}
+
+fn write_header(text: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {metric_type}");
+}
+
+/// Writes a metric without labels.
+fn write_metric(
+    text: &mut String,
+    name: &str,
+    metric_type: &str,
+    help: &str,
+    value: impl std::fmt::Display,
+) {
+    write_header(text, name, metric_type, help);
+    let _ = writeln!(text, "{name} {value}");
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{message::ContainerRestartInfo, rate_limit::RateLimiter, silence};
+
+/// Default period to drop duplicated notifications of the same restart
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Keys whose values are redacted in container logs by default
+const DEFAULT_REDACTED_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "authorization",
+];
+
+/// Map channel -> container key -> number of restarts
+pub type RestartsSummary = BTreeMap<String, BTreeMap<String, usize>>;
+
+/// Step between detection and sending which modifies or drops notifications,
+/// e.g. deduplication, redaction, rate limiting and enrichment.
+pub trait Middleware: Send {
+    /// Returns the notification passed to the next middleware, or `None` to drop it.
+    fn process(&mut self, info: ContainerRestartInfo) -> Option<ContainerRestartInfo>;
+
+    /// Takes notifications dropped since the last call, which are posted as a summary.
+    fn take_summarized(&mut self) -> RestartsSummary {
+        RestartsSummary::new()
+    }
+}
+
+/// Middlewares applied in order.
+/// `name[=argument],...` format, e.g. `dedup=10m,redact,rate_limit=30`.
+#[derive(Default)]
+pub struct MiddlewareChain {
+    middlewares: Vec<Box<dyn Middleware>>,
+    /// Keys of the `redact` middleware, also applied to logs posted outside the chain
+    redacted_keys: Vec<String>,
+}
+
+impl std::str::FromStr for MiddlewareChain {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chain = Self::default();
+        for middleware in s.split(',').filter(|m| !m.is_empty()) {
+            match middleware.split_once('=') {
+                None if middleware == "dedup" => chain.push(Dedup::new(DEFAULT_DEDUP_WINDOW)),
+                Some(("dedup", window)) => chain.push(Dedup::new(
+                    silence::parse_duration(window)
+                        .map_err(|e| anyhow::anyhow!("Invalid dedup window: {e}"))?
+                        .to_std()?,
+                )),
+                None if middleware == "redact" => {
+                    chain.push_redact(DEFAULT_REDACTED_KEYS.iter().map(|&key| key.to_owned()))
+                }
+                Some(("redact", keys)) => chain.push_redact(keys.split('|').map(str::to_owned)),
+                Some(("rate_limit", limit)) => match limit
+                    .parse()
+                    .with_context(|| format!("Invalid rate limit: {limit}"))?
+                {
+                    0 => bail!("Rate limit must be at least 1"),
+                    limit => chain.push(RateLimiter::new(limit)),
+                },
+                #[cfg(feature = "wasm")]
+                Some(("wasm", path)) => {
+                    chain.push(crate::wasm::WasmPlugin::load(std::path::Path::new(path))?)
+                }
+                #[cfg(not(feature = "wasm"))]
+                Some(("wasm", _)) => bail!("wasm middleware requires the `wasm` feature"),
+                _ => bail!("Unknown middleware: {middleware}"),
+            }
+        }
+        Ok(chain)
+    }
+}
+
+impl MiddlewareChain {
+    pub fn push(&mut self, middleware: impl Middleware + 'static) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    fn push_redact(&mut self, keys: impl IntoIterator<Item = String>) {
+        let redact = Redact::new(keys);
+        self.redacted_keys.extend(redact.keys.iter().cloned());
+        self.push(redact);
+    }
+
+    /// Redaction of the `redact` middlewares in the chain, e.g. for startup logs which
+    /// are posted without passing through the chain
+    pub fn redaction(&self) -> Option<Redact> {
+        (!self.redacted_keys.is_empty()).then(|| Redact::new(self.redacted_keys.clone()))
+    }
+
+    /// Inserts `middleware` before the others, e.g. to enrich notifications before
+    /// they are rate limited.
+    pub fn prepend(&mut self, middleware: impl Middleware + 'static) {
+        self.middlewares.insert(0, Box::new(middleware));
+    }
+
+    /// Applies middlewares in order. `None` when any of them drops the notification.
+    pub fn process(&mut self, info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        self.middlewares
+            .iter_mut()
+            .try_fold(info, |info, middleware| middleware.process(info))
+    }
+
+    pub fn take_summarized(&mut self) -> RestartsSummary {
+        let mut summary = RestartsSummary::new();
+        for middleware in &mut self.middlewares {
+            for (channel, restarts) in middleware.take_summarized() {
+                let channel_summary = summary.entry(channel).or_default();
+                for (container, count) in restarts {
+                    *channel_summary.entry(container).or_default() += count;
+                }
+            }
+        }
+        summary
+    }
+}
+
+/// Drops notifications of the same restart of a container within `window`,
+/// e.g. restarts detected again after the watcher relisted pods.
+#[derive(Debug)]
+pub struct Dedup {
+    window: Duration,
+    /// Key: container key and restart count
+    seen: HashMap<(String, i32), Instant>,
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl Middleware for Dedup {
+    fn process(&mut self, info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        let now = Instant::now();
+        self.seen
+            .retain(|_, seen_at| now.duration_since(*seen_at) < self.window);
+        let key = (info.container_key(), info.restart_count);
+        if self.seen.insert(key, now).is_some() {
+            log::info!("Dropping duplicated notification: {info}");
+            return None;
+        }
+        Some(info)
+    }
+}
+
+/// Redacts values of `keys` in container logs, e.g. `password=...` or `"token": "..."`.
+/// Keys are matched case-insensitively.
+#[derive(Debug)]
+pub struct Redact {
+    keys: Vec<String>,
+}
+
+impl Redact {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| key.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn redact(&self, logs: &str) -> String {
+        // ASCII lowercase keeps byte offsets
+        let lower = logs.to_ascii_lowercase();
+        let mut redacted = String::with_capacity(logs.len());
+        let mut pos = 0;
+        while let Some((start, end)) = self.find_value(&lower, pos) {
+            redacted.push_str(&logs[pos..start]);
+            redacted.push_str("[REDACTED]");
+            pos = end;
+        }
+        redacted.push_str(&logs[pos..]);
+        redacted
+    }
+
+    /// Finds the byte range of the next value of any key from `pos`.
+    fn find_value(&self, lower: &str, pos: usize) -> Option<(usize, usize)> {
+        self.keys
+            .iter()
+            .filter_map(|key| {
+                let mut from = pos;
+                while let Some(i) = lower[from..].find(key.as_str()) {
+                    let after_key = from + i + key.len();
+                    if let Some(range) = value_range(lower, after_key) {
+                        return Some(range);
+                    }
+                    from = after_key;
+                }
+                None
+            })
+            .min()
+    }
+}
+
+/// Byte range of the value following `key=value`, `key: value` or `"key": "value"`
+/// where the key ends at `pos`.
+fn value_range(s: &str, pos: usize) -> Option<(usize, usize)> {
+    let bytes = s.as_bytes();
+    let skip = |mut i: usize, chars: &[u8]| {
+        while i < bytes.len() && chars.contains(&bytes[i]) {
+            i += 1;
+        }
+        i
+    };
+    // Closing quote of the key and spaces before the separator
+    let separator = skip(skip(pos, b"\"'"), b" ");
+    if !matches!(bytes.get(separator), Some(b'=' | b':')) {
+        return None;
+    }
+    let start = skip(separator + 1, b" \"'");
+    let end = bytes[start..]
+        .iter()
+        .position(|b| b" \t\r\n\"',;&".contains(b))
+        .map_or(bytes.len(), |n| start + n);
+    (end > start).then_some((start, end))
+}
+
+impl Middleware for Redact {
+    fn process(&mut self, mut info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        for logs in std::iter::once(&mut info.logs).chain(
+            info.sidecar_logs
+                .iter_mut()
+                .map(|sidecar| &mut sidecar.logs),
+        ) {
+            if let Ok(text) = &logs.0 {
+                logs.0 = Ok(self.redact(text));
+            }
+        }
+        Some(info)
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn process(&mut self, info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        if !self.admit(&info) {
+            log::warn!("Notification rate limit exceeded, summarizing: {info}");
+            return None;
+        }
+        Some(info)
+    }
+
+    fn take_summarized(&mut self) -> RestartsSummary {
+        self.take_overflow()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ContainerLog, ContainerResources, SidecarLog};
+
+    fn restart_info(restart_count: i32, logs: &str) -> ContainerRestartInfo {
+        ContainerRestartInfo {
+            namespace: Some("default".to_owned()),
+            pod_name: "app-0".to_owned(),
+            pod_uid: None,
+            container_name: "app".to_owned(),
+            container_image: "app:latest".to_owned(),
+            node_name: None,
+            workload: None,
+            labels: Default::default(),
+            restart_count,
+            last_state: None,
+            resources: ContainerResources::default(),
+            logs: ContainerLog(Ok(logs.to_owned())),
+            channel: "#alerts".to_owned(),
+            options: Default::default(),
+            links: Vec::new(),
+            notes: Vec::new(),
+            category: None,
+            sidecar_logs: Vec::new(),
+            priority: None,
+            node_info: None,
+            attachments: Vec::new(),
+            span: tracing::Span::none(),
+            queue_id: None,
+        }
+    }
+
+    #[test]
+    fn test_middleware_chain() {
+        let mut chain = "dedup,redact=password,rate_limit=2"
+            .parse::<MiddlewareChain>()
+            .unwrap();
+        let info = chain.process(restart_info(1, "password=hunter2")).unwrap();
+        assert_eq!(info.logs.0.unwrap(), "password=[REDACTED]");
+        assert_eq!(
+            chain.redaction().unwrap().redact("password=hunter2"),
+            "password=[REDACTED]"
+        );
+        assert!(MiddlewareChain::default().redaction().is_none());
+        // Logs of sidecars as well
+        let mut sidecar = restart_info(1, "");
+        sidecar.sidecar_logs = vec![SidecarLog {
+            container: "proxy".to_owned(),
+            logs: ContainerLog(Ok("upstream password: hunter2".to_owned())),
+        }];
+        let sidecar = Redact::new(["password".to_owned()])
+            .process(sidecar)
+            .unwrap();
+        assert_eq!(
+            sidecar.sidecar_logs[0].logs.0.as_deref().unwrap(),
+            "upstream password: [REDACTED]"
+        );
+        // Duplicated
+        assert!(chain.process(restart_info(1, "")).is_none());
+        assert!(chain.process(restart_info(2, "")).is_some());
+        // Rate limited
+        assert!(chain.process(restart_info(3, "")).is_none());
+        assert_eq!(chain.take_summarized()["#alerts"]["default/app-0/app"], 1);
+        assert!(chain.take_summarized().is_empty());
+
+        assert!("unknown".parse::<MiddlewareChain>().is_err());
+        assert!("rate_limit=0".parse::<MiddlewareChain>().is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let redact = Redact::new(["password".to_owned(), "token".to_owned()]);
+        assert_eq!(
+            redact.redact("login password=hunter2 ok\nTOKEN: abc123, next"),
+            "login password=[REDACTED] ok\nTOKEN: [REDACTED], next"
+        );
+        assert_eq!(
+            redact.redact(r#"{"password": "hunter2", "user": "bob"}"#),
+            r#"{"password": "[REDACTED]", "user": "bob"}"#
+        );
+        // Keys without values are kept
+        assert_eq!(
+            redact.redact("invalid password for user"),
+            "invalid password for user"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{self, DateTime, Utc};
+
+use crate::{message::ContainerRestartInfo, silence};
+
+/// Period in which restarts on a node are correlated by default
+pub const DEFAULT_NODE_CORRELATION_WINDOW: chrono::Duration = chrono::Duration::minutes(3);
+
+/// Configuration of correlating restarts of containers on the same node
+#[derive(Debug, Clone)]
+pub struct NodeCorrelationConfig {
+    /// Number of containers restarted on a node within `window` to alert
+    pub threshold: usize,
+    pub window: chrono::Duration,
+    /// Channel of node alerts, or the channel of the restart reaching the threshold
+    pub channel: Option<String>,
+}
+
+impl NodeCorrelationConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `NODE_CORRELATION_THRESHOLD` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(threshold) = std::env::var("NODE_CORRELATION_THRESHOLD") else {
+            return Ok(None);
+        };
+        let threshold = threshold
+            .parse()
+            .context("Invalid NODE_CORRELATION_THRESHOLD")?;
+        if threshold < 2 {
+            bail!("NODE_CORRELATION_THRESHOLD must be at least 2");
+        }
+        let window = match std::env::var("NODE_CORRELATION_WINDOW") {
+            Ok(window) => silence::parse_duration(&window)
+                .map_err(|e| anyhow::anyhow!("Invalid NODE_CORRELATION_WINDOW: {e}"))?,
+            Err(_) => DEFAULT_NODE_CORRELATION_WINDOW,
+        };
+        let channel = std::env::var("NODE_CORRELATION_CHANNEL").ok();
+        Ok(Some(Self {
+            threshold,
+            window,
+            channel,
+        }))
+    }
+}
+
+/// Alert of restarts of many containers on a node
+#[derive(Debug, PartialEq)]
+pub struct NodeAlert {
+    pub node: String,
+    pub channel: String,
+    pub window: chrono::Duration,
+    /// Container key -> number of restarts in the window
+    pub restarts: BTreeMap<String, usize>,
+}
+
+/// Restarts on a node in the window
+#[derive(Debug, Default)]
+struct NodeRestarts {
+    /// Time and container key, the oldest first
+    restarts: VecDeque<(DateTime<Utc>, String)>,
+    /// Whether the current burst of restarts has been alerted
+    alerted: bool,
+}
+
+/// Detects bursts of restarts on the same node, which are likely caused by the node,
+/// e.g. memory pressure or a failing disk
+#[derive(Debug)]
+pub struct NodeCorrelation {
+    config: NodeCorrelationConfig,
+    nodes: HashMap<String, NodeRestarts>,
+}
+
+impl NodeCorrelation {
+    pub fn new(config: NodeCorrelationConfig) -> Self {
+        Self {
+            config,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Records the restart and marks it as likely node-related when at least `threshold`
+    /// containers restarted on the node within the window. Returns the alert of the node
+    /// once per burst, which ends when the node has no restarts for the window.
+    pub fn observe(&mut self, info: &mut ContainerRestartInfo) -> Option<NodeAlert> {
+        self.observe_at(info, Utc::now())
+    }
+
+    fn observe_at(
+        &mut self,
+        info: &mut ContainerRestartInfo,
+        now: DateTime<Utc>,
+    ) -> Option<NodeAlert> {
+        let window = self.config.window;
+        self.nodes.retain(|_, node| {
+            node.restarts.retain(|(time, _)| now - *time < window);
+            !node.restarts.is_empty()
+        });
+        let node_name = info.node_name.clone()?;
+        let node = self.nodes.entry(node_name.clone()).or_default();
+        node.restarts.push_back((now, info.container_key()));
+
+        let mut restarts = BTreeMap::<String, usize>::new();
+        for (_, container) in &node.restarts {
+            *restarts.entry(container.clone()).or_default() += 1;
+        }
+        if restarts.len() < self.config.threshold {
+            return None;
+        }
+        info.notes.push(format!(
+            ":link: Likely node-related: {} containers on node `{node_name}` restarted in {}",
+            restarts.len(),
+            silence::format_duration(window)
+        ));
+        if std::mem::replace(&mut node.alerted, true) {
+            return None;
+        }
+        Some(NodeAlert {
+            node: node_name,
+            channel: self
+                .config
+                .channel
+                .clone()
+                .unwrap_or_else(|| info.channel.clone()),
+            window,
+            restarts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart_info(pod: &str, node: &str) -> ContainerRestartInfo {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            pod,
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.node_name = Some(node.to_owned());
+        info
+    }
+
+    #[test]
+    fn test_node_correlation() {
+        let mut correlation = NodeCorrelation::new(NodeCorrelationConfig {
+            threshold: 3,
+            window: chrono::Duration::minutes(3),
+            channel: None,
+        });
+        let now = Utc::now();
+        let mut observe = |pod: &str, node: &str, minutes: i64| {
+            let mut info = restart_info(pod, node);
+            let alert = correlation.observe_at(&mut info, now + chrono::Duration::minutes(minutes));
+            (alert, info.notes.len())
+        };
+        assert_eq!(observe("app-0", "node-1", 0), (None, 0));
+        assert_eq!(observe("app-0", "node-1", 1), (None, 0));
+        assert_eq!(observe("app-1", "node-2", 1), (None, 0));
+        assert_eq!(observe("app-1", "node-1", 1), (None, 0));
+        let (alert, notes) = observe("app-2", "node-1", 2);
+        assert_eq!(
+            alert,
+            Some(NodeAlert {
+                node: "node-1".to_owned(),
+                channel: "#alerts".to_owned(),
+                window: chrono::Duration::minutes(3),
+                restarts: BTreeMap::from([
+                    ("default/app-0/app".to_owned(), 2),
+                    ("default/app-1/app".to_owned(), 1),
+                    ("default/app-2/app".to_owned(), 1),
+                ]),
+            })
+        );
+        assert_eq!(notes, 1);
+        // Marked without alerting again in the same burst
+        assert_eq!(observe("app-3", "node-1", 3), (None, 1));
+        // A new burst after the node has been quiet for the window
+        assert_eq!(observe("app-0", "node-1", 7), (None, 0));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Context;
+use k8s_openapi::{
+    api::{core::v1::Pod, policy::v1::PodDisruptionBudget},
+    apimachinery::pkg::apis::meta::v1::LabelSelector,
+    chrono::{self, DateTime, Utc},
+};
+use kube::{
+    api::{Api, ListParams},
+    Client, ResourceExt,
+};
+
+use crate::{message, silence, slack::SlackPoster};
+
+/// Period for which a PodDisruptionBudget is blocked before alerting by default
+pub const DEFAULT_PDB_ALERT_AFTER: chrono::Duration = chrono::Duration::minutes(15);
+
+/// Interval to check PodDisruptionBudgets
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Configuration of alerts of PodDisruptionBudgets blocked by crash-looping pods
+#[derive(Debug, Clone)]
+pub struct PdbAlertConfig {
+    pub channel: String,
+    /// Period for which `disruptionsAllowed` stays 0 with crash-looping pods before alerting
+    pub after: chrono::Duration,
+}
+
+impl PdbAlertConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `PDB_ALERT_CHANNEL` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(channel) = std::env::var("PDB_ALERT_CHANNEL") else {
+            return Ok(None);
+        };
+        let after = match std::env::var("PDB_ALERT_AFTER") {
+            Ok(after) => silence::parse_duration(&after)
+                .map_err(|e| anyhow::anyhow!("Invalid PDB_ALERT_AFTER: {e}"))?,
+            Err(_) => DEFAULT_PDB_ALERT_AFTER,
+        };
+        Ok(Some(Self { channel, after }))
+    }
+}
+
+/// PodDisruptionBudget allowing no disruptions while some of its pods are crash-looping,
+/// which blocks draining their nodes
+#[derive(Debug, PartialEq)]
+struct BlockedPdb {
+    namespace: String,
+    name: String,
+    /// Names of the crash-looping pods selected by the PodDisruptionBudget
+    crash_looping: Vec<String>,
+    /// Number of pods selected by the PodDisruptionBudget
+    pods: usize,
+}
+
+impl BlockedPdb {
+    fn key(&self) -> String {
+        format!("{}/{}", self.namespace, self.name)
+    }
+}
+
+/// Time since each PodDisruptionBudget has been blocked and whether it has been alerted
+#[derive(Debug, Default)]
+struct PdbTracker {
+    blocked_since: HashMap<String, (DateTime<Utc>, bool)>,
+}
+
+impl PdbTracker {
+    /// Records the currently `blocked` PodDisruptionBudgets and returns those blocked for
+    /// `after` with the period, once per blockage
+    fn update(
+        &mut self,
+        blocked: Vec<BlockedPdb>,
+        after: chrono::Duration,
+        now: DateTime<Utc>,
+    ) -> Vec<(BlockedPdb, chrono::Duration)> {
+        let keys = blocked.iter().map(BlockedPdb::key).collect::<HashSet<_>>();
+        self.blocked_since.retain(|key, _| keys.contains(key));
+        blocked
+            .into_iter()
+            .filter_map(|pdb| {
+                let (since, alerted) = self.blocked_since.entry(pdb.key()).or_insert((now, false));
+                if *alerted || now - *since < after {
+                    return None;
+                }
+                *alerted = true;
+                Some((pdb, now - *since))
+            })
+            .collect()
+    }
+}
+
+/// Task to alert PodDisruptionBudgets in `namespaces`, or all namespaces when empty, which
+/// allow no disruptions for `config.after` while their pods are crash-looping.
+/// Failures to check are logged and retried on the next interval.
+pub async fn watch(
+    config: PdbAlertConfig,
+    client: Client,
+    namespaces: Vec<String>,
+    poster: SlackPoster,
+) {
+    let mut tracker = PdbTracker::default();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let blocked = match blocked_pdbs(&client, &namespaces).await {
+            Ok(blocked) => blocked,
+            Err(e) => {
+                log::warn!("Failed to check PodDisruptionBudgets: {e:#}");
+                continue;
+            }
+        };
+        for (pdb, period) in tracker.update(blocked, config.after, Utc::now()) {
+            let period = silence::format_duration(period);
+            log::warn!(
+                "PodDisruptionBudget {} has allowed no disruptions for {period}",
+                pdb.key()
+            );
+            let blocks = message::pdb_alert(&pdb.key(), &period, &pdb.crash_looping, pdb.pods);
+            if let Err(e) = poster.post_blocks(&config.channel, blocks).await {
+                log::error!("Failed to post PodDisruptionBudget alert: {e}");
+            }
+        }
+    }
+}
+
+async fn blocked_pdbs(client: &Client, namespaces: &[String]) -> anyhow::Result<Vec<BlockedPdb>> {
+    let apis: Vec<Api<PodDisruptionBudget>> = if namespaces.is_empty() {
+        vec![Api::all(client.clone())]
+    } else {
+        namespaces
+            .iter()
+            .map(|namespace| Api::namespaced(client.clone(), namespace))
+            .collect()
+    };
+    let mut blocked = Vec::new();
+    for api in apis {
+        let pdbs = api
+            .list(&ListParams::default())
+            .await
+            .context("Failed to list PodDisruptionBudgets")?;
+        for pdb in pdbs {
+            let blocking = pdb.status.as_ref().filter(|s| s.disruptions_allowed == 0);
+            let selector = pdb.spec.as_ref().and_then(|spec| spec.selector.as_ref());
+            let (Some(_), Some(namespace), Some(selector)) = (blocking, pdb.namespace(), selector)
+            else {
+                continue;
+            };
+            // Watched pods are pruned of labels, so the selected pods are listed
+            let params = ListParams::default().labels(&label_selector(selector));
+            // Other PDBs are still checked when the pods of this one cannot be listed
+            let pods = match Api::<Pod>::namespaced(client.clone(), &namespace)
+                .list(&params)
+                .await
+            {
+                Ok(pods) => pods,
+                Err(e) => {
+                    log::warn!("Failed to list pods of {namespace}/{}: {e}", pdb.name_any());
+                    continue;
+                }
+            };
+            let crash_looping = pods
+                .iter()
+                .filter(|p| is_crash_looping(p))
+                .map(|p| p.name_any())
+                .collect::<Vec<_>>();
+            if !crash_looping.is_empty() {
+                blocked.push(BlockedPdb {
+                    namespace,
+                    name: pdb.name_any(),
+                    crash_looping,
+                    pods: pods.items.len(),
+                });
+            }
+        }
+    }
+    Ok(blocked)
+}
+
+/// Whether any container of `p` is waiting in `CrashLoopBackOff`
+fn is_crash_looping(p: &Pod) -> bool {
+    p.status
+        .iter()
+        .flat_map(|status| {
+            let init = status.init_container_statuses.iter().flatten();
+            init.chain(status.container_statuses.iter().flatten())
+        })
+        .filter_map(|c| c.state.as_ref()?.waiting.as_ref()?.reason.as_deref())
+        .any(|reason| reason == "CrashLoopBackOff")
+}
+
+/// `selector` in the format of `kubectl get -l`, e.g. `app=web,tier in (api,worker)`
+fn label_selector(selector: &LabelSelector) -> String {
+    let labels = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .map(|(key, value)| format!("{key}={value}"));
+    let expressions = selector.match_expressions.iter().flatten().map(|e| {
+        let values = e.values.as_deref().unwrap_or_default().join(",");
+        match e.operator.as_str() {
+            "In" => format!("{} in ({values})", e.key),
+            "NotIn" => format!("{} notin ({values})", e.key),
+            "Exists" => e.key.clone(),
+            _ => format!("!{}", e.key),
+        }
+    });
+    labels.chain(expressions).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
+
+    use super::*;
+
+    fn blocked(name: &str) -> BlockedPdb {
+        BlockedPdb {
+            namespace: "default".to_owned(),
+            name: name.to_owned(),
+            crash_looping: vec![format!("{name}-0")],
+            pods: 2,
+        }
+    }
+
+    #[test]
+    fn test_pdb_tracker() {
+        let mut tracker = PdbTracker::default();
+        let after = chrono::Duration::minutes(15);
+        let now = Utc::now();
+        let mut update = |names: &[&str], minutes: i64| {
+            let blocked = names.iter().map(|name| blocked(name)).collect();
+            tracker
+                .update(blocked, after, now + chrono::Duration::minutes(minutes))
+                .into_iter()
+                .map(|(pdb, period)| (pdb.name, period.num_minutes()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(update(&["web", "api"], 0), []);
+        assert_eq!(update(&["web"], 10), []);
+        assert_eq!(update(&["web", "api"], 15), [("web".to_owned(), 15)]);
+        // Alerted once per blockage
+        assert_eq!(update(&["web", "api"], 20), []);
+        assert_eq!(update(&["api"], 30), [("api".to_owned(), 15)]);
+        // Blocked again after recovering
+        assert_eq!(update(&["web"], 31), []);
+        assert_eq!(update(&["web"], 46), [("web".to_owned(), 15)]);
+    }
+
+    #[test]
+    fn test_label_selector() {
+        let selector = LabelSelector {
+            match_labels: Some([("app".to_owned(), "web".to_owned())].into()),
+            match_expressions: Some(vec![
+                LabelSelectorRequirement {
+                    key: "tier".to_owned(),
+                    operator: "In".to_owned(),
+                    values: Some(vec!["api".to_owned(), "worker".to_owned()]),
+                },
+                LabelSelectorRequirement {
+                    key: "canary".to_owned(),
+                    operator: "DoesNotExist".to_owned(),
+                    values: None,
+                },
+            ]),
+        };
+        assert_eq!(
+            label_selector(&selector),
+            "app=web,tier in (api,worker),!canary"
+        );
+        assert_eq!(label_selector(&LabelSelector::default()), "");
+    }
+}
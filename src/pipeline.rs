@@ -0,0 +1,263 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use futures::future::BoxFuture;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    runtime::reflector::{self, Store},
+    Client,
+};
+
+#[cfg(feature = "script")]
+use crate::script::RoutingScript;
+use crate::{
+    health::{self, Health},
+    history::{NotificationRecord, RestartHistory},
+    hooks::Hooks,
+    kubernetes::{KubernetesSource, PodRestartCounts, WatchConfig, WatchState},
+    message::ContainerRestartInfo,
+    metrics,
+    queue::{self, QueuePolicy},
+    self_alert::SelfAlert,
+    silence::Silences,
+    source::{EventSink, EventSource},
+    Router,
+};
+
+/// Sink of restart events detected by `Watcher`.
+/// Implemented for closures returning a future, e.g. `|event| async move { Ok(()) }`.
+pub trait Notifier: Send + Sync + 'static {
+    /// Notifies a restart routed to `event.channel`.
+    fn notify(&self, event: ContainerRestartInfo) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
+impl<F, Fut> Notifier for F
+where
+    F: Fn(ContainerRestartInfo) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    fn notify(&self, event: ContainerRestartInfo) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(self(event))
+    }
+}
+
+/// Detection pipeline of johari-mirror: watches pods in all namespaces and other
+/// `EventSource`s, routes container restarts with `Router` and sends them with logs
+/// to `Notifier`.
+pub struct Watcher {
+    sources: Vec<Box<dyn EventSource>>,
+    router: Router,
+    silences: Silences,
+    notifier: Arc<dyn Notifier>,
+    hooks: Option<Arc<dyn Hooks>>,
+    pod_store: Store<Pod>,
+}
+
+impl Watcher {
+    /// Builder of `Watcher` of pods with `client`. Apply `rate_limit::ApiLimits` to the client with
+    /// `kube::client::ClientBuilder::with_layer` to throttle and time out its requests.
+    pub fn builder(client: Client) -> WatcherBuilder {
+        WatcherBuilder {
+            client: Some(client),
+            ..Self::builder_without_kubernetes()
+        }
+    }
+
+    /// Builder of `Watcher` only with sources added by `WatcherBuilder::source`
+    pub fn builder_without_kubernetes() -> WatcherBuilder {
+        WatcherBuilder {
+            client: None,
+            sources: Vec::new(),
+            config: WatchConfig::new(Router::default()),
+            notifier: None,
+            hooks: None,
+            silences: Silences::default(),
+            history: RestartHistory::default(),
+            restart_counts: PodRestartCounts::default(),
+        }
+    }
+
+    /// Cache of watched Pods. Pods are pruned to the fields needed to detect restarts.
+    /// Always empty without Kubernetes.
+    pub fn pod_store(&self) -> Store<Pod> {
+        self.pod_store.clone()
+    }
+
+    /// Runs until all sources end or any of them fails.
+    /// Notifications failed in `Notifier` are logged and not retried.
+    pub async fn run(self) -> anyhow::Result<()> {
+        let (tx, mut rx) = queue::channel(
+            queue::DEFAULT_QUEUE_CAPACITY,
+            QueuePolicy::Block,
+            None,
+            Vec::new(),
+        );
+        let notifier = self.notifier;
+        let hooks = self.hooks.clone();
+        let notify = tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let event = match &hooks {
+                    Some(hooks) => match hooks.before_send(event).await {
+                        Some(event) => event,
+                        None => continue,
+                    },
+                    None => event,
+                };
+                let container_key = event.container_key();
+                let record = NotificationRecord::new(&event);
+                let result = notifier.notify(event).await;
+                match &result {
+                    Ok(()) => metrics::notification_sent(),
+                    Err(e) => {
+                        metrics::notification_failed();
+                        log::error!("Failed to notify restart of {container_key}: {e}");
+                    }
+                }
+                if let Some(hooks) = &hooks {
+                    hooks.after_send(&record, &result).await;
+                }
+            }
+        });
+        let sink = EventSink::new(self.router, self.silences, self.hooks, tx);
+        let sources = self
+            .sources
+            .into_iter()
+            .map(|source| tokio::spawn(source.run(sink.clone())))
+            .collect::<Vec<_>>();
+        // The queue is closed when all sources end
+        drop(sink);
+        futures::future::try_join_all(sources.into_iter().map(|source| async { source.await? }))
+            .await?;
+        notify.await?;
+        Ok(())
+    }
+}
+
+/// Builder of `Watcher`. `router` and `notifier` are required.
+/// Other options default to the same values as the environment variables of the binary.
+pub struct WatcherBuilder {
+    client: Option<Client>,
+    sources: Vec<Box<dyn EventSource>>,
+    config: WatchConfig,
+    notifier: Option<Arc<dyn Notifier>>,
+    hooks: Option<Arc<dyn Hooks>>,
+    silences: Silences,
+    history: RestartHistory,
+    restart_counts: PodRestartCounts,
+}
+
+impl WatcherBuilder {
+    pub fn router(mut self, router: Router) -> Self {
+        self.config.notification_config = router;
+        self
+    }
+
+    /// Adds a source of restarts other than Kubernetes
+    pub fn source(mut self, source: impl EventSource) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    pub fn notifier(mut self, notifier: impl Notifier) -> Self {
+        self.notifier = Some(Arc::new(notifier));
+        self
+    }
+
+    /// Callbacks run when restarts are detected and notified
+    pub fn hooks(mut self, hooks: impl Hooks) -> Self {
+        self.hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    /// Number of log lines to fetch
+    pub fn log_tail_lines(mut self, lines: i64) -> Self {
+        self.config.log_tail_lines = lines;
+        self
+    }
+
+    /// Maximum size of logs to keep in bytes
+    pub fn log_max_bytes(mut self, bytes: usize) -> Self {
+        self.config.log_max_bytes = bytes;
+        self
+    }
+
+    pub fn log_fetch_timeout(mut self, timeout: Duration) -> Self {
+        self.config.log_fetch_timeout = timeout;
+        self
+    }
+
+    /// Maximum number of container logs fetched concurrently
+    pub fn log_fetch_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.log_fetch_concurrency = concurrency;
+        self
+    }
+
+    /// Time to wait for following events of a restart. Zero disables coalescing.
+    pub fn coalesce_window(mut self, window: Duration) -> Self {
+        self.config.coalesce_window = window;
+        self
+    }
+
+    /// Script to route restarts before the patterns of the router
+    #[cfg(feature = "script")]
+    pub fn routing_script(mut self, script: RoutingScript) -> Self {
+        self.config.routing_script = Some(Arc::new(script));
+        self
+    }
+
+    /// Silences to skip notifications, which can be added while running
+    pub fn silences(mut self, silences: Silences) -> Self {
+        self.silences = silences;
+        self
+    }
+
+    /// History to record restarts in, e.g. for summary reports
+    pub fn history(mut self, history: RestartHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Restart counts known before, e.g. loaded from a file, to detect restarts missed
+    pub fn restart_counts(mut self, restart_counts: PodRestartCounts) -> Self {
+        self.restart_counts = restart_counts;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<Watcher> {
+        if self.config.notification_config == Router::default() {
+            anyhow::bail!("Router is required");
+        }
+        let notifier = self
+            .notifier
+            .ok_or_else(|| anyhow::anyhow!("Notifier is required"))?;
+        self.config.validate()?;
+        let (pod_store, pod_store_writer) = reflector::store();
+        let router = self.config.notification_config.clone();
+        let mut sources = self.sources;
+        if let Some(client) = self.client {
+            let kubernetes = KubernetesSource {
+                client,
+                config: self.config,
+                health: Health::new(health::DEFAULT_WATCH_STALL_TIMEOUT),
+                state: WatchState {
+                    pod_store: pod_store_writer,
+                    restart_counts: self.restart_counts,
+                    startup_grace: None,
+                    silences: self.silences.clone(),
+                    history: self.history,
+                    hooks: self.hooks.clone(),
+                },
+                // Self-alerts are disabled without destinations
+                self_alert: SelfAlert::default(),
+            };
+            sources.insert(0, Box::new(kubernetes));
+        }
+        Ok(Watcher {
+            sources,
+            router,
+            silences: self.silences,
+            notifier,
+            hooks: self.hooks,
+            pod_store,
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::bail;
+use k8s_openapi::{
+    api::core::v1::Pod,
+    chrono::{DateTime, Utc},
+};
+use kube::{
+    api::{ApiResource, DynamicObject, GroupVersionKind, Patch, PatchParams},
+    Api, Client,
+};
+use serde_json::json;
+
+use crate::message::ContainerRestartInfo;
+
+/// Prefix of annotations written by johari-mirror
+pub const ANNOTATION_PREFIX: &str = "johari-mirror.flywheel.jp";
+
+/// Object annotated with the last notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationTarget {
+    Pod,
+    /// Controller of the pod, or the controller of the controller, e.g. Deployment of
+    /// ReplicaSet or CronJob of Job. The pod itself when not controlled.
+    Workload,
+}
+
+impl std::str::FromStr for AnnotationTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pod" => Ok(Self::Pod),
+            "workload" => Ok(Self::Workload),
+            _ => bail!("Unknown annotation target: {s}"),
+        }
+    }
+}
+
+/// Records the last notification in annotations, so that the Slack thread can be found
+/// from the cluster side
+#[derive(Clone)]
+pub struct PodAnnotator {
+    client: Client,
+    target: AnnotationTarget,
+}
+
+impl PodAnnotator {
+    pub fn new(client: Client, target: AnnotationTarget) -> Self {
+        Self { client, target }
+    }
+
+    /// Patches the annotations in the background. Failures are only logged.
+    pub fn annotate(&self, info: &ContainerRestartInfo, permalink: Option<String>) {
+        let Some(namespace) = info.namespace.clone() else {
+            return;
+        };
+        let patch = patch(info, permalink.as_deref(), Utc::now());
+        let pod = info.pod_name.clone();
+        let annotator = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = annotator.patch(&namespace, &pod, &patch).await {
+                log::warn!("Failed to annotate {namespace}/{pod}: {e}");
+            }
+        });
+    }
+
+    async fn patch(
+        &self,
+        namespace: &str,
+        pod: &str,
+        patch: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let (resource, name) = match self.target {
+            AnnotationTarget::Pod => (ApiResource::erase::<Pod>(&()), pod.to_owned()),
+            AnnotationTarget::Workload => self.find_workload(namespace, pod).await?,
+        };
+        Api::<DynamicObject>::namespaced_with(self.client.clone(), namespace, &resource)
+            .patch(&name, &PatchParams::default(), &Patch::Merge(patch))
+            .await?;
+        log::debug!("Annotated {} {namespace}/{name}", resource.kind);
+        Ok(())
+    }
+
+    /// Follows controller references from the pod up to two levels,
+    /// e.g. Pod -> ReplicaSet -> Deployment
+    async fn find_workload(
+        &self,
+        namespace: &str,
+        pod: &str,
+    ) -> anyhow::Result<(ApiResource, String)> {
+        let mut resource = ApiResource::erase::<Pod>(&());
+        let mut name = pod.to_owned();
+        for _ in 0..2 {
+            let object =
+                Api::<DynamicObject>::namespaced_with(self.client.clone(), namespace, &resource)
+                    .get(&name)
+                    .await?;
+            let Some(owner) = object
+                .metadata
+                .owner_references
+                .iter()
+                .flatten()
+                .find(|o| o.controller == Some(true))
+            else {
+                break;
+            };
+            let (group, version) = owner
+                .api_version
+                .rsplit_once('/')
+                .unwrap_or(("", &owner.api_version));
+            resource = ApiResource::from_gvk(&GroupVersionKind::gvk(group, version, &owner.kind));
+            name = owner.name.clone();
+        }
+        Ok((resource, name))
+    }
+}
+
+/// Merge patch of the annotations. A missing permalink removes the previous one.
+fn patch(
+    info: &ContainerRestartInfo,
+    permalink: Option<&str>,
+    now: DateTime<Utc>,
+) -> serde_json::Value {
+    let restart_count = info.restart_count.to_string();
+    json!({
+        "metadata": {
+            "annotations": {
+                format!("{ANNOTATION_PREFIX}/last-notified-at"): now.to_rfc3339(),
+                format!("{ANNOTATION_PREFIX}/last-notified-channel"): info.channel,
+                format!("{ANNOTATION_PREFIX}/last-notified-container"): info.container_name,
+                format!("{ANNOTATION_PREFIX}/last-notified-restart-count"): restart_count,
+                format!("{ANNOTATION_PREFIX}/last-notified-permalink"): permalink,
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_patch() {
+        let info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let permalink = "https://example.slack.com/archives/C01/p1704164645000100";
+        let body = patch(&info, Some(permalink), now);
+        let annotations = &body["metadata"]["annotations"];
+        assert_eq!(
+            annotations["johari-mirror.flywheel.jp/last-notified-at"],
+            "2024-01-02T03:04:05+00:00"
+        );
+        assert_eq!(
+            annotations["johari-mirror.flywheel.jp/last-notified-channel"],
+            "#alerts"
+        );
+        assert_eq!(
+            annotations["johari-mirror.flywheel.jp/last-notified-restart-count"],
+            "1"
+        );
+        assert_eq!(
+            annotations["johari-mirror.flywheel.jp/last-notified-permalink"],
+            permalink
+        );
+
+        let body = patch(&info, None, now);
+        assert!(body["metadata"]["annotations"]
+            ["johari-mirror.flywheel.jp/last-notified-permalink"]
+            .is_null());
+    }
+
+    #[test]
+    fn test_annotation_target() {
+        assert_eq!(
+            "workload".parse::<AnnotationTarget>().unwrap(),
+            AnnotationTarget::Workload
+        );
+        assert!("deployment".parse::<AnnotationTarget>().is_err());
+    }
+}
@@ -0,0 +1,589 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+    api::core::v1::{
+        Container, ContainerState, ContainerStatus, EnvVar, EnvVarSource, Event, Pod, Volume,
+    },
+    apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::ObjectMeta},
+    chrono::{DateTime, Utc},
+};
+use kube::ResourceExt;
+
+use crate::{message::Attachment, silence, yaml};
+
+/// Replaces values of environment variables in attached pod specs
+const REDACTED: &str = "[REDACTED]";
+
+/// Annotation of `kubectl apply` holding the whole manifest, including the values
+const LAST_APPLIED_ANNOTATION: &str = "kubectl.kubernetes.io/last-applied-configuration";
+
+/// Column of values of fields in the describe report, as `kubectl describe`
+const FIELD_WIDTH: usize = 18;
+
+/// Manifest of `p` as `<pod>.yaml` for the `pod_spec` option
+pub fn pod_spec(p: &Pod) -> Attachment {
+    Attachment {
+        title: "Pod spec".to_owned(),
+        filename: format!("{}.yaml", p.name_any()),
+        content: yaml::to_yaml(&[sanitize(p)]),
+    }
+}
+
+/// Metadata and spec of `p` without the status and fields managed by the API server.
+/// Values of environment variables are redacted since they often hold secrets, while
+/// references to ConfigMaps and Secrets are kept.
+fn sanitize(p: &Pod) -> serde_json::Value {
+    let mut annotations = p.annotations().clone();
+    annotations.remove(LAST_APPLIED_ANNOTATION);
+    let metadata = ObjectMeta {
+        name: p.metadata.name.clone(),
+        namespace: p.metadata.namespace.clone(),
+        labels: p.metadata.labels.clone(),
+        annotations: Some(annotations).filter(|a| !a.is_empty()),
+        owner_references: p.metadata.owner_references.clone(),
+        ..Default::default()
+    };
+    let mut spec = p.spec.clone().unwrap_or_default();
+    let containers = spec
+        .containers
+        .iter_mut()
+        .chain(spec.init_containers.iter_mut().flatten())
+        .map(|c| &mut c.env);
+    let ephemeral_containers = spec
+        .ephemeral_containers
+        .iter_mut()
+        .flatten()
+        .map(|c| &mut c.env);
+    for env in containers.chain(ephemeral_containers).flatten() {
+        env.iter_mut().for_each(redact);
+    }
+    let pod = Pod {
+        metadata,
+        spec: Some(spec),
+        status: None,
+    };
+    serde_json::to_value(pod).unwrap_or_default()
+}
+
+fn redact(env: &mut EnvVar) {
+    if env.value.is_some() {
+        env.value = Some(REDACTED.to_owned());
+    }
+}
+
+/// `kubectl describe pod`-style report of `p` and its `events` as `<pod>.describe.txt` for
+/// the `describe` option. `events` is `None` when they failed to be read.
+pub fn describe(p: &Pod, events: Option<&[Event]>, now: DateTime<Utc>) -> Attachment {
+    Attachment {
+        title: "Describe".to_owned(),
+        filename: format!("{}.describe.txt", p.name_any()),
+        content: describe_pod(p, events, now),
+    }
+}
+
+fn describe_pod(p: &Pod, events: Option<&[Event]>, now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    let spec = p.spec.clone().unwrap_or_default();
+    let status = p.status.clone().unwrap_or_default();
+    field(&mut out, 0, "Name", &p.name_any());
+    field(&mut out, 0, "Namespace", &p.namespace().unwrap_or_default());
+    if let Some(priority) = spec.priority {
+        field(&mut out, 0, "Priority", &priority.to_string());
+    }
+    if let Some(class) = &spec.priority_class_name {
+        field(&mut out, 0, "Priority Class Name", class);
+    }
+    let node = match (&spec.node_name, &status.host_ip) {
+        (Some(node), Some(ip)) => format!("{node}/{ip}"),
+        (Some(node), None) => node.clone(),
+        (None, _) => "<none>".to_owned(),
+    };
+    field(&mut out, 0, "Node", &node);
+    if let Some(start_time) = &status.start_time {
+        field(&mut out, 0, "Start Time", &start_time.0.to_rfc2822());
+    }
+    map_field(&mut out, "Labels", p.labels());
+    let mut annotations = p.annotations().clone();
+    annotations.remove(LAST_APPLIED_ANNOTATION);
+    map_field(&mut out, "Annotations", &annotations);
+    field(
+        &mut out,
+        0,
+        "Status",
+        status.phase.as_deref().unwrap_or("Unknown"),
+    );
+    if let Some(reason) = &status.reason {
+        field(&mut out, 0, "Reason", reason);
+    }
+    if let Some(message) = &status.message {
+        field(&mut out, 0, "Message", message);
+    }
+    field(
+        &mut out,
+        0,
+        "IP",
+        status.pod_ip.as_deref().unwrap_or("<none>"),
+    );
+    if let Some(owner) = p
+        .owner_references()
+        .iter()
+        .find(|o| o.controller == Some(true))
+    {
+        field(
+            &mut out,
+            0,
+            "Controlled By",
+            &format!("{}/{}", owner.kind, owner.name),
+        );
+    }
+    let init_containers = spec.init_containers.as_deref().unwrap_or_default();
+    if !init_containers.is_empty() {
+        out.push_str("Init Containers:\n");
+        let statuses = status
+            .init_container_statuses
+            .as_deref()
+            .unwrap_or_default();
+        for c in init_containers {
+            describe_container(&mut out, c, statuses.iter().find(|s| s.name == c.name));
+        }
+    }
+    out.push_str("Containers:\n");
+    let statuses = status.container_statuses.as_deref().unwrap_or_default();
+    for c in &spec.containers {
+        describe_container(&mut out, c, statuses.iter().find(|s| s.name == c.name));
+    }
+
+    let conditions = status.conditions.as_deref().unwrap_or_default();
+    if !conditions.is_empty() {
+        out.push_str("Conditions:\n");
+        let mut rows = vec![["Type".to_owned(), "Status".to_owned()]];
+        rows.extend(
+            conditions
+                .iter()
+                .map(|c| [c.type_.clone(), c.status.clone()]),
+        );
+        table(&mut out, &rows);
+    }
+    let volumes = spec.volumes.as_deref().unwrap_or_default();
+    if volumes.is_empty() {
+        field(&mut out, 0, "Volumes", "<none>");
+    } else {
+        out.push_str("Volumes:\n");
+        for volume in volumes {
+            out.push_str(&format!("  {}:\n", volume.name));
+            field(&mut out, 4, "Type", &volume_type(volume));
+        }
+    }
+    field(
+        &mut out,
+        0,
+        "QoS Class",
+        status.qos_class.as_deref().unwrap_or("<none>"),
+    );
+    describe_events(&mut out, events, now);
+    out
+}
+
+fn describe_container(out: &mut String, c: &Container, status: Option<&ContainerStatus>) {
+    out.push_str(&format!("  {}:\n", c.name));
+    field(out, 4, "Image", c.image.as_deref().unwrap_or_default());
+    if let Some(status) = status {
+        describe_state(out, "State", status.state.as_ref());
+        describe_state(out, "Last State", status.last_state.as_ref());
+        field(out, 4, "Ready", if status.ready { "True" } else { "False" });
+        field(out, 4, "Restart Count", &status.restart_count.to_string());
+    }
+    if let Some(resources) = &c.resources {
+        quantities(out, "Limits", resources.limits.as_ref());
+        quantities(out, "Requests", resources.requests.as_ref());
+    }
+    let env = c.env.as_deref().unwrap_or_default();
+    if !env.is_empty() {
+        out.push_str("    Environment:\n");
+        for var in env {
+            let value = match (&var.value, &var.value_from) {
+                (Some(_), _) => REDACTED.to_owned(),
+                (None, Some(source)) => env_source(source),
+                (None, None) => String::new(),
+            };
+            field(out, 6, &var.name, &value);
+        }
+    }
+    let mounts = c.volume_mounts.as_deref().unwrap_or_default();
+    if !mounts.is_empty() {
+        out.push_str("    Mounts:\n");
+        for mount in mounts {
+            let mode = if mount.read_only == Some(true) {
+                "ro"
+            } else {
+                "rw"
+            };
+            out.push_str(&format!(
+                "      {} from {} ({mode})\n",
+                mount.mount_path, mount.name
+            ));
+        }
+    }
+}
+
+fn describe_state(out: &mut String, name: &str, state: Option<&ContainerState>) {
+    let Some(state) = state else {
+        return;
+    };
+    if let Some(running) = &state.running {
+        field(out, 4, name, "Running");
+        if let Some(started_at) = &running.started_at {
+            field(out, 6, "Started", &started_at.0.to_rfc2822());
+        }
+    } else if let Some(terminated) = &state.terminated {
+        field(out, 4, name, "Terminated");
+        if let Some(reason) = &terminated.reason {
+            field(out, 6, "Reason", reason);
+        }
+        if let Some(message) = &terminated.message {
+            field(out, 6, "Message", message);
+        }
+        field(out, 6, "Exit Code", &terminated.exit_code.to_string());
+        if let Some(signal) = terminated.signal {
+            field(out, 6, "Signal", &signal.to_string());
+        }
+        if let Some(started_at) = &terminated.started_at {
+            field(out, 6, "Started", &started_at.0.to_rfc2822());
+        }
+        if let Some(finished_at) = &terminated.finished_at {
+            field(out, 6, "Finished", &finished_at.0.to_rfc2822());
+        }
+    } else if let Some(waiting) = &state.waiting {
+        field(out, 4, name, "Waiting");
+        if let Some(reason) = &waiting.reason {
+            field(out, 6, "Reason", reason);
+        }
+    }
+}
+
+fn quantities(out: &mut String, name: &str, quantities: Option<&BTreeMap<String, Quantity>>) {
+    let Some(quantities) = quantities.filter(|q| !q.is_empty()) else {
+        return;
+    };
+    out.push_str(&format!("    {name}:\n"));
+    for (resource, quantity) in quantities {
+        field(out, 6, resource, &quantity.0);
+    }
+}
+
+/// Source of the environment variable without the value, as `kubectl describe`
+fn env_source(source: &EnvVarSource) -> String {
+    if let Some(secret) = &source.secret_key_ref {
+        format!(
+            "<set to the key '{}' in secret '{}'>",
+            secret.key,
+            reference_name(secret)
+        )
+    } else if let Some(config_map) = &source.config_map_key_ref {
+        format!(
+            "<set to the key '{}' of config map '{}'>",
+            config_map.key,
+            reference_name(config_map)
+        )
+    } else if let Some(field) = &source.field_ref {
+        format!(
+            "({}:{})",
+            field.api_version.as_deref().unwrap_or("v1"),
+            field.field_path
+        )
+    } else if let Some(resource) = &source.resource_field_ref {
+        format!("{} ({})", resource.resource, resource.resource)
+    } else {
+        "<unknown>".to_owned()
+    }
+}
+
+/// `name` of the reference to a ConfigMap or a Secret, which is optional in older versions
+/// of the API
+fn reference_name(reference: &impl serde::Serialize) -> String {
+    let reference = serde_json::to_value(reference).unwrap_or_default();
+    reference["name"].as_str().unwrap_or_default().to_owned()
+}
+
+fn volume_type(volume: &Volume) -> String {
+    if let Some(claim) = &volume.persistent_volume_claim {
+        format!("PersistentVolumeClaim (claimName: {})", claim.claim_name)
+    } else if let Some(config_map) = &volume.config_map {
+        format!("ConfigMap (name: {})", reference_name(config_map))
+    } else if let Some(secret) = &volume.secret {
+        format!(
+            "Secret (secretName: {})",
+            secret.secret_name.as_deref().unwrap_or_default()
+        )
+    } else if volume.empty_dir.is_some() {
+        "EmptyDir".to_owned()
+    } else if let Some(host_path) = &volume.host_path {
+        format!("HostPath (path: {})", host_path.path)
+    } else if volume.projected.is_some() {
+        "Projected".to_owned()
+    } else if volume.downward_api.is_some() {
+        "DownwardAPI".to_owned()
+    } else {
+        "Other".to_owned()
+    }
+}
+
+/// Events in the order of the last occurrence, with the number of repeats
+fn describe_events(out: &mut String, events: Option<&[Event]>, now: DateTime<Utc>) {
+    let Some(events) = events else {
+        field(out, 0, "Events", "<failed to read>");
+        return;
+    };
+    if events.is_empty() {
+        field(out, 0, "Events", "<none>");
+        return;
+    }
+    let last_seen = |e: &Event| {
+        e.last_timestamp
+            .as_ref()
+            .map(|t| t.0)
+            .or(e.event_time.as_ref().map(|t| t.0))
+            .or(e.metadata.creation_timestamp.as_ref().map(|t| t.0))
+    };
+    let mut events = events.iter().collect::<Vec<_>>();
+    events.sort_by_key(|e| last_seen(e));
+    let mut rows = vec![[
+        "Type".to_owned(),
+        "Reason".to_owned(),
+        "Age".to_owned(),
+        "From".to_owned(),
+        "Message".to_owned(),
+    ]];
+    for event in events {
+        let age = |t: DateTime<Utc>| silence::format_duration(now - t);
+        let mut seen = last_seen(event).map_or_else(|| "<unknown>".to_owned(), age);
+        if let (Some(count @ 2..), Some(first)) = (event.count, &event.first_timestamp) {
+            seen = format!("{seen} (x{count} over {})", age(first.0));
+        }
+        let from = event
+            .source
+            .as_ref()
+            .and_then(|s| s.component.clone())
+            .or(event.reporting_component.clone())
+            .unwrap_or_default();
+        rows.push([
+            event.type_.clone().unwrap_or_default(),
+            event.reason.clone().unwrap_or_default(),
+            seen,
+            from,
+            event
+                .message
+                .as_deref()
+                .unwrap_or_default()
+                .trim()
+                .replace('\n', " "),
+        ]);
+    }
+    out.push_str("Events:\n");
+    table(out, &rows);
+}
+
+/// `name: value` aligned at `FIELD_WIDTH`, indented by `indent` spaces
+fn field(out: &mut String, indent: usize, name: &str, value: &str) {
+    let width = FIELD_WIDTH.saturating_sub(indent).max(name.len() + 2);
+    let name = format!("{name}:");
+    out.push_str(format!("{:indent$}{name:width$}{value}", "").trim_end());
+    out.push('\n');
+}
+
+/// `key=value` per line, or `<none>` when `map` is empty
+fn map_field(out: &mut String, name: &str, map: &BTreeMap<String, String>) {
+    if map.is_empty() {
+        field(out, 0, name, "<none>");
+        return;
+    }
+    let width = FIELD_WIDTH;
+    for (i, (key, value)) in map.iter().enumerate() {
+        let name = if i == 0 {
+            format!("{name}:")
+        } else {
+            String::new()
+        };
+        out.push_str(&format!("{name:width$}{key}={value}\n"));
+    }
+}
+
+/// Rows aligned in columns, indented by 2 spaces
+fn table<const N: usize>(out: &mut String, rows: &[[String; N]]) {
+    let widths = (0..N)
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out.push_str(&format!("  {}\n", line.trim_end()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::{
+            ContainerStateTerminated, EventSource, PodSpec, PodStatus, SecretKeySelector,
+        },
+        apimachinery::pkg::apis::meta::v1::Time,
+        chrono,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_pod_spec() {
+        let pod = Pod {
+            metadata: ObjectMeta {
+                name: Some("app-0".to_owned()),
+                namespace: Some("default".to_owned()),
+                annotations: Some([(LAST_APPLIED_ANNOTATION.to_owned(), "{}".to_owned())].into()),
+                managed_fields: Some(Vec::new()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "app".to_owned(),
+                    env: Some(vec![
+                        EnvVar {
+                            name: "DATABASE_URL".to_owned(),
+                            value: Some("postgres://user:secret@db".to_owned()),
+                            value_from: None,
+                        },
+                        EnvVar {
+                            name: "API_KEY".to_owned(),
+                            value: None,
+                            value_from: Some(EnvVarSource {
+                                secret_key_ref: Some(SecretKeySelector {
+                                    key: "api-key".to_owned(),
+                                    ..Default::default()
+                                }),
+                                ..Default::default()
+                            }),
+                        },
+                    ]),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            status: Some(Default::default()),
+        };
+        let attachment = pod_spec(&pod);
+        assert_eq!(attachment.filename, "app-0.yaml");
+        let content = attachment.content;
+        assert!(content.starts_with("apiVersion: v1\nkind: Pod\nmetadata:\n  name: app-0\n"));
+        assert!(content.contains("value: '[REDACTED]'"));
+        assert!(content.contains("key: api-key"));
+        assert!(describe(&pod, Some(&[]), Utc::now())
+            .content
+            .contains("      API_KEY:    <set to the key 'api-key' in secret ''>\n"));
+        assert!(!content.contains("secret@db"));
+        assert!(!content.contains("last-applied-configuration"));
+        assert!(!content.contains("managedFields"));
+        assert!(!content.contains("status"));
+    }
+
+    #[test]
+    fn test_describe() {
+        let now = "2024-01-02T03:10:00Z".parse::<DateTime<Utc>>().unwrap();
+        let pod = Pod {
+            metadata: ObjectMeta {
+                name: Some("app-0".to_owned()),
+                namespace: Some("default".to_owned()),
+                labels: Some([("app".to_owned(), "web".to_owned())].into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: Some("node-1".to_owned()),
+                containers: vec![Container {
+                    name: "app".to_owned(),
+                    image: Some("nginx:1.25".to_owned()),
+                    env: Some(vec![EnvVar {
+                        name: "TOKEN".to_owned(),
+                        value: Some("secret".to_owned()),
+                        value_from: None,
+                    }]),
+                    ..Default::default()
+                }],
+                volumes: Some(vec![Volume {
+                    name: "tmp".to_owned(),
+                    empty_dir: Some(Default::default()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            status: Some(PodStatus {
+                phase: Some("Running".to_owned()),
+                container_statuses: Some(vec![ContainerStatus {
+                    name: "app".to_owned(),
+                    restart_count: 3,
+                    last_state: Some(ContainerState {
+                        terminated: Some(ContainerStateTerminated {
+                            exit_code: 137,
+                            reason: Some("OOMKilled".to_owned()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+        };
+        let event = Event {
+            type_: Some("Warning".to_owned()),
+            reason: Some("BackOff".to_owned()),
+            message: Some("Back-off restarting failed container\n".to_owned()),
+            count: Some(5),
+            first_timestamp: Some(Time(now - chrono::Duration::minutes(8))),
+            last_timestamp: Some(Time(now - chrono::Duration::minutes(2))),
+            source: Some(EventSource {
+                component: Some("kubelet".to_owned()),
+                host: None,
+            }),
+            ..Default::default()
+        };
+        let report = describe(&pod, Some(&[event]), now);
+        assert_eq!(report.filename, "app-0.describe.txt");
+        assert_eq!(
+            report.content,
+            "Name:             app-0\n\
+             Namespace:        default\n\
+             Node:             node-1\n\
+             Labels:           app=web\n\
+             Annotations:      <none>\n\
+             Status:           Running\n\
+             IP:               <none>\n\
+             Containers:\n  \
+               app:\n    \
+                 Image:        nginx:1.25\n    \
+                 Last State:   Terminated\n      \
+                   Reason:     OOMKilled\n      \
+                   Exit Code:  137\n    \
+                 Ready:        False\n    \
+                 Restart Count: 3\n    \
+                 Environment:\n      \
+                   TOKEN:      [REDACTED]\n\
+             Volumes:\n  \
+               tmp:\n    \
+                 Type:         EmptyDir\n\
+             QoS Class:        <none>\n\
+             Events:\n  \
+               Type     Reason   Age              From     Message\n  \
+               Warning  BackOff  2m (x5 over 8m)  kubelet  Back-off restarting failed container\n"
+        );
+        assert!(describe(&pod, None, now)
+            .content
+            .ends_with("Events:           <failed to read>\n"));
+    }
+}
@@ -0,0 +1,227 @@
+use k8s_openapi::{
+    api::core::v1::{ContainerStatus, Event, EventSource, ObjectReference, Pod},
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time},
+    chrono::{DateTime, Utc},
+};
+use kube::{api::PostParams, Api, Client, ResourceExt};
+
+use crate::message::ContainerRestartInfo;
+
+/// `source.component` and `reportingComponent` of Events
+const REPORTING_COMPONENT: &str = "johari-mirror";
+
+/// Messages are truncated to the limit of notes of `events.k8s.io/v1`
+const MESSAGE_MAX_BYTES: usize = 1024;
+
+/// Restart of a container an Event is recorded on
+#[derive(Debug, Clone)]
+pub struct EventTarget {
+    pub namespace: String,
+    pub pod: String,
+    /// Required by `kubectl describe pod` to find Events of the pod
+    pub pod_uid: Option<String>,
+    pub container: String,
+    pub restart_count: i32,
+}
+
+impl EventTarget {
+    pub fn of_container(p: &Pod, container: &ContainerStatus) -> Self {
+        Self {
+            namespace: p.namespace().unwrap_or_default(),
+            pod: p.name_any(),
+            pod_uid: p.uid(),
+            container: container.name.clone(),
+            restart_count: container.restart_count,
+        }
+    }
+
+    pub fn of_restart(info: &ContainerRestartInfo) -> Self {
+        Self {
+            namespace: info.namespace.clone().unwrap_or_default(),
+            pod: info.pod_name.clone(),
+            pod_uid: info.pod_uid.clone(),
+            container: info.container_name.clone(),
+            restart_count: info.restart_count,
+        }
+    }
+}
+
+/// What johari-mirror did about a restart
+#[derive(Debug)]
+pub enum NotificationOutcome<'a> {
+    /// Sent to `channel` by the notifier, e.g. `Slack`
+    Sent {
+        notifier: &'a str,
+        channel: &'a str,
+    },
+    Failed {
+        channel: &'a str,
+        error: String,
+    },
+    /// Suppressed by the silence of the ID
+    Silenced(u64),
+    /// Dropped by `NOTIFICATION_MIDDLEWARES`
+    Dropped,
+}
+
+/// Records notification outcomes as Events on pods, shown by `kubectl describe pod`
+#[derive(Clone)]
+pub struct PodEvents {
+    client: Client,
+    /// `reportingInstance` of Events, the pod name of johari-mirror if known
+    instance: String,
+}
+
+impl PodEvents {
+    pub fn new(client: Client) -> Self {
+        // `HOSTNAME` is the pod name in Kubernetes
+        let instance = std::env::var("HOSTNAME").unwrap_or_else(|_| "johari-mirror".to_owned());
+        Self { client, instance }
+    }
+
+    /// Creates the Event in the background. Failures are only logged.
+    pub fn publish(&self, target: EventTarget, outcome: NotificationOutcome<'_>) {
+        let event = event(&target, &outcome, &self.instance, Utc::now());
+        let api = Api::<Event>::namespaced(self.client.clone(), &target.namespace);
+        tokio::spawn(async move {
+            if let Err(e) = api.create(&PostParams::default(), &event).await {
+                log::warn!(
+                    "Failed to create event on pod {}/{}: {e}",
+                    target.namespace,
+                    target.pod
+                );
+            }
+        });
+    }
+}
+
+/// Event on the pod, e.g. `NotifiedSlack` with message `#alerts, restart 5 of container app`
+fn event(
+    target: &EventTarget,
+    outcome: &NotificationOutcome<'_>,
+    instance: &str,
+    now: DateTime<Utc>,
+) -> Event {
+    let restart = format!(
+        "restart {} of container {}",
+        target.restart_count, target.container
+    );
+    let (type_, reason, mut message) = match outcome {
+        NotificationOutcome::Sent { notifier, channel } => (
+            "Normal",
+            format!("Notified{notifier}"),
+            format!("{channel}, {restart}"),
+        ),
+        NotificationOutcome::Failed { channel, error } => (
+            "Warning",
+            "NotificationFailed".to_owned(),
+            format!("{channel}, {restart}: {error}"),
+        ),
+        NotificationOutcome::Silenced(id) => (
+            "Normal",
+            "NotificationSilenced".to_owned(),
+            format!("By silence #{id}, {restart}"),
+        ),
+        NotificationOutcome::Dropped => (
+            "Normal",
+            "NotificationDropped".to_owned(),
+            format!("By NOTIFICATION_MIDDLEWARES, {restart}"),
+        ),
+    };
+    if message.len() > MESSAGE_MAX_BYTES {
+        let mut end = MESSAGE_MAX_BYTES;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    Event {
+        metadata: ObjectMeta {
+            // Same format as Events of kubelet
+            name: Some(format!(
+                "{}.{:x}",
+                target.pod,
+                now.timestamp_nanos_opt().unwrap_or_default()
+            )),
+            namespace: Some(target.namespace.clone()),
+            ..Default::default()
+        },
+        first_timestamp: Some(Time(now)),
+        last_timestamp: Some(Time(now)),
+        count: Some(1),
+        action: Some("Notify".to_owned()),
+        reason: Some(reason),
+        message: Some(message),
+        type_: Some(type_.to_owned()),
+        involved_object: ObjectReference {
+            api_version: Some("v1".to_owned()),
+            kind: Some("Pod".to_owned()),
+            name: Some(target.pod.clone()),
+            namespace: Some(target.namespace.clone()),
+            uid: target.pod_uid.clone(),
+            field_path: Some(format!("spec.containers{{{}}}", target.container)),
+            ..Default::default()
+        },
+        source: Some(EventSource {
+            component: Some(REPORTING_COMPONENT.to_owned()),
+            ..Default::default()
+        }),
+        reporting_component: Some(REPORTING_COMPONENT.to_owned()),
+        reporting_instance: Some(instance.to_owned()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::TimeZone;
+
+    use super::*;
+
+    fn target() -> EventTarget {
+        EventTarget {
+            namespace: "default".to_owned(),
+            pod: "app-0".to_owned(),
+            pod_uid: Some("uid".to_owned()),
+            container: "app".to_owned(),
+            restart_count: 5,
+        }
+    }
+
+    #[test]
+    fn test_event() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let outcome = NotificationOutcome::Sent {
+            notifier: "Slack",
+            channel: "#alerts",
+        };
+        let e = event(&target(), &outcome, "johari-mirror-0", now);
+        assert_eq!(e.metadata.name.as_deref(), Some("app-0.17a668b730013200"));
+        assert_eq!(e.reason.as_deref(), Some("NotifiedSlack"));
+        assert_eq!(
+            e.message.as_deref(),
+            Some("#alerts, restart 5 of container app")
+        );
+        assert_eq!(e.type_.as_deref(), Some("Normal"));
+        assert_eq!(e.involved_object.uid.as_deref(), Some("uid"));
+        assert_eq!(
+            e.involved_object.field_path.as_deref(),
+            Some("spec.containers{app}")
+        );
+
+        let e = event(&target(), &NotificationOutcome::Silenced(3), "", now);
+        assert_eq!(e.reason.as_deref(), Some("NotificationSilenced"));
+        assert_eq!(
+            e.message.as_deref(),
+            Some("By silence #3, restart 5 of container app")
+        );
+
+        let outcome = NotificationOutcome::Failed {
+            channel: "#alerts",
+            error: "é".repeat(MESSAGE_MAX_BYTES),
+        };
+        let e = event(&target(), &outcome, "", now);
+        assert_eq!(e.type_.as_deref(), Some("Warning"));
+        assert!(e.message.unwrap().len() <= MESSAGE_MAX_BYTES);
+    }
+}
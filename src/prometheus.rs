@@ -0,0 +1,466 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{self, DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    http::{self, parse_headers},
+    message::{ContainerRestartInfo, MessageLink},
+    silence,
+};
+
+/// Range of the query before the restart by default
+pub const DEFAULT_QUERY_RANGE: chrono::Duration = chrono::Duration::minutes(30);
+
+/// Range of the peak memory usage compared with the limit of OOM killed containers by
+/// default, which covers recent restarts in crash loops
+pub const DEFAULT_MEMORY_RANGE: chrono::Duration = chrono::Duration::hours(6);
+
+/// Peak memory usage of the container over `{range}` by default
+const DEFAULT_MEMORY_QUERY: &str = "max(max_over_time(container_memory_working_set_bytes\
+{namespace=\"{namespace}\",pod=\"{pod}\",container=\"{container}\"}[{range}]))";
+
+/// Ratio of the peak memory usage to the limit to suggest raising the limit
+const MEMORY_LIMIT_RATIO: f64 = 0.9;
+
+/// Title of the query result by default
+const DEFAULT_QUERY_TITLE: &str = "Prometheus";
+
+/// Number of points of the range query, which decides the step
+const QUERY_POINTS: i64 = 60;
+
+/// Timeout of each query not to delay notifications when Prometheus is unavailable
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Unit of query results, used to format values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValueUnit {
+    #[default]
+    None,
+    /// Formatted in binary units, e.g. `512.0Mi`
+    Bytes,
+}
+
+impl std::str::FromStr for ValueUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "bytes" => Ok(Self::Bytes),
+            _ => bail!("Unknown unit: {s}"),
+        }
+    }
+}
+
+/// Configuration of enriching messages with a PromQL query
+#[derive(Debug, Clone)]
+pub struct PrometheusConfig {
+    /// e.g. `http://prometheus:9090`
+    pub url: String,
+    /// PromQL with `{namespace}`, `{pod}` and `{container}` placeholders
+    pub query: String,
+    pub title: String,
+    pub range: chrono::Duration,
+    pub unit: ValueUnit,
+    /// e.g. `Authorization` of hosted Prometheus
+    pub headers: Vec<(String, String)>,
+    /// PromQL of the peak memory usage of OOM killed containers, with `{range}` placeholder
+    /// in addition to those of `query`
+    pub memory_query: String,
+    pub memory_range: chrono::Duration,
+}
+
+impl PrometheusConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `PROMETHEUS_URL` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("PROMETHEUS_URL") else {
+            return Ok(None);
+        };
+        reqwest::Url::parse(&url).context("Invalid PROMETHEUS_URL")?;
+        let query = std::env::var("PROMETHEUS_QUERY").context("PROMETHEUS_QUERY is required")?;
+        let title = std::env::var("PROMETHEUS_QUERY_TITLE")
+            .unwrap_or_else(|_| DEFAULT_QUERY_TITLE.to_owned());
+        let range = match std::env::var("PROMETHEUS_QUERY_RANGE") {
+            Ok(range) => silence::parse_duration(&range)
+                .map_err(|e| anyhow::anyhow!("Invalid PROMETHEUS_QUERY_RANGE: {e}"))?,
+            Err(_) => DEFAULT_QUERY_RANGE,
+        };
+        let unit = match std::env::var("PROMETHEUS_QUERY_UNIT") {
+            Ok(unit) => unit.parse().context("Invalid PROMETHEUS_QUERY_UNIT")?,
+            Err(_) => ValueUnit::default(),
+        };
+        let headers = match std::env::var("PROMETHEUS_HEADERS") {
+            Ok(headers) => parse_headers(&headers).context("Invalid PROMETHEUS_HEADERS")?,
+            Err(_) => Vec::new(),
+        };
+        let memory_query = std::env::var("PROMETHEUS_MEMORY_QUERY")
+            .unwrap_or_else(|_| DEFAULT_MEMORY_QUERY.to_owned());
+        let memory_range = match std::env::var("PROMETHEUS_MEMORY_RANGE") {
+            Ok(range) => silence::parse_duration(&range)
+                .map_err(|e| anyhow::anyhow!("Invalid PROMETHEUS_MEMORY_RANGE: {e}"))?,
+            Err(_) => DEFAULT_MEMORY_RANGE,
+        };
+        Ok(Some(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            query,
+            title,
+            range,
+            unit,
+            headers,
+            memory_query,
+            memory_range,
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryResponse {
+    data: QueryData,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryData {
+    result: Vec<Series>,
+}
+
+/// Series of a matrix result, whose values are `[<unix time>, "<value>"]`
+#[derive(Debug, Deserialize)]
+struct Series {
+    values: Vec<(f64, String)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstantResponse {
+    data: InstantData,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstantData {
+    result: Vec<Sample>,
+}
+
+/// Series of a vector result, whose value is `[<unix time>, "<value>"]`
+#[derive(Debug, Deserialize)]
+struct Sample {
+    value: (f64, String),
+}
+
+/// Runs the configured query over the period before restarts
+#[derive(Clone)]
+pub struct Prometheus {
+    config: PrometheusConfig,
+    http: reqwest::Client,
+}
+
+impl Prometheus {
+    pub fn new(config: PrometheusConfig) -> Self {
+        Self {
+            config,
+            http: http::client(),
+        }
+    }
+
+    /// Link to the graph of the query titled with the statistics of the result,
+    /// e.g. `Memory over 30m: last 1.9Gi, min 512.0Mi, max 2.0Gi`.
+    /// `None` when the query returns no series.
+    pub async fn query(&self, info: &ContainerRestartInfo) -> anyhow::Result<Option<MessageLink>> {
+        let query = render_query(&self.config.query, info);
+        let end = termination_time(info);
+        let start = end - self.config.range;
+        let step = (self.config.range.num_seconds() / QUERY_POINTS).max(1);
+        let mut request = self
+            .http
+            .get(format!("{}/api/v1/query_range", self.config.url))
+            .timeout(QUERY_TIMEOUT)
+            .query(&[
+                ("query", query.clone()),
+                ("start", start.timestamp().to_string()),
+                ("end", end.timestamp().to_string()),
+                ("step", step.to_string()),
+            ]);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<QueryResponse>()
+            .await
+            .context("Invalid response of Prometheus")?;
+        let Some(series) = response.data.result.first() else {
+            log::debug!("No result of Prometheus query for {info}");
+            return Ok(None);
+        };
+        if response.data.result.len() > 1 {
+            log::debug!(
+                "Prometheus query for {info} returned {} series, using the first",
+                response.data.result.len()
+            );
+        }
+        let Some(stats) = stats(series, self.config.unit) else {
+            return Ok(None);
+        };
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/graph", self.config.url),
+            &[
+                ("g0.expr", query),
+                ("g0.tab", "0".to_owned()),
+                (
+                    "g0.range_input",
+                    format!("{}s", self.config.range.num_seconds()),
+                ),
+                ("g0.end_input", end.format("%Y-%m-%d %H:%M:%S").to_string()),
+            ],
+        )?;
+        Ok(Some(MessageLink {
+            title: format!(
+                "{} over {}: {stats}",
+                self.config.title,
+                silence::format_duration(self.config.range)
+            ),
+            url: url.into(),
+        }))
+    }
+
+    /// Suggestion on the memory limit of OOM killed containers from the peak memory usage
+    /// over `memory_range` up to the termination.
+    /// `None` for other containers, containers without memory limits and no results.
+    pub async fn oom_headroom(
+        &self,
+        info: &ContainerRestartInfo,
+    ) -> anyhow::Result<Option<String>> {
+        let oom_killed = info
+            .last_state
+            .as_ref()
+            .is_some_and(|s| s.reason.as_deref() == Some("OOMKilled"));
+        let Some((limit, limit_bytes)) = info
+            .resources
+            .limits
+            .iter()
+            .find(|(name, _)| name == "memory")
+            .and_then(|(_, limit)| Some((limit, parse_bytes(limit)?)))
+            .filter(|_| oom_killed)
+        else {
+            return Ok(None);
+        };
+        let range = format!("{}s", self.config.memory_range.num_seconds());
+        let query = render_query(&self.config.memory_query, info).replace("{range}", &range);
+        let mut request = self
+            .http
+            .get(format!("{}/api/v1/query", self.config.url))
+            .timeout(QUERY_TIMEOUT)
+            .query(&[
+                ("query", query),
+                ("time", termination_time(info).timestamp().to_string()),
+            ]);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<InstantResponse>()
+            .await
+            .context("Invalid response of Prometheus")?;
+        let Some(peak) = response
+            .data
+            .result
+            .first()
+            .and_then(|sample| sample.value.1.parse::<f64>().ok())
+            .filter(|peak| peak.is_finite())
+        else {
+            log::debug!("No peak memory usage of {info} in Prometheus");
+            return Ok(None);
+        };
+        Ok(Some(headroom_note(
+            peak,
+            limit,
+            limit_bytes,
+            self.config.memory_range,
+        )))
+    }
+}
+
+/// End of queries, the termination of the container or now when unknown
+fn termination_time(info: &ContainerRestartInfo) -> DateTime<Utc> {
+    info.last_state
+        .as_ref()
+        .and_then(|s| s.finished_at.as_deref())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map_or_else(Utc::now, |t| t.with_timezone(&Utc))
+}
+
+/// e.g. `Memory peaked at 98% of the 512Mi limit over 6h 0m; consider raising the limit`
+fn headroom_note(peak: f64, limit: &str, limit_bytes: f64, range: chrono::Duration) -> String {
+    let ratio = peak / limit_bytes;
+    let advice = if ratio >= MEMORY_LIMIT_RATIO {
+        "consider raising the limit"
+    } else {
+        "the usage likely spiked faster than the metrics are scraped, \
+         so check allocations before raising the limit"
+    };
+    format!(
+        ":bulb: Memory peaked at {:.0}% of the `{limit}` limit over {}; {advice}.",
+        ratio * 100.0,
+        silence::format_duration(range),
+    )
+}
+
+/// Bytes of a Kubernetes quantity, e.g. `512Mi`, `1G` or `1e9`
+fn parse_bytes(quantity: &str) -> Option<f64> {
+    if let Ok(bytes) = quantity.parse::<f64>() {
+        return Some(bytes);
+    }
+    let split = quantity.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, suffix) = quantity.split_at(split);
+    let multiplier = match suffix {
+        "Ki" => 1024_f64,
+        "Mi" => 1024_f64.powi(2),
+        "Gi" => 1024_f64.powi(3),
+        "Ti" => 1024_f64.powi(4),
+        "Pi" => 1024_f64.powi(5),
+        "Ei" => 1024_f64.powi(6),
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        _ => return None,
+    };
+    Some(number.parse::<f64>().ok()? * multiplier)
+}
+
+/// Fills the placeholders with the container. Other braces are label matchers of PromQL.
+fn render_query(template: &str, info: &ContainerRestartInfo) -> String {
+    template
+        .replace("{namespace}", info.namespace.as_deref().unwrap_or(""))
+        .replace("{pod}", &info.pod_name)
+        .replace("{container}", &info.container_name)
+}
+
+/// Last, minimum and maximum of the series, e.g. `last 1.9Gi, min 512.0Mi, max 2.0Gi`.
+/// Values which are not numbers, e.g. `NaN`, are ignored.
+fn stats(series: &Series, unit: ValueUnit) -> Option<String> {
+    let values = series
+        .values
+        .iter()
+        .filter_map(|(_, value)| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .collect::<Vec<_>>();
+    let last = *values.last()?;
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Some(format!(
+        "last {}, min {}, max {}",
+        format_value(last, unit),
+        format_value(min, unit),
+        format_value(max, unit)
+    ))
+}
+
+fn format_value(value: f64, unit: ValueUnit) -> String {
+    match unit {
+        ValueUnit::None => {
+            let value = format!("{value:.3}");
+            value.trim_end_matches('0').trim_end_matches('.').to_owned()
+        }
+        ValueUnit::Bytes => {
+            if value.abs() < 1024.0 {
+                return format!("{value:.0}B");
+            }
+            let mut value = value;
+            let mut suffix = "";
+            for next in ["Ki", "Mi", "Gi", "Ti"] {
+                if value.abs() < 1024.0 {
+                    break;
+                }
+                value /= 1024.0;
+                suffix = next;
+            }
+            format!("{value:.1}{suffix}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_query() {
+        let info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let template =
+            r#"max(memory{namespace="{namespace}",pod="{pod}",container="{container}"})"#;
+        assert_eq!(
+            render_query(template, &info),
+            r#"max(memory{namespace="default",pod="app-0",container="app"})"#
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let series = Series {
+            values: vec![
+                (1.0, "536870912".to_owned()),
+                (2.0, "NaN".to_owned()),
+                (3.0, "2147483648".to_owned()),
+                (4.0, "2040109465".to_owned()),
+            ],
+        };
+        assert_eq!(
+            stats(&series, ValueUnit::Bytes).as_deref(),
+            Some("last 1.9Gi, min 512.0Mi, max 2.0Gi")
+        );
+        assert_eq!(
+            stats(
+                &Series {
+                    values: vec![(1.0, "0.25".to_owned())]
+                },
+                ValueUnit::None
+            )
+            .as_deref(),
+            Some("last 0.25, min 0.25, max 0.25")
+        );
+        assert_eq!(stats(&Series { values: vec![] }, ValueUnit::None), None);
+    }
+
+    #[test]
+    fn test_headroom_note() {
+        assert_eq!(parse_bytes("512Mi"), Some(536870912.0));
+        assert_eq!(parse_bytes("1.5G"), Some(1.5e9));
+        assert_eq!(parse_bytes("1e3"), Some(1000.0));
+        assert_eq!(parse_bytes("128974848"), Some(128974848.0));
+        assert_eq!(parse_bytes("1Xi"), None);
+
+        let range = chrono::Duration::hours(6);
+        assert_eq!(
+            headroom_note(526133657.0, "512Mi", 536870912.0, range),
+            ":bulb: Memory peaked at 98% of the `512Mi` limit over 6h 0m; \
+             consider raising the limit."
+        );
+        assert!(headroom_note(268435456.0, "512Mi", 536870912.0, range)
+            .starts_with(":bulb: Memory peaked at 50% of the `512Mi` limit over 6h 0m; the usage"));
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(512.0, ValueUnit::Bytes), "512B");
+        assert_eq!(format_value(1536.0, ValueUnit::Bytes), "1.5Ki");
+        assert_eq!(format_value(3.0, ValueUnit::None), "3");
+        assert_eq!(format_value(1.23456, ValueUnit::None), "1.235");
+    }
+}
@@ -0,0 +1,507 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use tokio::sync::Notify;
+
+use crate::{message::ContainerRestartInfo, metrics};
+
+/// Default maximum number of notifications in the queue
+pub const DEFAULT_QUEUE_CAPACITY: usize = 320;
+
+/// Behavior when the notification queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Pauses watching pods until the queue has space
+    #[default]
+    Block,
+    /// Drops the oldest notification, which is reported by a summary message
+    DropOldest,
+    /// Keeps notifications only in `DiskQueue` until the queue has space
+    Spill,
+}
+
+impl std::str::FromStr for QueuePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            "spill" => Ok(Self::Spill),
+            _ => bail!("Invalid queue policy: {s}"),
+        }
+    }
+}
+
+/// Creates the notification queue from the watcher to `slack_send`.
+/// `pending` notifications loaded from `disk` are queued first, and those over `capacity`
+/// are read from `disk` as the queue has space.
+pub fn channel(
+    capacity: usize,
+    policy: QueuePolicy,
+    disk: Option<DiskQueue>,
+    pending: Vec<ContainerRestartInfo>,
+) -> (NotificationSender, NotificationReceiver) {
+    let mut state = State {
+        senders: 1,
+        ..Default::default()
+    };
+    for info in pending {
+        match info.queue_id {
+            Some(id) if state.items.len() >= capacity => state.spilled.push_back(id),
+            _ => state.items.push_back(info),
+        }
+    }
+    let shared = Arc::new(Shared {
+        state: Mutex::new(state),
+        capacity,
+        policy,
+        disk,
+        pushed: Notify::new(),
+        popped: Notify::new(),
+    });
+    (
+        NotificationSender(Arc::clone(&shared)),
+        NotificationReceiver(shared),
+    )
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    policy: QueuePolicy,
+    disk: Option<DiskQueue>,
+    /// Notified when a notification is queued or all senders are dropped
+    pushed: Notify,
+    /// Notified when a notification is taken or the receiver is dropped
+    popped: Notify,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    items: VecDeque<ContainerRestartInfo>,
+    /// IDs of notifications kept only in `DiskQueue`, which follow `items`
+    spilled: VecDeque<u64>,
+    /// Number of dropped notifications per channel since the last summary
+    dropped: BTreeMap<String, usize>,
+    senders: usize,
+    closed: bool,
+}
+
+impl Shared {
+    /// Reads spilled notifications into `items` while the queue has space.
+    fn refill(&self, state: &mut State) {
+        let Some(disk) = &self.disk else {
+            return;
+        };
+        while state.items.len() < self.capacity {
+            let Some(id) = state.spilled.pop_front() else {
+                return;
+            };
+            match disk.load(id) {
+                Ok(info) => state.items.push_back(info),
+                Err(e) => log::error!("Failed to read spilled notification: {e}"),
+            }
+        }
+    }
+}
+
+/// Sending side of the notification queue from the watcher to `slack_send`.
+/// Notifications are also written to `DiskQueue` when configured.
+#[derive(Debug)]
+pub struct NotificationSender(Arc<Shared>);
+
+impl NotificationSender {
+    /// Queues `info` following `QueuePolicy` when the queue is full.
+    pub async fn send(&self, mut info: ContainerRestartInfo) -> anyhow::Result<()> {
+        let shared = &self.0;
+        if let Some(disk) = &shared.disk {
+            disk.push(&mut info);
+        }
+        let mut blocked_since = None;
+        loop {
+            // Registered before checking the state, as `notify_waiters` on closing the queue
+            // does not wake up senders which start waiting after it
+            let mut popped = std::pin::pin!(shared.popped.notified());
+            popped.as_mut().enable();
+            {
+                let mut state = shared.state.lock().unwrap();
+                if state.closed {
+                    bail!("Notification queue is closed");
+                }
+                log::debug!(
+                    "Message queue length: {} / {}",
+                    state.items.len(),
+                    shared.capacity
+                );
+                let full = state.items.len() >= shared.capacity;
+                // Notifications follow spilled ones to keep the order
+                let spill =
+                    !state.spilled.is_empty() || (full && shared.policy == QueuePolicy::Spill);
+                if let Some(id) = info.queue_id.filter(|_| spill) {
+                    log::warn!("Message queue is full, keeping the notification on the disk");
+                    state.spilled.push_back(id);
+                    shared.pushed.notify_one();
+                    break;
+                }
+                if full && shared.policy == QueuePolicy::DropOldest {
+                    if let Some(oldest) = state.items.pop_front() {
+                        log::warn!(
+                            "Message queue is full, dropping the oldest notification: {oldest}"
+                        );
+                        *state.dropped.entry(oldest.channel.clone()).or_default() += 1;
+                        metrics::queue_dropped();
+                        if let Some(disk) = &shared.disk {
+                            disk.remove(&oldest);
+                        }
+                    }
+                }
+                if state.items.len() < shared.capacity {
+                    state.items.push_back(info);
+                    shared.pushed.notify_one();
+                    break;
+                }
+            }
+            // Watching is blocked until Slack delivery catches up
+            if blocked_since.is_none() {
+                log::warn!("Message queue is full, waiting for Slack delivery");
+                blocked_since = Some(Instant::now());
+            }
+            popped.await;
+        }
+        if let Some(started) = blocked_since {
+            metrics::queue_blocked(started.elapsed());
+            log::warn!(
+                "Waited {} ms for the message queue",
+                started.elapsed().as_millis()
+            );
+        }
+        Ok(())
+    }
+
+    /// Handle to inspect the queue, which does not keep the queue open
+    pub fn monitor(&self) -> QueueMonitor {
+        QueueMonitor(Arc::clone(&self.0))
+    }
+
+    /// Handle to send notifications later, which does not keep the queue open
+    pub fn downgrade(&self) -> WeakNotificationSender {
+        WeakNotificationSender(Arc::clone(&self.0))
+    }
+}
+
+impl Clone for NotificationSender {
+    fn clone(&self) -> Self {
+        self.0.state.lock().unwrap().senders += 1;
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl Drop for NotificationSender {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.0.pushed.notify_one();
+        }
+    }
+}
+
+/// Sending side of the notification queue which does not keep the queue open,
+/// e.g. for requests to the HTTP server
+#[derive(Debug, Clone)]
+pub struct WeakNotificationSender(Arc<Shared>);
+
+impl WeakNotificationSender {
+    /// Returns a sender unless all senders are dropped
+    pub fn upgrade(&self) -> Option<NotificationSender> {
+        let mut state = self.0.state.lock().unwrap();
+        if state.senders == 0 {
+            return None;
+        }
+        state.senders += 1;
+        Some(NotificationSender(Arc::clone(&self.0)))
+    }
+}
+
+/// Receiving side of the notification queue
+#[derive(Debug)]
+pub struct NotificationReceiver(Arc<Shared>);
+
+impl NotificationReceiver {
+    /// Takes the oldest notification. Returns `None` when all senders are dropped.
+    pub async fn recv(&mut self) -> Option<ContainerRestartInfo> {
+        loop {
+            {
+                let mut state = self.0.state.lock().unwrap();
+                if let Some(info) = state.items.pop_front() {
+                    self.0.refill(&mut state);
+                    self.0.popped.notify_one();
+                    return Some(info);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            self.0.pushed.notified().await;
+        }
+    }
+
+    /// Takes the number of notifications dropped per channel since the last call.
+    pub fn take_dropped(&mut self) -> BTreeMap<String, usize> {
+        std::mem::take(&mut self.0.state.lock().unwrap().dropped)
+    }
+}
+
+impl Drop for NotificationReceiver {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().closed = true;
+        self.0.popped.notify_waiters();
+    }
+}
+
+/// Read-only handle of the notification queue for metrics and debugging
+#[derive(Debug, Clone)]
+pub struct QueueMonitor(Arc<Shared>);
+
+impl QueueMonitor {
+    /// Number of notifications in memory
+    pub fn len(&self) -> usize {
+        self.0.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+
+    /// Number of notifications kept only on the disk
+    pub fn spilled(&self) -> usize {
+        self.0.state.lock().unwrap().spilled.len()
+    }
+}
+
+/// Notifications persisted in a directory until they are delivered,
+/// so that they survive restarts of johari-mirror.
+#[derive(Debug, Clone)]
+pub struct DiskQueue(Arc<DiskQueueInner>);
+
+#[derive(Debug)]
+struct DiskQueueInner {
+    dir: PathBuf,
+    next_id: AtomicU64,
+}
+
+impl DiskQueue {
+    /// Opens the queue in `dir` and returns notifications left undelivered in order.
+    pub fn open(dir: PathBuf) -> anyhow::Result<(Self, Vec<ContainerRestartInfo>)> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(id) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+                .filter(|_| path.extension().is_some_and(|e| e == "json"))
+            else {
+                continue;
+            };
+            ids.push(id);
+        }
+        ids.sort();
+        let queue = Self(Arc::new(DiskQueueInner {
+            dir,
+            next_id: AtomicU64::new(ids.last().map_or(0, |id| id + 1)),
+        }));
+        let mut pending = Vec::new();
+        for id in ids {
+            match queue.load(id) {
+                Ok(info) => pending.push(info),
+                Err(e) => {
+                    log::warn!("Discarding invalid queued notification: {e:#}");
+                    std::fs::remove_file(queue.path(id))?;
+                }
+            }
+        }
+        log::info!("Loaded {} queued notifications", pending.len());
+        Ok((queue, pending))
+    }
+
+    /// Reads the notification with `id`.
+    fn load(&self, id: u64) -> anyhow::Result<ContainerRestartInfo> {
+        let path = self.path(id);
+        let content =
+            std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut info: ContainerRestartInfo = serde_json::from_slice(&content)
+            .with_context(|| format!("Invalid notification: {}", path.display()))?;
+        info.queue_id = Some(id);
+        Ok(info)
+    }
+
+    /// Writes `info` and sets its ID in the queue.
+    fn push(&self, info: &mut ContainerRestartInfo) {
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let result = serde_json::to_vec(&info)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(std::fs::write(self.path(id), content)?));
+        match result {
+            Ok(()) => info.queue_id = Some(id),
+            Err(e) => log::error!("Failed to write queued notification: {e}"),
+        }
+    }
+
+    /// Removes the notification which has been processed.
+    pub fn remove(&self, info: &ContainerRestartInfo) {
+        if let Some(id) = info.queue_id {
+            self.remove_id(id);
+        }
+    }
+
+    pub fn remove_id(&self, id: u64) {
+        if let Err(e) = std::fs::remove_file(self.path(id)) {
+            log::error!("Failed to remove queued notification: {e}");
+        }
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        // Zero padding keeps the order of file names
+        self.0.dir.join(format!("{id:020}.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ContainerLog, ContainerResources};
+
+    fn restart_info(pod_name: &str) -> ContainerRestartInfo {
+        ContainerRestartInfo {
+            namespace: Some("default".to_owned()),
+            pod_name: pod_name.to_owned(),
+            pod_uid: None,
+            container_name: "app".to_owned(),
+            container_image: "app:latest".to_owned(),
+            node_name: None,
+            workload: None,
+            labels: Default::default(),
+            restart_count: 1,
+            last_state: None,
+            resources: ContainerResources::default(),
+            logs: ContainerLog(Ok("log".to_owned())),
+            channel: "#alerts".to_owned(),
+            options: Default::default(),
+            links: Vec::new(),
+            notes: Vec::new(),
+            category: None,
+            sidecar_logs: Vec::new(),
+            priority: None,
+            node_info: None,
+            attachments: Vec::new(),
+            span: tracing::Span::none(),
+            queue_id: None,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("johari-mirror-{name}-test-{}", std::process::id()))
+    }
+
+    async fn recv_all(rx: &mut NotificationReceiver) -> Vec<String> {
+        let mut pods = Vec::new();
+        while let Some(info) = rx.recv().await {
+            pods.push(info.pod_name);
+        }
+        pods
+    }
+
+    #[test]
+    fn test_disk_queue() {
+        let dir = temp_dir("disk-queue");
+        let (queue, pending) = DiskQueue::open(dir.clone()).unwrap();
+        assert!(pending.is_empty());
+        let mut infos = ["pod-1", "pod-2", "pod-3"].map(restart_info);
+        for info in &mut infos {
+            queue.push(info);
+        }
+        queue.remove(&infos[1]);
+
+        let (queue, pending) = DiskQueue::open(dir.clone()).unwrap();
+        let pods: Vec<_> = pending.iter().map(|info| info.pod_name.as_str()).collect();
+        assert_eq!(pods, ["pod-1", "pod-3"]);
+        let mut info = restart_info("pod-4");
+        queue.push(&mut info);
+        assert_eq!(info.queue_id, Some(3));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let (tx, mut rx) = channel(2, QueuePolicy::DropOldest, None, Vec::new());
+        for pod in ["pod-1", "pod-2", "pod-3"] {
+            tx.send(restart_info(pod)).await.unwrap();
+        }
+        drop(tx);
+        assert_eq!(recv_all(&mut rx).await, ["pod-2", "pod-3"]);
+        assert_eq!(
+            rx.take_dropped(),
+            BTreeMap::from([("#alerts".to_owned(), 1)])
+        );
+        assert!(rx.take_dropped().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spill() {
+        let dir = temp_dir("spill");
+        let (disk, _) = DiskQueue::open(dir.clone()).unwrap();
+        let (tx, mut rx) = channel(2, QueuePolicy::Spill, Some(disk), Vec::new());
+        for pod in ["pod-1", "pod-2", "pod-3", "pod-4"] {
+            tx.send(restart_info(pod)).await.unwrap();
+        }
+        assert_eq!(tx.monitor().len(), 2);
+        assert_eq!(tx.monitor().spilled(), 2);
+        drop(tx);
+        assert_eq!(
+            recv_all(&mut rx).await,
+            ["pod-1", "pod-2", "pod-3", "pod-4"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_weak_sender() {
+        let (tx, mut rx) = channel(4, QueuePolicy::Block, None, Vec::new());
+        let weak = tx.downgrade();
+        let upgraded = weak.upgrade().unwrap();
+        drop(tx);
+        upgraded.send(restart_info("pod-1")).await.unwrap();
+        drop(upgraded);
+        // Weak senders do not keep the queue open
+        assert_eq!(recv_all(&mut rx).await, ["pod-1"]);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_close_while_blocked() {
+        let (tx, rx) = channel(1, QueuePolicy::Block, None, Vec::new());
+        tx.send(restart_info("pod-1")).await.unwrap();
+        let send = tokio::spawn(async move { tx.send(restart_info("pod-2")).await });
+        tokio::task::yield_now().await;
+        drop(rx);
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), send).await;
+        assert!(result.unwrap().unwrap().is_err());
+    }
+}
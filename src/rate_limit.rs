@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context as _};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::{message::ContainerRestartInfo, silence};
+
+/// Default timeout of Kubernetes API requests until the response
+pub const DEFAULT_KUBE_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Limits notifications to `per_minute` across all channels.
+/// Restarts over the limit are aggregated per channel to be posted as a summary.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: TokenBucket,
+    /// Map channel -> container key -> number of restarts not notified
+    overflow: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            bucket: TokenBucket::new(
+                per_minute.into(),
+                f64::from(per_minute) / 60.0,
+                Instant::now(),
+            ),
+            overflow: BTreeMap::new(),
+        }
+    }
+
+    /// Returns whether to send `info`, otherwise it is recorded in the overflow.
+    pub fn admit(&mut self, info: &ContainerRestartInfo) -> bool {
+        if self.bucket.try_acquire(Instant::now()) {
+            return true;
+        }
+        *self
+            .overflow
+            .entry(info.channel.clone())
+            .or_default()
+            .entry(info.container_key())
+            .or_default() += 1;
+        false
+    }
+
+    /// Takes restarts not notified since the last call.
+    pub fn take_overflow(&mut self) -> BTreeMap<String, BTreeMap<String, usize>> {
+        std::mem::take(&mut self.overflow)
+    }
+}
+
+/// Client-side limits of Kubernetes API requests, applied to all requests of a `kube::Client`
+/// as a tower layer with `kube::client::ClientBuilder::with_layer`.
+/// Watches and log streams are throttled when they start, and the timeout bounds the time to
+/// their response, not the streamed bodies.
+#[derive(Debug, Clone)]
+pub struct ApiLimits {
+    /// Requests per second on average and in a burst. Requests are not throttled when `None`.
+    pub rate_limit: Option<(f64, u32)>,
+    pub request_timeout: Duration,
+}
+
+impl Default for ApiLimits {
+    fn default() -> Self {
+        Self {
+            rate_limit: None,
+            request_timeout: DEFAULT_KUBE_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl ApiLimits {
+    /// Reads `KUBE_QPS`, `KUBE_BURST` and `KUBE_REQUEST_TIMEOUT`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let rate_limit = match std::env::var("KUBE_QPS") {
+            Ok(qps) => {
+                let qps: f64 = qps.parse().context("Invalid KUBE_QPS")?;
+                if !qps.is_finite() || qps <= 0.0 {
+                    bail!("KUBE_QPS must be positive");
+                }
+                let burst = match std::env::var("KUBE_BURST") {
+                    Ok(burst) => burst.parse().context("Invalid KUBE_BURST")?,
+                    Err(_) => (qps.ceil() as u32).max(1),
+                };
+                if burst == 0 {
+                    bail!("KUBE_BURST must be at least 1");
+                }
+                Some((qps, burst))
+            }
+            Err(_) => None,
+        };
+        let request_timeout = match std::env::var("KUBE_REQUEST_TIMEOUT") {
+            Ok(timeout) => silence::parse_duration(&timeout)
+                .map_err(|e| anyhow::anyhow!("Invalid KUBE_REQUEST_TIMEOUT: {e}"))?
+                .to_std()?,
+            Err(_) => DEFAULT_KUBE_REQUEST_TIMEOUT,
+        };
+        Ok(Self {
+            rate_limit,
+            request_timeout,
+        })
+    }
+}
+
+impl<S> Layer<S> for ApiLimits {
+    type Service = ApiLimited<S>;
+
+    fn layer(&self, inner: S) -> ApiLimited<S> {
+        ApiLimited {
+            inner,
+            bucket: self
+                .rate_limit
+                .map(|(qps, burst)| TokenBucket::new(burst.into(), qps, Instant::now())),
+            delay: None,
+            acquired: false,
+            request_timeout: self.request_timeout,
+        }
+    }
+}
+
+/// Service throttling and timing out requests to `inner` by `ApiLimits`
+pub struct ApiLimited<S> {
+    inner: S,
+    bucket: Option<TokenBucket>,
+    /// Wait for the next token of `bucket`
+    delay: Option<Pin<Box<tokio::time::Sleep>>>,
+    /// Whether a token is taken for the next request
+    acquired: bool,
+    request_timeout: Duration,
+}
+
+impl<S, R> Service<R> for ApiLimited<S>
+where
+    S: Service<R>,
+    S::Response: Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<S::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        if let Some(bucket) = &mut self.bucket {
+            while !self.acquired {
+                if let Some(delay) = &mut self.delay {
+                    ready!(delay.as_mut().poll(cx));
+                    self.delay = None;
+                }
+                self.acquired = bucket.try_acquire(Instant::now());
+                if !self.acquired {
+                    let delay = tokio::time::sleep(bucket.time_to_next_token());
+                    self.delay = Some(Box::pin(delay));
+                }
+            }
+        }
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        self.acquired = false;
+        let timeout = self.request_timeout;
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, response).await {
+                Ok(response) => response.map_err(Into::into),
+                Err(_) => Err(format!("No response from Kubernetes API in {timeout:?}").into()),
+            }
+        })
+    }
+}
+
+/// Token bucket refilled continuously by `rate` tokens per second up to `capacity`
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.updated_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Time until a token is available as of the last update
+    fn time_to_next_token(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Responds immediately to `true` and never to `false`
+    struct Respond;
+
+    impl Service<bool> for Respond {
+        type Response = ();
+        type Error = BoxError;
+        type Future = BoxFuture<'static, Result<(), BoxError>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, respond: bool) -> Self::Future {
+            if respond {
+                Box::pin(futures::future::ready(Ok(())))
+            } else {
+                Box::pin(futures::future::pending())
+            }
+        }
+    }
+
+    async fn send(service: &mut ApiLimited<Respond>, respond: bool) -> Result<(), BoxError> {
+        futures::future::poll_fn(|cx| service.poll_ready(cx)).await?;
+        service.call(respond).await
+    }
+
+    #[tokio::test]
+    async fn test_api_limits() {
+        let limits = ApiLimits {
+            rate_limit: Some((20.0, 2)),
+            request_timeout: Duration::from_millis(10),
+        };
+        let mut service = limits.layer(Respond);
+        let start = Instant::now();
+        send(&mut service, true).await.unwrap();
+        send(&mut service, true).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(40));
+        // Throttled after the burst
+        send(&mut service, true).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        let mut service = ApiLimits {
+            rate_limit: None,
+            request_timeout: Duration::from_millis(10),
+        }
+        .layer(Respond);
+        assert!(send(&mut service, false).await.is_err());
+        send(&mut service, true).await.unwrap();
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(30.0, 0.5, now);
+        for _ in 0..30 {
+            assert!(bucket.try_acquire(now));
+        }
+        assert!(!bucket.try_acquire(now));
+        assert_eq!(bucket.time_to_next_token(), Duration::from_secs(2));
+        // A token is refilled every 2 seconds
+        assert!(!bucket.try_acquire(now + Duration::from_secs(1)));
+        assert!(bucket.try_acquire(now + Duration::from_secs(2)));
+        assert!(!bucket.try_acquire(now + Duration::from_secs(2)));
+        // Tokens are not accumulated over the capacity
+        let later = now + Duration::from_secs(3600);
+        for _ in 0..30 {
+            assert!(bucket.try_acquire(later));
+        }
+        assert!(!bucket.try_acquire(later));
+    }
+}
@@ -0,0 +1,328 @@
+use serde_json::json;
+
+use crate::pod_annotations::AnnotationTarget;
+
+/// Name of the ServiceAccount and the RBAC objects
+const NAME: &str = "johari-mirror";
+
+/// Features of johari-mirror which need permissions in addition to reading pods and logs
+#[derive(Debug, Default)]
+pub struct RbacFeatures {
+    /// `POD_EVENTS=true`
+    pub pod_events: bool,
+    /// `POD_ANNOTATIONS`
+    pub pod_annotations: Option<AnnotationTarget>,
+    /// `SILENCE_NAMESPACE`
+    pub silence_namespace: Option<String>,
+    /// `KUBE_IMPERSONATE_USER` and `KUBE_IMPERSONATE_GROUPS`
+    pub impersonate: Option<(String, Vec<String>)>,
+    /// `WATCH_NAMESPACES`, where pods are read with Roles instead of a ClusterRole
+    pub watch_namespaces: Vec<String>,
+    /// `describe` option of any rule, which reads Events of pods
+    pub describe: bool,
+    /// `PDB_ALERT_CHANNEL`
+    pub pdb_alerts: bool,
+    /// `NODE_INFO=true`
+    pub node_info: bool,
+}
+
+/// Generates the ServiceAccount in `namespace` and the least RBAC objects for `features`.
+/// With impersonation, the permissions are bound to the impersonated user and groups,
+/// and the ServiceAccount is only allowed to impersonate them.
+pub fn generate(features: &RbacFeatures, namespace: &str) -> Vec<serde_json::Value> {
+    let service_account = json!({
+        "kind": "ServiceAccount",
+        "name": NAME,
+        "namespace": namespace,
+    });
+    let subjects = match &features.impersonate {
+        Some((user, groups)) => std::iter::once(json!({
+            "apiGroup": "rbac.authorization.k8s.io",
+            "kind": "User",
+            "name": user,
+        }))
+        .chain(groups.iter().map(|group| {
+            json!({
+                "apiGroup": "rbac.authorization.k8s.io",
+                "kind": "Group",
+                "name": group,
+            })
+        }))
+        .collect(),
+        None => vec![service_account.clone()],
+    };
+
+    let mut objects = vec![json!({
+        "apiVersion": "v1",
+        "kind": "ServiceAccount",
+        "metadata": {
+            "name": NAME,
+            "namespace": namespace,
+        },
+    })];
+    let node_rule = rule("", &["nodes"], &["get"]);
+    if features.watch_namespaces.is_empty() {
+        let mut rules = pod_rules(features);
+        if features.node_info {
+            rules.push(node_rule.clone());
+        }
+        objects.push(json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": {
+                "name": NAME,
+            },
+            "rules": rules,
+        }));
+        objects.push(binding("ClusterRoleBinding", NAME, None, &subjects));
+    } else if features.node_info {
+        // Nodes are cluster-scoped, so they are read with a ClusterRole even with Roles
+        let name = format!("{NAME}-nodes");
+        objects.push(json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": {
+                "name": name,
+            },
+            "rules": [node_rule],
+        }));
+        objects.push(binding("ClusterRoleBinding", &name, None, &subjects));
+    }
+    for watch_namespace in &features.watch_namespaces {
+        objects.push(json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "Role",
+            "metadata": {
+                "name": NAME,
+                "namespace": watch_namespace,
+            },
+            "rules": pod_rules(features),
+        }));
+        objects.push(binding(
+            "RoleBinding",
+            NAME,
+            Some(watch_namespace),
+            &subjects,
+        ));
+    }
+
+    if let Some(silence_namespace) = &features.silence_namespace {
+        let name = format!("{NAME}-silences");
+        let verbs = ["list", "create", "delete"];
+        objects.push(json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "Role",
+            "metadata": {
+                "name": name,
+                "namespace": silence_namespace,
+            },
+            "rules": [
+                rule("johari-mirror.flywheel.jp", &["joharisilences"], &verbs),
+            ],
+        }));
+        objects.push(binding(
+            "RoleBinding",
+            &name,
+            Some(silence_namespace),
+            &subjects,
+        ));
+    }
+
+    if let Some((user, groups)) = &features.impersonate {
+        let name = format!("{NAME}-impersonator");
+        let mut rules = vec![with_names(
+            rule("", &["users"], &["impersonate"]),
+            std::slice::from_ref(user),
+        )];
+        if !groups.is_empty() {
+            rules.push(with_names(rule("", &["groups"], &["impersonate"]), groups));
+        }
+        objects.push(json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": {
+                "name": name,
+            },
+            "rules": rules,
+        }));
+        objects.push(binding(
+            "ClusterRoleBinding",
+            &name,
+            None,
+            &[service_account],
+        ));
+    }
+    objects
+}
+
+/// Rules of the ClusterRole or the Roles of `WATCH_NAMESPACES`, to watch pods and fetch logs
+fn pod_rules(features: &RbacFeatures) -> Vec<serde_json::Value> {
+    let mut pod_verbs = vec!["get", "watch", "list"];
+    if features.pod_annotations == Some(AnnotationTarget::Pod) {
+        pod_verbs.push("patch");
+    }
+    let mut rules = vec![
+        rule("", &["pods"], &pod_verbs),
+        rule("", &["pods/log"], &["get"]),
+    ];
+    let mut event_verbs = Vec::new();
+    if features.pod_events {
+        event_verbs.push("create");
+    }
+    if features.describe {
+        event_verbs.push("list");
+    }
+    if !event_verbs.is_empty() {
+        rules.push(rule("", &["events"], &event_verbs));
+    }
+    if features.pdb_alerts {
+        rules.push(rule("policy", &["poddisruptionbudgets"], &["list"]));
+    }
+    if features.pod_annotations == Some(AnnotationTarget::Workload) {
+        // Controllers of pods are read to find the workloads, which are annotated
+        rules.push(rule("apps", &["replicasets"], &["get"]));
+        rules.push(rule("batch", &["jobs"], &["get"]));
+        rules.push(rule(
+            "apps",
+            &["deployments", "statefulsets", "daemonsets", "replicasets"],
+            &["patch"],
+        ));
+        rules.push(rule("batch", &["cronjobs", "jobs"], &["patch"]));
+    }
+    rules
+}
+
+fn rule(group: &str, resources: &[&str], verbs: &[&str]) -> serde_json::Value {
+    json!({
+        "apiGroups": [group],
+        "resources": resources,
+        "verbs": verbs,
+    })
+}
+
+fn with_names(mut rule: serde_json::Value, names: &[String]) -> serde_json::Value {
+    rule["resourceNames"] = json!(names);
+    rule
+}
+
+/// `kind` of `RoleBinding` or `ClusterRoleBinding` of the role of the same `name`
+fn binding(
+    kind: &str,
+    name: &str,
+    namespace: Option<&str>,
+    subjects: &[serde_json::Value],
+) -> serde_json::Value {
+    let mut metadata = json!({ "name": name });
+    if let Some(namespace) = namespace {
+        metadata["namespace"] = json!(namespace);
+    }
+    json!({
+        "apiVersion": "rbac.authorization.k8s.io/v1",
+        "kind": kind,
+        "metadata": metadata,
+        "roleRef": {
+            "apiGroup": "rbac.authorization.k8s.io",
+            "kind": kind.trim_end_matches("Binding"),
+            "name": name,
+        },
+        "subjects": subjects,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yaml::to_yaml;
+
+    #[test]
+    fn test_generate() {
+        let objects = generate(&RbacFeatures::default(), "monitoring");
+        assert_eq!(
+            objects
+                .iter()
+                .map(|o| o["kind"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            ["ServiceAccount", "ClusterRole", "ClusterRoleBinding"]
+        );
+        assert_eq!(
+            to_yaml(&objects[1..2]),
+            "apiVersion: rbac.authorization.k8s.io/v1\n\
+             kind: ClusterRole\n\
+             metadata:\n  \
+               name: johari-mirror\n\
+             rules:\n  \
+               - apiGroups:\n      \
+                   - ''\n    \
+                 resources:\n      \
+                   - pods\n    \
+                 verbs:\n      \
+                   - get\n      \
+                   - watch\n      \
+                   - list\n  \
+               - apiGroups:\n      \
+                   - ''\n    \
+                 resources:\n      \
+                   - pods/log\n    \
+                 verbs:\n      \
+                   - get\n"
+        );
+
+        let features = RbacFeatures {
+            pod_events: true,
+            pod_annotations: Some(AnnotationTarget::Pod),
+            silence_namespace: Some("monitoring".to_owned()),
+            impersonate: Some(("johari-mirror-prod".to_owned(), vec!["viewers".to_owned()])),
+            watch_namespaces: Vec::new(),
+            describe: true,
+            pdb_alerts: true,
+            node_info: true,
+        };
+        let objects = generate(&features, "monitoring");
+        assert_eq!(objects.len(), 7);
+        assert_eq!(
+            objects[1]["rules"][0]["verbs"],
+            json!(["get", "watch", "list", "patch"])
+        );
+        assert_eq!(objects[1]["rules"][2]["resources"], json!(["events"]));
+        assert_eq!(objects[1]["rules"][2]["verbs"], json!(["create", "list"]));
+        assert_eq!(
+            objects[1]["rules"][3]["resources"],
+            json!(["poddisruptionbudgets"])
+        );
+        assert_eq!(objects[1]["rules"][4]["resources"], json!(["nodes"]));
+        // Permissions are bound to the impersonated identity
+        assert_eq!(objects[2]["subjects"][0]["kind"], "User");
+        assert_eq!(objects[2]["subjects"][1]["name"], "viewers");
+        assert_eq!(objects[3]["metadata"]["namespace"], "monitoring");
+        assert_eq!(objects[6]["subjects"][0]["kind"], "ServiceAccount");
+        assert_eq!(
+            objects[5]["rules"][0]["resourceNames"],
+            json!(["johari-mirror-prod"])
+        );
+
+        let features = RbacFeatures {
+            watch_namespaces: vec!["web".to_owned(), "batch".to_owned()],
+            node_info: true,
+            ..Default::default()
+        };
+        let objects = generate(&features, "monitoring");
+        assert_eq!(
+            objects
+                .iter()
+                .map(|o| o["kind"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            [
+                "ServiceAccount",
+                "ClusterRole",
+                "ClusterRoleBinding",
+                "Role",
+                "RoleBinding",
+                "Role",
+                "RoleBinding"
+            ]
+        );
+        assert_eq!(objects[1]["metadata"]["name"], "johari-mirror-nodes");
+        assert_eq!(objects[5]["metadata"]["namespace"], "batch");
+        assert_eq!(objects[6]["roleRef"]["kind"], "Role");
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use k8s_openapi::api::core::v1::Pod;
+use kube::runtime::watcher;
+use serde::{Deserialize, Serialize};
+
+use crate::kubernetes::ScopedEvent;
+
+/// Watcher event in a recording, one JSON object per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RecordedEvent {
+    Applied {
+        pod: Box<Pod>,
+    },
+    Deleted {
+        pod: Box<Pod>,
+    },
+    Restarted {
+        pods: Vec<Pod>,
+        /// Namespace whose pods are relisted, or all namespaces when missing
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+    },
+}
+
+impl From<&ScopedEvent> for RecordedEvent {
+    fn from(e: &ScopedEvent) -> Self {
+        match &e.event {
+            watcher::Event::Applied(p) => Self::Applied {
+                pod: Box::new(p.clone()),
+            },
+            watcher::Event::Deleted(p) => Self::Deleted {
+                pod: Box::new(p.clone()),
+            },
+            watcher::Event::Restarted(pods) => Self::Restarted {
+                pods: pods.clone(),
+                namespace: e.namespace.clone(),
+            },
+        }
+    }
+}
+
+impl From<RecordedEvent> for ScopedEvent {
+    fn from(e: RecordedEvent) -> Self {
+        match e {
+            RecordedEvent::Applied { pod } => watcher::Event::Applied(*pod).into(),
+            RecordedEvent::Deleted { pod } => watcher::Event::Deleted(*pod).into(),
+            RecordedEvent::Restarted { pods, namespace } => Self {
+                namespace,
+                event: watcher::Event::Restarted(pods),
+            },
+        }
+    }
+}
+
+/// Appends watcher events to a file to reproduce them with `kubernetes::replay`.
+/// Pods are recorded pruned as watched.
+pub struct EventRecorder {
+    writer: BufWriter<File>,
+}
+
+impl EventRecorder {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Records `e`. Failures are logged not to stop watching.
+    pub fn record(&mut self, e: &ScopedEvent) {
+        let result = serde_json::to_writer(&mut self.writer, &RecordedEvent::from(e))
+            .map_err(anyhow::Error::from)
+            .and_then(|()| writeln!(self.writer).map_err(anyhow::Error::from))
+            // Flushed per event to keep the recording up to date on crashes
+            .and_then(|()| self.writer.flush().map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            log::error!("Failed to record watcher event: {e}");
+        }
+    }
+}
+
+/// Loads watcher events recorded by `EventRecorder` in order.
+pub fn load(path: &Path) -> anyhow::Result<Vec<ScopedEvent>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut events = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let e = serde_json::from_str::<RecordedEvent>(&line)
+            .with_context(|| format!("Invalid event at line {} of {}", i + 1, path.display()))?;
+        events.push(e.into());
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kube::runtime::reflector;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        history::RestartHistory,
+        kubernetes::{self, PodRestartCounts, WatchConfig, WatchState},
+        queue::{self, QueuePolicy},
+        silence::Silences,
+        Router,
+    };
+
+    fn pod(uid: &str, restart_count: i32) -> Pod {
+        namespaced_pod("default", uid, restart_count)
+    }
+
+    fn namespaced_pod(namespace: &str, uid: &str, restart_count: i32) -> Pod {
+        serde_json::from_value(json!({
+            "metadata": { "name": "app-0", "namespace": namespace, "uid": uid },
+            "status": {
+                "containerStatuses": [{
+                    "name": "app",
+                    "image": "app:latest",
+                    "imageID": "",
+                    "ready": true,
+                    "restartCount": restart_count,
+                    "lastState": { "terminated": { "exitCode": 1, "reason": "Error" } },
+                }],
+            },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!(
+            "johari-mirror-replay-test-{}.jsonl",
+            std::process::id()
+        ));
+        let mut recorder = EventRecorder::create(&path).unwrap();
+        for e in [
+            watcher::Event::Restarted(vec![pod("uid-1", 0)]),
+            watcher::Event::Applied(pod("uid-1", 1)),
+            // Relist after the watch is restarted
+            watcher::Event::Restarted(vec![pod("uid-1", 1)]),
+            watcher::Event::Deleted(pod("uid-1", 1)),
+            // Pod recreated with the same name
+            watcher::Event::Applied(pod("uid-2", 0)),
+            watcher::Event::Applied(pod("uid-2", 1)),
+        ] {
+            recorder.record(&e.into());
+        }
+        drop(recorder);
+        let events = load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(events.len(), 6);
+
+        let mut config =
+            WatchConfig::new(Router::builder().route("*", "*", "*", "#alerts").build());
+        config.coalesce_window = Duration::ZERO;
+        let (tx, mut rx) = queue::channel(8, QueuePolicy::Block, None, Vec::new());
+        let (_, pod_store) = reflector::store();
+        let state = WatchState {
+            pod_store,
+            restart_counts: PodRestartCounts::default(),
+            startup_grace: None,
+            silences: Silences::default(),
+            history: RestartHistory::default(),
+            hooks: None,
+        };
+        kubernetes::replay(config, events, tx, state).await.unwrap();
+        let mut notified = Vec::new();
+        while let Some(info) = rx.recv().await {
+            assert!(info.logs.0.is_err());
+            notified.push((info.container_key(), info.restart_count));
+        }
+        assert_eq!(
+            notified,
+            [
+                ("default/app-0/app".to_owned(), 1),
+                ("default/app-0/app".to_owned(), 1)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_namespaced() {
+        let restarted = |namespace: &str, pods: Vec<Pod>| ScopedEvent {
+            namespace: Some(namespace.to_owned()),
+            event: watcher::Event::Restarted(pods),
+        };
+        let events = vec![
+            restarted("a", vec![namespaced_pod("a", "uid-a", 0)]),
+            restarted("b", vec![namespaced_pod("b", "uid-b", 0)]),
+            watcher::Event::Applied(namespaced_pod("a", "uid-a", 1)).into(),
+            // Relisting namespace b replaces only its pods
+            restarted("b", vec![namespaced_pod("b", "uid-b2", 0)]),
+            watcher::Event::Applied(namespaced_pod("a", "uid-a", 2)).into(),
+        ];
+
+        let mut config =
+            WatchConfig::new(Router::builder().route("*", "*", "*", "#alerts").build());
+        config.coalesce_window = Duration::ZERO;
+        let (tx, mut rx) = queue::channel(8, QueuePolicy::Block, None, Vec::new());
+        let (store, pod_store) = reflector::store();
+        let restart_counts = PodRestartCounts::default();
+        let state = WatchState {
+            pod_store,
+            restart_counts: restart_counts.clone(),
+            startup_grace: None,
+            silences: Silences::default(),
+            history: RestartHistory::default(),
+            hooks: None,
+        };
+        kubernetes::replay(config, events, tx, state).await.unwrap();
+        let mut notified = Vec::new();
+        while let Some(info) = rx.recv().await {
+            notified.push((info.container_key(), info.restart_count));
+        }
+        assert_eq!(
+            notified,
+            [("a/app-0/app".to_owned(), 1), ("a/app-0/app".to_owned(), 2)]
+        );
+        let mut uids = store
+            .state()
+            .iter()
+            .filter_map(|p| p.metadata.uid.clone())
+            .collect::<Vec<_>>();
+        uids.sort();
+        assert_eq!(uids, ["uid-a", "uid-b2"]);
+        let mut uids = restart_counts.snapshot().into_keys().collect::<Vec<_>>();
+        uids.sort();
+        assert_eq!(uids, ["uid-a", "uid-b2"]);
+    }
+}
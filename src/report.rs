@@ -0,0 +1,399 @@
+#[cfg(feature = "database")]
+use std::collections::BTreeMap;
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "database")]
+use k8s_openapi::chrono::Duration;
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde_json::json;
+
+#[cfg(feature = "database")]
+use crate::crash_store::CrashStore;
+use crate::{
+    history::{RestartHistory, RestartRecord},
+    slack::SlackPoster,
+};
+
+/// Number of entries in each ranking of the summary report
+const REPORT_RANKING_SIZE: usize = 10;
+
+/// Period of the top crashers report, compared with the previous one
+#[cfg(feature = "database")]
+const TOP_CRASHERS_PERIOD: Duration = Duration::weeks(1);
+
+/// Task to post summary reports of restarts on `schedule`
+pub async fn summary_report(
+    schedule: cron::Schedule,
+    poster: SlackPoster,
+    channel: String,
+    history: RestartHistory,
+) {
+    let mut since = Utc::now();
+    for next in schedule.upcoming(Utc) {
+        let wait = (next - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        let summary = Summary::new(&history.records(), since, next);
+        log::info!("Posting summary report: {} restarts", summary.total);
+        if let Err(e) = poster.post_blocks(&channel, summary.to_message()).await {
+            log::error!("Failed to post summary report to Slack: {e}");
+        }
+        since = next;
+    }
+}
+
+/// Summary of restarts in a period
+#[derive(Debug, PartialEq)]
+struct Summary {
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    total: usize,
+    /// Workloads sorted by restart count in descending order
+    workloads: Vec<(String, usize)>,
+    /// Workloads which have not restarted before the period
+    new_crashers: Vec<String>,
+    /// Termination reasons sorted by count in descending order
+    reasons: Vec<(String, usize)>,
+}
+
+impl Summary {
+    fn new(records: &[RestartRecord], since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        let in_period = records
+            .iter()
+            .filter(|r| since <= r.time && r.time < until)
+            .collect::<Vec<_>>();
+        let known = records
+            .iter()
+            .filter(|r| r.time < since)
+            .map(|r| r.workload_key())
+            .collect::<HashSet<_>>();
+
+        let workloads = count_sorted(in_period.iter().map(|r| r.workload_key()));
+        let new_crashers = workloads
+            .iter()
+            .map(|(w, _)| w.clone())
+            .filter(|w| !known.contains(w))
+            .collect();
+        let reasons = count_sorted(
+            in_period
+                .iter()
+                .map(|r| r.reason.clone().unwrap_or_else(|| "unknown".to_owned())),
+        );
+        Self {
+            since,
+            until,
+            total: in_period.len(),
+            workloads,
+            new_crashers,
+            reasons,
+        }
+    }
+
+    fn to_message(&self) -> Vec<serde_json::Value> {
+        let mut text = format!(
+            "*Restart summary* from {} to {}\nTotal restarts: `{}` in `{}` workloads",
+            format_time(self.since),
+            format_time(self.until),
+            self.total,
+            self.workloads.len(),
+        );
+        if !self.workloads.is_empty() {
+            text += "\n\n*Top workloads*";
+            for (workload, count) in self.workloads.iter().take(REPORT_RANKING_SIZE) {
+                text += &format!("\n`{workload}`: {count}");
+            }
+        }
+        if !self.new_crashers.is_empty() {
+            text += "\n\n*New crashers*";
+            for workload in self.new_crashers.iter().take(REPORT_RANKING_SIZE) {
+                text += &format!("\n`{workload}`");
+            }
+        }
+        if !self.reasons.is_empty() {
+            text += "\n\n*Top exit reasons*";
+            for (reason, count) in self.reasons.iter().take(REPORT_RANKING_SIZE) {
+                text += &format!("\n`{reason}`: {count}");
+            }
+        }
+        vec![json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": text,
+            },
+        })]
+    }
+}
+
+/// Task to post the workloads restarted most in the last week to each channel their
+/// restarts were notified to, on `schedule`
+#[cfg(feature = "database")]
+pub async fn top_crashers_report(schedule: cron::Schedule, poster: SlackPoster, store: CrashStore) {
+    for next in schedule.upcoming(Utc) {
+        let wait = (next - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        let records = match store
+            .notified_restarts_since(next - TOP_CRASHERS_PERIOD * 2)
+            .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                log::error!("Failed to read restarts for top crashers report: {e:#}");
+                continue;
+            }
+        };
+        for (channel, crashers) in TopCrashers::by_channel(&records, next) {
+            log::info!(
+                "Posting top crashers report to {channel}: {} restarts",
+                crashers.total
+            );
+            if let Err(e) = poster.post_blocks(&channel, crashers.to_message()).await {
+                log::error!("Failed to post top crashers report to {channel}: {e}");
+            }
+        }
+    }
+}
+
+/// Workloads restarted most in a channel in a week, compared with the previous week
+#[cfg(feature = "database")]
+#[derive(Debug, PartialEq)]
+struct TopCrashers {
+    until: DateTime<Utc>,
+    total: usize,
+    previous_total: usize,
+    /// Sorted by restart count in descending order
+    workloads: Vec<Crasher>,
+}
+
+/// Restarts of a workload in `TopCrashers`
+#[cfg(feature = "database")]
+#[derive(Debug, PartialEq)]
+struct Crasher {
+    /// `namespace/workload` format
+    workload: String,
+    restarts: usize,
+    /// Restarts in the previous week
+    previous: usize,
+    /// Most frequent termination reason and its count
+    reason: (String, usize),
+}
+
+#[cfg(feature = "database")]
+impl TopCrashers {
+    /// Reports of the channels with restarts in the week until `until`.
+    /// `records` are pairs of channels and restarts notified to them.
+    fn by_channel(
+        records: &[(String, RestartRecord)],
+        until: DateTime<Utc>,
+    ) -> BTreeMap<String, Self> {
+        let since = until - TOP_CRASHERS_PERIOD;
+        let previous_since = since - TOP_CRASHERS_PERIOD;
+        let mut by_channel = BTreeMap::<&str, (Vec<&RestartRecord>, Vec<&RestartRecord>)>::new();
+        for (channel, record) in records {
+            let (current, previous) = by_channel.entry(channel).or_default();
+            if since <= record.time && record.time < until {
+                current.push(record);
+            } else if previous_since <= record.time && record.time < since {
+                previous.push(record);
+            }
+        }
+        by_channel
+            .into_iter()
+            .filter(|(_, (current, _))| !current.is_empty())
+            .map(|(channel, (current, previous))| {
+                let previous_counts = count_sorted(previous.iter().map(|r| r.workload_key()))
+                    .into_iter()
+                    .collect::<HashMap<_, _>>();
+                let workloads = count_sorted(current.iter().map(|r| r.workload_key()))
+                    .into_iter()
+                    .map(|(workload, restarts)| {
+                        let reason = count_sorted(
+                            current
+                                .iter()
+                                .filter(|r| r.workload_key() == workload)
+                                .map(|r| r.reason.clone().unwrap_or_else(|| "unknown".to_owned())),
+                        )
+                        .swap_remove(0);
+                        Crasher {
+                            previous: previous_counts.get(&workload).copied().unwrap_or_default(),
+                            workload,
+                            restarts,
+                            reason,
+                        }
+                    })
+                    .collect();
+                let report = Self {
+                    until,
+                    total: current.len(),
+                    previous_total: previous.len(),
+                    workloads,
+                };
+                (channel.to_owned(), report)
+            })
+            .collect()
+    }
+
+    fn to_message(&self) -> Vec<serde_json::Value> {
+        let mut text = format!(
+            "*Top crashers* of the week until {}\nTotal restarts: `{}` in `{}` workloads ({})",
+            format_time(self.until),
+            self.total,
+            self.workloads.len(),
+            format_change(self.total, self.previous_total),
+        );
+        for (i, crasher) in self.workloads.iter().take(REPORT_RANKING_SIZE).enumerate() {
+            let (reason, count) = &crasher.reason;
+            text += &format!(
+                "\n{}. `{}`: {} ({}), mostly `{reason}` ({count})",
+                i + 1,
+                crasher.workload,
+                crasher.restarts,
+                format_change(crasher.restarts, crasher.previous),
+            );
+        }
+        vec![json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": text,
+            },
+        })]
+    }
+}
+
+/// Week-over-week change, e.g. `+3 from last week`
+#[cfg(feature = "database")]
+fn format_change(current: usize, previous: usize) -> String {
+    if previous == 0 {
+        return "new this week".to_owned();
+    }
+    match current.cmp(&previous) {
+        std::cmp::Ordering::Greater => format!("+{} from last week", current - previous),
+        std::cmp::Ordering::Less => format!("-{} from last week", previous - current),
+        std::cmp::Ordering::Equal => "same as last week".to_owned(),
+    }
+}
+
+/// Counts occurrences of `items` and sorts them by count in descending order.
+fn count_sorted(items: impl Iterator<Item = String>) -> Vec<(String, usize)> {
+    let mut counts = HashMap::<String, usize>::new();
+    for item in items {
+        *counts.entry(item).or_default() += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Formats `time` with Slack date formatting
+fn format_time(time: DateTime<Utc>) -> String {
+    format!(
+        "<!date^{}^{{date_short_pretty}} {{time}}|{}>",
+        time.timestamp(),
+        time.to_rfc3339()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn record(time: DateTime<Utc>, workload: &str, reason: &str) -> RestartRecord {
+        RestartRecord {
+            time,
+            namespace: "default".to_owned(),
+            workload: workload.to_owned(),
+            pod: format!("{workload}-abc"),
+            container: "app".to_owned(),
+            restart_count: 1,
+            exit_code: Some(1),
+            reason: Some(reason.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let since = Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap();
+        let until = since + Duration::days(1);
+        let records = vec![
+            record(since - Duration::hours(1), "web", "Error"),
+            record(since + Duration::hours(1), "web", "OOMKilled"),
+            record(since + Duration::hours(2), "worker", "OOMKilled"),
+            record(since + Duration::hours(3), "worker", "Error"),
+            record(since + Duration::hours(4), "worker", "OOMKilled"),
+            record(until, "batch", "Error"),
+        ];
+        assert_eq!(
+            Summary::new(&records, since, until),
+            Summary {
+                since,
+                until,
+                total: 4,
+                workloads: vec![
+                    ("default/worker".to_owned(), 3),
+                    ("default/web".to_owned(), 1)
+                ],
+                new_crashers: vec!["default/worker".to_owned()],
+                reasons: vec![("OOMKilled".to_owned(), 3), ("Error".to_owned(), 1)],
+            }
+        );
+    }
+
+    #[cfg(feature = "database")]
+    #[test]
+    fn test_top_crashers() {
+        let until = Utc.with_ymd_and_hms(2024, 1, 8, 9, 0, 0).unwrap();
+        let this_week = until - Duration::days(1);
+        let last_week = until - Duration::days(8);
+        let records = [
+            ("#alerts", record(this_week, "worker", "OOMKilled")),
+            ("#alerts", record(this_week, "worker", "OOMKilled")),
+            ("#alerts", record(this_week, "worker", "Error")),
+            ("#alerts", record(this_week, "web", "Error")),
+            ("#alerts", record(last_week, "worker", "OOMKilled")),
+            (
+                "#alerts",
+                record(last_week - Duration::days(7), "web", "Error"),
+            ),
+            ("#batch", record(last_week, "batch", "Error")),
+        ]
+        .map(|(channel, record)| (channel.to_owned(), record));
+        let reports = TopCrashers::by_channel(&records, until);
+        // Channels without restarts in the week are not reported
+        assert_eq!(reports.keys().collect::<Vec<_>>(), ["#alerts"]);
+        assert_eq!(
+            reports["#alerts"],
+            TopCrashers {
+                until,
+                total: 4,
+                previous_total: 1,
+                workloads: vec![
+                    Crasher {
+                        workload: "default/worker".to_owned(),
+                        restarts: 3,
+                        previous: 1,
+                        reason: ("OOMKilled".to_owned(), 2),
+                    },
+                    Crasher {
+                        workload: "default/web".to_owned(),
+                        restarts: 1,
+                        previous: 0,
+                        reason: ("Error".to_owned(), 1),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[cfg(feature = "database")]
+    #[test]
+    fn test_format_change() {
+        assert_eq!(format_change(3, 1), "+2 from last week");
+        assert_eq!(format_change(1, 3), "-2 from last week");
+        assert_eq!(format_change(2, 2), "same as last week");
+        assert_eq!(format_change(2, 0), "new this week");
+    }
+}
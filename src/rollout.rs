@@ -0,0 +1,256 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use k8s_openapi::{
+    api::core::v1::{ContainerStatus, Pod},
+    chrono::{self, DateTime, Utc},
+};
+use kube::ResourceExt;
+
+use crate::silence;
+
+/// Period after an image change or a rollout in which crashes are called out by default
+pub const DEFAULT_ROLLOUT_WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+/// Labels identifying the pod template of Deployments, StatefulSets and DaemonSets
+pub const TEMPLATE_HASH_LABELS: [&str; 2] = ["pod-template-hash", "controller-revision-hash"];
+
+/// Revisions remembered for each container of a workload
+const MAX_REVISIONS: usize = 3;
+
+/// Revision of a container of a workload, identified by its image and pod template
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Revision {
+    image: String,
+    template_hash: Option<String>,
+    /// Creation of the oldest pod of the revision, i.e. around the rollout
+    since: DateTime<Utc>,
+    /// Whether a crash of the revision has been called out
+    crashed: bool,
+}
+
+/// Remembers images of containers of each workload across pods and restarts, to call out
+/// crashes following an image change or a rollout
+#[derive(Debug)]
+pub struct RolloutTracker {
+    window: chrono::Duration,
+    /// `namespace/workload/container` -> revisions, the oldest first
+    revisions: Mutex<HashMap<String, Vec<Revision>>>,
+}
+
+impl RolloutTracker {
+    pub fn new(window: chrono::Duration) -> Self {
+        Self {
+            window,
+            revisions: Mutex::default(),
+        }
+    }
+
+    /// Records revisions of the containers of Pod `p` of `workload`.
+    pub fn observe(&self, p: &Pod, workload: &str) {
+        let created = p.creation_timestamp().map_or_else(Utc::now, |t| t.0);
+        let template_hash = template_hash(p);
+        let mut revisions = self.revisions.lock().unwrap();
+        for container in containers(p) {
+            if container.image.is_empty() {
+                continue;
+            }
+            let revisions = revisions
+                .entry(revision_key(p, workload, &container.name))
+                .or_default();
+            match revisions
+                .iter_mut()
+                .find(|r| r.image == container.image && r.template_hash == template_hash)
+            {
+                Some(revision) => revision.since = revision.since.min(created),
+                None => revisions.push(Revision {
+                    image: container.image.clone(),
+                    template_hash: template_hash.clone(),
+                    since: created,
+                    crashed: false,
+                }),
+            }
+            revisions.sort_by_key(|r| r.since);
+            let excess = revisions.len().saturating_sub(MAX_REVISIONS);
+            revisions.drain(..excess);
+        }
+    }
+
+    /// Note of the crash of `container` in Pod `p` of `workload` when its revision replaced
+    /// another within the window, e.g. "First crash after image changed from `v1.4.2` →
+    /// `v1.5.0` 6m ago"
+    pub fn crashed(&self, p: &Pod, workload: &str, container: &ContainerStatus) -> Option<String> {
+        self.crashed_at(p, workload, container, Utc::now())
+    }
+
+    fn crashed_at(
+        &self,
+        p: &Pod,
+        workload: &str,
+        container: &ContainerStatus,
+        now: DateTime<Utc>,
+    ) -> Option<String> {
+        let template_hash = template_hash(p);
+        let mut revisions = self.revisions.lock().unwrap();
+        let revisions = revisions.get_mut(&revision_key(p, workload, &container.name))?;
+        let pos = revisions
+            .iter()
+            .position(|r| r.image == container.image && r.template_hash == template_hash)?;
+        // The first revision seen has nothing to compare with
+        let [.., previous, current] = &mut revisions[..=pos] else {
+            return None;
+        };
+        let ago = now - current.since;
+        if ago > self.window {
+            return None;
+        }
+        let change = if previous.image == current.image {
+            format!("rollout of `{workload}`")
+        } else {
+            let (from, to) = image_change(&previous.image, &current.image);
+            format!("image changed from `{from}` → `{to}`")
+        };
+        let crash = if std::mem::replace(&mut current.crashed, true) {
+            "Crashed again"
+        } else {
+            "First crash"
+        };
+        Some(format!(
+            ":rocket: {crash} after {change} {} ago",
+            silence::format_duration(ago)
+        ))
+    }
+}
+
+fn revision_key(p: &Pod, workload: &str, container: &str) -> String {
+    format!(
+        "{}/{workload}/{container}",
+        p.namespace().as_deref().unwrap_or("")
+    )
+}
+
+fn template_hash(p: &Pod) -> Option<String> {
+    TEMPLATE_HASH_LABELS
+        .iter()
+        .find_map(|label| p.labels().get(*label).cloned())
+}
+
+fn containers(p: &Pod) -> impl Iterator<Item = &ContainerStatus> {
+    p.status
+        .iter()
+        .flat_map(|status| status.container_statuses.iter().flatten())
+}
+
+/// Tags or digests of the images when only they differ, or the whole images otherwise
+fn image_change<'a>(from: &'a str, to: &'a str) -> (&'a str, &'a str) {
+    match (split_image(from), split_image(to)) {
+        ((from_repo, Some(from_tag)), (to_repo, Some(to_tag))) if from_repo == to_repo => {
+            (from_tag, to_tag)
+        }
+        _ => (from, to),
+    }
+}
+
+/// Splits `image` into the repository and the tag or digest, if any.
+/// A colon before the last slash is the port of the registry.
+fn split_image(image: &str) -> (&str, Option<&str>) {
+    if let Some((repo, digest)) = image.split_once('@') {
+        return (repo, Some(digest));
+    }
+    match image.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, Some(tag)),
+        _ => (image, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::PodStatus,
+        apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time},
+    };
+
+    use super::*;
+
+    fn pod(image: &str, hash: &str, created: DateTime<Utc>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(format!("web-{hash}-x2x7k")),
+                namespace: Some("default".to_owned()),
+                labels: Some([("pod-template-hash".to_owned(), hash.to_owned())].into()),
+                creation_timestamp: Some(Time(created)),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    name: "app".to_owned(),
+                    image: image.to_owned(),
+                    restart_count: 1,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rollout_tracker() {
+        let tracker = RolloutTracker::new(chrono::Duration::hours(1));
+        let now = Utc::now();
+        let crashed = |p: &Pod, minutes: i64| {
+            let container = containers(p).next().unwrap();
+            tracker.crashed_at(
+                p,
+                "web",
+                container,
+                now + chrono::Duration::minutes(minutes),
+            )
+        };
+        let old = pod(
+            "example.com:5000/web:v1.4.2",
+            "aaa",
+            now - chrono::Duration::days(1),
+        );
+        let new = pod("example.com:5000/web:v1.5.0", "bbb", now);
+        tracker.observe(&old, "web");
+        assert_eq!(crashed(&old, 0), None);
+        tracker.observe(&new, "web");
+        assert_eq!(
+            crashed(&new, 6).as_deref(),
+            Some(":rocket: First crash after image changed from `v1.4.2` → `v1.5.0` 6m ago")
+        );
+        assert_eq!(
+            crashed(&new, 8).as_deref(),
+            Some(":rocket: Crashed again after image changed from `v1.4.2` → `v1.5.0` 8m ago")
+        );
+        assert_eq!(crashed(&new, 61), None);
+        // Pods of the previous revision are not called out
+        assert_eq!(crashed(&old, 6), None);
+
+        // Rollout without changing the image
+        let config = pod(
+            "example.com:5000/web:v1.5.0",
+            "ccc",
+            now + chrono::Duration::hours(2),
+        );
+        tracker.observe(&config, "web");
+        assert_eq!(
+            crashed(&config, 125).as_deref(),
+            Some(":rocket: First crash after rollout of `web` 5m ago")
+        );
+    }
+
+    #[test]
+    fn test_image_change() {
+        assert_eq!(image_change("web:v1", "web:v2"), ("v1", "v2"));
+        assert_eq!(
+            image_change("web@sha256:aaa", "web@sha256:bbb"),
+            ("sha256:aaa", "sha256:bbb")
+        );
+        assert_eq!(image_change("web:v1", "api:v1"), ("web:v1", "api:v1"));
+        assert_eq!(
+            image_change("localhost:5000/web", "localhost:5000/web:v2"),
+            ("localhost:5000/web", "localhost:5000/web:v2")
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use anyhow::Context;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+/// Maximum number of operations per evaluation to stop runaway scripts
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Routing script in Rhai which computes the destination channel and severity of a restart
+/// from the `restart` map, for routing that patterns of `SLACK_NOTIFICATION_CONFIG` cannot
+/// express. The script evaluates to:
+/// - `()` to use the channel of the pattern rules
+/// - a channel name, or `""` to disable the notification
+/// - a map with `channel` and optional `severity`
+#[derive(Debug)]
+pub struct RoutingScript {
+    engine: Engine,
+    ast: AST,
+}
+
+/// Result of `RoutingScript`
+#[derive(Debug, PartialEq)]
+pub enum ScriptRoute {
+    /// Routed by the pattern rules
+    Default,
+    Disabled,
+    Channel {
+        channel: String,
+        severity: Option<String>,
+    },
+}
+
+impl RoutingScript {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read routing script {}", path.display()))?;
+        Self::compile(&source)
+    }
+
+    pub fn compile(source: &str) -> anyhow::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| anyhow::anyhow!("Invalid routing script: {e}"))?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Evaluates the script with `restart`, which holds attributes of the restart.
+    pub fn route(&self, restart: Map) -> anyhow::Result<ScriptRoute> {
+        let mut scope = Scope::new();
+        scope.push("restart", restart);
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| anyhow::anyhow!("Routing script failed: {e}"))?;
+        if result.is_unit() {
+            return Ok(ScriptRoute::Default);
+        }
+        let (channel, severity) = if result.is_string() {
+            (result.into_string().unwrap(), None)
+        } else if let Some(mut route) = result.try_cast::<Map>() {
+            let channel = route
+                .remove("channel")
+                .and_then(|channel| channel.into_string().ok())
+                .context("Routing script returned a map without string `channel`")?;
+            let severity = route
+                .remove("severity")
+                .filter(|severity| !severity.is_unit())
+                .map(|severity| severity.to_string());
+            (channel, severity)
+        } else {
+            anyhow::bail!("Routing script must return (), a string or a map");
+        };
+        Ok(if channel.is_empty() {
+            ScriptRoute::Disabled
+        } else {
+            ScriptRoute::Channel { channel, severity }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart(namespace: &str, exit_code: i64, team: Option<&str>) -> Map {
+        let mut labels = Map::new();
+        if let Some(team) = team {
+            labels.insert("team".into(), team.into());
+        }
+        let mut restart = Map::new();
+        restart.insert("namespace".into(), namespace.into());
+        restart.insert("exit_code".into(), exit_code.into());
+        restart.insert("labels".into(), labels.into());
+        restart
+    }
+
+    #[test]
+    fn test_routing_script() {
+        let script = RoutingScript::compile(
+            r##"
+            if restart.namespace == "ignored" {
+                return "";
+            }
+            if restart.exit_code == 137 {
+                return #{ channel: "#oom", severity: "critical" };
+            }
+            if "team" in restart.labels {
+                return "#team-" + restart.labels.team;
+            }
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            script.route(restart("ignored", 1, None)).unwrap(),
+            ScriptRoute::Disabled
+        );
+        assert_eq!(
+            script.route(restart("default", 137, None)).unwrap(),
+            ScriptRoute::Channel {
+                channel: "#oom".to_owned(),
+                severity: Some("critical".to_owned()),
+            }
+        );
+        assert_eq!(
+            script
+                .route(restart("default", 1, Some("payments")))
+                .unwrap(),
+            ScriptRoute::Channel {
+                channel: "#team-payments".to_owned(),
+                severity: None,
+            }
+        );
+        assert_eq!(
+            script.route(restart("default", 1, None)).unwrap(),
+            ScriptRoute::Default
+        );
+
+        assert!(RoutingScript::compile("if {").is_err());
+        let script = RoutingScript::compile("42").unwrap();
+        assert!(script.route(Map::new()).is_err());
+        let script = RoutingScript::compile("loop {}").unwrap();
+        assert!(script.route(Map::new()).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "slack")]
+use serde_json::json;
+
+use crate::message::escape_mrkdwn;
+#[cfg(feature = "slack")]
+use crate::slack::SlackPoster;
+
+/// Number of consecutive failures to send a self-alert
+const FAILURE_THRESHOLD: usize = 5;
+
+/// Minimum interval of self-alerts for each component
+const ALERT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Part of the notification pipeline monitored by self-alerts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Component {
+    Watcher,
+    Slack,
+    /// Notifier other than Slack, e.g. Alertmanager
+    Notifier,
+    Queue,
+}
+
+impl std::fmt::Display for Component {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Watcher => f.write_str("Kubernetes watcher"),
+            Self::Slack => f.write_str("Slack delivery"),
+            Self::Notifier => f.write_str("Notification delivery"),
+            Self::Queue => f.write_str("Notification queue"),
+        }
+    }
+}
+
+/// Alerts failures of johari-mirror itself to an ops channel or an incoming webhook.
+/// Alerts are only logged without the `slack` feature.
+#[derive(Debug, Clone, Default)]
+pub struct SelfAlert {
+    failures: Arc<Mutex<HashMap<Component, FailureState>>>,
+    #[cfg(feature = "slack")]
+    destination: Option<Arc<Destination>>,
+}
+
+#[cfg(feature = "slack")]
+#[derive(Debug)]
+struct Destination {
+    poster: SlackPoster,
+    http: reqwest::Client,
+    /// Slack channel to post alerts
+    channel: Option<String>,
+    /// Slack incoming webhook used when posting to `channel` fails
+    webhook_url: Option<String>,
+}
+
+impl SelfAlert {
+    /// Alerts are disabled when neither `channel` nor `webhook_url` is specified.
+    #[cfg(feature = "slack")]
+    pub fn new(poster: SlackPoster, channel: Option<String>, webhook_url: Option<String>) -> Self {
+        let destination = (channel.is_some() || webhook_url.is_some()).then(|| {
+            Arc::new(Destination {
+                http: poster.http().clone(),
+                poster,
+                channel,
+                webhook_url,
+            })
+        });
+        Self {
+            failures: Default::default(),
+            destination,
+        }
+    }
+
+    pub fn success(&self, component: Component) {
+        if let Some(state) = self.failures.lock().unwrap().get_mut(&component) {
+            state.consecutive = 0;
+        }
+    }
+
+    /// Records a failure of `component` and sends an alert when it keeps failing.
+    pub fn failure(&self, component: Component, error: &impl std::fmt::Display) {
+        #[cfg(feature = "slack")]
+        if self.destination.is_none() {
+            return;
+        }
+        let consecutive = {
+            let mut failures = self.failures.lock().unwrap();
+            let state = failures.entry(component).or_default();
+            if !state.record_failure(Instant::now()) {
+                return;
+            }
+            state.consecutive
+        };
+        let text = format!(
+            ":rotating_light: *johari-mirror*: {component} failed {consecutive} times in a row. \
+            Restart notifications may be delayed or missing.\nLast error: `{}`",
+            escape_mrkdwn(&error.to_string())
+        );
+        self.send(text);
+    }
+
+    #[cfg(feature = "slack")]
+    fn send(&self, text: String) {
+        if let Some(destination) = &self.destination {
+            tokio::spawn(Arc::clone(destination).send(text));
+        }
+    }
+
+    #[cfg(not(feature = "slack"))]
+    fn send(&self, text: String) {
+        log::warn!("Self-alert: {text}");
+    }
+}
+
+#[cfg(feature = "slack")]
+impl Destination {
+    async fn send(self: Arc<Self>, text: String) {
+        log::warn!("Sending self-alert: {text}");
+        if let Some(channel) = &self.channel {
+            let blocks = vec![json!({
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": &text,
+                },
+            })];
+            match self.poster.post_blocks(channel, blocks).await {
+                Ok(()) => return,
+                Err(e) => log::error!("Failed to post self-alert to {channel}: {e}"),
+            }
+        }
+        if let Some(webhook_url) = &self.webhook_url {
+            let result = self
+                .http
+                .post(webhook_url)
+                .json(&json!({ "text": &text }))
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+            if let Err(e) = result {
+                log::error!("Failed to send self-alert to the webhook: {e}");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FailureState {
+    consecutive: usize,
+    last_alert: Option<Instant>,
+}
+
+impl FailureState {
+    /// Returns whether to send an alert.
+    fn record_failure(&mut self, now: Instant) -> bool {
+        self.consecutive += 1;
+        if self.consecutive < FAILURE_THRESHOLD
+            || matches!(self.last_alert, Some(t) if now.duration_since(t) < ALERT_INTERVAL)
+        {
+            return false;
+        }
+        self.last_alert = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_failure() {
+        let now = Instant::now();
+        let mut state = FailureState::default();
+        for _ in 1..FAILURE_THRESHOLD {
+            assert!(!state.record_failure(now));
+        }
+        assert!(state.record_failure(now));
+        assert!(!state.record_failure(now + Duration::from_secs(60)));
+        assert!(state.record_failure(now + ALERT_INTERVAL));
+
+        state.consecutive = 0;
+        assert!(!state.record_failure(now + ALERT_INTERVAL * 2));
+    }
+}
@@ -0,0 +1,461 @@
+use std::net::SocketAddr;
+
+#[cfg(feature = "slack")]
+use axum::body::Bytes;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::runtime::reflector::Store;
+use serde::Deserialize;
+use serde_json::json;
+
+#[cfg(feature = "slack")]
+use crate::slash_command;
+use crate::{
+    bearer,
+    health::Health,
+    kubernetes::{NotificationConfig, PodRestartCounts},
+    message::ContainerRestartInfo,
+    queue::{QueueMonitor, WeakNotificationSender},
+    silence::{self, Silence, Silences},
+};
+
+/// Default address of the HTTP server
+pub const DEFAULT_LISTEN_ADDRESS: &str = "0.0.0.0:8080";
+
+/// Default container of synthetic restarts injected by `/api/inject`
+const INJECT_NAMESPACE: &str = "johari-mirror-test";
+const INJECT_POD: &str = "fault-injection";
+const INJECT_CONTAINER: &str = "app";
+
+#[derive(Clone)]
+struct AppState {
+    /// Slash commands are disabled when `None`
+    #[cfg(feature = "slack")]
+    slack_signing_secret: Option<String>,
+    silences: Silences,
+    health: Health,
+    /// `/debug/state` is disabled when `None`
+    debug: Option<DebugState>,
+    /// `/api/*` is disabled when `None`
+    api: Option<ApiState>,
+}
+
+/// Internal state exposed by `/debug/state`
+#[derive(Clone)]
+pub struct DebugState {
+    /// Bearer token required to access the endpoint
+    pub token: String,
+    pub pod_restart_count: PodRestartCounts,
+    pub pod_store: Store<Pod>,
+    /// Queue of notifications, not to keep the channel open
+    pub queue: QueueMonitor,
+}
+
+/// Configuration of the API to manage silences and inspect routing
+#[derive(Clone)]
+pub struct ApiState {
+    /// Bearer token required to access the API
+    pub token: String,
+    pub router: NotificationConfig,
+    /// Whether `ROUTING_SCRIPT` routes restarts before the rules
+    pub routing_script: bool,
+    /// Queue to inject synthetic restarts into, not to keep the channel open
+    pub queue: WeakNotificationSender,
+}
+
+/// Task to serve HTTP endpoints, e.g. health checks and Slack slash commands
+pub async fn serve(
+    addr: SocketAddr,
+    #[cfg(feature = "slack")] slack_signing_secret: Option<String>,
+    silences: Silences,
+    health: Health,
+    debug: Option<DebugState>,
+    api: Option<ApiState>,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/debug/state", get(debug_state))
+        .route("/api/silences", get(list_silences).post(create_silence))
+        .route("/api/silences/:id", delete(delete_silence))
+        .route("/api/rules", get(list_rules))
+        .route("/api/route", get(find_route))
+        .route("/api/inject", post(inject_restart));
+    #[cfg(feature = "slack")]
+    let app = app.route("/slack/commands", post(slack_command));
+    let app = app.with_state(AppState {
+        #[cfg(feature = "slack")]
+        slack_signing_secret,
+        silences,
+        health,
+        debug,
+        api,
+    });
+    log::info!("Listening on {addr}");
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+    Ok(())
+}
+
+/// Liveness probe, failing when the watcher has stalled
+async fn healthz(State(state): State<AppState>) -> Response {
+    probe_response(state.health.liveness())
+}
+
+/// Readiness probe, failing until the watcher is initialized or while Slack is unavailable
+async fn readyz(State(state): State<AppState>) -> Response {
+    probe_response(state.health.readiness())
+}
+
+fn probe_response(result: Result<(), String>) -> Response {
+    match result {
+        Ok(()) => "ok".into_response(),
+        Err(e) => {
+            log::warn!("Health check failed: {e}");
+            (StatusCode::SERVICE_UNAVAILABLE, e).into_response()
+        }
+    }
+}
+
+/// Prometheus metrics
+async fn metrics() -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        crate::metrics::render(),
+    )
+        .into_response()
+}
+
+/// Whether the request has `Authorization: Bearer <token>` header.
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    let authorization = headers.get("authorization").and_then(|v| v.to_str().ok());
+    bearer::is_bearer(authorization, token)
+}
+
+fn silence_json(silence: &Silence) -> serde_json::Value {
+    json!({
+        "id": silence.id,
+        "pattern": silence.pattern.to_string(),
+        "expires_at": silence.expires_at.to_rfc3339(),
+        "created_by": silence.created_by,
+    })
+}
+
+/// Dumps internal state to investigate missing notifications
+async fn debug_state(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(debug) = &state.debug else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !is_authorized(&headers, &debug.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let silences = state
+        .silences
+        .active()
+        .iter()
+        .map(silence_json)
+        .collect::<Vec<_>>();
+    let last_watch_error = state.health.last_watch_error().map(|(time, error)| {
+        json!({
+            "time": time.to_rfc3339(),
+            "error": error,
+        })
+    });
+    Json(json!({
+        "watched_pods": debug.pod_store.state().len(),
+        "pod_restart_count": debug.pod_restart_count.snapshot(),
+        "silences": silences,
+        "queue_depth": debug.queue.len(),
+        "queue_spilled": debug.queue.spilled(),
+        "last_watch_error": last_watch_error,
+    }))
+    .into_response()
+}
+
+/// Handles `/johari` slash command requests from Slack
+#[cfg(feature = "slack")]
+async fn slack_command(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let Some(slack_signing_secret) = &state.slack_signing_secret else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+    if let Err(e) = slash_command::verify_signature(
+        slack_signing_secret,
+        header("x-slack-request-timestamp"),
+        header("x-slack-signature"),
+        &body,
+    ) {
+        log::warn!("Rejected slash command request: {e}");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let param = |name| {
+        form_urlencoded::parse(&body)
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+            .unwrap_or_default()
+    };
+    let response = slash_command::execute(&state.silences, &param("text"), &param("user_id"));
+    Json(response.to_json()).into_response()
+}
+
+/// Returns the API configuration if the request is authorized, or the error status.
+fn authorize_api<'a>(state: &'a AppState, headers: &HeaderMap) -> Result<&'a ApiState, StatusCode> {
+    let api = state.api.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    if !is_authorized(headers, &api.token) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(api)
+}
+
+fn bad_request(message: impl std::fmt::Display) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": message.to_string() })),
+    )
+        .into_response()
+}
+
+/// Lists active silences
+async fn list_silences(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(status) = authorize_api(&state, &headers) {
+        return status.into_response();
+    }
+    let silences = state
+        .silences
+        .active()
+        .iter()
+        .map(silence_json)
+        .collect::<Vec<_>>();
+    Json(json!({ "silences": silences })).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateSilenceRequest {
+    /// `namespace/pod/container` pattern
+    pattern: String,
+    /// e.g. `2h`
+    duration: String,
+    created_by: String,
+}
+
+/// Creates a silence, same as `/johari silence`
+async fn create_silence(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateSilenceRequest>,
+) -> Response {
+    if let Err(status) = authorize_api(&state, &headers) {
+        return status.into_response();
+    }
+    let pattern = match request.pattern.parse() {
+        Ok(pattern) => pattern,
+        Err(e) => return bad_request(e),
+    };
+    let duration = match silence::parse_duration(&request.duration) {
+        Ok(duration) => duration,
+        Err(e) => return bad_request(e),
+    };
+    let silence = match state.silences.add(pattern, duration, &request.created_by) {
+        Ok(silence) => silence,
+        Err(e) => return bad_request(e),
+    };
+    log::info!(
+        "Silence added by {} via API: {} for {}",
+        silence.created_by,
+        silence.pattern,
+        silence::format_duration(duration)
+    );
+    (StatusCode::CREATED, Json(silence_json(&silence))).into_response()
+}
+
+async fn delete_silence(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Response {
+    if let Err(status) = authorize_api(&state, &headers) {
+        return status.into_response();
+    }
+    match state.silences.remove(id) {
+        Some(silence) => {
+            log::info!("Silence #{id} removed via API: {}", silence.pattern);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Lists routing rules of `SLACK_NOTIFICATION_CONFIG` in priority order
+async fn list_rules(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let api = match authorize_api(&state, &headers) {
+        Ok(api) => api,
+        Err(status) => return status.into_response(),
+    };
+    Json(json!({
+        "rules": api.router.to_json(),
+        "routing_script": api.routing_script,
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteQuery {
+    namespace: String,
+    pod: String,
+    container: String,
+}
+
+/// Returns the destination of restarts of a container by the rules and silences.
+/// Routing by `ROUTING_SCRIPT` is not evaluated, which needs the Pod.
+async fn find_route(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<RouteQuery>,
+) -> Response {
+    let api = match authorize_api(&state, &headers) {
+        Ok(api) => api,
+        Err(status) => return status.into_response(),
+    };
+    let route = api
+        .router
+        .find_route(&query.namespace, &query.pod, &query.container);
+    let silence = state
+        .silences
+        .find(&query.namespace, &query.pod, &query.container);
+    Json(json!({
+        "channel": route.map(|(channel, _)| channel),
+        "options": route.map(|(_, options)| options),
+        "silence": silence.as_ref().map(silence_json),
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct InjectRequest {
+    namespace: Option<String>,
+    pod: Option<String>,
+    container: Option<String>,
+    /// Routed by the rules when omitted
+    channel: Option<String>,
+}
+
+/// Injects a synthetic restart with canned logs into the notification queue
+/// to verify routing, middlewares and delivery after deploys.
+/// Silences and `ROUTING_SCRIPT` are not applied.
+async fn inject_restart(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<InjectRequest>,
+) -> Response {
+    let api = match authorize_api(&state, &headers) {
+        Ok(api) => api,
+        Err(status) => return status.into_response(),
+    };
+    let namespace = request.namespace.as_deref().unwrap_or(INJECT_NAMESPACE);
+    let pod = request.pod.as_deref().unwrap_or(INJECT_POD);
+    let container = request.container.as_deref().unwrap_or(INJECT_CONTAINER);
+    let route = api.router.find_route(namespace, pod, container);
+    let options = route
+        .map(|(_, options)| options.clone())
+        .unwrap_or_default();
+    let Some(channel) = request
+        .channel
+        .or_else(|| route.map(|(channel, _)| channel.to_owned()))
+    else {
+        return bad_request("Notification of the container is disabled, specify channel");
+    };
+    let Some(queue) = api.queue.upgrade() else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    let info = ContainerRestartInfo::synthetic(
+        namespace,
+        pod,
+        container,
+        channel,
+        options,
+        "the fault-injection API",
+    );
+    log::info!("Injecting synthetic restart via API: {info}");
+    let response = json!({
+        "container": info.container_key(),
+        "channel": info.channel,
+    });
+    if let Err(e) = queue.send(info).await {
+        log::error!("Failed to queue synthetic restart: {e}");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_authorized() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("authorization", value.parse().unwrap());
+            headers
+        };
+        assert!(is_authorized(&headers("Bearer secret"), "secret"));
+        assert!(!is_authorized(&headers("Bearer secret2"), "secret"));
+        assert!(!is_authorized(&headers("Bearer secre"), "secret"));
+        assert!(!is_authorized(&headers("secret"), "secret"));
+        assert!(!is_authorized(&HeaderMap::new(), "secret"));
+    }
+
+    #[tokio::test]
+    async fn test_create_silence_too_long() {
+        let (sender, _receiver) = crate::queue::channel(1, Default::default(), None, vec![]);
+        let state = AppState {
+            #[cfg(feature = "slack")]
+            slack_signing_secret: None,
+            silences: Silences::default(),
+            health: Health::new(std::time::Duration::from_secs(60)),
+            debug: None,
+            api: Some(ApiState {
+                token: "secret".to_owned(),
+                router: NotificationConfig::default(),
+                routing_script: false,
+                queue: sender.downgrade(),
+            }),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        let request = |duration: &str| {
+            Json(CreateSilenceRequest {
+                pattern: "a/b/c".to_owned(),
+                duration: duration.to_owned(),
+                created_by: "alice".to_owned(),
+            })
+        };
+        let response = create_silence(
+            State(state.clone()),
+            headers.clone(),
+            request("99999999999999d"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = create_silence(State(state.clone()), headers, request("2h")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(state.silences.active().len(), 1);
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context};
+use wildmatch::WildMatch;
+
+use crate::{
+    category::CrashCategory,
+    message::ContainerRestartInfo,
+    middleware::{Middleware, RestartsSummary},
+    rate_limit::RateLimiter,
+};
+
+/// Severity of a restart, which controls how it is notified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Self; 3] = [Self::Info, Self::Warning, Self::Critical];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+
+    /// Shown at the beginning of the message header
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Self::Info => ":information_source:",
+            Self::Warning => ":warning:",
+            Self::Critical => ":rotating_light:",
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::ALL
+            .into_iter()
+            .find(|severity| severity.as_str().eq_ignore_ascii_case(s))
+        {
+            Some(severity) => Ok(severity),
+            None => bail!("Unknown severity: {s}"),
+        }
+    }
+}
+
+/// Destination of notifications, which severities can be restricted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    /// Slack, the log or Alertmanager by `NOTIFIER`
+    Notifier,
+    Archive,
+    Loki,
+    Elasticsearch,
+    Alertmanager,
+    Apm,
+    Syslog,
+}
+
+impl std::str::FromStr for Sink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "notifier" => Self::Notifier,
+            "archive" => Self::Archive,
+            "loki" => Self::Loki,
+            "elasticsearch" => Self::Elasticsearch,
+            "alertmanager" => Self::Alertmanager,
+            "apm" => Self::Apm,
+            "syslog" => Self::Syslog,
+            _ => bail!("Unknown sink: {s}"),
+        })
+    }
+}
+
+/// Rule to compute the severity of restarts matching all of its conditions.
+/// `severity:condition;condition` format, e.g. `critical:namespace=prod-*;min_restarts=5`.
+#[derive(Debug, Clone)]
+pub struct SeverityRule {
+    severity: Severity,
+    namespace: Option<WildMatch>,
+    /// Matches any exit code when empty
+    exit_codes: Vec<i32>,
+    min_restarts: Option<i32>,
+    /// Matches any crash when empty
+    categories: Vec<CrashCategory>,
+}
+
+impl std::str::FromStr for SeverityRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (severity, conditions) = s.split_once(':').unwrap_or((s, ""));
+        let mut rule = Self {
+            severity: severity.parse()?,
+            namespace: None,
+            exit_codes: Vec::new(),
+            min_restarts: None,
+            categories: Vec::new(),
+        };
+        for condition in conditions.split(';').filter(|c| !c.is_empty()) {
+            match condition.split_once('=') {
+                Some(("namespace", namespace)) => rule.namespace = Some(WildMatch::new(namespace)),
+                Some(("exit_code", codes)) => {
+                    rule.exit_codes = codes
+                        .split('|')
+                        .map(|code| {
+                            code.parse()
+                                .with_context(|| format!("Invalid exit code: {code}"))
+                        })
+                        .collect::<anyhow::Result<_>>()?
+                }
+                Some(("min_restarts", count)) => {
+                    rule.min_restarts = Some(
+                        count
+                            .parse()
+                            .with_context(|| format!("Invalid min_restarts: {count}"))?,
+                    )
+                }
+                Some(("category", categories)) => {
+                    rule.categories = categories
+                        .split('|')
+                        .map(str::parse)
+                        .collect::<anyhow::Result<_>>()?
+                }
+                _ => bail!("Unknown severity condition: {condition}"),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl SeverityRule {
+    fn matches(&self, info: &ContainerRestartInfo) -> bool {
+        if let Some(namespace) = &self.namespace {
+            if !namespace.matches(info.namespace.as_deref().unwrap_or("")) {
+                return false;
+            }
+        }
+        if let Some(min_restarts) = self.min_restarts {
+            if info.restart_count < min_restarts {
+                return false;
+            }
+        }
+        let exit_code = info.last_state.as_ref().map(|state| state.exit_code);
+        (self.exit_codes.is_empty()
+            || exit_code.is_some_and(|code| self.exit_codes.contains(&code)))
+            && (self.categories.is_empty()
+                || info.category.is_some_and(|c| self.categories.contains(&c)))
+    }
+}
+
+/// Behavior of notifications of a severity
+#[derive(Debug, Clone, Default)]
+pub struct SeverityLevel {
+    /// Handle of Slack user group to mention unless the route mentions another
+    pub mention: Option<String>,
+    /// Notifications per minute of the severity
+    pub rate_limit: Option<u32>,
+    /// Notifications are sent to all configured sinks when `None`
+    pub sinks: Option<Vec<Sink>>,
+}
+
+impl SeverityLevel {
+    /// Reads `SEVERITY_<SEVERITY>_*` environment variables of `severity`.
+    fn from_env(severity: Severity) -> anyhow::Result<Self> {
+        let prefix = format!("SEVERITY_{}", severity.as_str().to_ascii_uppercase());
+        let mention = std::env::var(format!("{prefix}_MENTION"))
+            .ok()
+            .map(|handle| handle.trim_start_matches('@').to_owned());
+        let rate_limit = match std::env::var(format!("{prefix}_RATE_LIMIT")) {
+            Ok(limit) => match limit
+                .parse()
+                .with_context(|| format!("Invalid {prefix}_RATE_LIMIT"))?
+            {
+                0 => bail!("{prefix}_RATE_LIMIT must be at least 1"),
+                limit => Some(limit),
+            },
+            Err(_) => None,
+        };
+        let sinks = match std::env::var(format!("{prefix}_SINKS")) {
+            Ok(sinks) => Some(
+                sinks
+                    .split(',')
+                    .filter(|sink| !sink.is_empty())
+                    .map(str::parse)
+                    .collect::<anyhow::Result<_>>()
+                    .with_context(|| format!("Invalid {prefix}_SINKS"))?,
+            ),
+            Err(_) => None,
+        };
+        Ok(Self {
+            mention,
+            rate_limit,
+            sinks,
+        })
+    }
+}
+
+/// Configuration of computing severities of restarts and notifying them by severity
+#[derive(Debug, Clone)]
+pub struct SeverityConfig {
+    /// Earlier rules have higher priority
+    pub rules: Vec<SeverityRule>,
+    pub levels: BTreeMap<Severity, SeverityLevel>,
+}
+
+impl SeverityConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `SEVERITY_RULES` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(rules) = std::env::var("SEVERITY_RULES") else {
+            return Ok(None);
+        };
+        let rules = rules
+            .split(',')
+            .filter(|rule| !rule.is_empty())
+            .map(str::parse)
+            .collect::<anyhow::Result<_>>()
+            .context("Invalid SEVERITY_RULES")?;
+        let levels = Severity::ALL
+            .into_iter()
+            .map(|severity| Ok((severity, SeverityLevel::from_env(severity)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(Self { rules, levels }))
+    }
+
+    /// Severity of the `severity` option of the route, or of the first matching rule
+    pub fn severity(&self, info: &ContainerRestartInfo) -> Option<Severity> {
+        match &info.options.severity {
+            Some(severity) => severity.parse().ok(),
+            None => self
+                .rules
+                .iter()
+                .find(|rule| rule.matches(info))
+                .map(|rule| rule.severity),
+        }
+    }
+
+    /// Sinks notifications of the severity of `info` are restricted to, if any
+    pub fn sinks(&self, info: &ContainerRestartInfo) -> Option<&[Sink]> {
+        let severity = self.severity(info)?;
+        self.levels.get(&severity)?.sinks.as_deref()
+    }
+}
+
+/// Sets severities of notifications by the rules, then mentions the user group of
+/// the severity and limits notifications per severity
+pub struct SeverityClassifier {
+    config: SeverityConfig,
+    rate_limiters: BTreeMap<Severity, RateLimiter>,
+}
+
+impl SeverityClassifier {
+    pub fn new(config: SeverityConfig) -> Self {
+        let rate_limiters = config
+            .levels
+            .iter()
+            .filter_map(|(&severity, level)| Some((severity, RateLimiter::new(level.rate_limit?))))
+            .collect();
+        Self {
+            config,
+            rate_limiters,
+        }
+    }
+}
+
+impl Middleware for SeverityClassifier {
+    fn process(&mut self, mut info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        let Some(severity) = self.config.severity(&info) else {
+            return Some(info);
+        };
+        info.options.severity = Some(severity.to_string());
+        if let Some(level) = self.config.levels.get(&severity) {
+            if info.options.mention.is_none() {
+                info.options.mention = level.mention.clone();
+            }
+        }
+        if let Some(limiter) = self.rate_limiters.get_mut(&severity) {
+            if !limiter.admit(&info) {
+                log::warn!("Rate limit of {severity} notifications exceeded, summarizing: {info}");
+                return None;
+            }
+        }
+        Some(info)
+    }
+
+    fn take_summarized(&mut self) -> RestartsSummary {
+        let mut summary = RestartsSummary::new();
+        for limiter in self.rate_limiters.values_mut() {
+            for (channel, restarts) in limiter.take_overflow() {
+                let channel_summary = summary.entry(channel).or_default();
+                for (container, count) in restarts {
+                    *channel_summary.entry(container).or_default() += count;
+                }
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ContainerState;
+
+    fn restart_info(namespace: &str, restart_count: i32, exit_code: i32) -> ContainerRestartInfo {
+        let mut info = ContainerRestartInfo::synthetic(
+            namespace,
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.restart_count = restart_count;
+        info.last_state = Some(ContainerState {
+            exit_code,
+            signal: None,
+            reason: None,
+            message: None,
+            started_at: None,
+            finished_at: None,
+        });
+        info
+    }
+
+    fn config(rules: &str) -> SeverityConfig {
+        SeverityConfig {
+            rules: rules.split(',').map(|r| r.parse().unwrap()).collect(),
+            levels: BTreeMap::from([
+                (
+                    Severity::Critical,
+                    SeverityLevel {
+                        mention: Some("oncall".to_owned()),
+                        rate_limit: Some(1),
+                        sinks: None,
+                    },
+                ),
+                (
+                    Severity::Info,
+                    SeverityLevel {
+                        sinks: Some(vec![Sink::Elasticsearch]),
+                        ..Default::default()
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_severity() {
+        let config =
+            config("critical:namespace=prod-*;min_restarts=5,warning:exit_code=137|139,info");
+        let severity = |namespace, restart_count, exit_code| {
+            config.severity(&restart_info(namespace, restart_count, exit_code))
+        };
+        assert_eq!(severity("prod-api", 5, 1), Some(Severity::Critical));
+        assert_eq!(severity("prod-api", 4, 137), Some(Severity::Warning));
+        assert_eq!(severity("dev", 9, 1), Some(Severity::Info));
+        // The severity option of the route takes precedence
+        let mut info = restart_info("dev", 1, 1);
+        info.options.severity = Some("Critical".to_owned());
+        assert_eq!(config.severity(&info), Some(Severity::Critical));
+        assert_eq!(
+            config.sinks(&restart_info("dev", 1, 1)),
+            Some(&[Sink::Elasticsearch][..])
+        );
+        assert!("fatal:namespace=prod".parse::<SeverityRule>().is_err());
+        assert!("critical:node=node-1".parse::<SeverityRule>().is_err());
+    }
+
+    #[test]
+    fn test_severity_classifier() {
+        let mut classifier = SeverityClassifier::new(config("critical:min_restarts=3,info"));
+        let info = classifier.process(restart_info("prod", 3, 1)).unwrap();
+        assert_eq!(info.options.severity.as_deref(), Some("critical"));
+        assert_eq!(info.options.mention.as_deref(), Some("oncall"));
+        // Over the rate limit of critical notifications
+        assert!(classifier.process(restart_info("prod", 4, 1)).is_none());
+        let info = classifier.process(restart_info("prod", 1, 1)).unwrap();
+        assert_eq!(info.options.severity.as_deref(), Some("info"));
+        assert_eq!(info.options.mention, None);
+        assert_eq!(
+            classifier.take_summarized(),
+            RestartsSummary::from([(
+                "#alerts".to_owned(),
+                BTreeMap::from([("prod/app-0/app".to_owned(), 1)])
+            )])
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use hmac::{Hmac, Mac};
+use k8s_openapi::chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+/// Credentials and scope of AWS Signature Version 4
+pub(crate) struct SigV4<'a> {
+    pub access_key_id: &'a str,
+    pub secret_access_key: &'a str,
+    pub region: &'a str,
+    /// e.g. `s3` or `secretsmanager`
+    pub service: &'a str,
+}
+
+impl SigV4<'_> {
+    /// `Authorization` header of requests without query strings.
+    /// `headers` are lowercase and sorted by name.
+    /// https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-header-based-auth.html
+    pub(crate) fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        payload_hash: &str,
+        time: DateTime<Utc>,
+    ) -> String {
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect::<String>();
+        let canonical_request =
+            format!("{method}\n{path}\n\n{canonical_headers}\n{signed_headers}\n{payload_hash}");
+        let date = time.format("%Y%m%d").to_string();
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+            time.format("%Y%m%dT%H%M%SZ"),
+            hex::encode(Sha256::digest(canonical_request.as_bytes())),
+        );
+        let key = [date.as_str(), self.region, self.service, "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_access_key).into_bytes(),
+                |key, data| hmac_sha256(&key, data),
+            );
+        let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
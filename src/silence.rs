@@ -0,0 +1,252 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use tokio::sync::Notify;
+use wildmatch::WildMatch;
+
+/// Upper limit of durations, to keep timestamps far from the range of `DateTime`
+const MAX_DURATION_DAYS: i64 = 10 * 365;
+
+/// Pattern to select containers to silence.
+/// `namespace/pod/container` format.
+/// namespace, pod and container name can include `*` wildcard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SilencePattern {
+    namespace: WildMatch,
+    pod: WildMatch,
+    container: WildMatch,
+    /// Original representation for display
+    source: String,
+}
+
+impl std::str::FromStr for SilencePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (namespace, pod, container) = (|| {
+            let (namespace, rest) = s.split_once('/')?;
+            let (pod, container) = rest.split_once('/')?;
+            Some((namespace, pod, container))
+        })()
+        .with_context(|| format!("Invalid silence pattern: {}", s))?;
+        Ok(Self {
+            namespace: WildMatch::new(namespace),
+            pod: WildMatch::new(pod),
+            container: WildMatch::new(container),
+            source: s.to_owned(),
+        })
+    }
+}
+
+impl std::fmt::Display for SilencePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl SilencePattern {
+    fn matches(&self, namespace: &str, pod: &str, container: &str) -> bool {
+        self.namespace.matches(namespace)
+            && self.pod.matches(pod)
+            && self.container.matches(container)
+    }
+}
+
+/// Notifications of containers matching `pattern` are suppressed until `expires_at`.
+#[derive(Debug, Clone)]
+pub struct Silence {
+    pub id: u64,
+    pub pattern: SilencePattern,
+    pub expires_at: DateTime<Utc>,
+    /// Slack user who created the silence
+    pub created_by: String,
+}
+
+/// Set of active silences shared between the watcher and the slash command handler.
+#[derive(Debug, Clone, Default)]
+pub struct Silences {
+    inner: Arc<Mutex<SilencesInner>>,
+    /// Notified when silences are added or removed
+    changed: Arc<Notify>,
+}
+
+#[derive(Debug, Default)]
+struct SilencesInner {
+    next_id: u64,
+    silences: Vec<Silence>,
+}
+
+impl Silences {
+    /// Adds a silence and returns it.
+    /// Fails when the silence would expire out of the range of `DateTime`.
+    pub fn add(
+        &self,
+        pattern: SilencePattern,
+        duration: Duration,
+        created_by: &str,
+    ) -> anyhow::Result<Silence> {
+        let expires_at = Utc::now()
+            .checked_add_signed(duration)
+            .with_context(|| format!("Duration is too long: {}", format_duration(duration)))?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let silence = Silence {
+            id: inner.next_id,
+            pattern,
+            expires_at,
+            created_by: created_by.to_owned(),
+        };
+        inner.silences.push(silence.clone());
+        self.changed.notify_one();
+        Ok(silence)
+    }
+
+    /// Adds silences stored before, e.g. restored after restarting.
+    /// New silences are numbered after them.
+    pub fn restore(&self, silences: impl IntoIterator<Item = Silence>) {
+        let mut inner = self.inner.lock().unwrap();
+        for silence in silences {
+            inner.next_id = inner.next_id.max(silence.id);
+            inner.silences.push(silence);
+        }
+    }
+
+    /// Waits for silences to be added or removed.
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    /// Returns silences which have not expired yet.
+    pub fn active(&self) -> Vec<Silence> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Utc::now();
+        inner.silences.retain(|s| s.expires_at > now);
+        inner.silences.clone()
+    }
+
+    /// Removes the silence with `id` and returns it, `None` if it does not exist.
+    pub fn remove(&self, id: u64) -> Option<Silence> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner.silences.iter().position(|s| s.id == id)?;
+        self.changed.notify_one();
+        Some(inner.silences.remove(index))
+    }
+
+    /// Returns the first active silence matching the container.
+    pub fn find(&self, namespace: &str, pod: &str, container: &str) -> Option<Silence> {
+        self.active()
+            .into_iter()
+            .find(|s| s.pattern.matches(namespace, pod, container))
+    }
+}
+
+/// Parses duration in `<number><unit>` format, e.g. `30m`, `2h`, `1d`.
+/// Supported units are `s`, `m`, `h` and `d`, up to 3650 days.
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let unit_pos = s
+        .find(|c: char| !c.is_ascii_digit())
+        .with_context(|| format!("Missing unit in duration: {}", s))?;
+    let (value, unit) = s.split_at(unit_pos);
+    let value = value
+        .parse::<i64>()
+        .with_context(|| format!("Invalid duration: {}", s))?;
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("Unknown unit in duration: {}", s),
+    };
+    let seconds = value
+        .checked_mul(unit_seconds)
+        .filter(|seconds| *seconds <= MAX_DURATION_DAYS * 24 * 60 * 60)
+        .with_context(|| format!("Duration is too long: {}", s))?;
+    if seconds <= 0 {
+        bail!("Duration must be positive: {}", s);
+    }
+    Ok(Duration::seconds(seconds))
+}
+
+/// Formats duration in the largest units, e.g. `1h 23m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    match (minutes / 60 / 24, minutes / 60 % 24, minutes % 60) {
+        (0, 0, 0) => format!("{}s", duration.num_seconds().max(0)),
+        (0, 0, m) => format!("{m}m"),
+        (0, h, m) => format!("{h}h {m}m"),
+        (d, h, _) => format!("{d}d {h}h"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::seconds(30));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::days(1));
+        assert_eq!(parse_duration("3650d").unwrap(), Duration::days(3650));
+        assert!(parse_duration("3651d").is_err());
+        assert!(parse_duration("99999999999999d").is_err());
+        assert!(parse_duration("9223372036854775807s").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("3w").is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::seconds(42)), "42s");
+        assert_eq!(format_duration(Duration::minutes(15)), "15m");
+        assert_eq!(format_duration(Duration::minutes(83)), "1h 23m");
+        assert_eq!(format_duration(Duration::hours(50)), "2d 2h");
+    }
+
+    #[test]
+    fn test_silences_find() {
+        let silences = Silences::default();
+        silences
+            .add("foo/bar-*/*".parse().unwrap(), Duration::hours(1), "alice")
+            .unwrap();
+        assert_eq!(silences.find("foo", "bar-1", "baz").unwrap().id, 1);
+        assert!(silences.find("foo", "qux", "baz").is_none());
+        assert!(silences.find("other", "bar-1", "baz").is_none());
+    }
+
+    #[test]
+    fn test_silences_remove() {
+        let silences = Silences::default();
+        let silence = silences
+            .add("*/*/*".parse().unwrap(), Duration::hours(1), "alice")
+            .unwrap();
+        assert!(silences.remove(silence.id).is_some());
+        assert!(silences.remove(silence.id).is_none());
+        assert!(silences.find("foo", "bar", "baz").is_none());
+    }
+
+    #[test]
+    fn test_silences_expire() {
+        let silences = Silences::default();
+        silences
+            .add("*/*/*".parse().unwrap(), Duration::seconds(-1), "alice")
+            .unwrap();
+        assert!(silences.active().is_empty());
+        assert!(silences.find("foo", "bar", "baz").is_none());
+    }
+
+    #[test]
+    fn test_silences_add_too_long() {
+        let silences = Silences::default();
+        let duration = Duration::milliseconds(i64::MAX);
+        assert!(silences
+            .add("*/*/*".parse().unwrap(), duration, "alice")
+            .is_err());
+        assert!(silences.active().is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::Context;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{
+    api::{
+        Api, ApiResource, DeleteParams, DynamicObject, GroupVersionKind, ListParams, PostParams,
+    },
+    Client, ResourceExt,
+};
+use serde_json::json;
+
+use crate::silence::{Silence, Silences};
+
+/// API group and version of `JohariSilence`
+const GROUP: &str = "johari-mirror.flywheel.jp";
+const VERSION: &str = "v1alpha1";
+const KIND: &str = "JohariSilence";
+
+/// Label of resources created by johari-mirror
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+
+/// Interval to delete resources of expired silences
+const SYNC_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Stores silences as `JohariSilence` resources in `namespace`, so that they survive
+/// restarts of johari-mirror and can be inspected with `kubectl get joharisilences`
+pub struct SilenceStore {
+    api: Api<DynamicObject>,
+    resource: ApiResource,
+}
+
+impl SilenceStore {
+    pub fn new(client: Client, namespace: &str) -> Self {
+        let resource = ApiResource::from_gvk_with_plural(
+            &GroupVersionKind::gvk(GROUP, VERSION, KIND),
+            "joharisilences",
+        );
+        Self {
+            api: Api::namespaced_with(client, namespace, &resource),
+            resource,
+        }
+    }
+
+    /// Reads silences which have not expired yet.
+    /// Resources which are not valid silences are skipped with warnings.
+    pub async fn load(&self) -> anyhow::Result<Vec<Silence>> {
+        let list = self
+            .api
+            .list(&ListParams::default())
+            .await
+            .context("Failed to list JohariSilences")?;
+        let now = Utc::now();
+        let silences = list
+            .into_iter()
+            .filter_map(|object| match from_object(&object) {
+                Ok(silence) => Some(silence),
+                Err(e) => {
+                    log::warn!("Skipping JohariSilence {}: {e:#}", object.name_any());
+                    None
+                }
+            })
+            .filter(|silence| silence.expires_at > now)
+            .collect::<Vec<_>>();
+        log::info!("Loaded {} silences from JohariSilences", silences.len());
+        Ok(silences)
+    }
+
+    /// Task to create and delete resources following silences added, removed or expired.
+    /// Failures are logged and retried on the next change or interval.
+    pub async fn sync(self, silences: Silences) {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            tokio::select! {
+                () = silences.changed() => {}
+                _ = interval.tick() => {}
+            }
+            if let Err(e) = self.sync_once(&silences.active()).await {
+                log::error!("Failed to store silences as JohariSilences: {e:#}");
+            }
+        }
+    }
+
+    /// Resources of silences are also created by hand with `kubectl`, so they are listed
+    /// regardless of the label
+    async fn sync_once(&self, active: &[Silence]) -> anyhow::Result<()> {
+        let stored = self
+            .api
+            .list(&ListParams::default())
+            .await?
+            .iter()
+            .filter_map(|object| object_id(&object.name_any()))
+            .collect::<HashSet<_>>();
+        let active_ids = active.iter().map(|s| s.id).collect::<HashSet<_>>();
+        for silence in active.iter().filter(|s| !stored.contains(&s.id)) {
+            let object = to_object(silence, &self.resource);
+            self.api.create(&PostParams::default(), &object).await?;
+            log::debug!("Created JohariSilence of silence #{}", silence.id);
+        }
+        for id in stored.difference(&active_ids) {
+            self.api
+                .delete(&object_name(*id), &DeleteParams::default())
+                .await?;
+            log::debug!("Deleted JohariSilence of silence #{id}");
+        }
+        Ok(())
+    }
+}
+
+/// e.g. `silence-3`
+fn object_name(id: u64) -> String {
+    format!("silence-{id}")
+}
+
+/// ID of the silence of the resource named `name`, if any
+fn object_id(name: &str) -> Option<u64> {
+    name.strip_prefix("silence-")?.parse().ok()
+}
+
+fn to_object(silence: &Silence, resource: &ApiResource) -> DynamicObject {
+    let mut object = DynamicObject::new(&object_name(silence.id), resource).data(json!({
+        "spec": {
+            "pattern": silence.pattern.to_string(),
+            "expiresAt": silence.expires_at.to_rfc3339(),
+            "createdBy": silence.created_by,
+        },
+    }));
+    object
+        .labels_mut()
+        .insert(MANAGED_BY_LABEL.to_owned(), "johari-mirror".to_owned());
+    object
+}
+
+fn from_object(object: &DynamicObject) -> anyhow::Result<Silence> {
+    let id = object_id(&object.name_any()).context("Name is not `silence-<id>`")?;
+    let spec = &object.data["spec"];
+    let pattern = spec["pattern"]
+        .as_str()
+        .context("Missing spec.pattern")?
+        .parse()?;
+    let expires_at = spec["expiresAt"]
+        .as_str()
+        .context("Missing spec.expiresAt")?
+        .parse::<DateTime<Utc>>()
+        .context("Invalid spec.expiresAt")?;
+    Ok(Silence {
+        id,
+        pattern,
+        expires_at,
+        created_by: spec["createdBy"].as_str().unwrap_or_default().to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_roundtrip() {
+        let resource =
+            ApiResource::from_gvk_with_plural(&GroupVersionKind::gvk(GROUP, VERSION, KIND), "x");
+        let silence = Silence {
+            id: 3,
+            pattern: "default/app-*/*".parse().unwrap(),
+            expires_at: "2024-01-02T03:04:05Z".parse().unwrap(),
+            created_by: "U012AB3CD".to_owned(),
+        };
+        let object = to_object(&silence, &resource);
+        assert_eq!(object.name_any(), "silence-3");
+        assert_eq!(
+            object
+                .labels()
+                .get("app.kubernetes.io/managed-by")
+                .map(String::as_str),
+            Some("johari-mirror")
+        );
+        let restored = from_object(&object).unwrap();
+        assert_eq!(restored.id, 3);
+        assert_eq!(restored.pattern, silence.pattern);
+        assert_eq!(restored.expires_at, silence.expires_at);
+        assert_eq!(restored.created_by, "U012AB3CD");
+
+        let mut invalid = object.clone();
+        invalid.metadata.name = Some("my-silence".to_owned());
+        assert!(from_object(&invalid).is_err());
+    }
+}
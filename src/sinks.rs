@@ -0,0 +1,782 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use serde_json::json;
+
+#[cfg(feature = "alertmanager")]
+use crate::alertmanager::{Alertmanager, AlertmanagerConfig};
+#[cfg(feature = "apm")]
+use crate::apm::{ApmConfig, ApmEvents};
+#[cfg(feature = "archive")]
+use crate::archive::{Archive, ArchiveConfig};
+#[cfg(feature = "core_dump")]
+use crate::core_dump::{CoreDumpConfig, CoreDumps};
+#[cfg(feature = "elasticsearch")]
+use crate::elasticsearch::{Elasticsearch, ElasticsearchConfig};
+#[cfg(feature = "gitops")]
+use crate::gitops::{self, GitOpsConfig};
+#[cfg(feature = "jira")]
+use crate::jira::{Jira, JiraConfig};
+#[cfg(feature = "loki")]
+use crate::loki::{Loki, LokiConfig};
+#[cfg(feature = "prometheus")]
+use crate::prometheus::{Prometheus, PrometheusConfig};
+#[cfg(feature = "syslog")]
+use crate::syslog::{Syslog, SyslogConfig};
+use crate::{
+    history::{NotificationRecord, RecentNotifications},
+    hooks::Hooks,
+    message::{self, ContainerRestartInfo},
+    metrics,
+    middleware::MiddlewareChain,
+    pod_annotations::PodAnnotator,
+    pod_events::{EventTarget, NotificationOutcome, PodEvents},
+    queue::{DiskQueue, NotificationReceiver},
+    rate_limit::RateLimiter,
+    self_alert::{Component, SelfAlert},
+    severity::{SeverityClassifier, SeverityConfig, Sink},
+    template::MessageTemplates,
+};
+
+/// Interval to post or log summaries of notifications dropped by rate limiting
+pub const RATE_LIMIT_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Destination of notifications
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotifierKind {
+    /// Posts notifications to Slack with `slack_send`. Requires the `slack` feature.
+    #[default]
+    Slack,
+    /// Renders messages and log files to logs without network calls, e.g. in staging
+    Log,
+    /// Sends notifications to Alertmanager instead of Slack.
+    /// Other messages are posted to Slack if the token is set, or logged otherwise.
+    Alertmanager,
+}
+
+impl NotifierKind {
+    /// Name in `NotifiedSlack` and other reasons of pod Events
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Slack => "Slack",
+            Self::Log => "Log",
+            Self::Alertmanager => "Alertmanager",
+        }
+    }
+}
+
+impl std::str::FromStr for NotifierKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slack" => Ok(Self::Slack),
+            "log" => Ok(Self::Log),
+            "alertmanager" => Ok(Self::Alertmanager),
+            _ => bail!("Invalid notifier: {s}"),
+        }
+    }
+}
+
+/// Configuration of the notifier, middlewares and other destinations of notifications,
+/// shared by `slack_send` and `send`
+pub struct SinksConfig {
+    pub notifier: NotifierKind,
+    /// Applied to notifications before sending
+    pub middlewares: MiddlewareChain,
+    /// Severities computed for restarts and how each of them is notified
+    pub severity: Option<SeverityConfig>,
+    /// Layouts of messages selected per rule with the `template` option
+    pub templates: MessageTemplates,
+    /// Object storage to archive crash reports in addition to the notifier
+    #[cfg(feature = "archive")]
+    pub archive: Option<ArchiveConfig>,
+    /// Loki to push crash logs to in addition to the notifier
+    #[cfg(feature = "loki")]
+    pub loki: Option<LokiConfig>,
+    /// Elasticsearch or OpenSearch to index restart events to
+    #[cfg(feature = "elasticsearch")]
+    pub elasticsearch: Option<ElasticsearchConfig>,
+    /// Jira to track repeatedly crashing containers as issues
+    #[cfg(feature = "jira")]
+    pub jira: Option<JiraConfig>,
+    /// Alertmanager to send restarts to, alongside the notifier unless
+    /// `NotifierKind::Alertmanager`
+    #[cfg(feature = "alertmanager")]
+    pub alertmanager: Option<AlertmanagerConfig>,
+    /// New Relic or another APM to emit restart events to
+    #[cfg(feature = "apm")]
+    pub apm: Option<ApmConfig>,
+    /// Syslog receiver of a SIEM to send CEF records of restarts to
+    #[cfg(feature = "syslog")]
+    pub syslog: Option<SyslogConfig>,
+    /// Argo CD or Flux to link the application managing the pod from messages
+    #[cfg(feature = "gitops")]
+    pub gitops: Option<GitOpsConfig>,
+    /// Prometheus to run a query about the container before notifying
+    #[cfg(feature = "prometheus")]
+    pub prometheus: Option<PrometheusConfig>,
+    /// Directory of a core dump handler to link dumps of crashes from messages
+    #[cfg(feature = "core_dump")]
+    pub core_dump: Option<CoreDumpConfig>,
+}
+
+impl SinksConfig {
+    /// Reads configuration from environment variables.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let notifier = match std::env::var("NOTIFIER") {
+            Ok(notifier) => notifier.parse().context("Invalid NOTIFIER")?,
+            Err(_) => NotifierKind::default(),
+        };
+        #[cfg(not(feature = "slack"))]
+        if notifier == NotifierKind::Slack {
+            bail!("NOTIFIER=slack, the default, requires the `slack` feature");
+        }
+        #[cfg(feature = "alertmanager")]
+        let alertmanager = AlertmanagerConfig::from_env()?;
+        #[cfg(feature = "alertmanager")]
+        if notifier == NotifierKind::Alertmanager && alertmanager.is_none() {
+            bail!("ALERTMANAGER_URL is required with NOTIFIER=alertmanager");
+        }
+        #[cfg(not(feature = "alertmanager"))]
+        if notifier == NotifierKind::Alertmanager || std::env::var("ALERTMANAGER_URL").is_ok() {
+            bail!("NOTIFIER=alertmanager and ALERTMANAGER_URL require the `alertmanager` feature");
+        }
+        let mut middlewares = match std::env::var("NOTIFICATION_MIDDLEWARES") {
+            Ok(middlewares) => middlewares
+                .parse::<MiddlewareChain>()
+                .context("Invalid NOTIFICATION_MIDDLEWARES")?,
+            Err(_) => MiddlewareChain::default(),
+        };
+        let severity = SeverityConfig::from_env()?;
+        // Applied before other middlewares, which may refer to severities
+        if let Some(severity) = &severity {
+            middlewares.prepend(SeverityClassifier::new(severity.clone()));
+        }
+        // Applied after other middlewares
+        if let Ok(limit) = std::env::var("NOTIFICATION_RATE_LIMIT") {
+            match limit.parse().context("Invalid NOTIFICATION_RATE_LIMIT")? {
+                0 => bail!("NOTIFICATION_RATE_LIMIT must be at least 1"),
+                limit => middlewares.push(RateLimiter::new(limit)),
+            }
+        }
+        #[cfg(feature = "archive")]
+        let archive = ArchiveConfig::from_env()?;
+        #[cfg(not(feature = "archive"))]
+        if std::env::var("ARCHIVE_BUCKET").is_ok() {
+            bail!("ARCHIVE_BUCKET requires the `archive` feature");
+        }
+        #[cfg(feature = "loki")]
+        let loki = LokiConfig::from_env()?;
+        #[cfg(not(feature = "loki"))]
+        if std::env::var("LOKI_URL").is_ok() {
+            bail!("LOKI_URL requires the `loki` feature");
+        }
+        #[cfg(feature = "elasticsearch")]
+        let elasticsearch = ElasticsearchConfig::from_env()?;
+        #[cfg(not(feature = "elasticsearch"))]
+        if std::env::var("ELASTICSEARCH_URL").is_ok() {
+            bail!("ELASTICSEARCH_URL requires the `elasticsearch` feature");
+        }
+        #[cfg(feature = "jira")]
+        let jira = JiraConfig::from_env()?;
+        #[cfg(not(feature = "jira"))]
+        if std::env::var("JIRA_URL").is_ok() {
+            bail!("JIRA_URL requires the `jira` feature");
+        }
+        #[cfg(feature = "apm")]
+        let apm = ApmConfig::from_env()?;
+        #[cfg(not(feature = "apm"))]
+        if std::env::var("NEW_RELIC_ACCOUNT_ID").is_ok() || std::env::var("APM_EVENT_URL").is_ok() {
+            bail!("NEW_RELIC_ACCOUNT_ID and APM_EVENT_URL require the `apm` feature");
+        }
+        #[cfg(feature = "syslog")]
+        let syslog = SyslogConfig::from_env()?;
+        #[cfg(not(feature = "syslog"))]
+        if std::env::var("SYSLOG_ADDRESS").is_ok() {
+            bail!("SYSLOG_ADDRESS requires the `syslog` feature");
+        }
+        #[cfg(feature = "gitops")]
+        let gitops = GitOpsConfig::from_env()?;
+        #[cfg(not(feature = "gitops"))]
+        if std::env::var("ARGOCD_URL").is_ok() || std::env::var("FLUX_URL_TEMPLATE").is_ok() {
+            bail!("ARGOCD_URL and FLUX_URL_TEMPLATE require the `gitops` feature");
+        }
+        #[cfg(feature = "prometheus")]
+        let prometheus = PrometheusConfig::from_env()?;
+        #[cfg(not(feature = "prometheus"))]
+        if std::env::var("PROMETHEUS_URL").is_ok() {
+            bail!("PROMETHEUS_URL requires the `prometheus` feature");
+        }
+        #[cfg(feature = "core_dump")]
+        let core_dump = CoreDumpConfig::from_env()?;
+        #[cfg(not(feature = "core_dump"))]
+        if std::env::var("CORE_DUMP_DIR").is_ok() {
+            bail!("CORE_DUMP_DIR requires the `core_dump` feature");
+        }
+        Ok(Self {
+            notifier,
+            middlewares,
+            severity,
+            templates: MessageTemplates::from_env()?,
+            #[cfg(feature = "archive")]
+            archive,
+            #[cfg(feature = "loki")]
+            loki,
+            #[cfg(feature = "elasticsearch")]
+            elasticsearch,
+            #[cfg(feature = "jira")]
+            jira,
+            #[cfg(feature = "alertmanager")]
+            alertmanager,
+            #[cfg(feature = "apm")]
+            apm,
+            #[cfg(feature = "syslog")]
+            syslog,
+            #[cfg(feature = "gitops")]
+            gitops,
+            #[cfg(feature = "prometheus")]
+            prometheus,
+            #[cfg(feature = "core_dump")]
+            core_dump,
+        })
+    }
+
+    /// Configuration with `notifier` and without other destinations
+    pub fn new(notifier: NotifierKind) -> Self {
+        Self {
+            notifier,
+            middlewares: MiddlewareChain::default(),
+            severity: None,
+            templates: MessageTemplates::default(),
+            #[cfg(feature = "archive")]
+            archive: None,
+            #[cfg(feature = "loki")]
+            loki: None,
+            #[cfg(feature = "elasticsearch")]
+            elasticsearch: None,
+            #[cfg(feature = "jira")]
+            jira: None,
+            #[cfg(feature = "alertmanager")]
+            alertmanager: None,
+            #[cfg(feature = "apm")]
+            apm: None,
+            #[cfg(feature = "syslog")]
+            syslog: None,
+            #[cfg(feature = "gitops")]
+            gitops: None,
+            #[cfg(feature = "prometheus")]
+            prometheus: None,
+            #[cfg(feature = "core_dump")]
+            core_dump: None,
+        }
+    }
+}
+
+/// Notifier other than Slack and other destinations of notifications, which also add
+/// links and notes to notifications before they are sent
+pub struct Sinks {
+    notifier: NotifierKind,
+    severity: Option<SeverityConfig>,
+    templates: MessageTemplates,
+    #[cfg(feature = "archive")]
+    archive: Option<Archive>,
+    #[cfg(feature = "loki")]
+    loki: Option<Loki>,
+    #[cfg(feature = "elasticsearch")]
+    elasticsearch: Option<Elasticsearch>,
+    #[cfg(feature = "jira")]
+    jira: Option<Jira>,
+    #[cfg(feature = "alertmanager")]
+    alertmanager: Option<Alertmanager>,
+    #[cfg(feature = "apm")]
+    apm: Option<ApmEvents>,
+    #[cfg(feature = "syslog")]
+    syslog: Option<Syslog>,
+    #[cfg(feature = "gitops")]
+    gitops: Option<GitOpsConfig>,
+    #[cfg(feature = "prometheus")]
+    prometheus: Option<Prometheus>,
+    #[cfg(feature = "core_dump")]
+    core_dumps: Option<CoreDumps>,
+}
+
+impl Sinks {
+    /// Clients of the destinations in `config`. Middlewares are not used.
+    pub fn new(config: SinksConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            notifier: config.notifier,
+            severity: config.severity,
+            templates: config.templates,
+            #[cfg(feature = "archive")]
+            archive: config.archive.map(Archive::new),
+            #[cfg(feature = "loki")]
+            loki: config.loki.map(Loki::new),
+            #[cfg(feature = "elasticsearch")]
+            elasticsearch: config.elasticsearch.map(Elasticsearch::new),
+            #[cfg(feature = "jira")]
+            jira: config.jira.map(Jira::new),
+            #[cfg(feature = "alertmanager")]
+            alertmanager: config.alertmanager.map(Alertmanager::new),
+            #[cfg(feature = "apm")]
+            apm: config.apm.map(ApmEvents::new),
+            #[cfg(feature = "syslog")]
+            syslog: config.syslog.map(Syslog::new).transpose()?,
+            #[cfg(feature = "gitops")]
+            gitops: config.gitops,
+            #[cfg(feature = "prometheus")]
+            prometheus: config.prometheus.map(Prometheus::new),
+            #[cfg(feature = "core_dump")]
+            core_dumps: config.core_dump.map(CoreDumps::new),
+        })
+    }
+
+    pub fn notifier(&self) -> NotifierKind {
+        self.notifier
+    }
+
+    pub fn templates(&self) -> &MessageTemplates {
+        &self.templates
+    }
+
+    /// Whether the notifier is among the destinations of the severity of `restart_info`
+    pub fn notifies(&self, restart_info: &ContainerRestartInfo) -> bool {
+        self.enabled(restart_info, Sink::Notifier)
+    }
+
+    /// Whether `sink` is among the destinations of the severity of `restart_info`.
+    /// All destinations are enabled for restarts without severities.
+    fn enabled(&self, restart_info: &ContainerRestartInfo, sink: Sink) -> bool {
+        match self
+            .severity
+            .as_ref()
+            .and_then(|severity| severity.sinks(restart_info))
+        {
+            Some(sinks) => sinks.contains(&sink),
+            None => true,
+        }
+    }
+
+    /// Adds links and notes from GitOps, Prometheus, core dumps and Jira before notifying
+    pub async fn enrich(&self, restart_info: &mut ContainerRestartInfo) {
+        #[cfg(feature = "gitops")]
+        if let Some(link) = self
+            .gitops
+            .as_ref()
+            .and_then(|gitops| gitops::application_link(restart_info, gitops))
+        {
+            restart_info.links.push(link);
+        }
+        // Before notifying to embed the result in the message
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = &self.prometheus {
+            match prometheus.query(restart_info).await {
+                Ok(Some(link)) => restart_info.links.push(link),
+                Ok(None) => {}
+                Err(e) => log::warn!("Failed to query Prometheus for {restart_info}: {e:#}"),
+            }
+            match prometheus.oom_headroom(restart_info).await {
+                Ok(Some(note)) => restart_info.notes.push(note),
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!("Failed to query memory usage of {restart_info}: {e:#}")
+                }
+            }
+        }
+        #[cfg(feature = "core_dump")]
+        if let Some(core_dumps) = &self.core_dumps {
+            match core_dumps.find(restart_info).await {
+                Ok(links) => restart_info.links.extend(links),
+                Err(e) => log::warn!("Failed to find core dumps of {restart_info}: {e:#}"),
+            }
+        }
+        // Before notifying to link the issue from the message
+        #[cfg(feature = "jira")]
+        if let Some(jira) = &self.jira {
+            match jira.track(restart_info).await {
+                Ok(Some(link)) => restart_info.links.push(link),
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to track {restart_info} in Jira: {e:#}"),
+            }
+        }
+        #[cfg(not(any(
+            feature = "core_dump",
+            feature = "gitops",
+            feature = "jira",
+            feature = "prometheus"
+        )))]
+        let _ = restart_info;
+    }
+
+    /// Sends the notification with `NotifierKind::Log` or `NotifierKind::Alertmanager`.
+    /// Notifications to Slack are posted by `slack_send`.
+    pub async fn notify(&self, restart_info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        match self.notifier {
+            NotifierKind::Slack => bail!("Notifications to Slack are posted by `slack_send`"),
+            NotifierKind::Log => {
+                log_notification(restart_info, &self.templates);
+                Ok(())
+            }
+            #[cfg(feature = "alertmanager")]
+            NotifierKind::Alertmanager => match &self.alertmanager {
+                Some(alertmanager) => alertmanager.send(restart_info).await,
+                None => bail!("Alertmanager is not configured"),
+            },
+            // Rejected by `SinksConfig::from_env`
+            #[cfg(not(feature = "alertmanager"))]
+            NotifierKind::Alertmanager => bail!("Alertmanager requires the `alertmanager` feature"),
+        }
+    }
+
+    /// Sends the crash report to the configured destinations other than the notifier,
+    /// restricted to the destinations of its severity if any. Failures are only logged
+    /// because the notifier is the primary destination.
+    pub async fn export(&self, restart_info: &ContainerRestartInfo) {
+        #[cfg(feature = "archive")]
+        let archive = async {
+            let Some(archive) = self
+                .archive
+                .as_ref()
+                .filter(|_| self.enabled(restart_info, Sink::Archive))
+            else {
+                return;
+            };
+            if let Err(e) = archive.upload(restart_info).await {
+                log::error!("Failed to archive crash report of {restart_info}: {e:#}");
+            }
+        };
+        #[cfg(not(feature = "archive"))]
+        let archive = async {};
+        #[cfg(feature = "loki")]
+        let loki = async {
+            let Some(loki) = self
+                .loki
+                .as_ref()
+                .filter(|_| self.enabled(restart_info, Sink::Loki))
+            else {
+                return;
+            };
+            if let Err(e) = loki.push(restart_info).await {
+                log::error!("Failed to push logs of {restart_info} to Loki: {e:#}");
+            }
+        };
+        #[cfg(not(feature = "loki"))]
+        let loki = async {};
+        #[cfg(feature = "elasticsearch")]
+        let elasticsearch = async {
+            let Some(elasticsearch) = self
+                .elasticsearch
+                .as_ref()
+                .filter(|_| self.enabled(restart_info, Sink::Elasticsearch))
+            else {
+                return;
+            };
+            if let Err(e) = elasticsearch.index(restart_info).await {
+                log::error!("Failed to index restart of {restart_info} to Elasticsearch: {e:#}");
+            }
+        };
+        #[cfg(not(feature = "elasticsearch"))]
+        let elasticsearch = async {};
+        #[cfg(feature = "alertmanager")]
+        let alertmanager = async {
+            // Sent as the notification itself with `NotifierKind::Alertmanager`
+            let Some(alertmanager) = self
+                .alertmanager
+                .as_ref()
+                .filter(|_| self.notifier != NotifierKind::Alertmanager)
+                .filter(|_| self.enabled(restart_info, Sink::Alertmanager))
+            else {
+                return;
+            };
+            if let Err(e) = alertmanager.send(restart_info).await {
+                log::error!("Failed to send {restart_info} to Alertmanager: {e:#}");
+            }
+        };
+        #[cfg(not(feature = "alertmanager"))]
+        let alertmanager = async {};
+        #[cfg(feature = "apm")]
+        let apm = async {
+            let Some(apm) = self
+                .apm
+                .as_ref()
+                .filter(|_| self.enabled(restart_info, Sink::Apm))
+            else {
+                return;
+            };
+            if let Err(e) = apm.emit(restart_info).await {
+                log::error!("Failed to emit APM event of {restart_info}: {e:#}");
+            }
+        };
+        #[cfg(not(feature = "apm"))]
+        let apm = async {};
+        #[cfg(feature = "syslog")]
+        let syslog = async {
+            let Some(syslog) = self
+                .syslog
+                .as_ref()
+                .filter(|_| self.enabled(restart_info, Sink::Syslog))
+            else {
+                return;
+            };
+            if let Err(e) = syslog.send(restart_info).await {
+                log::error!("Failed to send {restart_info} to syslog: {e:#}");
+            }
+        };
+        #[cfg(not(feature = "syslog"))]
+        let syslog = async {};
+        #[cfg(not(any(
+            feature = "alertmanager",
+            feature = "apm",
+            feature = "archive",
+            feature = "elasticsearch",
+            feature = "loki",
+            feature = "syslog"
+        )))]
+        let _ = restart_info;
+        tokio::join!(archive, loki, elasticsearch, alertmanager, apm, syslog);
+    }
+}
+
+/// Logs the messages and log files of a notification as they would be posted to Slack,
+/// without network calls. Mentions are not resolved and messages are not updated.
+fn log_notification(restart_info: &ContainerRestartInfo, templates: &MessageTemplates) {
+    let files = restart_info.log_files();
+    // Titles stand in for the URLs of uploaded files
+    let file_urls = files
+        .iter()
+        .map(|(title, _)| title.clone())
+        .collect::<Vec<_>>();
+    let mut blocks = templates.notification_blocks(restart_info, &file_urls);
+    if !restart_info.attachments.is_empty() {
+        // Filenames stand in for the URLs of uploaded attachments
+        let attachments = restart_info
+            .attachments
+            .iter()
+            .map(|a| (a.title.clone(), a.filename.clone()))
+            .collect::<Vec<_>>();
+        blocks.push(message::attachments_context(&attachments));
+    }
+    let mentions = restart_info
+        .options
+        .mentions(restart_info.restart_count)
+        .iter()
+        .map(|handle| format!("@{handle}"))
+        .collect::<Vec<_>>();
+    if !mentions.is_empty() {
+        blocks.insert(0, message::mention_block(&mentions.join(" ")));
+    }
+    let channel = &restart_info.channel;
+    log::info!(
+        "Message to {channel}: {}",
+        json!({
+            "channel": channel,
+            "blocks": blocks,
+            "metadata": restart_info.to_metadata(),
+        })
+    );
+    if restart_info.options.thread_logs {
+        log::info!(
+            "Thread message to {channel}: {}",
+            json!({
+                "channel": channel,
+                "blocks": restart_info.to_log_message(&file_urls),
+            })
+        );
+    }
+    let options = &restart_info.options;
+    if let (Some(after), Some(escalate_channel)) =
+        (options.escalate_after, &options.escalate_channel)
+    {
+        if options.escalated(restart_info.restart_count) {
+            let mut blocks = restart_info.to_escalation_message(after, None);
+            if let Some(handle) = &options.escalate_mention {
+                blocks.insert(0, message::mention_block(&format!("@{handle}")));
+            }
+            log::info!(
+                "Escalation message to {escalate_channel}: {}",
+                json!({ "channel": escalate_channel, "blocks": blocks })
+            );
+        }
+    }
+    for (title, content) in files {
+        let compression = if restart_info.options.gzip_logs {
+            ", gzip-compressed when posted"
+        } else {
+            ""
+        };
+        log::info!("File {title}{compression}:\n{content}");
+    }
+    for attachment in &restart_info.attachments {
+        log::info!("File {}:\n{}", attachment.filename, attachment.content);
+    }
+}
+
+/// Records of notification outcomes, shared by notifiers
+#[derive(Default)]
+pub struct SinkStores {
+    pub recent_notifications: RecentNotifications,
+    /// Called after each notification, e.g. to record the outcome in `CrashStore`
+    pub hooks: Option<Arc<dyn Hooks>>,
+    /// Records notification outcomes as Events on the pods
+    pub pod_events: Option<PodEvents>,
+    /// Annotates pods or workloads with the last notification
+    pub pod_annotator: Option<PodAnnotator>,
+}
+
+impl SinkStores {
+    /// Records that a middleware dropped the notification to `target`
+    pub fn dropped(&self, target: Option<EventTarget>) {
+        if let Some((pod_events, target)) = self.pod_events.as_ref().zip(target) {
+            pod_events.publish(target, NotificationOutcome::Dropped);
+        }
+    }
+
+    /// Records the outcome of the notification sent by `notifier`.
+    /// Pods are annotated with `permalink` of the message, if any, when it was sent.
+    pub async fn record(
+        &self,
+        restart_info: &ContainerRestartInfo,
+        result: &anyhow::Result<()>,
+        notifier: NotifierKind,
+        permalink: Option<String>,
+    ) {
+        if let Some(annotator) = self.pod_annotator.as_ref().filter(|_| result.is_ok()) {
+            annotator.annotate(restart_info, permalink);
+        }
+        let record = NotificationRecord::new(restart_info);
+        if let Some(hooks) = &self.hooks {
+            hooks.after_send(&record, result).await;
+        }
+        if let Some(pod_events) = &self.pod_events {
+            let channel = &restart_info.channel;
+            let outcome = match result {
+                Ok(()) => NotificationOutcome::Sent {
+                    notifier: notifier.name(),
+                    channel,
+                },
+                Err(e) => NotificationOutcome::Failed {
+                    channel,
+                    error: e.to_string(),
+                },
+            };
+            pod_events.publish(EventTarget::of_restart(restart_info), outcome);
+        }
+        if result.is_ok() {
+            self.recent_notifications.record(record);
+        }
+    }
+}
+
+/// Task to send notifications by `NotifierKind::Log` or `NotifierKind::Alertmanager`
+/// one by one, used instead of `slack_send` without the `slack` feature.
+/// Notifications pass `config.middlewares` in order before sending, and those dropped by
+/// rate limiting are logged as a summary.
+/// Processed notifications are removed from `disk_queue`.
+pub async fn send(
+    mut config: SinksConfig,
+    stores: SinkStores,
+    self_alert: SelfAlert,
+    disk_queue: Option<DiskQueue>,
+    mut rx: NotificationReceiver,
+) -> anyhow::Result<()> {
+    let mut middlewares = std::mem::take(&mut config.middlewares);
+    let sinks = Sinks::new(config)?;
+    let mut summary_interval = tokio::time::interval(RATE_LIMIT_SUMMARY_INTERVAL);
+    loop {
+        let restart_info = tokio::select! {
+            restart_info = rx.recv() => match restart_info {
+                Some(restart_info) => restart_info,
+                None => break,
+            },
+            _ = summary_interval.tick() => {
+                log_rate_limited(&mut middlewares);
+                continue;
+            }
+        };
+        for (channel, count) in rx.take_dropped() {
+            log::warn!("Dropped {count} notifications to {channel}");
+        }
+        let queue_id = restart_info.queue_id;
+        let target = stores
+            .pod_events
+            .as_ref()
+            .map(|_| EventTarget::of_restart(&restart_info));
+        let Some(mut restart_info) = middlewares.process(restart_info) else {
+            if let Some((disk_queue, id)) = disk_queue.as_ref().zip(queue_id) {
+                disk_queue.remove_id(id);
+            }
+            stores.dropped(target);
+            continue;
+        };
+        sinks.enrich(&mut restart_info).await;
+        let notify = async {
+            if !sinks.notifies(&restart_info) {
+                log::debug!("Not notifying by the severity: {restart_info}");
+                return Ok(());
+            }
+            sinks.notify(&restart_info).await
+        };
+        let (result, ()) = tokio::join!(notify, sinks.export(&restart_info));
+        stores
+            .record(&restart_info, &result, sinks.notifier(), None)
+            .await;
+        match result {
+            Ok(()) => {
+                metrics::notification_sent();
+                self_alert.success(Component::Notifier);
+            }
+            Err(e) => {
+                metrics::notification_failed();
+                log::error!("Failed to send notification: {e}");
+                self_alert.failure(Component::Notifier, &e);
+            }
+        }
+        if let Some(disk_queue) = &disk_queue {
+            disk_queue.remove(&restart_info);
+        }
+    }
+    log_rate_limited(&mut middlewares);
+    Ok(())
+}
+
+/// Logs summaries of restarts not notified by the rate limit to each channel.
+fn log_rate_limited(middlewares: &mut MiddlewareChain) {
+    for (channel, restarts) in middlewares.take_summarized() {
+        log::warn!(
+            "{} restarts to {channel} were not notified by the rate limit: {}",
+            restarts.values().sum::<usize>(),
+            restarts.keys().cloned().collect::<Vec<_>>().join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send() {
+        let stores = SinkStores::default();
+        let recent_notifications = stores.recent_notifications.clone();
+        let (tx, rx) = crate::queue::channel(1, Default::default(), None, Vec::new());
+        tx.send(ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            "thread_logs;gzip_logs".parse().unwrap(),
+            "test",
+        ))
+        .await
+        .unwrap();
+        drop(tx);
+        // Sent without network calls
+        let config = SinksConfig::new(NotifierKind::Log);
+        send(config, stores, SelfAlert::default(), None, rx)
+            .await
+            .unwrap();
+        let records = recent_notifications.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].channel, "#alerts");
+    }
+}
@@ -1,73 +1,1292 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
 use anyhow::{bail, Context};
+use flate2::{write::GzEncoder, Compression};
+use k8s_openapi::chrono::Utc;
 use serde_json::json;
+use sha2::{Digest, Sha256};
 use tokio::sync::mpsc;
+use tracing::Instrument;
+
+use crate::{
+    channels,
+    file_store::{FileStore, RecentUploads, UploadedFile, DEFAULT_FILE_REUSE_WINDOW},
+    health::Health,
+    http, message,
+    message_store::{MessageStore, PostedMessage},
+    metrics,
+    middleware::MiddlewareChain,
+    node_correlation::{NodeAlert, NodeCorrelation, NodeCorrelationConfig},
+    pod_events::EventTarget,
+    queue::{DiskQueue, NotificationReceiver},
+    self_alert::{Component, SelfAlert},
+    silence,
+    sinks::{NotifierKind, SinkStores, Sinks, SinksConfig, RATE_LIMIT_SUMMARY_INTERVAL},
+    stability::StabilityTracker,
+    startup_logs::StartupLogs,
+    template::MessageTemplates,
+};
+
+/// Maximum number of retries when Slack API is rate limited
+const RATE_LIMIT_MAX_RETRIES: usize = 5;
+
+/// Wait time when `Retry-After` header is missing in a rate limited response
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Upper limit of `Retry-After` not to hold notifications for long
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Default interval to check the reachability of Slack and the token after startup
+pub const DEFAULT_SLACK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default period for which Slack fails the checks before the readiness probe fails
+pub const DEFAULT_SLACK_UNREACHABLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Interval to check `SLACK_TOKEN_FILE` for a rotated token
+const TOKEN_FILE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum wait time for `SLACK_TOKEN_FILE` to be updated after the token was revoked.
+/// The kubelet updates mounted secrets up to about a minute after they change.
+const TOKEN_ROTATION_WAIT: Duration = Duration::from_secs(90);
+
+/// Interval to re-read `SLACK_TOKEN_FILE` while waiting for the rotated token
+const TOKEN_ROTATION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Default timeout to connect to Slack
+pub const DEFAULT_SLACK_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default timeout of each request to Slack, including file uploads
+pub const DEFAULT_SLACK_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default deadline to post each notification, including retries and the wait for
+/// a rotated token
+pub const DEFAULT_NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
-use crate::message;
+/// Bot token scopes required to post notifications.
+/// Each entry is satisfied by any of its scopes.
+const REQUIRED_SCOPES: [&[&str]; 2] = [&["chat:write", "chat:write.public"], &["files:write"]];
 
+/// Default number of notifications sent to Slack concurrently
+pub const DEFAULT_SLACK_SENDERS: usize = 4;
+
+/// Capacity of the queue of each sender.
+/// Kept small so that the notification queue reflects the backlog.
+const SENDER_QUEUE_CAPACITY: usize = 8;
+
+/// Interval to delete expired files
+const FILE_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Period to cache user groups
+const USERGROUP_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum interval to refetch user groups when a handle is not found
+const USERGROUP_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+const LIST_USERGROUPS_URL: &str = "https://slack.com/api/usergroups.list";
+const AUTH_TEST_URL: &str = "https://slack.com/api/auth.test";
 const POST_MESSAGE_URL: &str = "https://slack.com/api/chat.postMessage";
+const UPDATE_MESSAGE_URL: &str = "https://slack.com/api/chat.update";
+const GET_PERMALINK_URL: &str = "https://slack.com/api/chat.getPermalink";
+const JOIN_CONVERSATION_URL: &str = "https://slack.com/api/conversations.join";
+const LIST_CONVERSATIONS_URL: &str = "https://slack.com/api/conversations.list";
+const DELETE_FILE_URL: &str = "https://slack.com/api/files.delete";
 const GET_UPLOAD_URL: &str = "https://slack.com/api/files.getUploadURLExternal";
 const COMPLETE_UPLOAD_URL: &str = "https://slack.com/api/files.completeUploadExternal";
 
-/// Task to send messages to Slack channel
+/// Validates `slack_token` with `auth.test` API and checks its permission scopes.
+pub async fn validate_token(slack: &reqwest::Client, slack_token: &str) -> anyhow::Result<()> {
+    let resp = send_request(slack.post(AUTH_TEST_URL).bearer_auth(slack_token))
+        .await
+        .context("Failed to connect to Slack")?;
+    let scopes = resp
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let resp = parse_slack_response(resp)
+        .await
+        .context("Invalid SLACK_TOKEN")?;
+    let field = |name| resp.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+    log::info!(
+        "Authenticated to Slack: user={} ({}), team={}, scopes={}",
+        field("user"),
+        field("user_id"),
+        field("team"),
+        scopes,
+    );
+
+    let missing = missing_scopes(&scopes);
+    if !missing.is_empty() {
+        bail!(
+            "SLACK_TOKEN is missing required scopes: {}. Add them to the Slack app and reinstall it.",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn missing_scopes(scopes: &str) -> Vec<&'static str> {
+    let scopes = scopes.split(',').map(str::trim).collect::<Vec<_>>();
+    REQUIRED_SCOPES
+        .into_iter()
+        .filter(|required| !required.iter().any(|r| scopes.contains(r)))
+        .map(|required| required[0])
+        .collect()
+}
+
+/// Task to check every `interval` that Slack is reachable and accepts the token, so that
+/// delivery outages are noticed before a notification fails.
+/// A rejected token is read again from `slack_token_file`, Vault or cloud secret managers,
+/// and the check is retried with the rotated token.
+/// Failures are reported to `health` for the readiness probe once Slack has been
+/// unavailable for `unreachable_timeout`.
+pub async fn check_periodically(
+    poster: SlackPoster,
+    slack_token_file: Option<PathBuf>,
+    health: Health,
+    interval: Duration,
+    unreachable_timeout: Duration,
+) {
+    let mut failing_since = None;
+    loop {
+        tokio::time::sleep(interval).await;
+        let token = poster.slack_token.get();
+        let mut result = poster.validate_token().await;
+        if result.as_ref().is_err_and(is_revoked_token) {
+            log::warn!("Slack token was rejected, waiting for the rotated token");
+            poster.slack_token.reject();
+            let path = slack_token_file.as_deref();
+            if wait_rotated_token(path, &poster.slack_token, &token)
+                .await
+                .is_some()
+            {
+                result = poster.validate_token().await;
+            }
+        }
+        match result {
+            Ok(()) => {
+                if let Some(since) = failing_since.take() {
+                    let elapsed = Instant::now().duration_since(since);
+                    log::info!(
+                        "Slack is available again after {} seconds",
+                        elapsed.as_secs()
+                    );
+                }
+                health.slack_result(&Ok(()));
+            }
+            Err(e) => {
+                log::error!("Slack check failed: {e:#}");
+                let since = *failing_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= unreachable_timeout {
+                    health.slack_result(&Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Reads the token in a mounted secret file, ignoring the trailing newline
+fn read_token_file(path: &Path) -> anyhow::Result<String> {
+    let token = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let token = token.trim();
+    if token.is_empty() {
+        bail!("{} is empty", path.display());
+    }
+    Ok(token.to_owned())
+}
+
+/// Task to re-read `path` of `SLACK_TOKEN_FILE` and replace `slack_token` when the secret
+/// is rotated. Secret volumes are updated by the kubelet without restarting the pod.
+pub async fn watch_token_file(path: PathBuf, slack_token: SlackToken) {
+    loop {
+        tokio::time::sleep(TOKEN_FILE_POLL_INTERVAL).await;
+        match read_token_file(&path) {
+            Ok(token) if token != slack_token.get() => {
+                slack_token.apply_rotated("SLACK_TOKEN", token, "SLACK_TOKEN_FILE");
+            }
+            Ok(_) => {}
+            // Keeps the current token, e.g. while the volume is being updated
+            Err(e) => log::warn!("Failed to read SLACK_TOKEN_FILE: {e:#}"),
+        }
+    }
+}
+
+/// Waits for a token other than `revoked` to be written in `path` of `SLACK_TOKEN_FILE`,
+/// or to replace `slack_token` otherwise, e.g. by `watch_token_file` or Vault.
+/// Returns `None` when the token is not rotated within `TOKEN_ROTATION_WAIT`.
+async fn wait_rotated_token(
+    path: Option<&Path>,
+    slack_token: &SlackToken,
+    revoked: &str,
+) -> Option<String> {
+    let deadline = Instant::now() + TOKEN_ROTATION_WAIT;
+    loop {
+        let current = slack_token.get();
+        if current != revoked {
+            return Some(current);
+        }
+        match path.map(read_token_file) {
+            Some(Ok(token)) if token != revoked => {
+                slack_token.apply_rotated("SLACK_TOKEN", token.clone(), "SLACK_TOKEN_FILE");
+                return Some(token);
+            }
+            Some(Err(e)) => log::warn!("Failed to read SLACK_TOKEN_FILE: {e:#}"),
+            _ => {}
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        tokio::time::sleep(TOKEN_ROTATION_POLL_INTERVAL).await;
+    }
+}
+
+/// Whether `err` is caused by a token which is no longer valid, e.g. revoked by rotation
+fn is_revoked_token(err: &anyhow::Error) -> bool {
+    matches!(
+        slack_error_code(err),
+        Some("token_revoked" | "token_expired" | "invalid_auth")
+    )
+}
+
+/// Configuration of `slack_send`
+pub struct SlackConfig {
+    /// Empty when not set with `NotifierKind::Log` or `NotifierKind::Alertmanager`
+    pub slack_token: SlackToken,
+    /// Mounted secret file the token is read from and re-read when it changes
+    pub slack_token_file: Option<PathBuf>,
+    /// Channel to post notifications when posting to the routed channel fails
+    pub fallback_channel: Option<String>,
+    /// Number of notifications sent concurrently
+    pub senders: usize,
+    /// Proxy of requests to Slack instead of `HTTPS_PROXY`
+    pub proxy: Option<reqwest::Proxy>,
+    /// Timeout to connect to Slack
+    pub connect_timeout: Duration,
+    /// Timeout of each request to Slack
+    pub request_timeout: Duration,
+    /// Deadline to post each notification, after which the sender moves on
+    pub notification_timeout: Duration,
+    /// Window to link log files uploaded for the same crash with the same logs instead of
+    /// uploading them again. `None` to always upload.
+    pub file_reuse_window: Option<k8s_openapi::chrono::Duration>,
+    /// Alerts bursts of restarts on the same node
+    pub node_correlation: Option<NodeCorrelationConfig>,
+    /// Resolves channel names to IDs and rejects unknown channels on startup
+    pub resolve_channels: bool,
+    /// Interval to check the reachability of Slack and the token
+    pub check_interval: Duration,
+    /// Period for which Slack fails the checks before the readiness probe fails
+    pub unreachable_timeout: Duration,
+}
+
+impl SlackConfig {
+    /// Reads configuration from environment variables.
+    /// The token is optional unless `notifier` is `NotifierKind::Slack`.
+    pub fn from_env(notifier: NotifierKind) -> anyhow::Result<Self> {
+        let slack_token_file = std::env::var("SLACK_TOKEN_FILE").ok().map(PathBuf::from);
+        let slack_token = SlackToken::new(match (&slack_token_file, notifier) {
+            (Some(_), _) if std::env::var("SLACK_TOKEN").is_ok() => {
+                bail!("Set either SLACK_TOKEN or SLACK_TOKEN_FILE")
+            }
+            (Some(path), _) => read_token_file(path).context("Invalid SLACK_TOKEN_FILE")?,
+            (None, NotifierKind::Slack) => std::env::var("SLACK_TOKEN")
+                .context("SLACK_TOKEN or SLACK_TOKEN_FILE is required")?,
+            (None, NotifierKind::Log | NotifierKind::Alertmanager) => {
+                std::env::var("SLACK_TOKEN").unwrap_or_default()
+            }
+        });
+        let fallback_channel = std::env::var("SLACK_FALLBACK_CHANNEL").ok();
+        let proxy = match std::env::var("SLACK_PROXY_URL") {
+            Ok(url) => Some(reqwest::Proxy::all(url).context("Invalid SLACK_PROXY_URL")?),
+            Err(_) => None,
+        };
+        let connect_timeout =
+            timeout_from_env("SLACK_CONNECT_TIMEOUT", DEFAULT_SLACK_CONNECT_TIMEOUT)?;
+        let request_timeout =
+            timeout_from_env("SLACK_REQUEST_TIMEOUT", DEFAULT_SLACK_REQUEST_TIMEOUT)?;
+        let notification_timeout =
+            timeout_from_env("NOTIFICATION_TIMEOUT", DEFAULT_NOTIFICATION_TIMEOUT)?;
+        let check_interval =
+            timeout_from_env("SLACK_CHECK_INTERVAL", DEFAULT_SLACK_CHECK_INTERVAL)?;
+        let unreachable_timeout = timeout_from_env(
+            "SLACK_UNREACHABLE_TIMEOUT",
+            DEFAULT_SLACK_UNREACHABLE_TIMEOUT,
+        )?;
+        let file_reuse_window =
+            parse_file_reuse_window(std::env::var("SLACK_FILE_REUSE_WINDOW").ok().as_deref())?;
+        let senders = match std::env::var("SLACK_SENDERS") {
+            Ok(senders) => senders.parse().context("Invalid SLACK_SENDERS")?,
+            Err(_) => DEFAULT_SLACK_SENDERS,
+        };
+        if senders == 0 {
+            bail!("SLACK_SENDERS must be at least 1");
+        }
+        let node_correlation = NodeCorrelationConfig::from_env()?;
+        let resolve_channels = match std::env::var("SLACK_RESOLVE_CHANNELS") {
+            Ok(enabled) => enabled.parse().context("Invalid SLACK_RESOLVE_CHANNELS")?,
+            Err(_) => false,
+        };
+        Ok(Self {
+            slack_token,
+            slack_token_file,
+            fallback_channel,
+            senders,
+            proxy,
+            connect_timeout,
+            request_timeout,
+            notification_timeout,
+            file_reuse_window,
+            node_correlation,
+            resolve_channels,
+            check_interval,
+            unreachable_timeout,
+        })
+    }
+
+    /// HTTP client of Slack API and webhooks, through `proxy` when set.
+    /// Otherwise `HTTPS_PROXY` is used unless the host is in `NO_PROXY`.
+    pub fn http_client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = http::client_builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// `SLACK_FILE_REUSE_WINDOW`, `None` with `0` or `0s` to always upload
+fn parse_file_reuse_window(
+    window: Option<&str>,
+) -> anyhow::Result<Option<k8s_openapi::chrono::Duration>> {
+    match window {
+        Some("0" | "0s") => Ok(None),
+        Some(window) => {
+            Ok(Some(silence::parse_duration(window).map_err(|e| {
+                anyhow::anyhow!("Invalid SLACK_FILE_REUSE_WINDOW: {e}")
+            })?))
+        }
+        None => Ok(Some(DEFAULT_FILE_REUSE_WINDOW)),
+    }
+}
+
+/// Duration in environment variable `name`, e.g. `30s`, or `default` when not set
+fn timeout_from_env(name: &str, default: Duration) -> anyhow::Result<Duration> {
+    let timeout = match std::env::var(name) {
+        Ok(timeout) => silence::parse_duration(&timeout)
+            .map_err(|e| anyhow::anyhow!("Invalid {name}: {e}"))?
+            .to_std()?,
+        Err(_) => default,
+    };
+    if timeout.is_zero() {
+        bail!("{name} must be positive");
+    }
+    Ok(timeout)
+}
+
+/// Task to send messages to Slack channel.
+/// Notifications are sent concurrently by `config.senders` senders, and those to the same
+/// channel are sent by the same sender in order.
+/// Notifications pass `sinks.middlewares` in order before sending, and those dropped by
+/// rate limiting are posted as a summary. They are also sent to the destinations in
+/// `sinks`, and by the notifier of `sinks` instead of Slack if it is not Slack.
+/// Processed notifications are removed from `disk_queue`.
 pub async fn slack_send(
-    slack_token: String,
+    config: SlackConfig,
+    mut sinks: SinksConfig,
+    stores: SlackStores,
+    self_alert: SelfAlert,
+    disk_queue: Option<DiskQueue>,
+    mut rx: NotificationReceiver,
+) -> anyhow::Result<()> {
+    let http = config.http_client()?;
+    let SlackConfig {
+        slack_token,
+        slack_token_file,
+        fallback_channel,
+        senders,
+        proxy: _,
+        connect_timeout: _,
+        request_timeout: _,
+        notification_timeout,
+        file_reuse_window,
+        node_correlation,
+        resolve_channels: _,
+        check_interval: _,
+        unreachable_timeout: _,
+    } = config;
+    let mut middlewares = std::mem::take(&mut sinks.middlewares);
+    let sinks = Sinks::new(sinks)?;
+    let ctx = Arc::new(SenderContext {
+        poster: SlackPoster::new(http, slack_token, sinks.notifier()),
+        slack_token_file,
+        notification_timeout,
+        fallback_channel,
+        self_alert,
+        disk_queue,
+        stores: stores.sinks,
+        stability: stores.stability,
+        startup_logs: stores.startup_logs,
+        sinks,
+    });
+    let message_store = Arc::new(Mutex::new(stores.message_store));
+
+    let mut queues = Vec::with_capacity(senders);
+    let mut handles = Vec::with_capacity(senders);
+    for _ in 0..senders {
+        let (tx, rx) = mpsc::channel(SENDER_QUEUE_CAPACITY);
+        let state = SenderState {
+            message_store: Arc::clone(&message_store),
+            file_store: stores.file_store.clone(),
+            recent_uploads: file_reuse_window.map(RecentUploads::new),
+            usergroups: UsergroupCache::default(),
+        };
+        queues.push(tx);
+        handles.push(tokio::spawn(sender(Arc::clone(&ctx), state, rx)));
+    }
+    let mut node_correlation = node_correlation.map(NodeCorrelation::new);
+    let mut summary_interval = tokio::time::interval(RATE_LIMIT_SUMMARY_INTERVAL);
+    loop {
+        let mut restart_info = tokio::select! {
+            restart_info = rx.recv() => match restart_info {
+                Some(restart_info) => restart_info,
+                None => break,
+            },
+            _ = summary_interval.tick() => {
+                post_rate_limited(&ctx, &mut middlewares).await;
+                continue;
+            }
+        };
+        for (channel, count) in rx.take_dropped() {
+            log::warn!("Dropped {count} notifications to {channel}");
+            let blocks = vec![message::dropped_notifications_block(count)];
+            if let Err(e) = ctx.poster.post_blocks(&channel, blocks).await {
+                log::error!("Failed to post dropped notifications to {channel}: {e}");
+            }
+        }
+        // Before middlewares to alert restarts on the node even if they are dropped
+        if let Some(alert) = node_correlation
+            .as_mut()
+            .and_then(|correlation| correlation.observe(&mut restart_info))
+        {
+            post_node_alert(&ctx, alert).await;
+        }
+        let queue_id = restart_info.queue_id;
+        let target = ctx
+            .stores
+            .pod_events
+            .as_ref()
+            .map(|_| EventTarget::of_restart(&restart_info));
+        let Some(restart_info) = middlewares.process(restart_info) else {
+            if let Some((disk_queue, id)) = ctx.disk_queue.as_ref().zip(queue_id) {
+                disk_queue.remove_id(id);
+            }
+            ctx.stores.dropped(target);
+            continue;
+        };
+        let queue = &queues[sender_index(&restart_info.channel, senders)];
+        if queue.send(restart_info).await.is_err() {
+            bail!("Slack sender stopped unexpectedly");
+        }
+    }
+    // Wait for the senders to process the remaining notifications
+    drop(queues);
+    for handle in handles {
+        handle.await?;
+    }
+    post_rate_limited(&ctx, &mut middlewares).await;
+    Ok(())
+}
+
+/// Posts summaries of restarts not notified by the rate limit to each channel.
+async fn post_rate_limited(ctx: &SenderContext, middlewares: &mut MiddlewareChain) {
+    for (channel, restarts) in middlewares.take_summarized() {
+        let blocks = message::rate_limited_summary(&restarts);
+        if let Err(e) = ctx.poster.post_blocks(&channel, blocks).await {
+            log::error!("Failed to post rate limited restarts to {channel}: {e}");
+        }
+    }
+}
+
+/// Posts the alert of restarts of many containers on a node.
+async fn post_node_alert(ctx: &SenderContext, alert: NodeAlert) {
+    let window = silence::format_duration(alert.window);
+    log::warn!(
+        "{} containers on node {} restarted in {window}",
+        alert.restarts.len(),
+        alert.node
+    );
+    let blocks = message::node_alert(&alert.node, &window, &alert.restarts);
+    if let Err(e) = ctx.poster.post_blocks(&alert.channel, blocks).await {
+        log::error!("Failed to post node alert to {}: {e}", alert.channel);
+    }
+}
+
+/// Index of the sender for `channel`, which keeps notifications to a channel in order.
+fn sender_index(channel: &str, senders: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    channel.hash(&mut hasher);
+    (hasher.finish() % senders as u64) as usize
+}
+
+/// Notification taken by a sender, which is posted again when Slack rate limits it
+struct Pending {
+    restart_info: message::ContainerRestartInfo,
+    /// Number of times Slack rate limited posting it
+    attempts: usize,
+    /// Steps done before Slack rate limited it, which are not repeated
+    progress: Progress,
+}
+
+/// Steps of posting a notification done so far, to resume from the request rate limited
+/// by Slack without uploading files or posting messages twice
+#[derive(Default)]
+struct Progress {
+    /// URLs of log files uploaded so far
+    file_urls: Vec<String>,
+    /// Set when all log files are uploaded, `Some(true)` when the upload failed
+    upload_failed: Option<bool>,
+    /// Titles and URLs of attachments processed so far, `None` for failed uploads
+    attachments: Vec<Option<(String, String)>>,
+    /// Message posted or updated in the routed channel
+    posted: Option<PostedMessage>,
+    /// Messages to post in the thread of `posted`
+    replies: VecDeque<Vec<serde_json::Value>>,
+    escalated: bool,
+}
+
+/// Notifications rate limited by Slack per channel, with the time to post them again
+type Deferred = HashMap<String, (tokio::time::Instant, VecDeque<Pending>)>;
+
+/// Task to send notifications one by one.
+/// Notifications rate limited by Slack are posted again after `Retry-After` without
+/// blocking those to other channels, and later ones to the same channel wait for them.
+async fn sender(
+    ctx: Arc<SenderContext>,
+    mut state: SenderState,
     mut rx: mpsc::Receiver<message::ContainerRestartInfo>,
 ) {
-    let slack = reqwest::Client::new();
+    let mut deferred = Deferred::new();
+    let mut closed = false;
+    while !closed || !deferred.is_empty() {
+        let due = deferred.values().map(|(not_before, _)| *not_before).min();
+        let mut pending = tokio::select! {
+            restart_info = rx.recv(), if !closed => {
+                let Some(restart_info) = restart_info else {
+                    closed = true;
+                    continue;
+                };
+                let pending = Pending {
+                    restart_info,
+                    attempts: 0,
+                    progress: Progress::default(),
+                };
+                match deferred.get_mut(&pending.restart_info.channel) {
+                    Some((_, queue)) => {
+                        queue.push_back(pending);
+                        continue;
+                    }
+                    None => pending,
+                }
+            }
+            () = tokio::time::sleep_until(due.unwrap_or_else(tokio::time::Instant::now)),
+                if due.is_some() =>
+            {
+                let Some(pending) = take_due(&mut deferred) else {
+                    continue;
+                };
+                pending
+            }
+        };
+        let span = tracing::info_span!(
+            parent: &pending.restart_info.span,
+            "notification",
+            namespace = pending.restart_info.namespace.as_deref().unwrap_or(""),
+            pod = pending.restart_info.pod_name,
+            container = pending.restart_info.container_name,
+            channel = pending.restart_info.channel,
+        );
+        let retry_after = send_notification(&ctx, &mut state, &mut pending)
+            .instrument(span)
+            .await;
+        if let Some(retry_after) = retry_after {
+            let not_before = tokio::time::Instant::now() + retry_after;
+            let (time, queue) = deferred
+                .entry(pending.restart_info.channel.clone())
+                .or_insert_with(|| (not_before, VecDeque::new()));
+            *time = not_before;
+            queue.push_front(pending);
+        }
+    }
+}
+
+/// Takes the first notification to the channel which can be posted to the earliest
+fn take_due(deferred: &mut Deferred) -> Option<Pending> {
+    let channel = deferred
+        .iter()
+        .min_by_key(|(_, (not_before, _))| *not_before)
+        .map(|(channel, _)| channel.clone())?;
+    let (_, queue) = deferred.get_mut(&channel)?;
+    let pending = queue.pop_front();
+    if queue.is_empty() {
+        deferred.remove(&channel);
+    }
+    pending
+}
 
-    while let Some(restart_info) = rx.recv().await {
-        log::debug!("Start sending message to Slack: {restart_info}");
-        if let Err(e) = post_notification(&slack, &slack_token, &restart_info).await {
-            log::error!("Failed to post message to Slack: {e}");
+/// Sends the notification to Slack and the other destinations.
+/// Returns the time to wait when Slack rate limits it, to post it again then.
+async fn send_notification(
+    ctx: &SenderContext,
+    state: &mut SenderState,
+    pending: &mut Pending,
+) -> Option<Duration> {
+    let Pending {
+        restart_info,
+        attempts,
+        progress,
+    } = pending;
+    log::debug!("Start sending message to Slack: {restart_info}");
+    // Only on the first attempt, not to add links twice after being rate limited
+    if *attempts == 0 {
+        ctx.sinks.enrich(restart_info).await;
+    }
+    let restart_info = &*restart_info;
+    let mut keep = false;
+    let notifier = ctx.sinks.notifier();
+    // The message posted to Slack, if any
+    let post = async {
+        if !ctx.sinks.notifies(restart_info) {
+            log::debug!("Not notifying by the severity: {restart_info}");
+            return Ok(None);
         }
-        log::debug!("Finished sending message to Slack: {restart_info}");
+        if notifier != NotifierKind::Slack {
+            return ctx.sinks.notify(restart_info).await.map(|()| None);
+        }
+        let deadline = ctx.notification_timeout;
+        let post = post_notification_rotating(ctx, restart_info, state, progress);
+        match tokio::time::timeout(deadline, post).await {
+            Ok(posted) => posted.map(Some),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timed out posting after {} seconds",
+                deadline.as_secs()
+            )),
+        }
+    };
+    let export = async {
+        if *attempts == 0 {
+            ctx.sinks.export(restart_info).await;
+        }
+    };
+    let (posted, ()) = tokio::join!(post, export);
+    if let Some(retry_after) = posted
+        .as_ref()
+        .err()
+        .and_then(rate_limited)
+        .filter(|_| *attempts < RATE_LIMIT_MAX_RETRIES)
+    {
+        *attempts += 1;
+        log::warn!(
+            "Rate limited by Slack API, posting {restart_info} again in {} seconds ({}/{})",
+            retry_after.as_secs(),
+            attempts,
+            RATE_LIMIT_MAX_RETRIES,
+        );
+        return Some(retry_after);
+    }
+    let (result, posted) = match posted {
+        Ok(posted) => (Ok(()), posted),
+        Err(e) => (Err(e), None),
+    };
+    let permalink = match &posted {
+        Some(posted) if ctx.stores.pod_annotator.is_some() => {
+            get_permalink(&ctx.poster.slack, &ctx.poster.slack_token.get(), posted)
+                .await
+                .map_err(|e| log::warn!("Failed to get permalink of {restart_info}: {e}"))
+                .ok()
+        }
+        _ => None,
+    };
+    if let (Some(stability), Some(posted)) = (&ctx.stability, &posted) {
+        stability.record(restart_info, posted);
+    }
+    if let (Some(startup_logs), Some(posted)) = (&ctx.startup_logs, &posted) {
+        startup_logs.capture(restart_info, posted, ctx.poster.clone());
+    }
+    ctx.stores
+        .record(restart_info, &result, notifier, permalink)
+        .await;
+    let component = match notifier {
+        NotifierKind::Slack => Component::Slack,
+        NotifierKind::Log | NotifierKind::Alertmanager => Component::Notifier,
+    };
+    match result {
+        Ok(()) => {
+            metrics::notification_sent();
+            ctx.self_alert.success(component);
+        }
+        Err(e) => {
+            metrics::notification_failed();
+            let class = ErrorClass::of_error(&e);
+            log::error!("Failed to send notification ({class}): {e}");
+            ctx.self_alert.failure(component, &e);
+            // Kept in the disk queue to retry on the next start
+            keep = class == ErrorClass::Outage;
+        }
+    }
+    if let Some(disk_queue) = ctx.disk_queue.as_ref().filter(|_| !keep) {
+        disk_queue.remove(restart_info);
+    }
+    log::debug!("Finished sending message to Slack: {restart_info}");
+    None
+}
+
+/// Records of messages, files and notifications sent to Slack
+pub struct SlackStores {
+    pub message_store: MessageStore,
+    /// Records uploaded files when file retention is configured
+    pub file_store: Option<FileStore>,
+    /// Records of notification outcomes shared with other notifiers
+    pub sinks: SinkStores,
+    /// Follows up notifications when the containers have stabilized
+    pub stability: Option<StabilityTracker>,
+    /// Replies to notifications with logs of the new containers
+    pub startup_logs: Option<StartupLogs>,
+}
+
+/// Configuration shared by senders
+struct SenderContext {
+    poster: SlackPoster,
+    /// Re-read for the rotated token when the token is revoked while posting
+    slack_token_file: Option<PathBuf>,
+    /// Deadline of `post_notification_rotating` not to block the sender on hanging requests
+    notification_timeout: Duration,
+    fallback_channel: Option<String>,
+    self_alert: SelfAlert,
+    disk_queue: Option<DiskQueue>,
+    stores: SinkStores,
+    stability: Option<StabilityTracker>,
+    startup_logs: Option<StartupLogs>,
+    /// Notifier other than Slack, the other destinations and enrichment
+    sinks: Sinks,
+}
+
+/// State kept by each sender across notifications
+struct SenderState {
+    /// Shared by senders, which post to different channels
+    message_store: Arc<Mutex<MessageStore>>,
+    /// Records uploaded files when file retention is configured
+    file_store: Option<FileStore>,
+    /// Log files uploaded recently by this sender, which posts to the same channels
+    recent_uploads: Option<RecentUploads>,
+    usergroups: UsergroupCache,
+}
+
+/// Posts the notification, retrying once with the rotated token when the token is revoked
+/// in the middle of rotation of `SLACK_TOKEN_FILE`, so that the notification is not dropped.
+async fn post_notification_rotating(
+    ctx: &SenderContext,
+    restart_info: &message::ContainerRestartInfo,
+    state: &mut SenderState,
+    progress: &mut Progress,
+) -> anyhow::Result<PostedMessage> {
+    let token = ctx.poster.slack_token.get();
+    let result = post_notification(
+        &ctx.poster.slack,
+        &token,
+        ctx.fallback_channel.as_deref(),
+        ctx.sinks.templates(),
+        restart_info,
+        state,
+        progress,
+    )
+    .await;
+    let (Err(e), Some(path)) = (&result, &ctx.slack_token_file) else {
+        return result;
+    };
+    if !is_revoked_token(e) {
+        return result;
     }
+    log::warn!("Slack token is no longer valid, waiting for the rotated token: {e}");
+    ctx.poster.slack_token.reject();
+    let Some(token) = wait_rotated_token(Some(path), &ctx.poster.slack_token, &token).await else {
+        log::error!("Slack token was not rotated in SLACK_TOKEN_FILE");
+        return result;
+    };
+    post_notification(
+        &ctx.poster.slack,
+        &token,
+        ctx.fallback_channel.as_deref(),
+        ctx.sinks.templates(),
+        restart_info,
+        state,
+        progress,
+    )
+    .await
 }
 
+/// Posts the notification with replies in the thread and escalates it, resuming from
+/// `progress` of the previous attempt rate limited by Slack.
 async fn post_notification(
     slack: &reqwest::Client,
     slack_token: &str,
+    fallback_channel: Option<&str>,
+    templates: &MessageTemplates,
     restart_info: &message::ContainerRestartInfo,
-) -> anyhow::Result<()> {
-    let file_url = upload_log_file(slack, slack_token, restart_info).await?;
-    post_message(
+    state: &mut SenderState,
+    progress: &mut Progress,
+) -> anyhow::Result<PostedMessage> {
+    let posted = match &progress.posted {
+        Some(posted) => posted.clone(),
+        None => {
+            let posted = post_main_message(
+                slack,
+                slack_token,
+                fallback_channel,
+                templates,
+                restart_info,
+                state,
+                progress,
+            )
+            .await?;
+            progress.posted = Some(posted.clone());
+            posted
+        }
+    };
+    while let Some(blocks) = progress.replies.front() {
+        post_single_message(
+            slack,
+            slack_token,
+            &posted.channel,
+            blocks.clone(),
+            Some(&posted.ts),
+            None,
+        )
+        .await?;
+        progress.replies.pop_front();
+    }
+    if restart_info.options.update || restart_info.options.aggregate {
+        state
+            .message_store
+            .lock()
+            .unwrap()
+            .insert(restart_info.message_key(), posted.clone());
+    }
+    if !progress.escalated {
+        // Not to fail the notification already posted to the routed channel
+        match post_escalation(slack, slack_token, restart_info, &posted, state).await {
+            Ok(()) => {}
+            Err(e) if rate_limited(&e).is_some() => return Err(e),
+            Err(e) => log::error!("Failed to escalate {restart_info}: {e:#}"),
+        }
+        progress.escalated = true;
+    }
+    Ok(posted)
+}
+
+/// Uploads logs and attachments, and posts or updates the message in the routed channel.
+/// Replies to post in its thread are set to `progress.replies`.
+async fn post_main_message(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    fallback_channel: Option<&str>,
+    templates: &MessageTemplates,
+    restart_info: &message::ContainerRestartInfo,
+    state: &mut SenderState,
+    progress: &mut Progress,
+) -> anyhow::Result<PostedMessage> {
+    let upload_failed = match progress.upload_failed {
+        Some(upload_failed) => upload_failed,
+        None => {
+            let uploaded = upload_log_file(
+                slack,
+                slack_token,
+                restart_info,
+                state.file_store.as_ref(),
+                state.recent_uploads.as_mut(),
+                &mut progress.file_urls,
+            )
+            .await;
+            let upload_failed = match uploaded {
+                Ok(()) => false,
+                Err(e) if rate_limited(&e).is_some() => return Err(e),
+                Err(e) => {
+                    log::warn!("Failed to upload container logs, posting them in the thread: {e}");
+                    progress.file_urls.clear();
+                    true
+                }
+            };
+            progress.upload_failed = Some(upload_failed);
+            upload_failed
+        }
+    };
+    let file_urls = &progress.file_urls;
+    let mut blocks = templates.notification_blocks(restart_info, file_urls);
+    upload_attachments(
         slack,
         slack_token,
-        &restart_info.channel,
         restart_info,
-        &file_url,
+        state.file_store.as_ref(),
+        &mut progress.attachments,
     )
-    .await
+    .await?;
+    let attachments = progress
+        .attachments
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    if !attachments.is_empty() {
+        blocks.push(message::attachments_context(&attachments));
+    }
+    // Replied to the message of the workload, which shows only the latest restart
+    let aggregate = restart_info.options.aggregate;
+    let reply = blocks.clone();
+    let mut mentions = Vec::new();
+    for handle in restart_info.options.mentions(restart_info.restart_count) {
+        mentions.push(resolve_mention(slack, slack_token, handle, state).await);
+    }
+    if !mentions.is_empty() {
+        blocks.insert(0, message::mention_block(&mentions.join(" ")));
+    }
+
+    let metadata = restart_info.to_metadata();
+    let previous = state
+        .message_store
+        .lock()
+        .unwrap()
+        .get(&restart_info.message_key())
+        .filter(|_| restart_info.options.update || aggregate)
+        .cloned();
+    let pods = {
+        let mut pods = previous
+            .as_ref()
+            .map(|p| p.pods.clone())
+            .unwrap_or_default();
+        pods.insert(restart_info.pod_name.clone(), restart_info.restart_count);
+        pods
+    };
+    let workload = restart_info.workload.as_deref().filter(|_| aggregate);
+    if let Some(workload) = workload {
+        blocks.push(message::workload_context(workload, &pods));
+    }
+    let mut replies = VecDeque::new();
+    let mut posted = match previous {
+        Some(mut previous) => {
+            previous.notified_restarts += 1;
+            previous.updated_at = Utc::now();
+            blocks.push(message::updated_context(previous.notified_restarts));
+            match update_message(
+                slack,
+                slack_token,
+                &previous,
+                blocks.clone(),
+                Some(&metadata),
+            )
+            .await
+            {
+                Ok(()) => {
+                    if workload.is_some() {
+                        replies.extend(message::fit_blocks(reply));
+                    }
+                    previous
+                }
+                // Updated when posting again, not to post a new message instead
+                Err(e) if rate_limited(&e).is_some() => return Err(e),
+                Err(e) => {
+                    log::warn!("Failed to update message, posting a new one: {e}");
+                    blocks.pop();
+                    let (posted, rest) = post_to_channel(
+                        slack,
+                        slack_token,
+                        &restart_info.channel,
+                        fallback_channel,
+                        blocks,
+                        Some(&metadata),
+                    )
+                    .await?;
+                    replies.extend(rest);
+                    posted
+                }
+            }
+        }
+        None => {
+            let (posted, rest) = post_to_channel(
+                slack,
+                slack_token,
+                &restart_info.channel,
+                fallback_channel,
+                blocks,
+                Some(&metadata),
+            )
+            .await?;
+            replies.extend(rest);
+            posted
+        }
+    };
+    if workload.is_some() {
+        posted.pods = pods;
+    }
+
+    if restart_info.options.thread_logs {
+        replies.extend(message::fit_blocks(restart_info.to_log_message(file_urls)));
+    }
+    if upload_failed {
+        for blocks in restart_info.to_log_chunk_messages() {
+            replies.extend(message::fit_blocks(blocks));
+        }
+    }
+    progress.replies = replies;
+    Ok(posted)
+}
+
+/// Mention of user group `handle`, or the plain handle when it cannot be resolved
+async fn resolve_mention(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    handle: &str,
+    state: &mut SenderState,
+) -> String {
+    match state.usergroups.resolve(slack, slack_token, handle).await {
+        Ok(Some(id)) => format!("<!subteam^{id}>"),
+        Ok(None) => {
+            log::warn!("User group not found: @{handle}");
+            format!("@{handle}")
+        }
+        Err(e) => {
+            log::warn!("Failed to resolve user group @{handle}: {e}");
+            format!("@{handle}")
+        }
+    }
+}
+
+/// Posts an escalated restart to `escalate_channel` of the rule, linking `posted` in the
+/// routed channel. Does nothing unless the restart is escalated.
+async fn post_escalation(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    restart_info: &message::ContainerRestartInfo,
+    posted: &PostedMessage,
+    state: &mut SenderState,
+) -> anyhow::Result<()> {
+    let options = &restart_info.options;
+    let (Some(after), Some(channel)) = (options.escalate_after, &options.escalate_channel) else {
+        return Ok(());
+    };
+    if !options.escalated(restart_info.restart_count) {
+        return Ok(());
+    }
+    let permalink = get_permalink(slack, slack_token, posted)
+        .await
+        .map_err(|e| log::warn!("Failed to get permalink of {restart_info}: {e}"))
+        .ok();
+    let mut blocks = restart_info.to_escalation_message(after, permalink.as_deref());
+    if let Some(handle) = &options.escalate_mention {
+        let mention = resolve_mention(slack, slack_token, handle, state).await;
+        blocks.insert(0, message::mention_block(&mention));
+    }
+    let metadata = restart_info.to_metadata();
+    let (escalated, rest) =
+        post_to_channel(slack, slack_token, channel, None, blocks, Some(&metadata)).await?;
+    // Waits for rate limits not to post the escalation again
+    for blocks in rest {
+        retry_rate_limited(|| {
+            post_single_message(
+                slack,
+                slack_token,
+                &escalated.channel,
+                blocks.clone(),
+                Some(&escalated.ts),
+                None,
+            )
+        })
+        .await?;
+    }
+    log::info!("Escalated {restart_info} to {channel}");
+    Ok(())
+}
+
+/// Cache of user group handle -> user group ID
+#[derive(Debug, Default)]
+struct UsergroupCache {
+    ids: HashMap<String, String>,
+    updated_at: Option<Instant>,
+}
+
+impl UsergroupCache {
+    /// Resolves user group `handle` to its ID, refreshing the cache when it is stale.
+    async fn resolve(
+        &mut self,
+        slack: &reqwest::Client,
+        slack_token: &str,
+        handle: &str,
+    ) -> anyhow::Result<Option<String>> {
+        // Unknown handles are retried sooner, but not for every notification
+        let refresh_after = if self.ids.contains_key(handle) {
+            USERGROUP_CACHE_TTL
+        } else {
+            USERGROUP_RETRY_INTERVAL
+        };
+        if !matches!(self.updated_at, Some(t) if t.elapsed() < refresh_after) {
+            self.refresh(slack, slack_token).await?;
+        }
+        Ok(self.ids.get(handle).cloned())
+    }
+
+    async fn refresh(&mut self, slack: &reqwest::Client, slack_token: &str) -> anyhow::Result<()> {
+        let resp = send_request(slack.get(LIST_USERGROUPS_URL).bearer_auth(slack_token)).await?;
+        let resp = parse_slack_response(resp).await?;
+        self.ids = resp
+            .get("usergroups")
+            .and_then(|groups| groups.as_array())
+            .context("Failed to get user groups")?
+            .iter()
+            .filter_map(|group| {
+                let handle = group.get("handle")?.as_str()?;
+                let id = group.get("id")?.as_str()?;
+                Some((handle.to_owned(), id.to_owned()))
+            })
+            .collect();
+        self.updated_at = Some(Instant::now());
+        log::debug!("Fetched {} user groups", self.ids.len());
+        Ok(())
+    }
 }
 
+/// Uploads container logs and adds URLs of the uploaded files to `file_urls`.
+/// Logs larger than `LOG_FILE_BYTES` are split into multiple files, and those already
+/// in `file_urls` are skipped.
+/// Files in `recent_uploads` with the same fingerprint and content are linked instead.
 async fn upload_log_file(
     slack: &reqwest::Client,
     slack_token: &str,
     restart_info: &message::ContainerRestartInfo,
-) -> anyhow::Result<Option<String>> {
-    let log = match restart_info.logs.0.as_ref().map(|log| log.trim_end()) {
-        Ok(log) if !log.is_empty() => log.to_owned(),
-        _empty_or_error => return Ok(None),
-    };
-    let title = format!(
-        "{}_{}_{}",
-        restart_info.namespace.as_ref().unwrap_or(&"".to_owned()),
-        &restart_info.pod_name,
-        &restart_info.container_name
-    );
+    file_store: Option<&FileStore>,
+    mut recent_uploads: Option<&mut RecentUploads>,
+    file_urls: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    let files = restart_info.log_files();
+    for (title, part) in files.into_iter().skip(file_urls.len()) {
+        let key = upload_key(restart_info, part);
+        if let Some(url) = recent_uploads.as_mut().and_then(|recent| recent.get(&key)) {
+            log::debug!("Linking the file uploaded for the same logs: {title}");
+            file_urls.push(url);
+            continue;
+        }
+        let (file_id, file_url) = if restart_info.options.gzip_logs {
+            let content = gzip(part.as_bytes())?;
+            let filename = format!("{title}.log.gz");
+            upload_file(slack, slack_token, &title, &filename, None, content).await?
+        } else {
+            let content = part.as_bytes().to_vec();
+            upload_file(slack, slack_token, &title, &title, Some("text"), content).await?
+        };
+        if let Some(file_store) = file_store {
+            file_store.insert(UploadedFile {
+                id: file_id,
+                uploaded_at: Utc::now(),
+            });
+        }
+        if let Some(recent_uploads) = recent_uploads.as_mut() {
+            recent_uploads.insert(key, file_url.clone());
+        }
+        file_urls.push(file_url);
+    }
+    Ok(())
+}
 
-    let length = log.len().to_string();
-    let params = [
-        ("snippet_type", "text"),
-        ("length", &length),
-        ("filename", &title),
-    ];
-    let resp = slack
-        .post(GET_UPLOAD_URL)
-        .bearer_auth(slack_token)
-        .form(&params)
-        .send()
-        .await?;
+/// Uploads the attachments of the notification and adds their titles and URLs to
+/// `uploaded`, skipping those already in it.
+/// Failures other than rate limits are only logged since the notification is useful
+/// without them.
+async fn upload_attachments(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    restart_info: &message::ContainerRestartInfo,
+    file_store: Option<&FileStore>,
+    uploaded: &mut Vec<Option<(String, String)>>,
+) -> anyhow::Result<()> {
+    for attachment in restart_info.attachments.iter().skip(uploaded.len()) {
+        let filename = &attachment.filename;
+        let snippet_type = if filename.ends_with(".yaml") {
+            "yaml"
+        } else {
+            "text"
+        };
+        let content = attachment.content.as_bytes().to_vec();
+        let upload = upload_file(
+            slack,
+            slack_token,
+            filename,
+            filename,
+            Some(snippet_type),
+            content,
+        );
+        match upload.await {
+            Ok((file_id, file_url)) => {
+                if let Some(file_store) = file_store {
+                    file_store.insert(UploadedFile {
+                        id: file_id,
+                        uploaded_at: Utc::now(),
+                    });
+                }
+                uploaded.push(Some((attachment.title.clone(), file_url)));
+            }
+            Err(e) if rate_limited(&e).is_some() => return Err(e),
+            Err(e) => {
+                log::warn!("Failed to upload {filename} of {restart_info}: {e}");
+                uploaded.push(None);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Key of `RecentUploads` of the log file of `part`.
+/// Files are linked only from the same channel, where they are shared.
+fn upload_key(restart_info: &message::ContainerRestartInfo, part: &str) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        restart_info.channel,
+        restart_info.fingerprint(),
+        restart_info.options.gzip_logs,
+        hex::encode(Sha256::digest(part.as_bytes())),
+    )
+}
+
+/// Uploads `content` and returns the ID and the URL of the file.
+/// The file is shown as a snippet when `snippet_type` is specified.
+#[tracing::instrument(skip_all, fields(filename))]
+async fn upload_file(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    title: &str,
+    filename: &str,
+    snippet_type: Option<&str>,
+    content: Vec<u8>,
+) -> anyhow::Result<(String, String)> {
+    let length = content.len().to_string();
+    let mut params = vec![("length", length.as_str()), ("filename", filename)];
+    if let Some(snippet_type) = snippet_type {
+        params.push(("snippet_type", snippet_type));
+    }
+    let resp = send_request(
+        slack
+            .post(GET_UPLOAD_URL)
+            .bearer_auth(slack_token)
+            .form(&params),
+    )
+    .await?;
     let resp = parse_slack_response(resp).await?;
     let upload_url = resp
         .get("upload_url")
@@ -78,57 +1297,630 @@ async fn upload_log_file(
         .and_then(|id| id.as_str())
         .context("Failed to get file ID")?;
 
-    slack
-        .post(upload_url)
-        .body(log)
-        .send()
+    send_request(slack.post(upload_url).body(content))
         .await?
         .error_for_status()?;
 
-    let resp = slack
-        .post(COMPLETE_UPLOAD_URL)
-        .bearer_auth(slack_token)
-        .json(&json!({
-            "files": [
-                {
-                    "id": file_id,
-                    "title": &title,
-                },
-            ],
-        }))
-        .send()
-        .await?
-        .error_for_status()?;
+    let resp = send_request(
+        slack
+            .post(COMPLETE_UPLOAD_URL)
+            .bearer_auth(slack_token)
+            .json(&json!({
+                "files": [
+                    {
+                        "id": file_id,
+                        "title": title,
+                    },
+                ],
+            })),
+    )
+    .await?
+    .error_for_status()?;
     let resp = parse_slack_response(resp).await?;
     let file_url = get_file_url_from_response(&resp).context("Failed to get file URL")?;
 
-    Ok(Some(file_url.to_owned()))
+    Ok((file_id.to_owned(), file_url.to_owned()))
 }
 
-async fn post_message(
+/// Task to delete files uploaded more than `retention` ago
+pub async fn delete_expired_files(
+    poster: SlackPoster,
+    file_store: FileStore,
+    retention: k8s_openapi::chrono::Duration,
+) {
+    let mut interval = tokio::time::interval(FILE_CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let expired = file_store.expired(retention);
+        if !expired.is_empty() {
+            log::info!("Deleting {} expired files", expired.len());
+        }
+        for file in expired {
+            match delete_file(&poster.slack, &poster.slack_token.get(), &file.id).await {
+                Ok(()) => file_store.remove(&file.id),
+                Err(e)
+                    if matches!(
+                        slack_error_code(&e),
+                        Some("file_not_found" | "file_deleted")
+                    ) =>
+                {
+                    file_store.remove(&file.id)
+                }
+                Err(e) => log::warn!("Failed to delete file {}: {e}", file.id),
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(file_id))]
+async fn delete_file(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    file_id: &str,
+) -> anyhow::Result<()> {
+    let resp = send_request(
+        slack
+            .post(DELETE_FILE_URL)
+            .bearer_auth(slack_token)
+            .form(&[("file", file_id)]),
+    )
+    .await?;
+    parse_slack_response(resp).await?;
+    Ok(())
+}
+
+fn gzip(content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content)?;
+    Ok(encoder.finish()?)
+}
+
+/// Slack token shared by the tasks posting to Slack, replaced when it is rotated,
+/// e.g. in Vault
+#[derive(Clone, Default)]
+pub struct SlackToken(Arc<SlackTokenInner>);
+
+#[derive(Default)]
+struct SlackTokenInner {
+    token: RwLock<String>,
+    /// Notified when Slack rejects the token, to read the sources of the token early
+    rejected: tokio::sync::Notify,
+}
+
+impl SlackToken {
+    pub fn new(token: String) -> Self {
+        Self(Arc::new(SlackTokenInner {
+            token: RwLock::new(token),
+            rejected: Default::default(),
+        }))
+    }
+
+    pub fn get(&self) -> String {
+        self.0.token.read().unwrap().clone()
+    }
+
+    pub fn set(&self, token: String) {
+        *self.0.token.write().unwrap() = token;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.token.read().unwrap().is_empty()
+    }
+
+    /// Wakes the tasks waiting in `rejected` to read the rotated token.
+    pub fn reject(&self) {
+        self.0.rejected.notify_waiters();
+    }
+
+    /// Waits for the token to be rejected by Slack.
+    pub async fn rejected(&self) {
+        self.0.rejected.notified().await;
+    }
+
+    /// Applies the credential in environment variable `env` rotated in `source`, e.g. Vault.
+    /// Only `SLACK_TOKEN` is replaced while running, and others are applied on the next start.
+    pub fn apply_rotated(&self, env: &str, value: String, source: &str) {
+        if env == "SLACK_TOKEN" {
+            log::info!("SLACK_TOKEN was rotated in {source}");
+            self.set(value);
+        } else {
+            log::warn!("{env} was changed in {source}, restart johari-mirror to apply it");
+        }
+    }
+}
+
+impl std::fmt::Debug for SlackToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SlackToken(..)")
+    }
+}
+
+/// Posts messages other than notifications, e.g. reports and self-alerts
+#[derive(Debug, Clone)]
+pub struct SlackPoster {
+    slack: reqwest::Client,
+    slack_token: SlackToken,
+    notifier: NotifierKind,
+}
+
+impl SlackPoster {
+    /// `slack` is built by `SlackConfig::http_client`
+    pub fn new(slack: reqwest::Client, slack_token: SlackToken, notifier: NotifierKind) -> Self {
+        Self {
+            slack,
+            slack_token,
+            notifier,
+        }
+    }
+
+    /// HTTP client of Slack, e.g. for incoming webhooks
+    pub fn http(&self) -> &reqwest::Client {
+        &self.slack
+    }
+
+    /// Validates the current token with `validate_token`.
+    pub async fn validate_token(&self) -> anyhow::Result<()> {
+        validate_token(&self.slack, &self.slack_token.get()).await
+    }
+
+    /// Names and IDs of the public channels and the private channels the bot is in.
+    /// Archived channels are excluded.
+    pub async fn list_channels(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut ids = HashMap::new();
+        let mut cursor = String::new();
+        loop {
+            // Listed on startup and in the background, which can wait for the next page
+            let resp = retry_rate_limited(|| {
+                send_request(
+                    self.slack
+                        .get(LIST_CONVERSATIONS_URL)
+                        .bearer_auth(self.slack_token.get())
+                        .query(&[
+                            ("types", "public_channel,private_channel"),
+                            ("exclude_archived", "true"),
+                            ("limit", "1000"),
+                            ("cursor", &cursor),
+                        ]),
+                )
+            })
+            .await?;
+            let resp = parse_slack_response(resp)
+                .await
+                .context("Failed to list channels, which requires channels:read and groups:read")?;
+            let channels = resp
+                .get("channels")
+                .and_then(|channels| channels.as_array())
+                .context("Failed to get channels")?;
+            ids.extend(channels.iter().filter_map(|channel| {
+                let name = channel.get("name")?.as_str()?;
+                let id = channel.get("id")?.as_str()?;
+                Some((name.to_owned(), id.to_owned()))
+            }));
+            cursor = resp
+                .pointer("/response_metadata/next_cursor")
+                .and_then(|cursor| cursor.as_str())
+                .unwrap_or_default()
+                .to_owned();
+            if cursor.is_empty() {
+                return Ok(ids);
+            }
+        }
+    }
+
+    /// Whether messages are posted to Slack, not logged
+    pub fn posts_to_slack(&self) -> bool {
+        match self.notifier {
+            NotifierKind::Slack => true,
+            NotifierKind::Log => false,
+            NotifierKind::Alertmanager => !self.slack_token.is_empty(),
+        }
+    }
+
+    /// Posts `blocks` in the thread of `posted`.
+    pub async fn reply(
+        &self,
+        posted: &PostedMessage,
+        blocks: Vec<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        post_message(
+            &self.slack,
+            &self.slack_token.get(),
+            &posted.channel,
+            blocks,
+            Some(&posted.ts),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Posts `blocks` to `slack_channel`, or logs them unless `posts_to_slack`.
+    pub async fn post_blocks(
+        &self,
+        slack_channel: &str,
+        blocks: Vec<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        if self.posts_to_slack() {
+            post_message(
+                &self.slack,
+                &self.slack_token.get(),
+                slack_channel,
+                blocks,
+                None,
+                None,
+            )
+            .await?;
+        } else {
+            log::info!(
+                "Message to {slack_channel}: {}",
+                json!({ "channel": slack_channel, "blocks": blocks })
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Posts `blocks` to `slack_channel` without waiting for rate limits, and returns
+/// the message and the rest of `blocks` exceeding Slack limits to post in its thread.
+/// When the bot is not a member of the channel, joins the channel and retries.
+/// When it still fails, posts to `fallback_channel` with a note.
+async fn post_to_channel(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    slack_channel: &str,
+    fallback_channel: Option<&str>,
+    mut blocks: Vec<serde_json::Value>,
+    metadata: Option<&serde_json::Value>,
+) -> anyhow::Result<(PostedMessage, Vec<Vec<serde_json::Value>>)> {
+    let post = |channel, blocks| async move {
+        let mut messages = message::fit_blocks(blocks);
+        let first = messages.remove(0);
+        let posted =
+            post_single_message(slack, slack_token, channel, first, None, metadata).await?;
+        anyhow::Ok((posted, messages))
+    };
+    let err = match post(slack_channel, blocks.clone()).await {
+        Err(err)
+            if matches!(
+                slack_error_code(&err),
+                Some("not_in_channel" | "channel_not_found")
+            ) =>
+        {
+            err
+        }
+        result => return result,
+    };
+    log::warn!("Failed to post message to {slack_channel}: {err}");
+
+    match join_channel(slack, slack_token, slack_channel).await {
+        Ok(()) => {
+            log::info!("Joined channel: {slack_channel}");
+            match post(slack_channel, blocks.clone()).await {
+                Ok(posted) => return Ok(posted),
+                Err(e) if rate_limited(&e).is_some() => return Err(e),
+                Err(e) => log::warn!("Failed to post message to {slack_channel} after join: {e}"),
+            }
+        }
+        Err(e) if rate_limited(&e).is_some() => return Err(e),
+        Err(e) => log::warn!("Failed to join channel {slack_channel}: {e}"),
+    }
+
+    let Some(fallback_channel) = fallback_channel else {
+        return Err(err);
+    };
+    log::info!("Posting message to fallback channel: {fallback_channel}");
+    blocks.insert(
+        0,
+        message::fallback_context(slack_channel, slack_error_code(&err).unwrap_or_default()),
+    );
+    post(fallback_channel, blocks).await
+}
+
+#[tracing::instrument(skip_all, fields(channel = slack_channel))]
+async fn join_channel(
     slack: &reqwest::Client,
     slack_token: &str,
     slack_channel: &str,
-    restart_info: &message::ContainerRestartInfo,
-    file_url: &Option<String>,
 ) -> anyhow::Result<()> {
-    let message = serde_json::json!({
-        "channel": slack_channel,
-        "blocks": restart_info.to_message(file_url),
+    let resp = send_request(
+        slack
+            .post(JOIN_CONVERSATION_URL)
+            .bearer_auth(slack_token)
+            .form(&[("channel", &*channels::resolve(slack_channel))]),
+    )
+    .await?;
+    parse_slack_response(resp).await?;
+    Ok(())
+}
+
+/// Posts `blocks` to `slack_channel`.
+/// The message is posted as a reply when `thread_ts` is specified.
+/// `metadata` is attached for machine consumption.
+/// https://api.slack.com/metadata
+/// Blocks exceeding Slack limits are split and the rest is posted in the thread.
+/// Each message is posted again after `Retry-After` when Slack rate limits it.
+async fn post_message(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    slack_channel: &str,
+    blocks: Vec<serde_json::Value>,
+    thread_ts: Option<&str>,
+    metadata: Option<&serde_json::Value>,
+) -> anyhow::Result<PostedMessage> {
+    let mut messages = message::fit_blocks(blocks).into_iter();
+    let first = messages.next().unwrap_or_default();
+    let posted = retry_rate_limited(|| {
+        post_single_message(
+            slack,
+            slack_token,
+            slack_channel,
+            first.clone(),
+            thread_ts,
+            metadata,
+        )
+    })
+    .await?;
+    let thread_ts = thread_ts.unwrap_or(&posted.ts);
+    for blocks in messages {
+        retry_rate_limited(|| {
+            post_single_message(
+                slack,
+                slack_token,
+                &posted.channel,
+                blocks.clone(),
+                Some(thread_ts),
+                None,
+            )
+        })
+        .await?;
+    }
+    Ok(posted)
+}
+
+/// Posts `blocks` to `slack_channel` as is in a single message.
+#[tracing::instrument(skip_all, fields(channel = slack_channel))]
+async fn post_single_message(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    slack_channel: &str,
+    blocks: Vec<serde_json::Value>,
+    thread_ts: Option<&str>,
+    metadata: Option<&serde_json::Value>,
+) -> anyhow::Result<PostedMessage> {
+    let mut message = serde_json::json!({
+        "channel": channels::resolve(slack_channel),
+        "blocks": blocks,
         "unfurl_links": false,
     });
-    let resp = slack
-        .post(POST_MESSAGE_URL)
-        .bearer_auth(slack_token)
-        .json(&message)
-        .send()
-        .await?;
+    if let Some(thread_ts) = thread_ts {
+        message["thread_ts"] = thread_ts.into();
+    }
+    if let Some(metadata) = metadata {
+        message["metadata"] = metadata.clone();
+    }
+    let resp = send_request(
+        slack
+            .post(POST_MESSAGE_URL)
+            .bearer_auth(slack_token)
+            .json(&message),
+    )
+    .await?;
+    let resp = parse_slack_response(resp).await?;
+    let (channel, ts) = (|| Some((resp.get("channel")?.as_str()?, resp.get("ts")?.as_str()?)))()
+        .context("Failed to get channel and timestamp of the message")?;
+    Ok(PostedMessage {
+        channel: channel.to_owned(),
+        ts: ts.to_owned(),
+        notified_restarts: 1,
+        updated_at: Utc::now(),
+        pods: Default::default(),
+    })
+}
+
+/// Returns the permalink of `posted` message with `chat.getPermalink` API.
+async fn get_permalink(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    posted: &PostedMessage,
+) -> anyhow::Result<String> {
+    let resp = send_request(
+        slack
+            .get(GET_PERMALINK_URL)
+            .bearer_auth(slack_token)
+            .query(&[("channel", &posted.channel), ("message_ts", &posted.ts)]),
+    )
+    .await?;
+    let resp = parse_slack_response(resp).await?;
+    resp.get("permalink")
+        .and_then(|p| p.as_str())
+        .map(str::to_owned)
+        .context("Failed to get permalink of the message")
+}
+
+/// Replaces the content of `posted` message with `blocks`.
+/// Blocks exceeding Slack limits are split and only the first part is kept.
+#[tracing::instrument(skip_all, fields(channel = posted.channel))]
+async fn update_message(
+    slack: &reqwest::Client,
+    slack_token: &str,
+    posted: &PostedMessage,
+    blocks: Vec<serde_json::Value>,
+    metadata: Option<&serde_json::Value>,
+) -> anyhow::Result<()> {
+    let mut messages = message::fit_blocks(blocks);
+    if messages.len() > 1 {
+        log::warn!(
+            "Message {} is too large to update, dropping {} parts",
+            posted.ts,
+            messages.len() - 1
+        );
+    }
+    let blocks = messages.swap_remove(0);
+    let mut message = json!({
+        "channel": &posted.channel,
+        "ts": &posted.ts,
+        "blocks": blocks,
+    });
+    if let Some(metadata) = metadata {
+        message["metadata"] = metadata.clone();
+    }
+    let resp = send_request(
+        slack
+            .post(UPDATE_MESSAGE_URL)
+            .bearer_auth(slack_token)
+            .json(&message),
+    )
+    .await?;
     parse_slack_response(resp).await?;
     Ok(())
 }
 
+/// Sends `request`, failing with `RateLimited` when Slack API responds with HTTP 429
+/// instead of waiting, so that senders post other notifications meanwhile.
+/// https://api.slack.com/docs/rate-limits
+async fn send_request(request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+    let resp = match request.send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            metrics::slack_error("network_error");
+            return Err(e.into());
+        }
+    };
+    if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        metrics::slack_error("rate_limited");
+        let retry_after = parse_retry_after(resp.headers()).unwrap_or(DEFAULT_RETRY_AFTER);
+        log::debug!(
+            "Rate limited by Slack API for {} seconds: {}",
+            retry_after.as_secs(),
+            resp.url().path()
+        );
+        return Err(RateLimited(retry_after).into());
+    }
+    Ok(resp)
+}
+
+/// Runs `request` again after `Retry-After` while Slack API rate limits it, up to
+/// `RATE_LIMIT_MAX_RETRIES` times, for messages which senders do not post again
+async fn retry_rate_limited<T, F, Fut>(mut request: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        let err = match request().await {
+            Err(err) => err,
+            result => return result,
+        };
+        let Some(retry_after) = rate_limited(&err).filter(|_| retries < RATE_LIMIT_MAX_RETRIES)
+        else {
+            return Err(err);
+        };
+        retries += 1;
+        log::warn!(
+            "Rate limited by Slack API, retrying in {} seconds ({}/{})",
+            retry_after.as_secs(),
+            retries,
+            RATE_LIMIT_MAX_RETRIES,
+        );
+        tokio::time::sleep(retry_after).await;
+    }
+}
+
+/// `Retry-After` of `err` if Slack API rate limited the request
+fn rate_limited(err: &anyhow::Error) -> Option<Duration> {
+    err.downcast_ref::<RateLimited>().map(|e| e.0)
+}
+
+/// `Retry-After` up to `MAX_RETRY_AFTER`
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let secs = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+}
+
+/// Error code returned by Slack API with `"ok": false`
+/// https://api.slack.com/web#evaluating_responses
+#[derive(Debug)]
+pub struct SlackApiError(pub String);
+
+impl std::fmt::Display for SlackApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Slack response is not ok: {}", self.0)
+    }
+}
+
+impl std::error::Error for SlackApiError {}
+
+/// HTTP 429 response of Slack API with the time to wait before retrying
+#[derive(Debug)]
+pub struct RateLimited(pub Duration);
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Rate limited by Slack API, retry after {} seconds",
+            self.0.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Class of Slack API errors to distinguish configuration problems from outages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Invalid token, missing scopes or channels, which need fixes by operators
+    Config,
+    RateLimit,
+    /// Network errors, server errors and others, which are expected to be transient
+    Outage,
+}
+
+impl ErrorClass {
+    /// Classifies Slack API error `code`.
+    pub fn of(code: &str) -> Self {
+        match code {
+            "invalid_auth" | "not_authed" | "account_inactive" | "token_revoked"
+            | "token_expired" | "missing_scope" | "no_permission" | "channel_not_found"
+            | "not_in_channel" | "is_archived" | "restricted_action" => Self::Config,
+            "rate_limited" | "ratelimited" => Self::RateLimit,
+            _ => Self::Outage,
+        }
+    }
+
+    pub fn of_error(err: &anyhow::Error) -> Self {
+        if rate_limited(err).is_some() {
+            return Self::RateLimit;
+        }
+        slack_error_code(err).map_or(Self::Outage, Self::of)
+    }
+}
+
+impl std::fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config => f.write_str("config"),
+            Self::RateLimit => f.write_str("rate_limit"),
+            Self::Outage => f.write_str("outage"),
+        }
+    }
+}
+
+/// Returns Slack API error code of `err` if any.
+fn slack_error_code(err: &anyhow::Error) -> Option<&str> {
+    err.downcast_ref::<SlackApiError>().map(|e| e.0.as_str())
+}
+
 async fn parse_slack_response(resp: reqwest::Response) -> anyhow::Result<serde_json::Value> {
     if !resp.status().is_success() {
+        metrics::slack_error(&format!("http_{}", resp.status().as_u16()));
         bail!(
             "Slack API failed: {}",
             resp.text().await.unwrap_or_else(|err| err.to_string())
@@ -137,8 +1929,9 @@ async fn parse_slack_response(resp: reqwest::Response) -> anyhow::Result<serde_j
     log::debug!("Response from Slack: status={}", resp.status());
     let resp: serde_json::Value = resp.json().await?;
     if !matches!(resp.get("ok"), Some(serde_json::Value::Bool(true))) {
-        if let Some(error) = resp.get("error") {
-            bail!("Slack response is not ok: {}", error);
+        if let Some(error) = resp.get("error").and_then(|e| e.as_str()) {
+            metrics::slack_error(error);
+            return Err(SlackApiError(error.to_owned()).into());
         } else {
             bail!("Unexpected Slack response format: {:?}", resp);
         }
@@ -149,3 +1942,221 @@ async fn parse_slack_response(resp: reqwest::Response) -> anyhow::Result<serde_j
 fn get_file_url_from_response(resp: &serde_json::Value) -> Option<&str> {
     resp.get("files")?.get(0)?.get("permalink")?.as_str()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_class() {
+        assert_eq!(ErrorClass::of("invalid_auth"), ErrorClass::Config);
+        assert_eq!(ErrorClass::of("channel_not_found"), ErrorClass::Config);
+        assert_eq!(ErrorClass::of("rate_limited"), ErrorClass::RateLimit);
+        assert_eq!(ErrorClass::of("http_503"), ErrorClass::Outage);
+        let err = anyhow::Error::from(SlackApiError("token_revoked".to_owned()));
+        assert_eq!(ErrorClass::of_error(&err), ErrorClass::Config);
+    }
+
+    #[test]
+    fn test_missing_scopes() {
+        assert!(missing_scopes("chat:write,files:write,commands").is_empty());
+        assert!(missing_scopes("chat:write.public,files:write").is_empty());
+        assert_eq!(missing_scopes("chat:write"), vec!["files:write"]);
+        assert_eq!(missing_scopes(""), vec!["chat:write", "files:write"]);
+    }
+
+    #[test]
+    fn test_parse_file_reuse_window() {
+        assert_eq!(
+            parse_file_reuse_window(None).unwrap(),
+            Some(DEFAULT_FILE_REUSE_WINDOW)
+        );
+        assert_eq!(
+            parse_file_reuse_window(Some("6h")).unwrap(),
+            Some(k8s_openapi::chrono::Duration::hours(6))
+        );
+        assert_eq!(parse_file_reuse_window(Some("0s")).unwrap(), None);
+        assert_eq!(parse_file_reuse_window(Some("0")).unwrap(), None);
+        assert!(parse_file_reuse_window(Some("-1h")).is_err());
+    }
+
+    #[test]
+    fn test_gzip() {
+        use std::io::Read;
+
+        let compressed = gzip(b"hello").unwrap();
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "hello");
+    }
+
+    #[test]
+    fn test_take_due() {
+        let pending = |pod: &str, channel: &str| Pending {
+            restart_info: message::ContainerRestartInfo::synthetic(
+                "default",
+                pod,
+                "app",
+                channel.to_owned(),
+                Default::default(),
+                "test",
+            ),
+            attempts: 1,
+            progress: Progress::default(),
+        };
+        let now = tokio::time::Instant::now();
+        let mut deferred = Deferred::new();
+        deferred.insert(
+            "#alerts".to_owned(),
+            (
+                now + Duration::from_secs(30),
+                [pending("app-0", "#alerts"), pending("app-1", "#alerts")].into(),
+            ),
+        );
+        deferred.insert(
+            "#team".to_owned(),
+            (
+                now + Duration::from_secs(10),
+                [pending("web-0", "#team")].into(),
+            ),
+        );
+        let pods = std::iter::from_fn(|| take_due(&mut deferred))
+            .map(|pending| pending.restart_info.pod_name)
+            .collect::<Vec<_>>();
+        assert_eq!(pods, ["web-0", "app-0", "app-1"]);
+        assert!(deferred.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_rate_limited() {
+        let mut calls = 0;
+        let result = retry_rate_limited(|| {
+            calls += 1;
+            let result = if calls < 3 {
+                Err(RateLimited(Duration::ZERO).into())
+            } else {
+                Ok(calls)
+            };
+            async move { result }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result = retry_rate_limited(|| {
+            calls += 1;
+            async { anyhow::Result::<()>::Err(RateLimited(Duration::ZERO).into()) }
+        })
+        .await;
+        assert_eq!(rate_limited(&result.unwrap_err()), Some(Duration::ZERO));
+        assert_eq!(calls, RATE_LIMIT_MAX_RETRIES + 1);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, "30".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(30)));
+        headers.insert(reqwest::header::RETRY_AFTER, "3600".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(MAX_RETRY_AFTER));
+        headers.insert(reqwest::header::RETRY_AFTER, "invalid".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_read_token_file() {
+        let path = std::env::temp_dir().join(format!(
+            "johari-mirror-slack-token-test-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, "xoxb-1\n").unwrap();
+        assert_eq!(read_token_file(&path).unwrap(), "xoxb-1");
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read_token_file(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_token_file(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_rotated_token() {
+        let path = std::env::temp_dir().join(format!(
+            "johari-mirror-slack-token-rotation-test-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, "xoxb-1\n").unwrap();
+        let slack_token = SlackToken::new("xoxb-1".to_owned());
+        std::fs::write(&path, "xoxb-2\n").unwrap();
+        assert_eq!(
+            wait_rotated_token(Some(&path), &slack_token, "xoxb-1").await,
+            Some("xoxb-2".to_owned())
+        );
+        assert_eq!(slack_token.get(), "xoxb-2");
+        // Already rotated by `watch_token_file`
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            wait_rotated_token(Some(&path), &slack_token, "xoxb-1").await,
+            Some("xoxb-2".to_owned())
+        );
+        // Rotated by Vault or cloud secret managers
+        assert_eq!(
+            wait_rotated_token(None, &slack_token, "xoxb-1").await,
+            Some("xoxb-2".to_owned())
+        );
+        assert!(is_revoked_token(
+            &SlackApiError("token_revoked".to_owned()).into()
+        ));
+        assert!(!is_revoked_token(
+            &SlackApiError("channel_not_found".to_owned()).into()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_log_notifier() {
+        let config = SlackConfig {
+            slack_token: SlackToken::default(),
+            slack_token_file: None,
+            fallback_channel: None,
+            senders: 1,
+            proxy: None,
+            connect_timeout: DEFAULT_SLACK_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_SLACK_REQUEST_TIMEOUT,
+            notification_timeout: DEFAULT_NOTIFICATION_TIMEOUT,
+            file_reuse_window: None,
+            node_correlation: None,
+            resolve_channels: false,
+            check_interval: DEFAULT_SLACK_CHECK_INTERVAL,
+            unreachable_timeout: DEFAULT_SLACK_UNREACHABLE_TIMEOUT,
+        };
+        let stores = SlackStores {
+            message_store: MessageStore::default(),
+            file_store: None,
+            sinks: SinkStores::default(),
+            stability: None,
+            startup_logs: None,
+        };
+        let recent_notifications = stores.sinks.recent_notifications.clone();
+        let (tx, rx) = crate::queue::channel(1, Default::default(), None, Vec::new());
+        tx.send(message::ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            "thread_logs;gzip_logs".parse().unwrap(),
+            "test",
+        ))
+        .await
+        .unwrap();
+        drop(tx);
+        // Sent without network calls
+        let sinks = SinksConfig::new(NotifierKind::Log);
+        slack_send(config, sinks, stores, SelfAlert::default(), None, rx)
+            .await
+            .unwrap();
+        let records = recent_notifications.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].channel, "#alerts");
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{bail, Context};
+use hmac::{Hmac, Mac};
+use k8s_openapi::chrono::Utc;
+use sha2::Sha256;
+
+use crate::silence::{self, Silences};
+
+/// Requests older than this are rejected to prevent replay attacks.
+/// https://api.slack.com/authentication/verifying-requests-from-slack
+const MAX_REQUEST_AGE_SECS: i64 = 60 * 5;
+
+const USAGE: &str = r"Usage:
+`/johari silence <namespace/pod/container> <duration>` Silence notifications, e.g. `/johari silence default/web-*/* 2h`
+`/johari silences` List active silences";
+
+/// Verifies `X-Slack-Signature` of a request from Slack.
+pub fn verify_signature(
+    signing_secret: &str,
+    timestamp: &str,
+    signature: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    let age = Utc::now().timestamp()
+        - timestamp
+            .parse::<i64>()
+            .context("Invalid request timestamp")?;
+    if age.abs() > MAX_REQUEST_AGE_SECS {
+        bail!("Request timestamp is too old: {}", timestamp);
+    }
+    let signature = signature
+        .strip_prefix("v0=")
+        .and_then(|s| hex::decode(s).ok())
+        .context("Invalid signature format")?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_secret.as_bytes())?;
+    mac.update(format!("v0:{timestamp}:").as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).context("Signature mismatch")?;
+    Ok(())
+}
+
+/// Response of a slash command
+#[derive(Debug, PartialEq)]
+pub struct CommandResponse {
+    /// Whether the response is visible to everyone in the channel
+    pub in_channel: bool,
+    pub text: String,
+}
+
+impl CommandResponse {
+    fn ephemeral(text: impl Into<String>) -> Self {
+        Self {
+            in_channel: false,
+            text: text.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "response_type": if self.in_channel { "in_channel" } else { "ephemeral" },
+            "text": &self.text,
+        })
+    }
+}
+
+/// Executes `/johari` slash command with arguments `text` issued by `user`.
+pub fn execute(silences: &Silences, text: &str, user: &str) -> CommandResponse {
+    let args = text.split_whitespace().collect::<Vec<_>>();
+    match args.as_slice() {
+        ["silence", pattern, duration] => {
+            let (pattern, duration) = match (
+                pattern.parse::<silence::SilencePattern>(),
+                silence::parse_duration(duration),
+            ) {
+                (Ok(pattern), Ok(duration)) => (pattern, duration),
+                (Err(err), _) | (_, Err(err)) => {
+                    return CommandResponse::ephemeral(format!("{err}\n{USAGE}"))
+                }
+            };
+            let silence = match silences.add(pattern, duration, user) {
+                Ok(silence) => silence,
+                Err(err) => return CommandResponse::ephemeral(format!("{err}\n{USAGE}")),
+            };
+            log::info!(
+                "Silence added by {user}: {} for {}",
+                silence.pattern,
+                silence::format_duration(duration)
+            );
+            CommandResponse {
+                in_channel: true,
+                text: format!(
+                    "<@{}> silenced `{}` for {} (until {})",
+                    user,
+                    silence.pattern,
+                    silence::format_duration(duration),
+                    silence.expires_at.to_rfc3339(),
+                ),
+            }
+        }
+        ["silences"] => {
+            let active = silences.active();
+            if active.is_empty() {
+                return CommandResponse::ephemeral("No active silences");
+            }
+            let now = Utc::now();
+            let lines = active
+                .iter()
+                .map(|s| {
+                    format!(
+                        "#{} `{}` by <@{}>, expires in {}",
+                        s.id,
+                        s.pattern,
+                        s.created_by,
+                        silence::format_duration(s.expires_at - now)
+                    )
+                })
+                .collect::<Vec<_>>();
+            CommandResponse::ephemeral(format!("Active silences:\n{}", lines.join("\n")))
+        }
+        _ => CommandResponse::ephemeral(USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_signature() {
+        let secret = "8f742231b10e8888abcd99yyyzzz85a5";
+        let timestamp = Utc::now().timestamp().to_string();
+        let body = b"token=xyzz0WbapA4vBCDEFasx0q6G&command=%2Fjohari&text=silences";
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("v0:{timestamp}:").as_bytes());
+        mac.update(body);
+        let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(verify_signature(secret, &timestamp, &signature, body).is_ok());
+        assert!(verify_signature("wrong", &timestamp, &signature, body).is_err());
+        assert!(verify_signature(secret, &timestamp, &signature, b"tampered").is_err());
+        assert!(verify_signature(secret, "1531420618", &signature, body).is_err());
+    }
+
+    #[test]
+    fn test_execute() {
+        let silences = Silences::default();
+        assert_eq!(
+            execute(&silences, "silences", "U1"),
+            CommandResponse::ephemeral("No active silences")
+        );
+        assert!(execute(&silences, "silence default/web-*/* 2h", "U1").in_channel);
+        assert!(silences.find("default", "web-1", "app").is_some());
+        assert!(execute(&silences, "silences", "U1")
+            .text
+            .contains("`default/web-*/*` by <@U1>"));
+        assert!(!execute(&silences, "silence default 2h", "U1").in_channel);
+        assert!(!execute(&silences, "silence */*/* forever", "U1").in_channel);
+        assert!(!execute(&silences, "silence a/b/c 99999999999999d", "U1").in_channel);
+        assert_eq!(
+            execute(&silences, "", "U1"),
+            CommandResponse::ephemeral(USAGE)
+        );
+    }
+}
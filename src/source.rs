@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+use crate::{
+    history::RestartRecord, hooks::Hooks, kubernetes::NotificationOptions,
+    message::ContainerRestartInfo, queue::NotificationSender, silence::Silences, Router,
+};
+
+/// Producer of container restarts, e.g. Kubernetes or other orchestrators.
+/// Sources feed the same routing and notification pipeline through `EventSink`.
+pub trait EventSource: Send + 'static {
+    /// Runs until the source ends or fails, sending detected restarts to `sink`.
+    fn run(self: Box<Self>, sink: EventSink) -> BoxFuture<'static, anyhow::Result<()>>;
+}
+
+/// Routing and queueing of restarts shared by event sources
+#[derive(Clone)]
+pub struct EventSink {
+    router: Arc<Router>,
+    silences: Silences,
+    hooks: Option<Arc<dyn Hooks>>,
+    queue: NotificationSender,
+}
+
+impl EventSink {
+    pub(crate) fn new(
+        router: Router,
+        silences: Silences,
+        hooks: Option<Arc<dyn Hooks>>,
+        queue: NotificationSender,
+    ) -> Self {
+        Self {
+            router: Arc::new(router),
+            silences,
+            hooks,
+            queue,
+        }
+    }
+
+    pub fn router(&self) -> &Router {
+        &self.router
+    }
+
+    pub fn silences(&self) -> &Silences {
+        &self.silences
+    }
+
+    /// Reports a detected restart to `Hooks::on_restart_detected`.
+    /// Sources should report every restart before routing it.
+    pub async fn detected(&self, restart: &RestartRecord) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_restart_detected(restart).await;
+        }
+    }
+
+    /// Returns the channel and options to notify a restart of the container.
+    /// `None` when notification is disabled by the router or silenced.
+    /// Sources should route restarts before collecting logs of them.
+    pub fn route(
+        &self,
+        namespace: &str,
+        pod: &str,
+        container: &str,
+    ) -> Option<(String, NotificationOptions)> {
+        let (channel, options) = self.router.find_route(namespace, pod, container)?;
+        if let Some(silence) = self.silences.find(namespace, pod, container) {
+            log::debug!(
+                "Skipping notification by silence #{}: {namespace}/{pod} - {container}",
+                silence.id
+            );
+            return None;
+        }
+        Some((channel.to_owned(), options.clone()))
+    }
+
+    /// Queues `event` routed to `event.channel` to be notified.
+    pub async fn send(&self, event: ContainerRestartInfo) -> anyhow::Result<()> {
+        self.queue.send(event).await
+    }
+
+    pub(crate) fn hooks(&self) -> Option<&Arc<dyn Hooks>> {
+        self.hooks.as_ref()
+    }
+
+    pub(crate) fn queue(&self) -> &NotificationSender {
+        &self.queue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        history::NotificationRecord,
+        message::{ContainerLog, ContainerResources},
+        Watcher,
+    };
+
+    /// Source sending a restart of each container routed by the sink
+    struct Generator(Vec<(&'static str, &'static str)>);
+
+    impl EventSource for Generator {
+        fn run(self: Box<Self>, sink: EventSink) -> BoxFuture<'static, anyhow::Result<()>> {
+            Box::pin(async move {
+                for (namespace, pod) in self.0 {
+                    sink.detected(&RestartRecord {
+                        time: k8s_openapi::chrono::Utc::now(),
+                        namespace: namespace.to_owned(),
+                        workload: pod.to_owned(),
+                        pod: pod.to_owned(),
+                        container: "app".to_owned(),
+                        restart_count: 1,
+                        exit_code: None,
+                        reason: None,
+                    })
+                    .await;
+                    let Some((channel, options)) = sink.route(namespace, pod, "app") else {
+                        continue;
+                    };
+                    sink.send(ContainerRestartInfo {
+                        namespace: Some(namespace.to_owned()),
+                        pod_name: pod.to_owned(),
+                        pod_uid: None,
+                        container_name: "app".to_owned(),
+                        container_image: "app:latest".to_owned(),
+                        node_name: None,
+                        workload: None,
+                        labels: Default::default(),
+                        restart_count: 1,
+                        last_state: None,
+                        resources: ContainerResources::default(),
+                        logs: ContainerLog(Ok("log".to_owned())),
+                        channel,
+                        options,
+                        links: Vec::new(),
+                        notes: Vec::new(),
+                        category: None,
+                        sidecar_logs: Vec::new(),
+                        priority: None,
+                        node_info: None,
+                        attachments: Vec::new(),
+                        span: tracing::Span::none(),
+                        queue_id: None,
+                    })
+                    .await?;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn router() -> Router {
+        Router::builder()
+            .ignore("kube-system", "*", "*")
+            .route("*", "*", "*", "#alerts")
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_custom_source() {
+        let notified = Arc::new(Mutex::new(Vec::new()));
+        let router = router();
+        Watcher::builder_without_kubernetes()
+            .router(router)
+            .source(Generator(vec![
+                ("default", "app-0"),
+                ("kube-system", "coredns-0"),
+                ("default", "app-1"),
+            ]))
+            .notifier({
+                let notified = Arc::clone(&notified);
+                move |event: ContainerRestartInfo| {
+                    let notified = Arc::clone(&notified);
+                    async move {
+                        notified.lock().unwrap().push(format!(
+                            "{} {}",
+                            event.channel,
+                            event.container_key()
+                        ));
+                        Ok(())
+                    }
+                }
+            })
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(
+            *notified.lock().unwrap(),
+            ["#alerts default/app-0/app", "#alerts default/app-1/app"]
+        );
+    }
+
+    /// Records calls of hooks and drops notifications of `app-1`
+    #[derive(Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Hooks for Recorder {
+        fn on_restart_detected<'a>(&'a self, restart: &'a RestartRecord) -> BoxFuture<'a, ()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("detected {}", restart.pod));
+            Box::pin(async {})
+        }
+
+        fn before_send(
+            &self,
+            event: ContainerRestartInfo,
+        ) -> BoxFuture<'_, Option<ContainerRestartInfo>> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("before_send {}", event.pod_name));
+            Box::pin(async move { (event.pod_name != "app-1").then_some(event) })
+        }
+
+        fn after_send<'a>(
+            &'a self,
+            notification: &'a NotificationRecord,
+            result: &'a anyhow::Result<()>,
+        ) -> BoxFuture<'a, ()> {
+            self.0.lock().unwrap().push(format!(
+                "after_send {} {}",
+                notification.pod,
+                result.is_ok()
+            ));
+            Box::pin(async {})
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let recorder = Recorder::default();
+        let calls = Arc::clone(&recorder.0);
+        Watcher::builder_without_kubernetes()
+            .router(router())
+            .source(Generator(vec![
+                ("default", "app-0"),
+                ("kube-system", "coredns-0"),
+                ("default", "app-1"),
+            ]))
+            .hooks(recorder)
+            .notifier(|_| async { Ok(()) })
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        let mut calls = calls.lock().unwrap().clone();
+        // Hooks of detection and notification run concurrently
+        calls.sort();
+        assert_eq!(
+            calls,
+            [
+                "after_send app-0 true",
+                "before_send app-0",
+                "before_send app-1",
+                "detected app-0",
+                "detected app-1",
+                "detected coredns-0",
+            ]
+        );
+    }
+}
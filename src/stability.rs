@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use k8s_openapi::{
+    api::core::v1::Pod,
+    chrono::{self, DateTime, Utc},
+};
+use kube::{
+    runtime::reflector::{ObjectRef, Store},
+    ResourceExt,
+};
+
+use crate::{
+    message::{self, ContainerRestartInfo},
+    message_store::PostedMessage,
+    slack::SlackPoster,
+};
+
+/// Interval to check whether notified containers have stabilized
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Notified container waiting to stay up for `StabilityTracker::stable_after`
+#[derive(Debug, Clone)]
+struct Pending {
+    namespace: String,
+    pod_name: String,
+    pod_uid: Option<String>,
+    container_name: String,
+    /// Notification to reply to in the thread
+    posted: PostedMessage,
+}
+
+/// State of a pending container in the pod store
+#[derive(Debug, PartialEq, Eq)]
+enum Stability {
+    /// Running without restarts since the time for `stable_after`
+    Stable(DateTime<Utc>),
+    /// Not running yet or not for long enough
+    Waiting,
+    /// The pod or container no longer exists, e.g. replaced by a rollout
+    Gone,
+}
+
+/// Replies to notifications when the containers stay up for `stable_after` after them,
+/// so that channels know the restarts were resolved
+#[derive(Clone)]
+pub struct StabilityTracker {
+    stable_after: chrono::Duration,
+    /// Keyed by `ContainerRestartInfo::container_key`
+    pending: Arc<Mutex<HashMap<String, Pending>>>,
+}
+
+impl StabilityTracker {
+    pub fn new(stable_after: chrono::Duration) -> Self {
+        Self {
+            stable_after,
+            pending: Arc::default(),
+        }
+    }
+
+    /// Waits for the container of the notification `posted` to stabilize,
+    /// replacing the notification of its previous restart.
+    pub fn record(&self, info: &ContainerRestartInfo, posted: &PostedMessage) {
+        let Some(namespace) = info.namespace.clone() else {
+            return;
+        };
+        self.pending.lock().unwrap().insert(
+            info.container_key(),
+            Pending {
+                namespace,
+                pod_name: info.pod_name.clone(),
+                pod_uid: info.pod_uid.clone(),
+                container_name: info.container_name.clone(),
+                posted: posted.clone(),
+            },
+        );
+    }
+
+    /// Task to reply to the notifications of containers which have stabilized
+    pub async fn follow_up(self, poster: SlackPoster, pod_store: Store<Pod>) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            // The store is empty until the watcher is initialized
+            if pod_store.state().is_empty() {
+                continue;
+            }
+            let now = Utc::now();
+            let mut stable = Vec::new();
+            self.pending.lock().unwrap().retain(|_, pending| {
+                let pod =
+                    pod_store.get(&ObjectRef::new(&pending.pod_name).within(&pending.namespace));
+                match check(pending, pod.as_deref(), now, self.stable_after) {
+                    Stability::Stable(since) => {
+                        stable.push((pending.clone(), since));
+                        false
+                    }
+                    Stability::Waiting => true,
+                    Stability::Gone => false,
+                }
+            });
+            for (pending, since) in stable {
+                let blocks = message::stable_message(&pending.container_name, since);
+                match poster.reply(&pending.posted, blocks).await {
+                    Ok(()) => log::info!(
+                        "{}/{}/{} has stabilized since {since}",
+                        pending.namespace,
+                        pending.pod_name,
+                        pending.container_name
+                    ),
+                    Err(e) => log::warn!(
+                        "Failed to post that {}/{}/{} has stabilized: {e}",
+                        pending.namespace,
+                        pending.pod_name,
+                        pending.container_name
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Whether the container in `pod` has been running for `stable_after`
+fn check(
+    pending: &Pending,
+    pod: Option<&Pod>,
+    now: DateTime<Utc>,
+    stable_after: chrono::Duration,
+) -> Stability {
+    let Some(pod) = pod else {
+        return Stability::Gone;
+    };
+    if pending.pod_uid.is_some() && pod.uid() != pending.pod_uid {
+        return Stability::Gone;
+    }
+    let Some(status) = pod
+        .status
+        .iter()
+        .flat_map(|st| st.container_statuses.iter().flatten())
+        .find(|st| st.name == pending.container_name)
+    else {
+        return Stability::Gone;
+    };
+    let started_at = status
+        .state
+        .as_ref()
+        .and_then(|state| state.running.as_ref())
+        .and_then(|running| running.started_at.as_ref());
+    match started_at {
+        Some(started_at) if now - started_at.0 >= stable_after => Stability::Stable(started_at.0),
+        _ => Stability::Waiting,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::{ContainerState, ContainerStateRunning, ContainerStatus, PodStatus},
+        apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time},
+    };
+
+    use super::*;
+
+    fn pending() -> Pending {
+        Pending {
+            namespace: "default".to_owned(),
+            pod_name: "app-0".to_owned(),
+            pod_uid: Some("uid-1".to_owned()),
+            container_name: "app".to_owned(),
+            posted: PostedMessage {
+                channel: "C01".to_owned(),
+                ts: "1704164645.000100".to_owned(),
+                notified_restarts: 1,
+                updated_at: Utc::now(),
+                pods: Default::default(),
+            },
+        }
+    }
+
+    fn pod(uid: &str, started_at: Option<DateTime<Utc>>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("app-0".to_owned()),
+                uid: Some(uid.to_owned()),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    name: "app".to_owned(),
+                    restart_count: 3,
+                    state: Some(ContainerState {
+                        running: started_at.map(|t| ContainerStateRunning {
+                            started_at: Some(Time(t)),
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let now = Utc::now();
+        let stable_after = chrono::Duration::minutes(30);
+        let started_at = now - chrono::Duration::minutes(31);
+        assert_eq!(
+            check(
+                &pending(),
+                Some(&pod("uid-1", Some(started_at))),
+                now,
+                stable_after
+            ),
+            Stability::Stable(started_at)
+        );
+        let recent = now - chrono::Duration::minutes(5);
+        assert_eq!(
+            check(
+                &pending(),
+                Some(&pod("uid-1", Some(recent))),
+                now,
+                stable_after
+            ),
+            Stability::Waiting
+        );
+        // e.g. waiting in CrashLoopBackOff
+        assert_eq!(
+            check(&pending(), Some(&pod("uid-1", None)), now, stable_after),
+            Stability::Waiting
+        );
+        assert_eq!(
+            check(
+                &pending(),
+                Some(&pod("uid-2", Some(started_at))),
+                now,
+                stable_after
+            ),
+            Stability::Gone
+        );
+        assert_eq!(check(&pending(), None, now, stable_after), Stability::Gone);
+    }
+}
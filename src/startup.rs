@@ -0,0 +1,104 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::middleware::RestartsSummary;
+#[cfg(feature = "slack")]
+use crate::{message, slack::SlackPoster};
+
+/// Restarts detected during the grace period after the initial list of pods,
+/// which are summarized in one message per channel instead of notified individually.
+/// This avoids a flood of notifications when johari-mirror itself restarts mid-incident.
+#[derive(Debug, Clone)]
+pub struct StartupGrace {
+    inner: Arc<Mutex<StartupGraceInner>>,
+    started: Arc<Notify>,
+    period: Duration,
+}
+
+#[derive(Debug, Default)]
+struct StartupGraceInner {
+    ended: bool,
+    restarts: RestartsSummary,
+}
+
+impl StartupGrace {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            inner: Arc::default(),
+            started: Arc::default(),
+            period,
+        }
+    }
+
+    /// Starts the grace period when the initial list of pods is received.
+    /// Only the first call has effect.
+    pub fn start(&self) {
+        self.started.notify_one();
+    }
+
+    /// Records a restart of `container_key` to be summarized to `channel`.
+    /// Returns false after the grace period, when the restart should be notified individually.
+    pub fn record(&self, channel: &str, container_key: String) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.ended {
+            return false;
+        }
+        *inner
+            .restarts
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(container_key)
+            .or_default() += 1;
+        true
+    }
+
+    fn end(&self) -> RestartsSummary {
+        let mut inner = self.inner.lock().unwrap();
+        inner.ended = true;
+        std::mem::take(&mut inner.restarts)
+    }
+
+    /// Waits for the end of the grace period and returns the restarts recorded in it.
+    pub async fn wait_end(self) -> RestartsSummary {
+        self.started.notified().await;
+        tokio::time::sleep(self.period).await;
+        self.end()
+    }
+
+    /// Task to post the summary of restarts to each channel at the end of the grace period
+    #[cfg(feature = "slack")]
+    pub async fn post_summary(self, poster: SlackPoster) {
+        let restarts = self.wait_end().await;
+        for (channel, restarts) in restarts {
+            log::info!(
+                "Posting {} restarts detected on startup to {channel}",
+                restarts.values().sum::<usize>()
+            );
+            let blocks = message::startup_summary(&restarts);
+            if let Err(e) = poster.post_blocks(&channel, blocks).await {
+                log::error!("Failed to post restarts detected on startup to {channel}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_grace() {
+        let grace = StartupGrace::new(Duration::from_secs(60));
+        assert!(grace.record("#alerts", "default/app-0/app".to_owned()));
+        assert!(grace.record("#alerts", "default/app-0/app".to_owned()));
+        assert!(grace.record("#other", "default/web-0/web".to_owned()));
+        let restarts = grace.end();
+        assert_eq!(restarts["#alerts"]["default/app-0/app"], 2);
+        assert_eq!(restarts["#other"]["default/web-0/web"], 1);
+        assert!(!grace.record("#alerts", "default/app-0/app".to_owned()));
+    }
+}
@@ -0,0 +1,239 @@
+use std::{sync::Arc, time::Duration};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{api::LogParams, Api, Client, ResourceExt};
+
+use crate::{
+    message::{self, ContainerRestartInfo},
+    message_store::PostedMessage,
+    middleware::Redact,
+    slack::SlackPoster,
+};
+
+/// Default wait time after the restart before capturing the startup logs
+pub const DEFAULT_STARTUP_LOG_DELAY: Duration = Duration::from_secs(30);
+
+/// Bytes of logs read from the start of the new container, enough for the first lines
+const STARTUP_LOG_MAX_BYTES: i64 = 64 * 1024;
+
+/// Timeout of each request to capture the startup logs
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State of the restarted container when the startup logs are captured
+#[derive(Debug, PartialEq, Eq)]
+pub enum Health {
+    Ready,
+    /// Running but not passing the readiness probe yet
+    NotReady,
+    /// Waiting with the reason, e.g. `CrashLoopBackOff`
+    Waiting(String),
+    /// Restarted again, with the restart count
+    Restarted(i32),
+    /// The pod or container no longer exists, e.g. replaced by a rollout
+    Gone,
+}
+
+impl Health {
+    /// Shown at the top of the reply
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Ready => ":large_green_circle: Running and ready".to_owned(),
+            Self::NotReady => ":large_yellow_circle: Running, not ready yet".to_owned(),
+            Self::Waiting(reason) => format!(":red_circle: Waiting: {reason}"),
+            Self::Restarted(count) => format!(":red_circle: Restarted again ({count} restarts)"),
+            Self::Gone => ":white_circle: The pod no longer exists".to_owned(),
+        }
+    }
+}
+
+/// Replies to notifications with the first lines of logs of the new container a while
+/// after the restart, showing whether the new instance started up healthy
+#[derive(Clone)]
+pub struct StartupLogs {
+    client: Client,
+    delay: Duration,
+    /// Redaction configured in `NOTIFICATION_MIDDLEWARES`
+    redact: Option<Arc<Redact>>,
+}
+
+impl StartupLogs {
+    pub fn new(client: Client, delay: Duration, redact: Option<Redact>) -> Self {
+        Self {
+            client,
+            delay,
+            redact: redact.map(Arc::new),
+        }
+    }
+
+    /// Captures the startup logs in the background when the route of `info` has the
+    /// `startup_logs` option. Failures are only logged.
+    pub fn capture(
+        &self,
+        info: &ContainerRestartInfo,
+        posted: &PostedMessage,
+        poster: SlackPoster,
+    ) {
+        let (Some(lines), Some(namespace)) = (info.options.startup_logs, info.namespace.clone())
+        else {
+            return;
+        };
+        let startup_logs = self.clone();
+        let pod_name = info.pod_name.clone();
+        let pod_uid = info.pod_uid.clone();
+        let container = info.container_name.clone();
+        let restart_count = info.restart_count;
+        let posted = posted.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(startup_logs.delay).await;
+            let pods: Api<Pod> = Api::namespaced(startup_logs.client.clone(), &namespace);
+            let health = match tokio::time::timeout(REQUEST_TIMEOUT, pods.get_opt(&pod_name)).await
+            {
+                Ok(Ok(pod)) => check(pod.as_ref(), pod_uid.as_deref(), &container, restart_count),
+                Ok(Err(e)) => {
+                    log::warn!("Failed to get pod {namespace}/{pod_name}: {e}");
+                    return;
+                }
+                Err(_) => {
+                    log::warn!("Timed out getting pod {namespace}/{pod_name}");
+                    return;
+                }
+            };
+            let logs = match health {
+                Health::Gone => Err("The pod no longer exists".to_owned()),
+                _ => fetch_head(&pods, &pod_name, &container, lines).await,
+            };
+            let logs = match &startup_logs.redact {
+                Some(redact) => logs.map(|logs| redact.redact(&logs)),
+                None => logs,
+            };
+            let blocks = message::startup_log_message(
+                &container,
+                startup_logs.delay,
+                &health.describe(),
+                &logs,
+            );
+            if let Err(e) = poster.reply(&posted, blocks).await {
+                log::warn!(
+                    "Failed to post startup logs of {namespace}/{pod_name}/{container}: {e}"
+                );
+            }
+        });
+    }
+}
+
+/// Fetches the first `lines` lines of the logs of the running `container`
+async fn fetch_head(
+    pods: &Api<Pod>,
+    pod_name: &str,
+    container: &str,
+    lines: usize,
+) -> Result<String, String> {
+    let params = LogParams {
+        container: Some(container.to_owned()),
+        limit_bytes: Some(STARTUP_LOG_MAX_BYTES),
+        ..Default::default()
+    };
+    match tokio::time::timeout(REQUEST_TIMEOUT, pods.logs(pod_name, &params)).await {
+        Ok(Ok(logs)) => Ok(head(&logs, lines)),
+        Ok(Err(e)) => Err(format!("Failed to fetch logs: {e}")),
+        Err(_) => Err(format!(
+            "Timed out after {} seconds",
+            REQUEST_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// First `lines` lines of `logs`
+fn head(logs: &str, lines: usize) -> String {
+    logs.lines().take(lines).collect::<Vec<_>>().join("\n")
+}
+
+/// Health of `container` in `pod`, which was notified at `restart_count` restarts
+fn check(pod: Option<&Pod>, pod_uid: Option<&str>, container: &str, restart_count: i32) -> Health {
+    let Some(pod) = pod.filter(|pod| pod_uid.is_none() || pod.uid().as_deref() == pod_uid) else {
+        return Health::Gone;
+    };
+    let Some(status) = pod
+        .status
+        .iter()
+        .flat_map(|st| st.container_statuses.iter().flatten())
+        .find(|st| st.name == container)
+    else {
+        return Health::Gone;
+    };
+    if status.restart_count > restart_count {
+        return Health::Restarted(status.restart_count);
+    }
+    let state = status.state.as_ref();
+    if let Some(waiting) = state.and_then(|state| state.waiting.as_ref()) {
+        return Health::Waiting(
+            waiting
+                .reason
+                .clone()
+                .unwrap_or_else(|| "unknown".to_owned()),
+        );
+    }
+    if status.ready {
+        Health::Ready
+    } else {
+        Health::NotReady
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::{ContainerState, ContainerStateWaiting, ContainerStatus, PodStatus},
+        apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    };
+
+    use super::*;
+
+    fn pod(restart_count: i32, ready: bool, waiting: Option<&str>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("app-0".to_owned()),
+                uid: Some("uid-1".to_owned()),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    name: "app".to_owned(),
+                    restart_count,
+                    ready,
+                    state: Some(ContainerState {
+                        waiting: waiting.map(|reason| ContainerStateWaiting {
+                            reason: Some(reason.to_owned()),
+                            message: None,
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let check = |pod: &Pod, uid| check(Some(pod), uid, "app", 3);
+        assert_eq!(check(&pod(3, true, None), Some("uid-1")), Health::Ready);
+        assert_eq!(check(&pod(3, false, None), Some("uid-1")), Health::NotReady);
+        assert_eq!(
+            check(&pod(3, false, Some("CrashLoopBackOff")), None),
+            Health::Waiting("CrashLoopBackOff".to_owned())
+        );
+        assert_eq!(check(&pod(4, true, None), None), Health::Restarted(4));
+        // Replaced by another pod of the same name
+        assert_eq!(check(&pod(0, true, None), Some("uid-2")), Health::Gone);
+        assert_eq!(super::check(None, None, "app", 3), Health::Gone);
+    }
+
+    #[test]
+    fn test_head() {
+        assert_eq!(head("a\nb\nc\n", 2), "a\nb");
+        assert_eq!(head("a\n", 5), "a");
+    }
+}
@@ -0,0 +1,354 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use k8s_openapi::chrono::{DateTime, SecondsFormat, Utc};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use tokio_rustls::{rustls, TlsConnector};
+
+use crate::{http, message::ContainerRestartInfo};
+
+/// `Signature ID` of CEF records and `MSGID` of syslog records
+const EVENT_CLASS_ID: &str = "ContainerRestarted";
+
+/// Syslog severity of restarts without a recognized `severity` option: warning
+const DEFAULT_SEVERITY: u8 = 4;
+
+/// Maximum length of `HOSTNAME` of RFC 5424
+const MAX_HOSTNAME_LEN: usize = 255;
+
+/// Timeout of connecting and sending each record not to delay notifications
+const SYSLOG_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Transport of syslog records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    /// A datagram per record (RFC 5426)
+    Udp,
+    /// Octet-counted records (RFC 6587)
+    Tcp,
+    /// Octet-counted records over TLS (RFC 5425)
+    Tls,
+}
+
+impl std::str::FromStr for SyslogProtocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "udp" => Ok(Self::Udp),
+            "tcp" => Ok(Self::Tcp),
+            "tls" => Ok(Self::Tls),
+            _ => bail!("Unknown syslog protocol: {s}"),
+        }
+    }
+}
+
+/// Configuration of sending restarts to a syslog receiver, e.g. of a SIEM, as CEF records
+#[derive(Debug, Clone)]
+pub struct SyslogConfig {
+    /// `host:port` of the receiver
+    pub address: String,
+    pub protocol: SyslogProtocol,
+    /// Facility code, e.g. 16 for `local0`
+    pub facility: u8,
+    /// PEM file of CA certificates trusted with `tls` in addition to the public roots
+    /// and `TLS_CA_FILE`
+    pub ca_file: Option<String>,
+    /// `HOSTNAME` of records and `cs5` of CEF when set
+    pub cluster_name: Option<String>,
+}
+
+impl SyslogConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `SYSLOG_ADDRESS` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(address) = std::env::var("SYSLOG_ADDRESS") else {
+            return Ok(None);
+        };
+        if address.rsplit_once(':').is_none() {
+            bail!("SYSLOG_ADDRESS must be in the host:port format");
+        }
+        let protocol = match std::env::var("SYSLOG_PROTOCOL") {
+            Ok(protocol) => protocol.parse().context("Invalid SYSLOG_PROTOCOL")?,
+            Err(_) => SyslogProtocol::Udp,
+        };
+        let facility = match std::env::var("SYSLOG_FACILITY") {
+            Ok(facility) => parse_facility(&facility).context("Invalid SYSLOG_FACILITY")?,
+            Err(_) => parse_facility("local0")?,
+        };
+        let ca_file = std::env::var("SYSLOG_TLS_CA_FILE").ok();
+        if protocol == SyslogProtocol::Tls {
+            // Fail fast on invalid certificates instead of failing on the first restart
+            tls_connector(ca_file.as_deref()).context("Invalid SYSLOG_TLS_CA_FILE")?;
+        }
+        let cluster_name = std::env::var("CLUSTER_NAME").ok();
+        Ok(Some(Self {
+            address,
+            protocol,
+            facility,
+            ca_file,
+            cluster_name,
+        }))
+    }
+}
+
+/// Parses facility keywords of RFC 5424 used by applications
+fn parse_facility(s: &str) -> anyhow::Result<u8> {
+    match s {
+        "user" => Ok(1),
+        "daemon" => Ok(3),
+        "auth" => Ok(4),
+        "authpriv" => Ok(10),
+        _ => match s.strip_prefix("local").and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) if n <= 7 => Ok(16 + n),
+            _ => bail!("Unknown syslog facility: {s}"),
+        },
+    }
+}
+
+/// Sends a CEF record per restart over syslog
+#[derive(Clone)]
+pub struct Syslog {
+    config: SyslogConfig,
+    tls: Option<TlsConnector>,
+}
+
+impl Syslog {
+    /// Loads the CA certificates with `tls`
+    pub fn new(config: SyslogConfig) -> anyhow::Result<Self> {
+        let tls = match config.protocol {
+            SyslogProtocol::Tls => Some(tls_connector(config.ca_file.as_deref())?),
+            SyslogProtocol::Udp | SyslogProtocol::Tcp => None,
+        };
+        Ok(Self { config, tls })
+    }
+
+    /// Sends the record on a new connection, as restarts are too rare to keep one open
+    pub async fn send(&self, info: &ContainerRestartInfo) -> anyhow::Result<()> {
+        let record = record(info, &self.config, Utc::now());
+        tokio::time::timeout(SYSLOG_TIMEOUT, self.send_record(&record))
+            .await
+            .context("Timed out sending to syslog")??;
+        log::debug!("Sent restart of {info} to syslog");
+        Ok(())
+    }
+
+    async fn send_record(&self, record: &str) -> anyhow::Result<()> {
+        let address = &self.config.address;
+        match &self.tls {
+            None if self.config.protocol == SyslogProtocol::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(address).await?;
+                socket.send(record.as_bytes()).await?;
+            }
+            None => write_framed(TcpStream::connect(address).await?, record).await?,
+            Some(tls) => {
+                let (host, _) = address.rsplit_once(':').unwrap_or_default();
+                let server_name = rustls::ServerName::try_from(host.trim_matches(['[', ']']))
+                    .context("Invalid host of SYSLOG_ADDRESS")?;
+                let stream = TcpStream::connect(address).await?;
+                write_framed(tls.connect(server_name, stream).await?, record).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the record with the octet-counting framing of RFC 6587
+async fn write_framed(mut stream: impl AsyncWrite + Unpin, record: &str) -> anyhow::Result<()> {
+    stream
+        .write_all(format!("{} {record}", record.len()).as_bytes())
+        .await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Connector with the shared TLS configuration of `http`, i.e. `TLS_CA_FILE` and the client
+/// certificate, trusting `ca_file` in addition
+fn tls_connector(ca_file: Option<&str>) -> anyhow::Result<TlsConnector> {
+    let extra_roots = match ca_file {
+        Some(path) => {
+            let pem = std::fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+            rustls_pemfile::certs(&mut pem.as_slice())?
+        }
+        None => Vec::new(),
+    };
+    let config = http::rustls_client_config(&extra_roots)?;
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Syslog severity of the `severity` option, e.g. 2 for `critical`
+fn syslog_severity(severity: Option<&str>) -> u8 {
+    match severity.map(str::to_ascii_lowercase).as_deref() {
+        Some("emergency" | "emerg") => 0,
+        Some("alert") => 1,
+        Some("critical" | "crit") => 2,
+        Some("error" | "err") => 3,
+        Some("notice") => 5,
+        Some("info") => 6,
+        Some("debug") => 7,
+        _ => DEFAULT_SEVERITY,
+    }
+}
+
+/// RFC 5424 record with a CEF message. Logs are not included to fit in a UDP datagram.
+fn record(info: &ContainerRestartInfo, config: &SyslogConfig, now: DateTime<Utc>) -> String {
+    let severity = syslog_severity(info.options.severity.as_deref());
+    let priority = u16::from(config.facility) * 8 + u16::from(severity);
+    format!(
+        "<{priority}>1 {} {} johari-mirror - {EVENT_CLASS_ID} - {}",
+        now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        hostname(config.cluster_name.as_deref()),
+        cef(info, config.cluster_name.as_deref(), severity),
+    )
+}
+
+/// `HOSTNAME` of RFC 5424, which is up to 255 printable ASCII characters without spaces.
+/// Other characters of `cluster_name` are replaced with `_`.
+fn hostname(cluster_name: Option<&str>) -> String {
+    match cluster_name.filter(|name| !name.is_empty()) {
+        Some(name) => name
+            .chars()
+            .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+            .take(MAX_HOSTNAME_LEN)
+            .collect(),
+        None => "-".to_owned(),
+    }
+}
+
+/// CEF record of a restart. `rt` is when the container finished, if known.
+fn cef(info: &ContainerRestartInfo, cluster_name: Option<&str>, syslog_severity: u8) -> String {
+    // CEF severities are 0 to 10 in the ascending order
+    let severity = [10, 9, 8, 7, 5, 3, 2, 0][usize::from(syslog_severity)];
+    let state = info.last_state.as_ref();
+    let mut extension = Vec::new();
+    if let Some(finished_at) = state
+        .and_then(|s| s.finished_at.as_deref())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+    {
+        extension.push(("rt", finished_at.timestamp_millis().to_string()));
+    }
+    let namespace = info.namespace.as_deref().unwrap_or("");
+    extension.extend([
+        (
+            "msg",
+            format!("Container {} restarted", info.container_key()),
+        ),
+        ("cs1Label", "namespace".to_owned()),
+        ("cs1", namespace.to_owned()),
+        ("cs2Label", "pod".to_owned()),
+        ("cs2", info.pod_name.clone()),
+        ("cs3Label", "container".to_owned()),
+        ("cs3", info.container_name.clone()),
+        ("cs4Label", "image".to_owned()),
+        ("cs4", info.container_image.clone()),
+        ("cs6Label", "channel".to_owned()),
+        ("cs6", info.channel.clone()),
+        ("cn1Label", "restartCount".to_owned()),
+        ("cn1", info.restart_count.to_string()),
+    ]);
+    if let Some(cluster_name) = cluster_name {
+        extension.extend([
+            ("cs5Label", "cluster".to_owned()),
+            ("cs5", cluster_name.to_owned()),
+        ]);
+    }
+    if let Some(state) = state {
+        extension.extend([
+            ("cn2Label", "exitCode".to_owned()),
+            ("cn2", state.exit_code.to_string()),
+        ]);
+        if let Some(reason) = &state.reason {
+            extension.push(("reason", reason.clone()));
+        }
+    }
+    if let Some(node_name) = &info.node_name {
+        extension.push(("dvchost", node_name.clone()));
+    }
+    let extension = extension
+        .iter()
+        .map(|(key, value)| format!("{key}={}", escape_extension(value)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "CEF:0|flywheel|johari-mirror|{}|{EVENT_CLASS_ID}|Container restarted|{severity}|{extension}",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Escapes a value of the CEF extension
+fn escape_extension(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('=', r"\=")
+        .replace("\r\n", r"\n")
+        .replace(['\r', '\n'], r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::TimeZone;
+
+    use super::*;
+
+    fn config() -> SyslogConfig {
+        SyslogConfig {
+            address: "siem:514".to_owned(),
+            protocol: SyslogProtocol::Udp,
+            facility: 16,
+            ca_file: None,
+            cluster_name: Some("production".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        info.options.severity = Some("critical".to_owned());
+        info.last_state.as_mut().unwrap().finished_at = Some("2024-01-02T03:04:05Z".to_owned());
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 5, 0).unwrap();
+        let record = record(&info, &config(), now);
+        assert!(record.starts_with(&format!(
+            "<130>1 2024-01-02T03:05:00.000Z production johari-mirror - ContainerRestarted - \
+            CEF:0|flywheel|johari-mirror|{}|ContainerRestarted|Container restarted|8|rt=1704164645000 ",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(record.contains(" cs2Label=pod cs2=app-0 "));
+        assert!(record.contains(" cs5Label=cluster cs5=production "));
+        assert!(record.contains(" cn2Label=exitCode cn2=1 reason=Error"));
+    }
+
+    #[test]
+    fn test_hostname() {
+        assert_eq!(hostname(Some("production")), "production");
+        assert_eq!(hostname(Some("prod cluster\tÉ")), "prod_cluster__");
+        assert_eq!(hostname(Some(&"a".repeat(300))).len(), 255);
+        assert_eq!(hostname(Some("")), "-");
+        assert_eq!(hostname(None), "-");
+    }
+
+    #[test]
+    fn test_escape_extension() {
+        assert_eq!(escape_extension(r"a=b\c"), r"a\=b\\c");
+        assert_eq!(escape_extension("a\r\nb\nc"), r"a\nb\nc");
+        assert_eq!(escape_extension("a|b"), "a|b");
+    }
+
+    #[test]
+    fn test_parse_facility() {
+        assert_eq!(parse_facility("daemon").unwrap(), 3);
+        assert_eq!(parse_facility("local7").unwrap(), 23);
+        assert!(parse_facility("local8").is_err());
+        assert!(parse_facility("unknown").is_err());
+    }
+}
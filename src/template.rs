@@ -0,0 +1,204 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+
+use crate::message::{escape_mrkdwn, ContainerRestartInfo};
+
+/// Placeholders in strings of templates
+const PLACEHOLDERS: &[&str] = &[
+    "namespace",
+    "pod",
+    "container",
+    "image",
+    "node",
+    "workload",
+    "channel",
+    "restart_count",
+    "exit_code",
+    "reason",
+    "category",
+    "severity",
+    "logs_url",
+];
+
+/// Named Block Kit layouts of notifications read from `MESSAGE_TEMPLATES_PATH`,
+/// selected per rule with the `template` option.
+/// The file is a JSON object of names and arrays of blocks, where `{name}` in strings is
+/// replaced with the value of the placeholder, e.g. `{namespace}`.
+#[derive(Debug, Clone, Default)]
+pub struct MessageTemplates(HashMap<String, Vec<serde_json::Value>>);
+
+impl MessageTemplates {
+    /// Reads templates from `MESSAGE_TEMPLATES_PATH`, or no templates when not set.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("MESSAGE_TEMPLATES_PATH") {
+            Ok(path) => Self::load(Path::new(&path)).context("Invalid MESSAGE_TEMPLATES_PATH"),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        json.parse()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Blocks of the template `name` for `info`, `None` when there is no such template.
+    /// Values are escaped for mrkdwn, and `logs_url` is empty when logs are not uploaded.
+    pub fn render(
+        &self,
+        name: &str,
+        info: &ContainerRestartInfo,
+        file_urls: &[String],
+    ) -> Option<Vec<serde_json::Value>> {
+        let blocks = self.0.get(name)?;
+        let value = |placeholder: &str| {
+            let value = match placeholder {
+                "namespace" => info.namespace.clone().unwrap_or_default(),
+                "pod" => info.pod_name.clone(),
+                "container" => info.container_name.clone(),
+                "image" => info.container_image.clone(),
+                "node" => info.node_name.clone().unwrap_or_default(),
+                "workload" => info.workload.clone().unwrap_or_default(),
+                "channel" => info.channel.clone(),
+                "restart_count" => info.restart_count.to_string(),
+                "exit_code" => match &info.last_state {
+                    Some(state) => state.exit_code.to_string(),
+                    None => String::new(),
+                },
+                "reason" => info
+                    .last_state
+                    .as_ref()
+                    .and_then(|state| state.reason.clone())
+                    .unwrap_or_default(),
+                "category" => info
+                    .category
+                    .map(|category| category.label().to_owned())
+                    .unwrap_or_default(),
+                "severity" => info.options.severity.clone().unwrap_or_default(),
+                "logs_url" => file_urls.first().cloned().unwrap_or_default(),
+                _ => return None,
+            };
+            Some(escape_mrkdwn(&value))
+        };
+        let mut blocks = blocks.clone();
+        for block in &mut blocks {
+            // Placeholders are validated when the templates are read
+            map_strings(block, &|s| render(s, value)).ok()?;
+        }
+        Some(blocks)
+    }
+
+    /// Blocks of the notification laid out by the template of the rule if any.
+    /// Unknown templates, e.g. of notifications queued on disk before the templates
+    /// changed, fall back to the built-in layout.
+    pub fn notification_blocks(
+        &self,
+        info: &ContainerRestartInfo,
+        file_urls: &[String],
+    ) -> Vec<serde_json::Value> {
+        if let Some(name) = &info.options.template {
+            match self.render(name, info, file_urls) {
+                Some(blocks) => return blocks,
+                None => log::warn!("Unknown message template {name}, using the default layout"),
+            }
+        }
+        if info.options.thread_logs {
+            info.to_summary_message()
+        } else {
+            info.to_message(file_urls)
+        }
+    }
+}
+
+impl std::str::FromStr for MessageTemplates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let templates = serde_json::from_str::<HashMap<String, Vec<serde_json::Value>>>(s)
+            .context("Templates must be a JSON object of arrays of blocks")?;
+        let known = |p: &str| PLACEHOLDERS.contains(&p).then(String::new);
+        for (name, blocks) in &templates {
+            for block in blocks {
+                map_strings(&mut block.clone(), &|s| render(s, known))
+                    .with_context(|| format!("Invalid template {name}"))?;
+            }
+        }
+        Ok(Self(templates))
+    }
+}
+
+/// Replaces strings in `value` recursively with `f`, stopping at the first error
+fn map_strings(
+    value: &mut serde_json::Value,
+    f: &impl Fn(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<()> {
+    match value {
+        serde_json::Value::String(s) => *s = f(s)?,
+        serde_json::Value::Array(items) => {
+            for item in items {
+                map_strings(item, f)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                map_strings(item, f)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces `{name}` in `template` with `value(name)`, which is `None` for unknown names
+fn render(template: &str, value: impl Fn(&str) -> Option<String>) -> anyhow::Result<String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in {template}"))?;
+        let name = &rest[start + 1..start + end];
+        rendered.push_str(&value(name).with_context(|| format!("Unknown placeholder {{{name}}}"))?);
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let templates = r#"{
+            "oncall": [{
+                "type": "section",
+                "text": {"type": "mrkdwn", "text": "`{namespace}/{pod}` exited with {exit_code}"}
+            }]
+        }"#
+        .parse::<MessageTemplates>()
+        .unwrap();
+        let info = ContainerRestartInfo::synthetic(
+            "default",
+            "app-0",
+            "app",
+            "#alerts".to_owned(),
+            Default::default(),
+            "test",
+        );
+        let blocks = templates.render("oncall", &info, &[]).unwrap();
+        assert_eq!(blocks[0]["text"]["text"], "`default/app-0` exited with 1");
+        assert_eq!(blocks[0]["type"], "section");
+        assert!(templates.render("other", &info, &[]).is_none());
+
+        assert!(r#"{"a": [{"text": "{pod_ip}"}]}"#.parse::<MessageTemplates>().is_err());
+        assert!(r#"{"a": [{"text": "{pod"}]}"#.parse::<MessageTemplates>().is_err());
+    }
+}
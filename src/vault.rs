@@ -0,0 +1,312 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{http, slack::SlackToken};
+
+/// Token of the service account mounted in the pod, used to log in to Vault
+const SERVICE_ACCOUNT_TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// Mount path of the Kubernetes auth method by default
+const DEFAULT_VAULT_AUTH_PATH: &str = "kubernetes";
+
+/// Interval to read secrets again when the Vault token doesn't expire
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum interval of renewals, not to hammer Vault with short TTLs
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval to retry after renewal failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Timeout of each request to Vault
+const VAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variable set from a key of a Vault secret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretRef {
+    /// e.g. `SLACK_TOKEN`
+    pub env: String,
+    /// API path of the secret, e.g. `secret/data/johari-mirror` of KV version 2
+    pub path: String,
+    pub key: String,
+}
+
+/// Configuration of reading credentials from HashiCorp Vault with the Kubernetes auth method
+#[derive(Debug, Clone)]
+pub struct VaultConfig {
+    /// e.g. `https://vault.example.com:8200`
+    pub addr: String,
+    /// Role of the Kubernetes auth method bound to the service account
+    pub role: String,
+    pub auth_path: String,
+    pub secrets: Vec<SecretRef>,
+    /// PEM file of the CA certificate of Vault
+    pub ca_cert: Option<String>,
+}
+
+impl VaultConfig {
+    /// Reads configuration from environment variables.
+    /// Returns `None` when `VAULT_ADDR` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(addr) = std::env::var("VAULT_ADDR") else {
+            return Ok(None);
+        };
+        reqwest::Url::parse(&addr).context("Invalid VAULT_ADDR")?;
+        let role = std::env::var("VAULT_ROLE").context("VAULT_ROLE is required")?;
+        let auth_path =
+            std::env::var("VAULT_AUTH_PATH").unwrap_or_else(|_| DEFAULT_VAULT_AUTH_PATH.to_owned());
+        let secrets =
+            parse_secrets(&std::env::var("VAULT_SECRETS").context("VAULT_SECRETS is required")?)
+                .context("Invalid VAULT_SECRETS")?;
+        let ca_cert = std::env::var("VAULT_CACERT").ok();
+        Ok(Some(Self {
+            addr: addr.trim_end_matches('/').to_owned(),
+            role,
+            auth_path: auth_path.trim_matches('/').to_owned(),
+            secrets,
+            ca_cert,
+        }))
+    }
+}
+
+/// Parses `ENV=path#key` pairs delimited by commas,
+/// e.g. `SLACK_TOKEN=secret/data/johari-mirror#slack_token`
+fn parse_secrets(s: &str) -> anyhow::Result<Vec<SecretRef>> {
+    let secrets = s
+        .split(',')
+        .filter(|secret| !secret.trim().is_empty())
+        .map(|secret| {
+            let (env, reference) = secret
+                .trim()
+                .split_once('=')
+                .with_context(|| format!("Missing `=` in secret: {secret}"))?;
+            let (path, key) = reference
+                .rsplit_once('#')
+                .with_context(|| format!("Missing `#key` in secret: {secret}"))?;
+            Ok(SecretRef {
+                env: env.to_owned(),
+                path: path.trim_matches('/').to_owned(),
+                key: key.to_owned(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if secrets.is_empty() {
+        bail!("No secrets");
+    }
+    Ok(secrets)
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthResponse {
+    auth: Auth,
+}
+
+/// Vault token and its lease
+#[derive(Debug, Deserialize)]
+struct Auth {
+    client_token: String,
+    /// Seconds until the token expires, 0 if it doesn't
+    lease_duration: u64,
+    renewable: bool,
+}
+
+/// Client of Vault logged in with the service account
+pub struct Vault {
+    config: VaultConfig,
+    http: reqwest::Client,
+    auth: Auth,
+    /// Values of `config.secrets` read last
+    values: Vec<String>,
+}
+
+impl Vault {
+    /// Logs in to Vault and reads the secrets.
+    pub async fn connect(config: VaultConfig) -> anyhow::Result<Self> {
+        let mut builder = http::client_builder().timeout(VAULT_TIMEOUT);
+        if let Some(path) = &config.ca_cert {
+            let pem = std::fs::read(path).context("Failed to read VAULT_CACERT")?;
+            builder = builder.add_root_certificate(
+                reqwest::Certificate::from_pem(&pem).context("Invalid VAULT_CACERT")?,
+            );
+        }
+        let http = builder.build()?;
+        let auth = login(&http, &config).await?;
+        let mut vault = Self {
+            config,
+            http,
+            auth,
+            values: Vec::new(),
+        };
+        vault.values = vault.read_secrets().await?;
+        Ok(vault)
+    }
+
+    /// Environment variables and their values read from Vault
+    pub fn secrets(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.config
+            .secrets
+            .iter()
+            .zip(&self.values)
+            .map(|(secret, value)| (secret.env.as_str(), value.as_str()))
+    }
+
+    /// Task to renew the Vault token before it expires, logging in again when it can't
+    /// be renewed, and to read the secrets again. A rotated `SLACK_TOKEN` replaces
+    /// `slack_token`, while other credentials are applied on the next start.
+    /// The secrets are read early when Slack rejects the token.
+    pub async fn renew_periodically(mut self, slack_token: SlackToken) {
+        let mut interval = self.refresh_interval();
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = slack_token.rejected() => log::info!("Reading secrets from Vault again"),
+            }
+            interval = match self.refresh(&slack_token).await {
+                Ok(()) => self.refresh_interval(),
+                Err(e) => {
+                    log::error!("Failed to refresh secrets from Vault: {e:#}");
+                    RETRY_INTERVAL
+                }
+            };
+        }
+    }
+
+    /// Two thirds of the TTL of the token
+    fn refresh_interval(&self) -> Duration {
+        match self.auth.lease_duration {
+            0 => DEFAULT_REFRESH_INTERVAL,
+            ttl => Duration::from_secs(ttl * 2 / 3).max(MIN_REFRESH_INTERVAL),
+        }
+    }
+
+    async fn refresh(&mut self, slack_token: &SlackToken) -> anyhow::Result<()> {
+        let renewed = if self.auth.renewable {
+            self.renew()
+                .await
+                .map_err(|e| log::warn!("Failed to renew Vault token, logging in again: {e:#}"))
+                .ok()
+        } else {
+            None
+        };
+        self.auth = match renewed {
+            Some(auth) => auth,
+            None => login(&self.http, &self.config).await?,
+        };
+        let values = self.read_secrets().await?;
+        for ((secret, old), new) in self.config.secrets.iter().zip(&self.values).zip(&values) {
+            if old != new {
+                slack_token.apply_rotated(&secret.env, new.clone(), "Vault");
+            }
+        }
+        self.values = values;
+        Ok(())
+    }
+
+    async fn renew(&self) -> anyhow::Result<Auth> {
+        let resp = self
+            .http
+            .post(format!("{}/v1/auth/token/renew-self", self.config.addr))
+            .header("X-Vault-Token", &self.auth.client_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AuthResponse>()
+            .await?;
+        log::debug!("Renewed Vault token for {}s", resp.auth.lease_duration);
+        Ok(resp.auth)
+    }
+
+    async fn read_secrets(&self) -> anyhow::Result<Vec<String>> {
+        let mut values = Vec::with_capacity(self.config.secrets.len());
+        for secret in &self.config.secrets {
+            let body = self
+                .http
+                .get(format!("{}/v1/{}", self.config.addr, secret.path))
+                .header("X-Vault-Token", &self.auth.client_token)
+                .send()
+                .await?
+                .error_for_status()
+                .with_context(|| format!("Failed to read {} of {}", secret.path, secret.env))?
+                .json::<serde_json::Value>()
+                .await?;
+            let value = secret_value(&body, &secret.key)
+                .with_context(|| format!("{} has no key {}", secret.path, secret.key))?;
+            values.push(value.to_owned());
+        }
+        Ok(values)
+    }
+}
+
+/// Logs in with the token of the service account
+async fn login(http: &reqwest::Client, config: &VaultConfig) -> anyhow::Result<Auth> {
+    let jwt = std::fs::read_to_string(SERVICE_ACCOUNT_TOKEN_PATH)
+        .context("Failed to read the service account token")?;
+    let resp = http
+        .post(format!(
+            "{}/v1/auth/{}/login",
+            config.addr, config.auth_path
+        ))
+        .json(&json!({ "role": config.role, "jwt": jwt.trim() }))
+        .send()
+        .await?
+        .error_for_status()
+        .context("Failed to log in to Vault")?
+        .json::<AuthResponse>()
+        .await?;
+    log::info!(
+        "Logged in to Vault as role {} for {}s",
+        config.role,
+        resp.auth.lease_duration
+    );
+    Ok(resp.auth)
+}
+
+/// Value of `key` in the response of reading a secret of KV version 2, nested in
+/// `data.data`, or of version 1 in `data`
+fn secret_value<'a>(body: &'a serde_json::Value, key: &str) -> Option<&'a str> {
+    let data = &body["data"];
+    data["data"][key].as_str().or_else(|| data[key].as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secrets() {
+        assert_eq!(
+            parse_secrets(
+                "SLACK_TOKEN=secret/data/johari-mirror#slack_token, \
+                 JIRA_API_TOKEN=/kv/jira/#token"
+            )
+            .unwrap(),
+            [
+                SecretRef {
+                    env: "SLACK_TOKEN".to_owned(),
+                    path: "secret/data/johari-mirror".to_owned(),
+                    key: "slack_token".to_owned(),
+                },
+                SecretRef {
+                    env: "JIRA_API_TOKEN".to_owned(),
+                    path: "kv/jira".to_owned(),
+                    key: "token".to_owned(),
+                },
+            ]
+        );
+        assert!(parse_secrets("SLACK_TOKEN=secret/data/johari-mirror").is_err());
+        assert!(parse_secrets("secret/data/johari-mirror#slack_token").is_err());
+        assert!(parse_secrets("").is_err());
+    }
+
+    #[test]
+    fn test_secret_value() {
+        let v2 = json!({ "data": { "data": { "token": "xoxb-2" }, "metadata": {} } });
+        assert_eq!(secret_value(&v2, "token"), Some("xoxb-2"));
+        let v1 = json!({ "data": { "token": "xoxb-1" } });
+        assert_eq!(secret_value(&v1, "token"), Some("xoxb-1"));
+        assert_eq!(secret_value(&v1, "missing"), None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use anyhow::Context;
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::{message::ContainerRestartInfo, middleware::Middleware};
+
+/// Maximum fuel consumed per notification to stop runaway plugins. Plugins run on the
+/// thread sending notifications, which this keeps blocked for tens of milliseconds at most.
+const MAX_FUEL: u64 = 10_000_000;
+
+/// Maximum size of the memory of a plugin instance
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Enrichment filter compiled to WebAssembly, which inspects and modifies notifications
+/// in `NOTIFICATION_MIDDLEWARES`. Plugins run in a sandbox without imports and export:
+/// - `memory`
+/// - `alloc(len: i32) -> i32` returning a buffer of `len` bytes to write the input to
+/// - `process(ptr: i32, len: i32) -> i64` taking the notification in JSON and returning
+///   the modified notification in JSON as `ptr << 32 | len`, or `0` to drop it
+///
+/// Each notification is processed in a fresh instance with `MAX_FUEL` and `MAX_MEMORY_BYTES`.
+/// Notifications are passed through unchanged when the plugin fails.
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let wasm = std::fs::read(path)
+            .with_context(|| format!("Failed to read WASM plugin {}", path.display()))?;
+        Self::compile(path.display().to_string(), &wasm)
+    }
+
+    pub fn compile(name: String, wasm: &[u8]) -> anyhow::Result<Self> {
+        let engine = Engine::new(Config::default().consume_fuel(true));
+        let module =
+            Module::new(&engine, wasm).with_context(|| format!("Invalid WASM plugin {name}"))?;
+        Ok(Self {
+            name,
+            engine,
+            module,
+        })
+    }
+
+    /// Runs the plugin with `input` and returns its output. `None` to drop the notification.
+    fn call(&self, input: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.add_fuel(MAX_FUEL).map_err(wasmi::Error::from)?;
+        // No imports are linked to sandbox plugins
+        let instance = Linker::new(&self.engine)
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .context("Plugin does not export memory")?;
+        let alloc = instance.get_typed_func::<u32, u32>(&store, "alloc")?;
+        let process = instance.get_typed_func::<(u32, u32), u64>(&store, "process")?;
+
+        let len = u32::try_from(input.len()).context("Notification too large")?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory
+            .write(&mut store, ptr as usize, input)
+            .map_err(wasmi::Error::from)?;
+        let result = process.call(&mut store, (ptr, len))?;
+        if result == 0 {
+            return Ok(None);
+        }
+        // Checked before copying not to allocate lengths beyond the memory
+        let ptr = (result >> 32) as usize;
+        let len = (result & 0xffff_ffff) as usize;
+        let output = memory
+            .data(&store)
+            .get(ptr..ptr.saturating_add(len))
+            .context("Plugin output is out of its memory")?;
+        Ok(Some(output.to_vec()))
+    }
+
+    fn process_json(
+        &self,
+        info: &ContainerRestartInfo,
+    ) -> anyhow::Result<Option<ContainerRestartInfo>> {
+        let input = serde_json::to_vec(info)?;
+        let Some(output) = self.call(&input)? else {
+            return Ok(None);
+        };
+        let processed = serde_json::from_slice(&output).context("Invalid plugin output")?;
+        Ok(Some(processed))
+    }
+}
+
+impl Middleware for WasmPlugin {
+    fn process(&mut self, info: ContainerRestartInfo) -> Option<ContainerRestartInfo> {
+        match self.process_json(&info) {
+            Ok(Some(processed)) => Some(ContainerRestartInfo {
+                span: info.span,
+                queue_id: info.queue_id,
+                ..processed
+            }),
+            Ok(None) => {
+                log::info!("Notification dropped by WASM plugin {}: {info}", self.name);
+                None
+            }
+            Err(e) => {
+                log::error!("WASM plugin {} failed: {e:#}", self.name);
+                Some(info)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ContainerLog, ContainerResources};
+
+    /// Plugin allocating from a bump pointer with `process` as the body of its `process`
+    fn plugin(process: &str) -> WasmPlugin {
+        let wasm = wat::parse_str(format!(
+            r#"
+            (module
+              (memory (export "memory") 16)
+              (global $next (mut i32) (i32.const 1024))
+              (func (export "alloc") (param $len i32) (result i32)
+                (global.get $next)
+                (global.set $next (i32.add (global.get $next) (local.get $len))))
+              (func (export "process") (param $ptr i32) (param $len i32) (result i64)
+                {process}))
+            "#
+        ))
+        .unwrap();
+        WasmPlugin::compile("test".to_owned(), &wasm).unwrap()
+    }
+
+    fn restart_info() -> ContainerRestartInfo {
+        ContainerRestartInfo {
+            namespace: Some("default".to_owned()),
+            pod_name: "app-0".to_owned(),
+            pod_uid: None,
+            container_name: "app".to_owned(),
+            container_image: "app:latest".to_owned(),
+            node_name: None,
+            workload: None,
+            labels: Default::default(),
+            restart_count: 1,
+            last_state: None,
+            resources: ContainerResources::default(),
+            logs: ContainerLog(Ok("log".to_owned())),
+            channel: "#alerts".to_owned(),
+            options: Default::default(),
+            links: Vec::new(),
+            notes: Vec::new(),
+            category: None,
+            sidecar_logs: Vec::new(),
+            priority: None,
+            node_info: None,
+            attachments: Vec::new(),
+            span: tracing::Span::none(),
+            queue_id: Some(42),
+        }
+    }
+
+    #[test]
+    fn test_wasm_plugin() {
+        // Returns the input unchanged
+        let mut echo = plugin(
+            "(i64.or
+               (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+               (i64.extend_i32_u (local.get $len)))",
+        );
+        let info = echo.process(restart_info()).unwrap();
+        assert_eq!(info.container_key(), "default/app-0/app");
+        assert_eq!(info.queue_id, Some(42));
+
+        let mut drop = plugin("(i64.const 0)");
+        assert!(drop.process(restart_info()).is_none());
+
+        // Failing plugins pass notifications through
+        let mut trap = plugin("(unreachable)");
+        assert!(trap.process(restart_info()).is_some());
+        let mut runaway = plugin("(loop (br 0)) (i64.const 0)");
+        assert!(runaway.process(restart_info()).is_some());
+        let mut out_of_bounds = plugin("(i64.const 0xffff_ffff)");
+        assert!(out_of_bounds.process(restart_info()).is_some());
+        assert!(out_of_bounds.call(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_wasm_plugin_memory_limit() {
+        // 128 MiB of memory
+        let wasm = wat::parse_str(r#"(module (memory (export "memory") 2048))"#).unwrap();
+        let plugin = WasmPlugin::compile("test".to_owned(), &wasm).unwrap();
+        assert!(plugin.call(b"{}").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+/// Formats `objects` as a multi-document YAML, in the style of `deployment/example.yaml`
+pub fn to_yaml(objects: &[serde_json::Value]) -> String {
+    objects
+        .iter()
+        .map(|object| {
+            let mut yaml = String::new();
+            write_yaml(&mut yaml, object, 0);
+            yaml
+        })
+        .collect::<Vec<_>>()
+        .join("---\n")
+}
+
+/// Writes `value` of a mapping or a sequence indented by `indent` spaces
+fn write_yaml(out: &mut String, value: &serde_json::Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    _ if is_empty(value) => out.push_str(&format!("{pad}{key}: {value}\n")),
+                    serde_json::Value::Object(_) | serde_json::Value::Array(_) => {
+                        out.push_str(&format!("{pad}{key}:\n"));
+                        write_yaml(out, value, indent + 2);
+                    }
+                    _ => out.push_str(&format!("{pad}{key}: {}\n", scalar(value))),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                match item {
+                    _ if is_empty(item) => out.push_str(&format!("{pad}- {item}\n")),
+                    serde_json::Value::Object(_) | serde_json::Value::Array(_) => {
+                        // The first line of the item follows the dash
+                        let mut nested = String::new();
+                        write_yaml(&mut nested, item, indent + 2);
+                        out.push_str(&format!("{pad}- {}", &nested[indent + 2..]));
+                    }
+                    _ => out.push_str(&format!("{pad}- {}\n", scalar(item))),
+                }
+            }
+        }
+        _ => out.push_str(&format!("{pad}{}\n", scalar(value))),
+    }
+}
+
+/// Empty mappings and sequences are written in the flow style, e.g. `emptyDir: {}`
+fn is_empty(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Object(map) => map.is_empty(),
+        serde_json::Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+/// Plain scalar, or single-quoted when it would be read as another type or syntax.
+/// Strings of multiple lines or control characters are double-quoted with JSON escapes.
+fn scalar(value: &serde_json::Value) -> String {
+    let serde_json::Value::String(s) = value else {
+        return value.to_string();
+    };
+    if s.chars().any(char::is_control) {
+        return value.to_string();
+    }
+    let plain = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '/' | '_'))
+        && !matches!(s.as_str(), "true" | "false" | "null" | "yes" | "no")
+        && s.parse::<f64>().is_err();
+    if plain {
+        s.clone()
+    } else {
+        format!("'{}'", s.replace('\'', "''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_to_yaml() {
+        let object = json!({
+            "volumes": [{"name": "tmp", "emptyDir": {}}],
+            "args": [],
+            "command": ["sh", "-c", "echo a\necho b"],
+            "value": "true",
+        });
+        assert_eq!(
+            to_yaml(&[object]),
+            "args: []\n\
+             command:\n  \
+               - sh\n  \
+               - -c\n  \
+               - \"echo a\\necho b\"\n\
+             value: 'true'\n\
+             volumes:\n  \
+               - emptyDir: {}\n    \
+                 name: tmp\n"
+        );
+    }
+}